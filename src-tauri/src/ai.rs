@@ -1,7 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
-use std::path::PathBuf;
-use std::fs;
+use std::path::{Path, PathBuf};
 use reqwest;
 use tokio::io::AsyncRead;
 use uuid;
@@ -18,6 +17,16 @@ use futures::stream::{self, Stream};
 use std::sync::Arc;
 use serde_json::Value;
 use std::io;
+use crate::persistence;
+use crate::diagnostics::DiagnosticStep;
+use crate::secrets::{self, SecretStore};
+use crate::provider::{self, ProviderChain};
+use crate::retrieval;
+use crate::usage::{self, StreamUsage, UsageSource};
+use crate::retry::RetryPolicy;
+use crate::token_budget::{self, ContextStrategy};
+use crate::stream_registry::{self, StreamInfo, StreamStatus};
+use crate::role::RoleBinding;
 
 // 在ai.rs中定义AppState结构体类型别名，指向main.rs中的AppState
 // 必须在同一文件中实现，否则需要导出
@@ -76,52 +85,30 @@ impl AIManager {
     }
     
     /// 从文件加载角色
-    fn load_roles_from_file(file_path: &PathBuf) -> Result<Vec<AIRole>, String> {
-        if !file_path.exists() {
-            return Ok(Vec::new());
-        }
-        
-        let content = fs::read_to_string(file_path)
-            .map_err(|e| format!("读取角色文件失败: {}", e))?;
-            
-        serde_json::from_str(&content)
-            .map_err(|e| format!("解析角色JSON失败: {}", e))
+    fn load_roles_from_file(file_path: &Path) -> Result<Vec<AIRole>, String> {
+        persistence::load_json(file_path)
+            .map(|roles| roles.unwrap_or_default())
+            .map_err(|e| format!("读取角色文件失败: {}", e))
     }
     
     /// 保存角色到文件
-    fn save_roles_to_file(&self) -> Result<(), String> {
-        let roles = self.roles.lock()
-            .map_err(|e| format!("获取角色数据失败: {}", e))?;
-            
-        let content = serde_json::to_string_pretty(&*roles)
-            .map_err(|e| format!("序列化角色数据失败: {}", e))?;
-            
-        fs::write(&self.roles_file, content)
+    ///
+    /// 调用方已持有角色锁，因此直接传入数据而不是再次加锁
+    fn save_roles_to_file(&self, roles: &[AIRole]) -> Result<(), String> {
+        persistence::save_json(&self.roles_file, &roles)
             .map_err(|e| format!("写入角色文件失败: {}", e))
     }
     
     /// 从文件加载配置
-    fn load_config_from_file(file_path: &PathBuf) -> Result<AIConfig, String> {
-        if !file_path.exists() {
-            return Err("配置文件不存在".to_string());
-        }
-        
-        let content = fs::read_to_string(file_path)
-            .map_err(|e| format!("读取配置文件失败: {}", e))?;
-            
-        serde_json::from_str(&content)
-            .map_err(|e| format!("解析配置JSON失败: {}", e))
+    fn load_config_from_file(file_path: &Path) -> Result<AIConfig, String> {
+        persistence::load_json(file_path)
+            .map_err(|e| format!("读取配置文件失败: {}", e))?
+            .ok_or_else(|| "配置文件不存在".to_string())
     }
     
     /// 保存配置到文件
-    fn save_config_to_file(&self) -> Result<(), String> {
-        let config = self.config.lock()
-            .map_err(|e| format!("获取配置数据失败: {}", e))?;
-            
-        let content = serde_json::to_string_pretty(&*config)
-            .map_err(|e| format!("序列化配置数据失败: {}", e))?;
-            
-        fs::write(&self.config_file, content)
+    fn save_config_to_file(&self, config: &AIConfig) -> Result<(), String> {
+        persistence::save_json(&self.config_file, config)
             .map_err(|e| format!("写入配置文件失败: {}", e))
     }
     
@@ -144,7 +131,7 @@ impl AIManager {
         }
         
         roles.push(role);
-        self.save_roles_to_file()
+        self.save_roles_to_file(&roles)
     }
     
     /// 更新角色
@@ -156,7 +143,7 @@ impl AIManager {
             .ok_or_else(|| format!("未找到角色ID '{}'", updated_role.id))?;
             
         roles[index] = updated_role;
-        self.save_roles_to_file()
+        self.save_roles_to_file(&roles)
     }
     
     /// 删除角色
@@ -173,7 +160,7 @@ impl AIManager {
         }
         
         roles.remove(index);
-        self.save_roles_to_file()
+        self.save_roles_to_file(&roles)
     }
     
     /// 获取配置
@@ -190,7 +177,7 @@ impl AIManager {
             .map_err(|e| format!("获取配置数据失败: {}", e))?;
             
         *config = updated_config;
-        self.save_config_to_file()
+        self.save_config_to_file(&config)
     }
 }
 
@@ -203,22 +190,14 @@ pub enum AIProvider {
     Azure,
     #[serde(rename = "anthropic")]
     Anthropic,
+    #[serde(rename = "zhipu")]
+    Zhipu,
+    #[serde(rename = "ollama")]
+    Ollama,
     #[serde(rename = "custom")]
     Custom,
 }
 
-/// AI角色
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Role {
-    pub id: String,
-    pub name: String,
-    pub description: String,
-    pub system_prompt: String,
-    pub is_default: bool,
-    pub created_at: u64,
-    pub updated_at: u64,
-}
-
 /// AI聊天消息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -237,111 +216,53 @@ pub enum ChatRole {
     Assistant,
 }
 
-/// AI服务
-pub struct AIService {
-    config: Mutex<AIConfig>,
-    roles: Mutex<Vec<Role>>,
+/// 使用指定的提供商翻译文本
+pub async fn translate_text(
+    state: &AppState,
+    chain: ProviderChain,
+    source: UsageSource,
+    text: &str,
+    target_language: &str,
+) -> Result<String, String> {
+    let system_prompt = format!(
+        "你是一个专业的翻译助手。请将用户提供的内容翻译为{}，保留原文的格式，只输出译文，不要添加任何解释。",
+        target_language
+    );
+    complete_text(state, chain, source, system_prompt, text).await
 }
 
-impl AIService {
-    /// 创建新的AI服务实例
-    pub fn new(config: AIConfig) -> Self {
-        Self {
-            config: Mutex::new(config),
-            roles: Mutex::new(vec![create_default_role()]),
-        }
-    }
-    
-    /// 加载默认角色
-    pub fn load_roles(&self) -> Vec<Role> {
-        let roles = self.roles.lock().unwrap();
-        roles.clone()
-    }
-    
-    /// 获取AI配置
-    pub fn get_config(&self) -> AIConfig {
-        let config = self.config.lock().unwrap();
-        config.clone()
-    }
-    
-    /// 更新AI配置
-    pub fn update_config(&self, config: AIConfig) {
-        let mut current_config = self.config.lock().unwrap();
-        *current_config = config;
-    }
-    
-    /// 添加新角色
-    pub fn add_role(&self, role: Role) -> Result<(), String> {
-        let mut roles = self.roles.lock().unwrap();
-        
-        // 检查ID是否已存在
-        if roles.iter().any(|r| r.id == role.id) {
-            return Err("角色ID已存在".to_string());
-        }
-        
-        roles.push(role);
-        Ok(())
-    }
-    
-    /// 删除角色
-    pub fn delete_role(&self, id: &str) -> Result<(), String> {
-        let mut roles = self.roles.lock().unwrap();
-        
-        // 检查是否为默认角色
-        if let Some(role) = roles.iter().find(|r| r.id == id) {
-            if role.is_default {
-                return Err("不能删除默认角色".to_string());
-            }
-        } else {
-            return Err("角色不存在".to_string());
-        }
-        
-        roles.retain(|r| r.id != id);
-        Ok(())
-    }
-    
-    /// 更新角色
-    pub fn update_role(&self, role: Role) -> Result<(), String> {
-        let mut roles = self.roles.lock().unwrap();
-        
-        let index = roles.iter().position(|r| r.id == role.id)
-            .ok_or_else(|| "角色不存在".to_string())?;
-        
-        // 不能更改默认角色的默认状态
-        if roles[index].is_default && !role.is_default {
-            return Err("不能取消默认角色的默认状态".to_string());
-        }
-        
-        // 更新角色
-        roles[index] = role;
-        Ok(())
-    }
-    
-    /// 翻译文本
-    pub async fn translate_text(&self, text: &str, target_language: &str) -> Result<String, String> {
-        // 这里应该调用AI API进行翻译
-        // 目前返回模拟结果
-        Ok(format!("这是 {} 的{}翻译", text, target_language))
-    }
-    
-    /// 总结文本
-    pub async fn summarize_text(&self, text: &str) -> Result<String, String> {
-        // 这里应该调用AI API进行总结
-        // 目前返回模拟结果
-        Ok(format!("这是 {} 的总结", text))
-    }
+/// 使用指定的提供商总结文本
+pub async fn summarize_text(state: &AppState, chain: ProviderChain, source: UsageSource, text: &str) -> Result<String, String> {
+    let system_prompt = "你是一个擅长提炼要点的助手。请用简洁的中文总结用户提供的内容，突出关键信息，只输出总结内容。".to_string();
+    complete_text(state, chain, source, system_prompt, text).await
 }
 
-/// 创建默认角色
-fn create_default_role() -> Role {
-    Role {
-        id: "default".to_string(),
-        name: "默认助手".to_string(),
-        description: "一个通用的AI助手，可以帮助回答问题、翻译文本和总结内容。".to_string(),
-        system_prompt: "你是一个运行在Copy2AI智能剪贴板的AI助手，擅长以风趣幽默、生动活泼的方式与用户交流，能够灵活运用各种emoji表情包增添趣味性。你可以帮助用户针对剪贴板中的内容进行精准回答、高效翻译和简洁总结，同时还能提供实用的建议和有趣的见解，让用户在使用过程中感受到便捷与快乐。".to_string(),
-        is_default: true,
-        created_at: chrono::Utc::now().timestamp_millis() as u64,
-        updated_at: chrono::Utc::now().timestamp_millis() as u64,
+/// 发送一次非流式请求并返回回复的文本，请求计入用量统计并受预算限制
+///
+/// 过长的内容按首选模型的上下文长度截取，不受对话的上下文策略影响。
+async fn complete_text(
+    state: &AppState,
+    chain: ProviderChain,
+    source: UsageSource,
+    system_prompt: String,
+    text: &str,
+) -> Result<String, String> {
+    let messages = vec![
+        ChatCompletionMessage { role: "system".to_string(), content: system_prompt },
+        ChatCompletionMessage { role: "user".to_string(), content: text.to_string() },
+    ];
+    let request = chain.primary().request(None, messages, None, None)?;
+    let limit = token_budget::context_limit(&request.model, &chain.primary().context_limits);
+    let fitted = token_budget::fit(request, limit, ContextStrategy::Chunk);
+    if fitted.report.omitted_chunks > 0 {
+        eprintln!("内容超出模型 {} 的上下文长度 {}，省略了 {} 段", fitted.report.model, limit, fitted.report.omitted_chunks);
+    }
+    let max_tokens = Some(fitted.report.max_tokens as i32);
+    let (response, _) = usage::complete(state, chain, source, None, fitted.request.messages, None, max_tokens).await?;
+    
+    match response["choices"][0]["message"]["content"].as_str().map(str::trim) {
+        Some(content) if !content.is_empty() => Ok(content.to_string()),
+        _ => Err(format!("无法解析AI响应: {}", response)),
     }
 }
 
@@ -358,6 +279,80 @@ pub struct AIProviderSetting {
     pub dynamic_models: Option<Vec<String>>,
     pub last_test_time: Option<u64>,
     pub test_success: Option<bool>,
+    /// Azure OpenAI 的部署信息，设置后按 Azure 的接口格式发送请求
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure: Option<AzureDeployment>,
+}
+
+// Azure OpenAI 默认使用的接口版本
+const DEFAULT_AZURE_API_VERSION: &str = "2024-06-01";
+
+fn default_azure_api_version() -> String {
+    DEFAULT_AZURE_API_VERSION.to_string()
+}
+
+/// Azure OpenAI 的部署信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AzureDeployment {
+    /// 资源地址，例如 https://my-resource.openai.azure.com
+    pub endpoint: String,
+    /// 部署名称，为空时使用所选模型作为部署名称
+    #[serde(default)]
+    pub deployment: String,
+    #[serde(default = "default_azure_api_version")]
+    pub api_version: String,
+}
+
+impl AzureDeployment {
+    /// 根据资源地址创建，部署名称取自所选模型
+    pub fn from_endpoint(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            deployment: String::new(),
+            api_version: default_azure_api_version(),
+        }
+    }
+    
+    /// 未填写的资源地址使用API地址，未填写的接口版本使用默认版本
+    pub fn with_defaults(mut self, base_url: &str) -> Self {
+        if self.endpoint.trim().is_empty() {
+            self.endpoint = base_url.to_string();
+        }
+        if self.api_version.trim().is_empty() {
+            self.api_version = default_azure_api_version();
+        }
+        self
+    }
+    
+    fn base_url(&self) -> &str {
+        self.endpoint.trim_end_matches('/')
+    }
+    
+    /// 聊天接口地址
+    pub fn chat_url(&self, model: &str) -> String {
+        let deployment = if self.deployment.is_empty() { model } else { self.deployment.as_str() };
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.base_url(),
+            deployment,
+            self.api_version
+        )
+    }
+    
+    /// 向量接口地址
+    pub fn embeddings_url(&self, deployment: &str) -> String {
+        format!(
+            "{}/openai/deployments/{}/embeddings?api-version={}",
+            self.base_url(),
+            deployment,
+            self.api_version
+        )
+    }
+    
+    /// 部署列表接口地址，该接口只在 2022-12-01 版本中提供
+    pub fn deployments_url(&self) -> String {
+        format!("{}/openai/deployments?api-version=2022-12-01", self.base_url())
+    }
 }
 
 // 新增AI设置结构体
@@ -365,6 +360,18 @@ pub struct AIProviderSetting {
 pub struct AISettings {
     pub selected_provider_id: String,
     pub providers: std::collections::HashMap<String, AIProviderSetting>,
+    /// 请求超出模型上下文长度时的处理方式
+    #[serde(default)]
+    pub context_strategy: ContextStrategy,
+    /// 请求失败时依次尝试的备用提供商ID
+    #[serde(default)]
+    pub fallback_provider_ids: Vec<String>,
+    /// 限流或服务暂时不可用时的重试设置
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// 基于剪贴板回答时，是否允许把归为密码的条目发送给模型
+    #[serde(default)]
+    pub share_passwords_in_context: bool,
 }
 
 // 模型信息结构体
@@ -382,29 +389,39 @@ pub struct APITestResult {
     pub success: bool,
     pub message: String,
     pub response_time: Option<u64>,
+    /// 各诊断步骤的结果
+    #[serde(default)]
+    pub steps: Vec<DiagnosticStep>,
 }
 
 // AI设置管理器
+//
+// 内存和文件中的 api_key 只保存密钥引用，读取设置时才从密钥存储中取回明文
 pub struct AISettingsManager {
     settings: Mutex<AISettings>,
     settings_file: PathBuf,
+    secrets: Arc<SecretStore>,
+    /// 模型列表接口返回的上下文长度，模型ID -> token 数
+    context_limits: Mutex<std::collections::HashMap<String, u32>>,
+    context_limits_file: PathBuf,
 }
 
 impl AISettingsManager {
     /// 创建新的AI设置管理器
-    pub fn new(data_dir: PathBuf) -> Self {
+    pub fn new(data_dir: PathBuf, secrets: Arc<SecretStore>) -> Self {
         let settings_file = data_dir.join("ai_settings.json");
+        let context_limits_file = data_dir.join("model_context_limits.json");
         
         // 尝试加载设置
         let settings = Self::load_settings_from_file(&settings_file).unwrap_or_else(|_| {
             // 默认使用Copy2AI
             let mut providers = std::collections::HashMap::new();
             
-            // 添加Copy2AI提供商设置，含硬编码API密钥
+            // 添加Copy2AI提供商设置，默认密钥在构建时通过环境变量提供
             providers.insert("Copy2AI".to_string(), AIProviderSetting {
                 custom_api_base_url: None,
                 selected_model: "GLM-4-Flash-250414".to_string(),
-                api_key: Some("d9885ff5e6b14c21a34065588fb0face.aOMiNs6uIDMo5yOX".to_string()),
+                api_key: option_env!("COPY2AI_API_KEY").map(String::from),
                 models_list_url: None,
                 temperature: 0.7,
                 max_tokens: 2048,
@@ -412,6 +429,7 @@ impl AISettingsManager {
                 dynamic_models: None,
                 last_test_time: None,
                 test_success: None,
+                azure: None,
             });
             
             // 添加kimi提供商设置作为备选
@@ -426,60 +444,142 @@ impl AISettingsManager {
                 dynamic_models: None,
                 last_test_time: None,
                 test_success: None,
+                azure: None,
             });
             
             AISettings {
                 selected_provider_id: "Copy2AI".to_string(),
                 providers,
+                context_strategy: ContextStrategy::default(),
+                fallback_provider_ids: Vec::new(),
+                retry: RetryPolicy::default(),
+                share_passwords_in_context: false,
             }
         });
         
-        Self {
+        let context_limits = persistence::load_json(&context_limits_file)
+            .unwrap_or_else(|e| {
+                eprintln!("加载模型上下文长度失败: {}", e);
+                None
+            })
+            .unwrap_or_default();
+        
+        let manager = Self {
             settings: Mutex::new(settings),
             settings_file,
+            secrets,
+            context_limits: Mutex::new(context_limits),
+            context_limits_file,
+        };
+        
+        // 迁移旧版本以明文保存的密钥
+        if let Err(e) = manager.migrate_secrets() {
+            eprintln!("迁移AI密钥失败: {}", e);
         }
+        
+        manager
     }
     
-    /// 从文件加载设置
-    fn load_settings_from_file(file_path: &PathBuf) -> Result<AISettings, String> {
-        if !file_path.exists() {
-            return Err("设置文件不存在".to_string());
+    // 密钥存储中保存提供商API密钥的名称
+    fn secret_name(provider_id: &str) -> String {
+        format!("ai.{}", provider_id)
+    }
+    
+    /// 将提供商的API密钥保存到密钥存储，设置中只保留引用
+    fn store_secret(&self, provider_id: &str, setting: &mut AIProviderSetting) -> Result<(), String> {
+        if let Some(api_key) = &setting.api_key {
+            let stored = self.secrets.store(&Self::secret_name(provider_id), api_key)?;
+            setting.api_key = (!stored.is_empty()).then_some(stored);
+        }
+        Ok(())
+    }
+    
+    /// 将设置中的密钥引用替换为明文，无法读取的密钥替换为占位值
+    fn resolve_secret(&self, setting: &mut AIProviderSetting) {
+        if let Some(api_key) = &setting.api_key {
+            setting.api_key = match self.secrets.resolve(api_key) {
+                Ok(api_key) => Some(api_key),
+                Err(e) => {
+                    eprintln!("读取API密钥失败: {}", e);
+                    Some(secrets::LOCKED_MARKER.to_string())
+                }
+            };
+        }
+    }
+    
+    /// 前端未传入密钥或传回占位值时沿用已保存的密钥引用，避免密钥被覆盖丢失
+    fn keep_existing_secret(existing: Option<&AIProviderSetting>, setting: &mut AIProviderSetting) {
+        if setting.api_key.as_deref().is_none_or(secrets::is_locked_marker) {
+            setting.api_key = existing.and_then(|existing| existing.api_key.clone());
+        }
+    }
+    
+    /// 将明文保存的API密钥迁移到密钥存储，密钥存储不可用时跳过
+    pub fn migrate_secrets(&self) -> Result<(), String> {
+        let mut settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        let has_plaintext = settings.providers.values().any(|setting| {
+            setting.api_key.as_deref().is_some_and(|api_key| secrets::parse_reference(api_key).is_none())
+        });
+        if !has_plaintext || !self.secrets.is_available() {
+            return Ok(());
         }
         
-        let content = fs::read_to_string(file_path)
-            .map_err(|e| format!("读取设置文件失败: {}", e))?;
-            
-        serde_json::from_str(&content)
-            .map_err(|e| format!("解析设置JSON失败: {}", e))
+        for (provider_id, setting) in settings.providers.iter_mut() {
+            self.store_secret(provider_id, setting)?;
+        }
+        self.save_settings_to_file(&settings)
+    }
+    
+    /// 从文件加载设置
+    fn load_settings_from_file(file_path: &Path) -> Result<AISettings, String> {
+        persistence::load_json(file_path)
+            .map_err(|e| format!("读取设置文件失败: {}", e))?
+            .ok_or_else(|| "设置文件不存在".to_string())
     }
     
     /// 保存设置到文件
-    fn save_settings_to_file(&self) -> Result<(), String> {
-        let settings = self.settings.lock()
-            .map_err(|e| format!("获取设置数据失败: {}", e))?;
-            
-        let content = serde_json::to_string_pretty(&*settings)
-            .map_err(|e| format!("序列化设置数据失败: {}", e))?;
-            
-        fs::write(&self.settings_file, content)
+    ///
+    /// 调用方已持有设置锁，因此直接传入数据而不是再次加锁
+    fn save_settings_to_file(&self, settings: &AISettings) -> Result<(), String> {
+        persistence::save_json(&self.settings_file, settings)
             .map_err(|e| format!("写入设置文件失败: {}", e))
     }
     
     /// 获取设置
     pub fn get_settings(&self) -> Result<AISettings, String> {
-        let settings = self.settings.lock()
-            .map_err(|e| format!("获取设置数据失败: {}", e))?;
-            
-        Ok(settings.clone())
+        let mut settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?
+            .clone();
+        
+        for setting in settings.providers.values_mut() {
+            self.resolve_secret(setting);
+        }
+        Ok(settings)
     }
     
     /// 更新设置
-    pub fn update_settings(&self, new_settings: AISettings) -> Result<(), String> {
+    pub fn update_settings(&self, mut new_settings: AISettings) -> Result<(), String> {
         let mut settings = self.settings.lock()
             .map_err(|e| format!("获取设置数据失败: {}", e))?;
-            
+        
+        for (provider_id, setting) in new_settings.providers.iter_mut() {
+            Self::keep_existing_secret(settings.providers.get(provider_id), setting);
+            self.store_secret(provider_id, setting)?;
+        }
         *settings = new_settings;
-        self.save_settings_to_file()
+        self.save_settings_to_file(&settings)
+    }
+    
+    /// 获取全局设置，不含各提供商的设置，因此不读取任何密钥
+    pub fn get_global_settings(&self) -> Result<AISettings, String> {
+        let settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        
+        Ok(AISettings {
+            providers: std::collections::HashMap::new(),
+            ..settings.clone()
+        })
     }
     
     /// 获取提供商设置
@@ -487,75 +587,102 @@ impl AISettingsManager {
         let settings = self.settings.lock()
             .map_err(|e| format!("获取设置数据失败: {}", e))?;
             
-        settings.providers.get(provider_id)
+        let mut setting = settings.providers.get(provider_id)
             .cloned()
-            .ok_or_else(|| format!("未找到提供商ID '{}'的设置", provider_id))
+            .ok_or_else(|| format!("未找到提供商ID '{}'的设置", provider_id))?;
+        
+        self.resolve_secret(&mut setting);
+        Ok(setting)
     }
     
     /// 更新提供商设置
-    pub fn update_provider_setting(&self, provider_id: &str, setting: AIProviderSetting) -> Result<(), String> {
+    pub fn update_provider_setting(&self, provider_id: &str, mut setting: AIProviderSetting) -> Result<(), String> {
         let mut settings = self.settings.lock()
             .map_err(|e| format!("获取设置数据失败: {}", e))?;
-            
+        
+        Self::keep_existing_secret(settings.providers.get(provider_id), &mut setting);
+        self.store_secret(provider_id, &mut setting)?;
         settings.providers.insert(provider_id.to_string(), setting);
-        self.save_settings_to_file()
+        self.save_settings_to_file(&settings)
     }
-}
-
-#[tauri::command]
-// Modify the function signature to accept the test URL (passed as api_base_url from frontend)
-async fn test_api_connection(api_base_url: String, api_key: Option<String>, _model: String) -> Result<APITestResult, String> {
-    let url_to_test = api_base_url;
-    
-    // Add the missing reqwest client initialization
-    let client = reqwest::Client::new(); 
-
-    let is_local_service = url_to_test.contains("localhost") || url_to_test.contains("127.0.0.1");
-    let is_ollama_or_compatible = url_to_test.contains("open.bigmodel.cn/api/paas/v4") || 
-                                url_to_test.ends_with("/v1") || 
-                                url_to_test.ends_with("/v1/") ||
-                                url_to_test.contains("localhost:11434"); // Common Ollama port
-    
-    let start_time = std::time::Instant::now();
-    
-    // Determine the correct endpoint to ping based on the service type
-    let test_endpoint = if is_ollama_or_compatible {
-        // Standard OpenAI compatible or Ollama endpoint for listing models or simple check
-        // Prefer /models, but fallback might be needed for some servers
-        if url_to_test.ends_with("/") {
-            format!("{}models", url_to_test)
-        } else {
-            format!("{}/models", url_to_test)
-        }
-        // Alternatively, ping root or /health if /models isn't standard
-        // format!("{}", url_to_test) 
-    } else {
-        // Fallback for non-standard APIs, maybe just ping the base URL?
-        // Or assume it needs /models if not explicitly Ollama-like
-         if url_to_test.ends_with("/") {
-            format!("{}models", url_to_test)
-        } else {
-            format!("{}/models", url_to_test)
-        }
-    };
     
-    println!("实际测试端点: {}", test_endpoint);
+    /// 设置超出上下文长度时的处理方式
+    pub fn set_context_strategy(&self, strategy: ContextStrategy) -> Result<(), String> {
+        let mut settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        
+        settings.context_strategy = strategy;
+        self.save_settings_to_file(&settings)
+    }
     
-    // 发送请求到计算出的 test_endpoint
-    let mut request = client.get(&test_endpoint); 
+    /// 基于剪贴板回答时是否允许发送归为密码的条目，不读取密钥
+    pub fn share_passwords_in_context(&self) -> Result<bool, String> {
+        let settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        Ok(settings.share_passwords_in_context)
+    }
     
-    // 只有非本地服务才强制验证API密钥
-    // ... existing code ...
-
-    let end_time = std::time::Instant::now();
-    let response_time = end_time.duration_since(start_time).as_millis();
+    /// 设置基于剪贴板回答时是否允许发送归为密码的条目
+    pub fn set_share_passwords_in_context(&self, enabled: bool) -> Result<(), String> {
+        let mut settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        
+        settings.share_passwords_in_context = enabled;
+        self.save_settings_to_file(&settings)
+    }
     
-    Ok(APITestResult {
-        success: true,
-        message: "API连接测试成功".to_string(),
-        response_time: Some(response_time as u64), 
-    })
-} 
+    /// 设置请求失败时依次尝试的备用提供商
+    pub fn set_fallback_providers(&self, provider_ids: Vec<String>) -> Result<(), String> {
+        let mut settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        
+        settings.fallback_provider_ids = provider_ids;
+        self.save_settings_to_file(&settings)
+    }
+    
+    /// 设置限流或服务暂时不可用时的重试方式
+    pub fn set_retry_policy(&self, retry: RetryPolicy) -> Result<(), String> {
+        let mut settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        
+        settings.retry = retry;
+        self.save_settings_to_file(&settings)
+    }
+    
+    /// 已知的模型上下文长度
+    pub fn context_limits(&self) -> Result<std::collections::HashMap<String, u32>, String> {
+        Ok(self.context_limits.lock().map_err(|e| e.to_string())?.clone())
+    }
+    
+    /// 保存模型列表中返回的上下文长度
+    pub fn record_context_limits(&self, models: &[ModelInfo]) -> Result<(), String> {
+        let mut context_limits = self.context_limits.lock().map_err(|e| e.to_string())?;
+        
+        let mut changed = false;
+        for model in models {
+            if let Some(max_tokens) = model.max_tokens.filter(|max_tokens| *max_tokens > 0) {
+                changed |= context_limits.insert(model.id.clone(), max_tokens) != Some(max_tokens);
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+        persistence::save_json(&self.context_limits_file, &*context_limits)
+    }
+    
+    /// 记录连接测试的结果，未保存过设置的提供商忽略
+    pub fn record_test_result(&self, provider_id: &str, success: bool) -> Result<(), String> {
+        let mut settings = self.settings.lock()
+            .map_err(|e| format!("获取设置数据失败: {}", e))?;
+        
+        let Some(setting) = settings.providers.get_mut(provider_id) else {
+            return Ok(());
+        };
+        setting.last_test_time = Some(chrono::Utc::now().timestamp_millis() as u64);
+        setting.test_success = Some(success);
+        self.save_settings_to_file(&settings)
+    }
+}
 
 // 聊天相关的结构定义
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -594,6 +721,69 @@ pub struct ChatCompletionResponse {
     pub choices: Vec<ChatCompletionChoice>,
 }
 
+/// stream_chat 命令的请求参数
+#[derive(Debug, Deserialize)]
+pub struct StreamChatRequest {
+    /// API基础URL，未指定时使用提供商设置中的地址
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// 未指定时使用提供商设置中选择的模型
+    #[serde(default)]
+    pub model: Option<String>,
+    pub messages: Vec<ChatCompletionMessage>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    /// 提供商ID，未指定时使用当前选择的提供商
+    #[serde(default)]
+    pub provider_id: Option<String>,
+    /// 由前端指定的流ID，用于取消请求；未指定时自动生成
+    #[serde(default)]
+    pub stream_id: Option<String>,
+    /// 发起请求的剪贴板条目或角色，用于用量统计
+    #[serde(default)]
+    pub source: Option<UsageSource>,
+}
+
+/// 发起请求的角色绑定的提供商、模型和参数，没有角色时为空
+pub fn role_binding(state: &AppState, role_id: Option<&str>) -> Result<RoleBinding, String> {
+    match role_id {
+        Some(role_id) => Ok(state.role_storage.lock().map_err(|e| e.to_string())?.get_binding(role_id)),
+        None => Ok(RoleBinding::default()),
+    }
+}
+
+/// 请求未指定的提供商、模型和参数使用发起角色绑定的值
+fn apply_role_binding(state: &AppState, request: &mut StreamChatRequest) -> Result<(), String> {
+    let binding = role_binding(state, request.source.as_ref().and_then(|source| source.role_id.as_deref()))?;
+    
+    // 使用角色绑定的提供商时，传入的地址和密钥属于其他提供商，改用该提供商的设置
+    if request.provider_id.is_none() && binding.provider_id.is_some() {
+        request.provider_id = binding.provider_id;
+        request.url = None;
+        request.api_key = None;
+    }
+    request.model = request.model.take().or(binding.model);
+    request.temperature = request.temperature.or(binding.temperature);
+    request.max_tokens = request.max_tokens.or(binding.max_tokens);
+    Ok(())
+}
+
+/// stream-response 事件内容
+#[derive(Debug, Serialize)]
+pub struct StreamResponseEvent<'a> {
+    pub stream_id: &'a str,
+    pub status: StreamStatus,
+    /// 实际回答的提供商，主提供商失败时可能是备用提供商
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<&'a ChatCompletionResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<&'a str>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: ErrorDetail,
@@ -605,192 +795,203 @@ pub struct ErrorDetail {
     pub code: Option<String>,
 }
 
-// HTTP客户端辅助函数，用于发起请求
-async fn make_http_request(
-    url: &str,
-    api_key: &str,
-    request_body: &[u8],
-    _stream: bool, // 添加下划线前缀表示有意不使用
-) -> Result<Box<dyn AsyncRead + Unpin + Send>, String> {
-    let client = reqwest::Client::new();
-    
-    let response = client
-        .post(url)
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .body(request_body.to_vec())
-        .send()
-        .await
-        .map_err(|e| format!("网络请求失败: {}", e))?;
-    
-    if !response.status().is_success() {
-        // 先保存状态码再调用text()
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_else(|_| "未知错误".to_string());
-        return Err(format!("API请求失败 ({}): {}", status, error_text));
+impl AIProvider {
+    /// 根据提供商ID和接口地址判断接口格式，无法识别时按 OpenAI 兼容接口处理
+    pub fn detect(provider_id: &str, base_url: &str) -> Self {
+        let provider_id = provider_id.to_lowercase();
+        if provider_id == "anthropic" || provider_id == "claude" || base_url.contains("anthropic.com") {
+            AIProvider::Anthropic
+        } else if provider_id == "azure" || base_url.contains(".openai.azure.com") {
+            AIProvider::Azure
+        } else if provider_id == "zhipu" || provider_id == "copy2ai" || base_url.contains("bigmodel.cn") {
+            AIProvider::Zhipu
+        } else if provider_id.starts_with("ollama") || base_url.contains(":11434") {
+            AIProvider::Ollama
+        } else {
+            AIProvider::OpenAI
+        }
     }
-    
-    // 转换为可读流，使用StreamReader来包装字节流
-    let stream = response.bytes_stream()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{}", e)));
-    
-    let reader = StreamReader::new(stream);
-    Ok(Box::new(reader))
 }
 
-// 基于Tauri事件的流式响应发送函数
-async fn stream_chat_response<R: AsyncRead + Unpin + Send>(
-    window: Window,
-    event_name: &str,
-    reader: &mut R,
-) -> Result<(), String> {
-    let mut buffer = Vec::new();
-    // 不需要decoder，直接使用UTF-8解码
-    
-    loop {
-        let mut chunk = vec![0; 1024];
-        let n = reader.read(&mut chunk).await.map_err(|e| format!("读取响应失败: {}", e))?;
-        
-        if n == 0 {
-            break;
-        }
-        
-        chunk.truncate(n);
-        buffer.extend_from_slice(&chunk);
-        
-        // 处理buffer中的每一行
-        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-            let line = buffer.drain(..=pos).collect::<Vec<_>>();
-            
-            // 直接使用UTF-8解码
-            let line_str = String::from_utf8_lossy(&line);
-            
-            // 解析SSE数据行
-            if let Some(data) = line_str.strip_prefix("data: ") {
-                if data.trim() == "[DONE]" {
-                    // 流结束
-                    window.emit(event_name, "[DONE]")
-                        .map_err(|e| format!("发送事件失败: {}", e))?;
-                    return Ok(());
-                }
-                
-                // 尝试解析JSON
-                match serde_json::from_str::<ChatCompletionResponse>(data) {
-                    Ok(response) => {
-                        // 发送事件到前端
-                        window.emit(event_name, &response)
-                            .map_err(|e| format!("发送事件失败: {}", e))?;
-                    },
-                    Err(e) => {
-                        eprintln!("解析JSON失败: {} (数据: {})", e, data);
-                    }
-                }
+// 流式聊天，数据块通过 stream-response 事件发送，请求结束后返回流的最终状态
+#[tauri::command]
+pub async fn stream_chat(
+    app_handle: AppHandle,
+    mut request: StreamChatRequest,
+    state: State<'_, AppState>,
+) -> Result<StreamInfo, String> {
+    let stream_id = request.stream_id.take().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    apply_role_binding(&state, &mut request)?;
+    let mut chain = {
+        let ai_settings_manager = state.ai_settings_manager.lock().map_err(|_| "无法访问AI设置")?;
+        provider::resolve_chain(&ai_settings_manager, request.provider_id.as_deref(), request.url, request.api_key)?
+    };
+    let source = UsageSource::for_operation(request.source, "stream_chat");
+    let usage = StreamUsage::start(&state, &mut chain, source, request.model.as_deref(), &request.messages)?;
+    
+    let app_for_stream = app_handle.clone();
+    let id_for_stream = stream_id.clone();
+    let usage_for_stream = usage.clone();
+    let work = async move {
+        chain.stream(request.model, request.messages, request.temperature, request.max_tokens, &mut |provider_id, response| {
+            if let Ok(mut usage) = usage_for_stream.lock() {
+                usage.on_chunk(provider_id, response);
             }
-        }
-    }
+            let event = StreamResponseEvent {
+                stream_id: &id_for_stream,
+                status: StreamStatus::Running,
+                provider_id: Some(provider_id),
+                chunk: Some(response),
+                error: None,
+            };
+            app_for_stream.emit("stream-response", &event)
+                .map_err(|e| format!("发送事件失败: {}", e))
+        })
+        .await
+        .map(|_| ())
+    };
     
-    Ok(())
+    // 结束（包括被取消）时发送一个最终事件
+    let app_for_finish = app_handle.clone();
+    let finished = stream_registry::spawn_stream(&app_handle, stream_id, work, move |info| {
+        if let (Ok(usage), Some(state)) = (usage.lock(), app_for_finish.try_state::<AppState>()) {
+            usage.finish(&state, info.error.clone());
+        }
+        let event = StreamResponseEvent {
+            stream_id: &info.stream_id,
+            status: info.status,
+            provider_id: None,
+            chunk: None,
+            error: info.error.as_deref(),
+        };
+        let _ = app_for_finish.emit("stream-response", &event);
+    })?;
+    
+    finished.await.map_err(|_| "流式任务异常结束".to_string())
+}
+
+/// chat_completion 的请求参数，未指定的项见 chat_completion 的说明
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionOptions {
+    pub provider_id: Option<String>,
+    pub messages: Vec<ChatCompletionMessage>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    pub stream: Option<bool>,
+    pub clipboard_context: Option<bool>,
+    pub usage_source: Option<UsageSource>,
+    pub stream_id: Option<String>,
 }
 
 // 聊天完成函数，发起请求并返回结果
+//
+// 未指定的提供商、模型和参数（包括是否流式）使用发起角色绑定的值，其次使用提供商设置。
+// 流式请求返回事件名 chat_response_{stream_id}，数据块和结束标记通过该事件发送；
+// stream_id 由前端指定时可以在请求前注册监听，并通过 cancel_stream 取消请求。
+// clipboard_context 为 true 时先检索剪贴板历史，将相关条目注入系统提示词，
+// 引用的条目通过响应（流式时为第一个数据块）中的 references 字段返回。
+// 提供商失败时依次改用备用提供商，实际回答的提供商通过同样位置的 provider_id 字段返回。
 #[tauri::command]
-pub async fn chat_completion(
-    window: Window,
-    provider_id: String, 
-    messages: Vec<ChatCompletionMessage>,
-    model: String,
-    temperature: Option<f32>,
-    max_tokens: Option<i32>,
-    stream: bool,
-    state: State<'_, AppState>,
-) -> Result<String, String> {
-    // 解决MutexGuard不能Send的问题：在同步部分获取所有需要的值，然后释放锁
-    let api_key;
-    let api_base_url;
-    
-    {
-        // 使用块作用域来确保MutexGuard尽早释放
-        let ai_settings_manager = state.ai_settings_manager.lock().map_err(|_| "无法访问AI设置")?;
-        let provider_setting = ai_settings_manager.get_provider_setting(&provider_id)?;
-        
-        // 复制这些值而不是保持MutexGuard
-        api_key = provider_setting.api_key.clone().unwrap_or_default();
-        api_base_url = provider_setting.custom_api_base_url.clone().unwrap_or_default();
-    }
-    
-    // 构建请求体
-    let request = ChatCompletionRequest {
-        model: model.clone(),
-        messages,
+pub async fn chat_completion(window: Window, options: ChatCompletionOptions, state: State<'_, AppState>) -> Result<String, String> {
+    let ChatCompletionOptions {
+        provider_id,
+        mut messages,
+        model,
         temperature,
         max_tokens,
-        stream: Some(stream),
+        stream,
+        clipboard_context,
+        usage_source,
+        stream_id,
+    } = options;
+    let binding = role_binding(&state, usage_source.as_ref().and_then(|source| source.role_id.as_deref()))?;
+    let provider_id = provider_id.or(binding.provider_id);
+    let model = model.or(binding.model);
+    let temperature = temperature.or(binding.temperature);
+    let max_tokens = max_tokens.or(binding.max_tokens);
+    
+    // 解决MutexGuard不能Send的问题：在块作用域中解析提供商，然后释放锁
+    let mut chain = {
+        let ai_settings_manager = state.ai_settings_manager.lock().map_err(|_| "无法访问AI设置")?;
+        provider::resolve_chain(&ai_settings_manager, provider_id.as_deref(), None, None)?
     };
+    let stream = stream.or(binding.stream).unwrap_or(chain.primary().stream);
     
-    let request_body = serde_json::to_vec(&request)
-        .map_err(|e| format!("序列化请求失败: {}", e))?;
-    
-    // 构建API URL
-    let url = format!("{}/chat/completions", api_base_url);
+    let references = if clipboard_context.unwrap_or(false) {
+        let primary = chain.primary();
+        let model_name = model.clone().unwrap_or_else(|| primary.model.clone());
+        let context_limit = token_budget::context_limit(&model_name, &primary.context_limits);
+        retrieval::augment(&state, &mut messages, &model_name, context_limit).await?
+    } else {
+        Vec::new()
+    };
     
     // 使用唯一ID作为事件名
-    let event_id = uuid::Uuid::new_v4().to_string();
+    let event_id = stream_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
     let event_name = format!("chat_response_{}", event_id);
     
+    let source = UsageSource::for_operation(usage_source, "chat_completion");
+    
     // 如果是流式响应
     if stream {
-        // 创建一个新的任务来处理流式响应
-        let window_clone = window.clone();
-        let event_name_clone = event_name.clone(); // 克隆事件名
+        let usage = StreamUsage::start(&state, &mut chain, source, model.as_deref(), &messages)?;
         
-        tauri::async_runtime::spawn(async move {
-            match make_http_request(&url, &api_key, &request_body, true).await {
-                Ok(mut reader) => {
-                    // 再次克隆window，避免移动
-                    let window_for_stream = window_clone.clone();
-                    
-                    if let Err(e) = stream_chat_response(window_for_stream, &event_name_clone, &mut reader).await {
-                        eprintln!("流式响应处理错误: {}", e);
-                        // 发送错误事件
-                        let _ = window_clone.emit(&event_name_clone, format!("ERROR: {}", e));
-                    }
-                    // 发送结束事件
-                    let _ = window_clone.emit(&event_name_clone, "[DONE]");
-                },
-                Err(e) => {
-                    eprintln!("创建请求失败: {}", e);
-                    // 发送错误事件
-                    let _ = window_clone.emit(&event_name_clone, format!("ERROR: {}", e));
+        // 在登记表中启动流式任务，event_id 同时作为流ID，可通过 cancel_stream 取消
+        let window_for_stream = window.clone();
+        let event_name_for_stream = event_name.clone();
+        let usage_for_stream = usage.clone();
+        let work = async move {
+            let mut first_chunk = true;
+            chain.stream(model, messages, temperature, max_tokens, &mut |provider_id, response| {
+                if let Ok(mut usage) = usage_for_stream.lock() {
+                    usage.on_chunk(provider_id, response);
                 }
+                let result = if std::mem::take(&mut first_chunk) {
+                    let mut payload = serde_json::to_value(response).map_err(|e| format!("序列化响应失败: {}", e))?;
+                    payload["provider_id"] = serde_json::json!(provider_id);
+                    if !references.is_empty() {
+                        payload["references"] = serde_json::json!(references);
+                    }
+                    window_for_stream.emit(&event_name_for_stream, payload)
+                } else {
+                    window_for_stream.emit(&event_name_for_stream, response)
+                };
+                result.map_err(|e| format!("发送事件失败: {}", e))
+            })
+            .await
+            .map(|_| ())
+        };
+        
+        // 结束时发送 [DONE]、ERROR 或 [CANCELLED]
+        let event_name_for_finish = event_name.clone();
+        let app_handle = window.app_handle().clone();
+        let app_for_finish = app_handle.clone();
+        stream_registry::spawn_stream(&app_handle, event_id, work, move |info| {
+            if let (Ok(usage), Some(state)) = (usage.lock(), app_for_finish.try_state::<AppState>()) {
+                usage.finish(&state, info.error.clone());
             }
-        });
+            let payload = match info.status {
+                StreamStatus::Failed => {
+                    let error = info.error.clone().unwrap_or_default();
+                    eprintln!("流式响应处理错误: {}", error);
+                    format!("ERROR: {}", error)
+                },
+                StreamStatus::Cancelled => "[CANCELLED]".to_string(),
+                _ => "[DONE]".to_string(),
+            };
+            let _ = window.emit(&event_name_for_finish, payload);
+        })?;
         
         // 立即返回事件名，前端将使用此名称监听事件
         Ok(event_name)
     } else {
-        // 非流式响应
-        let client = reqwest::Client::new();
-        
-        let response = client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .body(request_body)
-            .send()
-            .await
-            .map_err(|e| format!("网络请求失败: {}", e))?;
-        
-        if !response.status().is_success() {
-            // 先保存状态码再调用text()
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "未知错误".to_string());
-            return Err(format!("API请求失败 ({}): {}", status, error_text));
+        // 非流式响应，统一返回 OpenAI 格式的完整响应
+        let (mut response, answered_by) = usage::complete(&state, chain, source, model, messages, temperature, max_tokens).await?;
+        response["provider_id"] = serde_json::json!(answered_by);
+        if !references.is_empty() {
+            response["references"] = serde_json::json!(references);
         }
-        
-        // 获取完整响应并返回
-        let response_text = response.text().await
-            .map_err(|e| format!("读取响应失败: {}", e))?;
-        
-        Ok(response_text)
+        serde_json::to_string(&response).map_err(|e| format!("序列化响应失败: {}", e))
     }
-} 
\ No newline at end of file
+}
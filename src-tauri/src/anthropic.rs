@@ -0,0 +1,211 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::ai::{ChatCompletionChoice, ChatCompletionDelta, ChatCompletionRequest, ChatCompletionResponse};
+
+/// Anthropic 接口的默认地址
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+
+// 请求头中的接口版本
+const API_VERSION: &str = "2023-06-01";
+
+// Messages API 要求必须指定 max_tokens，请求中未设置时使用该值
+const DEFAULT_MAX_TOKENS: i32 = 4096;
+
+/// Messages API 的地址
+pub fn messages_url(base_url: &str) -> String {
+    format!("{}/messages", base_url.trim_end_matches('/'))
+}
+
+/// 添加认证和版本请求头
+pub fn with_headers(builder: reqwest::RequestBuilder, api_key: &str) -> reqwest::RequestBuilder {
+    builder
+        .header("Content-Type", "application/json")
+        .header("x-api-key", api_key)
+        .header("anthropic-version", API_VERSION)
+}
+
+/// 将 OpenAI 格式的请求转换为 Messages API 的请求体
+///
+/// system 消息合并后放到顶层的 system 字段，其余消息按原顺序保留。
+/// Messages API 不接受空内容且要求以用户消息开始，聊天记录中的空消息和开头的助手消息会被跳过，
+/// 连续的同角色消息合并为一条。
+pub fn request_body(request: &ChatCompletionRequest) -> Value {
+    let system: Vec<&str> = request.messages
+        .iter()
+        .filter(|message| message.role == "system")
+        .map(|message| message.content.as_str())
+        .collect();
+
+    let mut messages: Vec<Value> = Vec::new();
+    for message in request.messages.iter().filter(|message| message.role != "system" && !message.content.trim().is_empty()) {
+        match messages.last_mut() {
+            None if message.role != "user" => continue,
+            Some(last) if last["role"] == message.role.as_str() => {
+                let merged = format!("{}\n\n{}", last["content"].as_str().unwrap_or_default(), message.content);
+                last["content"] = json!(merged);
+            },
+            _ => messages.push(json!({ "role": message.role, "content": message.content })),
+        }
+    }
+
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "stream": request.stream.unwrap_or(false),
+    });
+    if !system.is_empty() {
+        body["system"] = json!(system.join("\n\n"));
+    }
+    if let Some(temperature) = request.temperature {
+        // Anthropic 的温度范围为 0~1
+        body["temperature"] = json!(temperature.clamp(0.0, 1.0));
+    }
+
+    body
+}
+
+/// 将停止原因转换为 OpenAI 的 finish_reason
+fn finish_reason(stop_reason: &str) -> String {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        _ => "stop",
+    }
+    .to_string()
+}
+
+/// 将非流式响应转换为 OpenAI 的 chat.completion 格式
+pub fn normalize_response(response: &Value) -> Result<Value, String> {
+    let content = response["content"]
+        .as_array()
+        .ok_or_else(|| format!("无法解析Anthropic响应: {}", response))?
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect::<String>();
+
+    Ok(json!({
+        "id": response["id"],
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": response["model"],
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": response["stop_reason"].as_str().map(finish_reason),
+        }],
+        "usage": {
+            "prompt_tokens": response["usage"]["input_tokens"],
+            "completion_tokens": response["usage"]["output_tokens"],
+        },
+    }))
+}
+
+/// 流式响应中的事件，按 type 字段区分
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: MessageStart },
+    ContentBlockDelta { delta: ContentDelta },
+    MessageDelta { delta: MessageDelta },
+    MessageStop,
+    Error { error: StreamError },
+    /// ping、content_block_start 等不需要处理的事件
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageStart {
+    id: String,
+    model: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentDelta {
+    TextDelta { text: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+/// 解析一个数据行后的处理结果
+pub enum StreamStep {
+    /// 转换后的 OpenAI 格式数据块
+    Chunk(ChatCompletionResponse),
+    /// 没有需要发送的内容
+    Skip,
+    /// 消息结束
+    Done,
+}
+
+/// 流式响应解析器，记录 message_start 中的消息信息，用于填充后续的数据块
+pub struct StreamParser {
+    id: String,
+    model: String,
+    created: u64,
+}
+
+impl StreamParser {
+    pub fn new() -> Self {
+        Self {
+            id: String::new(),
+            model: String::new(),
+            created: chrono::Utc::now().timestamp() as u64,
+        }
+    }
+
+    /// 解析一个 SSE 数据行
+    pub fn parse(&mut self, data: &str) -> Result<StreamStep, String> {
+        let event = match serde_json::from_str::<StreamEvent>(data) {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("解析JSON失败: {} (数据: {})", e, data);
+                return Ok(StreamStep::Skip);
+            }
+        };
+
+        match event {
+            StreamEvent::MessageStart { message } => {
+                self.id = message.id;
+                self.model = message.model;
+                Ok(StreamStep::Skip)
+            },
+            StreamEvent::ContentBlockDelta { delta: ContentDelta::TextDelta { text } } => {
+                Ok(StreamStep::Chunk(self.chunk(Some(text), None)))
+            },
+            StreamEvent::MessageDelta { delta } => match delta.stop_reason {
+                Some(stop_reason) => Ok(StreamStep::Chunk(self.chunk(None, Some(finish_reason(&stop_reason))))),
+                None => Ok(StreamStep::Skip),
+            },
+            StreamEvent::MessageStop => Ok(StreamStep::Done),
+            StreamEvent::Error { error } => Err(format!("Anthropic接口返回错误: {}", error.message)),
+            _ => Ok(StreamStep::Skip),
+        }
+    }
+
+    fn chunk(&self, content: Option<String>, finish_reason: Option<String>) -> ChatCompletionResponse {
+        ChatCompletionResponse {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                delta: ChatCompletionDelta { content },
+                finish_reason,
+            }],
+        }
+    }
+}
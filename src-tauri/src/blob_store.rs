@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use base64::{engine::general_purpose, Engine as _};
+use crate::clipboard::ImageInfo;
+
+// 缩略图的最大边长（像素）
+const THUMBNAIL_SIZE: u32 = 160;
+
+// 图片文件的扩展名
+const BLOB_EXTENSION: &str = "png";
+
+/// 图片数据存储
+///
+/// 图片以 PNG 文件的形式保存在应用数据目录的 `blobs/` 下，文件名为内容哈希，
+/// 剪贴板条目只保存哈希、尺寸等元数据和一张内嵌的小缩略图。
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    /// 创建图片存储，目录不存在时自动创建
+    pub fn new(dir: PathBuf) -> Self {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("创建图片存储目录失败: {:?} - {}", dir, e);
+        }
+
+        Self { dir }
+    }
+
+    /// 图片文件路径
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", hash, BLOB_EXTENSION))
+    }
+
+    /// 保存一张 PNG 图片并生成缩略图，返回条目需要记录的元数据
+    ///
+    /// 相同哈希的图片已存在时不会重复写入。
+    pub fn put_png(&self, hash: &str, png: &[u8]) -> Result<ImageInfo, String> {
+        let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)
+            .map_err(|e| format!("解析图片失败: {}", e))?;
+
+        let path = self.blob_path(hash);
+        if !path.exists() {
+            // 先写入临时文件再重命名，避免留下不完整的图片
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, png)
+                .map_err(|e| format!("写入图片文件失败: {:?} - {}", tmp_path, e))?;
+            fs::rename(&tmp_path, &path)
+                .map_err(|e| format!("保存图片文件失败: {:?} - {}", path, e))?;
+        }
+
+        // 生成缩略图
+        let mut thumbnail = Vec::new();
+        image
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .write_to(&mut Cursor::new(&mut thumbnail), image::ImageFormat::Png)
+            .map_err(|e| format!("生成缩略图失败: {}", e))?;
+
+        Ok(ImageInfo {
+            hash: hash.to_string(),
+            width: image.width(),
+            height: image.height(),
+            size: png.len() as u64,
+            mime: "image/png".to_string(),
+            thumbnail: to_data_url(&thumbnail),
+        })
+    }
+
+    /// 读取图片的 PNG 数据
+    pub fn read(&self, hash: &str) -> Result<Vec<u8>, String> {
+        let path = self.blob_path(hash);
+        fs::read(&path).map_err(|e| format!("读取图片文件失败: {:?} - {}", path, e))
+    }
+
+    /// 删除一张图片
+    pub fn remove(&self, hash: &str) {
+        let path = self.blob_path(hash);
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("删除图片文件失败: {:?} - {}", path, e);
+            }
+        }
+    }
+
+    /// 删除不再被任何条目引用的图片文件，返回删除的文件数量
+    pub fn collect_garbage(&self, live_hashes: &HashSet<&str>) -> usize {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("读取图片存储目录失败: {:?} - {}", self.dir, e);
+                return 0;
+            }
+        };
+
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_live = path.extension().and_then(|ext| ext.to_str()) == Some(BLOB_EXTENSION)
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|hash| live_hashes.contains(hash));
+
+            // 未被引用的图片和写入中断留下的临时文件都需要清理
+            if !is_live {
+                match fs::remove_file(&path) {
+                    Ok(_) => removed += 1,
+                    Err(e) => eprintln!("删除图片文件失败: {:?} - {}", path, e),
+                }
+            }
+        }
+
+        removed
+    }
+}
+
+/// 将 PNG 数据编码为 data URL
+pub fn to_data_url(png: &[u8]) -> String {
+    format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(png))
+}
+
+/// 解析 PNG 的 data URL，返回原始数据
+pub fn from_data_url(data_url: &str) -> Result<Vec<u8>, String> {
+    let encoded = data_url
+        .split_once("base64,")
+        .map(|(_, data)| data)
+        .ok_or_else(|| "图片数据格式无效".to_string())?;
+
+    general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("解码图片数据失败: {}", e))
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 剪贴板条目分类
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Category {
+    pub id: String,
+    pub label: String,
+    /// 条目中保存的分类标识
+    pub value: String,
+    pub color: String,
+    #[serde(default)]
+    pub is_system: bool,
+    /// 显示顺序，从小到大排列
+    #[serde(default)]
+    pub order: u32,
+    #[serde(default)]
+    pub created_at: u64,
+}
+
+impl Category {
+    /// 创建一个新的自定义分类
+    pub fn new(label: String, value: String, color: String, order: u32) -> Self {
+        Self {
+            id: format!("category_{}", Uuid::new_v4()),
+            label,
+            value,
+            color,
+            is_system: false,
+            order,
+            created_at: chrono::Utc::now().timestamp_millis() as u64,
+        }
+    }
+
+    /// 检查分类标识是否合法，只能包含小写字母、数字和下划线
+    pub fn validate_value(value: &str) -> Result<(), String> {
+        if value.is_empty() {
+            return Err("分类标识不能为空".to_string());
+        }
+
+        if !value.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+            return Err(format!("分类标识只能包含小写字母、数字和下划线: {}", value));
+        }
+
+        Ok(())
+    }
+
+    /// 默认分类，系统分类与后端内容识别的结果一一对应
+    pub fn defaults() -> Vec<Category> {
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+
+        [
+            ("全部", "all", "#d9d9d9", true),
+            ("文本", "text", "#1890ff", true),
+            ("代码", "code", "#52c41a", true),
+            ("链接", "link", "#722ed1", true),
+            ("邮箱", "email", "#faad14", true),
+            ("密码", "password", "#f5222d", true),
+            ("文件路径", "path", "#08979c", true),
+            ("电话", "phone", "#fa8c16", true),
+            ("颜色", "color", "#c41d7f", true),
+            ("数字", "number", "#1d39c4", true),
+            ("图片", "image", "#fa541c", true),
+            ("其他", "other", "#d9d9d9", true),
+            ("学习", "study", "#13c2c2", false),
+            ("工作", "work", "#2f54eb", false),
+            ("生活", "life", "#eb2f96", false),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(order, &(label, value, color, is_system))| Category {
+            id: format!("category_{}", value),
+            label: label.to_string(),
+            value: value.to_string(),
+            color: color.to_string(),
+            is_system,
+            order: order as u32,
+            created_at: now,
+        })
+        .collect()
+    }
+}
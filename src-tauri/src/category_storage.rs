@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+use crate::category::Category;
+use crate::persistence;
+
+/// 分类存储管理器
+pub struct CategoryStorage {
+    file_path: PathBuf,
+    categories: Vec<Category>,
+}
+
+impl CategoryStorage {
+    /// 创建一个新的分类存储管理器
+    pub fn new(file_path: PathBuf) -> Self {
+        let mut storage = Self {
+            file_path,
+            categories: Vec::new(),
+        };
+
+        // 加载现有数据
+        storage.load().unwrap_or_else(|e| {
+            eprintln!("加载分类数据失败: {}", e);
+            storage.categories = Category::defaults();
+        });
+
+        storage
+    }
+
+    /// 从文件加载数据
+    fn load(&mut self) -> Result<(), String> {
+        // 文件损坏时会从最近的有效备份恢复
+        let categories: Vec<Category> = match persistence::load_json(&self.file_path)? {
+            Some(categories) => categories,
+            None => {
+                // 首次使用，初始化默认分类
+                self.categories = Category::defaults();
+                return self.save();
+            }
+        };
+        self.categories = categories;
+
+        // 补充新版本增加的系统分类
+        let missing: Vec<Category> = Category::defaults()
+            .into_iter()
+            .filter(|default| default.is_system && !self.contains_value(&default.value))
+            .collect();
+        if !missing.is_empty() {
+            for (order, mut category) in (self.next_order()..).zip(missing) {
+                category.order = order;
+                self.categories.push(category);
+            }
+            return self.save();
+        }
+
+        Ok(())
+    }
+
+    /// 保存数据到文件
+    fn save(&self) -> Result<(), String> {
+        // 原子写入并保留备份
+        persistence::save_json(&self.file_path, &self.categories)
+    }
+
+    /// 下一个分类的显示顺序
+    fn next_order(&self) -> u32 {
+        self.categories.iter().map(|category| category.order + 1).max().unwrap_or(0)
+    }
+
+    /// 是否存在指定标识的分类
+    pub fn contains_value(&self, value: &str) -> bool {
+        self.categories.iter().any(|category| category.value == value)
+    }
+
+    /// 获取所有分类，按显示顺序排列
+    pub fn get_all_categories(&self) -> Vec<Category> {
+        let mut categories = self.categories.clone();
+        categories.sort_by_key(|category| category.order);
+        categories
+    }
+
+    /// 添加分类
+    pub fn add_category(&mut self, label: String, value: String, color: String) -> Result<Category, String> {
+        Category::validate_value(&value)?;
+        if self.contains_value(&value) {
+            return Err(format!("分类标识已存在: {}", value));
+        }
+
+        let category = Category::new(label, value, color, self.next_order());
+        self.categories.push(category.clone());
+        self.save()?;
+
+        Ok(category)
+    }
+
+    /// 更新分类，标识改变时返回原标识，由调用方更新已有条目
+    pub fn update_category(
+        &mut self,
+        id: &str,
+        label: String,
+        value: String,
+        color: String,
+    ) -> Result<Option<String>, String> {
+        Category::validate_value(&value)?;
+
+        let index = self.categories
+            .iter()
+            .position(|category| category.id == id)
+            .ok_or_else(|| format!("分类不存在: {}", id))?;
+
+        let old_value = self.categories[index].value.clone();
+        if old_value != value {
+            // 系统分类的标识与内容识别结果对应，不能修改
+            if self.categories[index].is_system {
+                return Err("不能修改系统分类的标识".to_string());
+            }
+            if self.contains_value(&value) {
+                return Err(format!("分类标识已存在: {}", value));
+            }
+        }
+
+        let renamed = old_value != value;
+        let category = &mut self.categories[index];
+        category.label = label;
+        category.value = value;
+        category.color = color;
+        self.save()?;
+
+        Ok(if renamed { Some(old_value) } else { None })
+    }
+
+    /// 删除分类，返回被删除分类的标识
+    pub fn delete_category(&mut self, id: &str) -> Result<String, String> {
+        let index = self.deletable_index(id)?;
+        let category = self.categories.remove(index);
+        self.save()?;
+
+        Ok(category.value)
+    }
+
+    /// 检查分类能否删除，返回分类的值，用于删除前先处理该分类下的条目
+    pub fn deletable_value(&self, id: &str) -> Result<String, String> {
+        self.deletable_index(id).map(|index| self.categories[index].value.clone())
+    }
+
+    fn deletable_index(&self, id: &str) -> Result<usize, String> {
+        let index = self.categories
+            .iter()
+            .position(|category| category.id == id)
+            .ok_or_else(|| format!("分类不存在: {}", id))?;
+
+        if self.categories[index].is_system {
+            return Err("不能删除系统分类".to_string());
+        }
+        Ok(index)
+    }
+
+    /// 按给定的分类ID顺序重新排序，未列出的分类排在最后
+    pub fn reorder_categories(&mut self, ids: &[String]) -> Result<(), String> {
+        let position = |category: &Category| ids.iter().position(|id| *id == category.id).unwrap_or(ids.len());
+        self.categories.sort_by_key(|category| (position(category), category.order));
+
+        for (order, category) in self.categories.iter_mut().enumerate() {
+            category.order = order as u32;
+        }
+
+        self.save()
+    }
+
+    /// 重置为默认分类，返回不再存在的分类标识
+    pub fn reset_categories(&mut self) -> Result<Vec<String>, String> {
+        let defaults = Category::defaults();
+        let removed: Vec<String> = self.categories
+            .iter()
+            .filter(|category| !defaults.iter().any(|default| default.value == category.value))
+            .map(|category| category.value.clone())
+            .collect();
+
+        self.categories = defaults;
+        self.save()?;
+
+        Ok(removed)
+    }
+}
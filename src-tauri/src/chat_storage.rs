@@ -0,0 +1,504 @@
+use std::path::Path;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// 搜索结果中匹配位置前后保留的字符数
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+
+/// 聊天消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub id: String,
+    pub role: String,
+    pub content: String,
+    pub timestamp: u64,
+    /// 消息引用的剪贴板内容
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard_ref: Option<Value>,
+    /// 回答的提供商，主提供商失败时可能是备用提供商
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+}
+
+/// 聊天会话，列表中返回时不包含消息，只有消息数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSession {
+    #[serde(default)]
+    pub id: String,
+    pub title: String,
+    /// 前端的提供商信息
+    pub ai_provider: Value,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub role_prompt: Option<String>,
+    /// 创建会话时使用的角色
+    #[serde(default)]
+    pub role_id: Option<String>,
+    /// 会话引用的第一个剪贴板条目
+    #[serde(default)]
+    pub clipboard_item_id: Option<String>,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub updated_at: u64,
+    #[serde(default)]
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub message_count: usize,
+}
+
+/// 会话可修改的字段，未传入的字段保持不变
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSessionUpdate {
+    pub title: Option<String>,
+    pub ai_provider: Option<Value>,
+    pub system_prompt: Option<String>,
+    pub role_prompt: Option<String>,
+    pub role_id: Option<String>,
+}
+
+/// 分页的会话列表
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatSessionPage {
+    pub sessions: Vec<ChatSession>,
+    pub total: usize,
+}
+
+/// 消息搜索结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSearchHit {
+    pub session_id: String,
+    pub session_title: String,
+    pub message: ChatMessage,
+    /// 匹配位置附近的内容
+    pub snippet: String,
+}
+
+/// 会话导出格式
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatExportFormat {
+    #[default]
+    Json,
+    Markdown,
+}
+
+/// 聊天会话存储，保存在本地数据库中
+pub struct ChatStorage {
+    conn: Connection,
+    /// 数据库是否成功打开，为 false 时使用的是内存数据库
+    persistent: bool,
+}
+
+fn now_millis() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+fn to_json(value: &Value) -> String {
+    value.to_string()
+}
+
+fn from_json(text: Option<String>) -> Option<Value> {
+    text.and_then(|text| serde_json::from_str(&text).ok())
+}
+
+/// 转义 LIKE 中的通配符
+fn escape_like(query: &str) -> String {
+    query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// 截取匹配位置附近的内容
+fn snippet(content: &str, query: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let lower: Vec<char> = content.to_lowercase().chars().collect();
+    let needle: Vec<char> = query.to_lowercase().chars().collect();
+
+    // 转小写后字符数不变时才能按位置对应，否则从开头截取
+    let position = (lower.len() == chars.len() && !needle.is_empty())
+        .then(|| lower.windows(needle.len()).position(|window| window == needle.as_slice()))
+        .flatten()
+        .unwrap_or(0);
+
+    let start = position.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let end = (position + needle.len() + SNIPPET_CONTEXT_CHARS).min(chars.len());
+    let mut snippet: String = chars[start..end].iter().collect();
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+impl ChatStorage {
+    pub fn new(data_dir: &Path) -> Self {
+        let db_path = data_dir.join("chat_sessions.db");
+        let (conn, persistent) = match Connection::open(&db_path) {
+            Ok(conn) => (conn, true),
+            Err(e) => {
+                eprintln!("打开聊天数据库失败: {:?} - {}", db_path, e);
+                (Connection::open_in_memory().expect("无法创建内存数据库"), false)
+            }
+        };
+
+        let storage = Self { conn, persistent };
+        if let Err(e) = storage.init_schema() {
+            eprintln!("初始化聊天数据库失败: {}", e);
+        }
+        storage
+    }
+
+    /// 数据库是否成功打开；打开失败时聊天记录只保存在内存中，退出后丢失
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
+    fn init_schema(&self) -> Result<(), String> {
+        self.conn
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 PRAGMA foreign_keys = ON;
+                 CREATE TABLE IF NOT EXISTS chat_sessions (
+                     id TEXT PRIMARY KEY,
+                     title TEXT NOT NULL,
+                     ai_provider TEXT NOT NULL,
+                     system_prompt TEXT,
+                     role_prompt TEXT,
+                     role_id TEXT,
+                     clipboard_item_id TEXT,
+                     created_at INTEGER NOT NULL,
+                     updated_at INTEGER NOT NULL
+                 );
+                 CREATE INDEX IF NOT EXISTS idx_chat_sessions_updated_at
+                     ON chat_sessions (updated_at);
+                 CREATE TABLE IF NOT EXISTS chat_messages (
+                     session_id TEXT NOT NULL REFERENCES chat_sessions (id) ON DELETE CASCADE,
+                     id TEXT NOT NULL,
+                     role TEXT NOT NULL,
+                     content TEXT NOT NULL,
+                     timestamp INTEGER NOT NULL,
+                     clipboard_ref TEXT,
+                     provider_id TEXT,
+                     PRIMARY KEY (session_id, id)
+                 );",
+            )
+            .map_err(|e| format!("创建数据表失败: {}", e))?;
+        Ok(())
+    }
+
+    fn read_session(row: &Row) -> rusqlite::Result<ChatSession> {
+        Ok(ChatSession {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            ai_provider: from_json(row.get(2)?).unwrap_or(Value::Null),
+            system_prompt: row.get(3)?,
+            role_prompt: row.get(4)?,
+            role_id: row.get(5)?,
+            clipboard_item_id: row.get(6)?,
+            created_at: row.get::<_, i64>(7)? as u64,
+            updated_at: row.get::<_, i64>(8)? as u64,
+            messages: Vec::new(),
+            message_count: row.get::<_, i64>(9)? as usize,
+        })
+    }
+
+    fn read_message(row: &Row) -> rusqlite::Result<ChatMessage> {
+        Ok(ChatMessage {
+            id: row.get(0)?,
+            role: row.get(1)?,
+            content: row.get(2)?,
+            timestamp: row.get::<_, i64>(3)? as u64,
+            clipboard_ref: from_json(row.get(4)?),
+            provider_id: row.get(5)?,
+        })
+    }
+
+    const SESSION_COLUMNS: &'static str =
+        "s.id, s.title, s.ai_provider, s.system_prompt, s.role_prompt, s.role_id, s.clipboard_item_id, s.created_at, s.updated_at,
+         (SELECT COUNT(*) FROM chat_messages m WHERE m.session_id = s.id)";
+
+    /// 按更新时间从新到旧分页列出会话
+    pub fn list_sessions(&self, offset: usize, limit: usize) -> Result<ChatSessionPage, String> {
+        let total: i64 = self.conn
+            .query_row("SELECT COUNT(*) FROM chat_sessions", [], |row| row.get(0))
+            .map_err(|e| format!("查询会话数量失败: {}", e))?;
+
+        let sql = format!(
+            "SELECT {} FROM chat_sessions s ORDER BY s.updated_at DESC LIMIT ?1 OFFSET ?2",
+            Self::SESSION_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&sql).map_err(|e| format!("查询会话失败: {}", e))?;
+        let sessions = stmt
+            .query_map(params![limit as i64, offset as i64], Self::read_session)
+            .map_err(|e| format!("查询会话失败: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("读取会话失败: {}", e))?;
+
+        Ok(ChatSessionPage { sessions, total: total as usize })
+    }
+
+    /// 获取会话及其全部消息
+    pub fn get_session(&self, id: &str) -> Result<Option<ChatSession>, String> {
+        let sql = format!("SELECT {} FROM chat_sessions s WHERE s.id = ?1", Self::SESSION_COLUMNS);
+        let Some(mut session) = self.conn
+            .query_row(&sql, params![id], Self::read_session)
+            .optional()
+            .map_err(|e| format!("查询会话失败: {}", e))?
+        else {
+            return Ok(None);
+        };
+
+        let mut stmt = self.conn
+            .prepare(
+                "SELECT id, role, content, timestamp, clipboard_ref, provider_id FROM chat_messages
+                 WHERE session_id = ?1 ORDER BY rowid",
+            )
+            .map_err(|e| format!("查询消息失败: {}", e))?;
+        session.messages = stmt
+            .query_map(params![id], Self::read_message)
+            .map_err(|e| format!("查询消息失败: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("读取消息失败: {}", e))?;
+        session.message_count = session.messages.len();
+
+        Ok(Some(session))
+    }
+
+    /// 保存新会话及其中的消息，ID 为空或已存在时生成新的 ID
+    pub fn create_session(&mut self, mut session: ChatSession) -> Result<ChatSession, String> {
+        let exists = !session.id.is_empty() && self.session_exists(&session.id)?;
+        if session.id.is_empty() || exists {
+            session.id = uuid::Uuid::new_v4().to_string();
+        }
+        let now = now_millis();
+        if session.created_at == 0 {
+            session.created_at = now;
+        }
+        session.updated_at = now;
+        if session.clipboard_item_id.is_none() {
+            session.clipboard_item_id = session.messages.iter().find_map(Self::clipboard_item_id);
+        }
+
+        let tx = self.conn.transaction().map_err(|e| format!("开启事务失败: {}", e))?;
+        tx.execute(
+            "INSERT INTO chat_sessions (id, title, ai_provider, system_prompt, role_prompt, role_id, clipboard_item_id, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                session.id,
+                session.title,
+                to_json(&session.ai_provider),
+                session.system_prompt,
+                session.role_prompt,
+                session.role_id,
+                session.clipboard_item_id,
+                session.created_at as i64,
+                session.updated_at as i64,
+            ],
+        )
+        .map_err(|e| format!("保存会话失败: {}", e))?;
+        for message in &session.messages {
+            Self::insert_message(&tx, &session.id, message)?;
+        }
+        tx.commit().map_err(|e| format!("提交事务失败: {}", e))?;
+
+        session.message_count = session.messages.len();
+        Ok(session)
+    }
+
+    fn session_exists(&self, id: &str) -> Result<bool, String> {
+        self.conn
+            .query_row("SELECT 1 FROM chat_sessions WHERE id = ?1", params![id], |_| Ok(()))
+            .optional()
+            .map(|row| row.is_some())
+            .map_err(|e| format!("查询会话失败: {}", e))
+    }
+
+    fn insert_message(conn: &Connection, session_id: &str, message: &ChatMessage) -> Result<(), String> {
+        conn.execute(
+            "INSERT OR REPLACE INTO chat_messages (session_id, id, role, content, timestamp, clipboard_ref, provider_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                session_id,
+                message.id,
+                message.role,
+                message.content,
+                message.timestamp as i64,
+                message.clipboard_ref.as_ref().map(to_json),
+                message.provider_id,
+            ],
+        )
+        .map_err(|e| format!("保存消息失败: {}", e))?;
+        Ok(())
+    }
+
+    /// 消息引用的剪贴板条目ID
+    fn clipboard_item_id(message: &ChatMessage) -> Option<String> {
+        message.clipboard_ref.as_ref()?["id"].as_str().map(String::from)
+    }
+
+    /// 会话有变化时更新时间
+    fn touch(&self, id: &str) -> Result<bool, String> {
+        let changed = self.conn
+            .execute("UPDATE chat_sessions SET updated_at = ?1 WHERE id = ?2", params![now_millis() as i64, id])
+            .map_err(|e| format!("更新会话失败: {}", e))?;
+        Ok(changed > 0)
+    }
+
+    /// 修改会话的标题、提供商或提示词
+    pub fn update_session(&self, id: &str, update: ChatSessionUpdate) -> Result<(), String> {
+        let changed = self.conn
+            .execute(
+                "UPDATE chat_sessions SET
+                     title = COALESCE(?1, title),
+                     ai_provider = COALESCE(?2, ai_provider),
+                     system_prompt = COALESCE(?3, system_prompt),
+                     role_prompt = COALESCE(?4, role_prompt),
+                     role_id = COALESCE(?5, role_id),
+                     updated_at = ?6
+                 WHERE id = ?7",
+                params![
+                    update.title,
+                    update.ai_provider.as_ref().map(to_json),
+                    update.system_prompt,
+                    update.role_prompt,
+                    update.role_id,
+                    now_millis() as i64,
+                    id,
+                ],
+            )
+            .map_err(|e| format!("更新会话失败: {}", e))?;
+        if changed == 0 {
+            return Err(format!("未找到会话: {}", id));
+        }
+        Ok(())
+    }
+
+    pub fn delete_session(&self, id: &str) -> Result<bool, String> {
+        self.conn
+            .execute("DELETE FROM chat_sessions WHERE id = ?1", params![id])
+            .map(|changed| changed > 0)
+            .map_err(|e| format!("删除会话失败: {}", e))
+    }
+
+    /// 清空会话中的消息
+    pub fn clear_session(&self, id: &str) -> Result<(), String> {
+        self.conn
+            .execute("DELETE FROM chat_messages WHERE session_id = ?1", params![id])
+            .map_err(|e| format!("清空会话失败: {}", e))?;
+        self.touch(id)?;
+        Ok(())
+    }
+
+    /// 添加消息，会话还没有关联剪贴板条目时关联消息引用的条目
+    pub fn add_message(&self, session_id: &str, message: &ChatMessage) -> Result<(), String> {
+        if !self.touch(session_id)? {
+            return Err(format!("未找到会话: {}", session_id));
+        }
+        Self::insert_message(&self.conn, session_id, message)?;
+
+        if let Some(item_id) = Self::clipboard_item_id(message) {
+            self.conn
+                .execute(
+                    "UPDATE chat_sessions SET clipboard_item_id = ?1 WHERE id = ?2 AND clipboard_item_id IS NULL",
+                    params![item_id, session_id],
+                )
+                .map_err(|e| format!("更新会话失败: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// 修改消息内容，传入 provider_id 时同时记录回答的提供商
+    pub fn update_message(&self, session_id: &str, message_id: &str, content: &str, provider_id: Option<&str>) -> Result<(), String> {
+        let changed = self.conn
+            .execute(
+                "UPDATE chat_messages SET content = ?1, provider_id = COALESCE(?2, provider_id)
+                 WHERE session_id = ?3 AND id = ?4",
+                params![content, provider_id, session_id, message_id],
+            )
+            .map_err(|e| format!("更新消息失败: {}", e))?;
+        if changed == 0 {
+            return Err(format!("未找到消息: {}", message_id));
+        }
+        self.touch(session_id)?;
+        Ok(())
+    }
+
+    pub fn delete_message(&self, session_id: &str, message_id: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                "DELETE FROM chat_messages WHERE session_id = ?1 AND id = ?2",
+                params![session_id, message_id],
+            )
+            .map_err(|e| format!("删除消息失败: {}", e))?;
+        self.touch(session_id)?;
+        Ok(())
+    }
+
+    /// 在全部会话的消息中搜索，按时间从新到旧返回
+    pub fn search_messages(&self, query: &str, limit: usize) -> Result<Vec<ChatSearchHit>, String> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut stmt = self.conn
+            .prepare(
+                "SELECT m.id, m.role, m.content, m.timestamp, m.clipboard_ref, m.provider_id, s.id, s.title
+                 FROM chat_messages m JOIN chat_sessions s ON s.id = m.session_id
+                 WHERE m.content LIKE ?1 ESCAPE '\\'
+                 ORDER BY m.timestamp DESC LIMIT ?2",
+            )
+            .map_err(|e| format!("搜索消息失败: {}", e))?;
+        let hits = stmt
+            .query_map(params![format!("%{}%", escape_like(query)), limit as i64], |row| {
+                let message = Self::read_message(row)?;
+                Ok(ChatSearchHit {
+                    session_id: row.get(6)?,
+                    session_title: row.get(7)?,
+                    snippet: snippet(&message.content, query),
+                    message,
+                })
+            })
+            .map_err(|e| format!("搜索消息失败: {}", e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("读取消息失败: {}", e))?;
+        Ok(hits)
+    }
+
+    /// 导出会话为 JSON（可以再导入）或 Markdown
+    pub fn export_session(&self, id: &str, format: ChatExportFormat) -> Result<String, String> {
+        let session = self.get_session(id)?.ok_or_else(|| format!("未找到会话: {}", id))?;
+
+        match format {
+            ChatExportFormat::Json => serde_json::to_string_pretty(&session).map_err(|e| format!("序列化会话失败: {}", e)),
+            ChatExportFormat::Markdown => {
+                let mut markdown = format!("# {}\n", session.title);
+                for message in &session.messages {
+                    let speaker = match (message.role.as_str(), &message.provider_id) {
+                        ("user", _) => "用户".to_string(),
+                        ("assistant", Some(provider_id)) => format!("助手（{}）", provider_id),
+                        ("assistant", None) => "助手".to_string(),
+                        _ => "系统".to_string(),
+                    };
+                    let time = chrono::DateTime::from_timestamp_millis(message.timestamp as i64)
+                        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default();
+                    markdown.push_str(&format!("\n## {} · {}\n\n{}\n", speaker, time, message.content));
+
+                    if let Some(content) = message.clipboard_ref.as_ref().and_then(|reference| reference["content"].as_str()) {
+                        let quoted = content.lines().map(|line| format!("> {}", line)).collect::<Vec<_>>().join("\n");
+                        markdown.push_str(&format!("\n引用的剪贴板内容：\n\n{}\n", quoted));
+                    }
+                }
+                Ok(markdown)
+            },
+        }
+    }
+}
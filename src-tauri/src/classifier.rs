@@ -0,0 +1,301 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+// 按标签识别密码时内容的最大字符数，更长的内容通常是代码或配置文件
+const MAX_LABELED_SECRET_CHARS: usize = 200;
+
+lazy_static::lazy_static! {
+    static ref URL_RE: Regex = Regex::new(r"(?i)^(?:(?:https?|ftp)://|www\.)([^/\s:?#]+)\S*$").unwrap();
+    static ref EMAIL_RE: Regex = Regex::new(r"^[\w.+-]+@((?:[\w-]+\.)+[A-Za-z]{2,})$").unwrap();
+    static ref HEX_COLOR_RE: Regex = Regex::new(r"^#(?:[0-9a-fA-F]{3,4}|[0-9a-fA-F]{6}|[0-9a-fA-F]{8})$").unwrap();
+    static ref RGB_COLOR_RE: Regex = Regex::new(r"(?i)^rgba?\(\s*(\d{1,3})\s*,\s*(\d{1,3})\s*,\s*(\d{1,3})\s*(?:,\s*[\d.]+%?\s*)?\)$").unwrap();
+    static ref HSL_COLOR_RE: Regex = Regex::new(r"(?i)^hsla?\(\s*\d{1,3}(?:deg)?\s*,\s*\d{1,3}%\s*,\s*\d{1,3}%\s*(?:,\s*[\d.]+%?\s*)?\)$").unwrap();
+    static ref PHONE_RE: Regex = Regex::new(r"^\+?[\d\s().-]{7,20}$").unwrap();
+    static ref MOBILE_RE: Regex = Regex::new(r"^1[3-9]\d{9}$").unwrap();
+    static ref NUMBER_RE: Regex = Regex::new(r"^[+-]?(?:\d{1,3}(?:,\d{3})+|\d+)(?:\.\d+)?(?:[eE][+-]?\d+)?%?$").unwrap();
+    static ref UNIX_PATH_RE: Regex = Regex::new(r"^(?:/|~/|\./|\.\./)[^\n]*$").unwrap();
+    static ref WINDOWS_PATH_RE: Regex = Regex::new(r"^(?:[A-Za-z]:\\|\\\\[^\\\s]+\\)[^\n]*$").unwrap();
+    static ref SECRET_LABEL_RE: Regex = Regex::new(r"(?i)(?:\b(?:password|passwd|pwd|passphrase|secret|token|api[_-]?key)\b|密码|口令)\s*[:=：]").unwrap();
+    static ref KEY_VALUE_LINE_RE: Regex = Regex::new(r"^\s*[^\s:=：]+(?:\s+[^\s:=：]+)?\s*[:=：]\s*\S").unwrap();
+    static ref SECRET_TOKEN_RE: Regex = Regex::new(r"^(?:sk-[A-Za-z0-9_-]{20,}|(?:AKIA|ASIA)[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{36,}|xox[abprs]-[A-Za-z0-9-]{10,})$").unwrap();
+    static ref XML_RE: Regex = Regex::new(r"(?s)^<(?:\?xml|!DOCTYPE|[A-Za-z][\w:.-]*)[^>]*>.*>$").unwrap();
+    static ref HTML_RE: Regex = Regex::new(r"(?i)^<(?:!DOCTYPE html|html|head|body|div|span|p|a|ul|ol|li|table|script|style)\b").unwrap();
+    static ref YAML_LINE_RE: Regex = Regex::new(r"^\s*(?:-\s+\S|[\w.-]+:(?:\s|$)|#)").unwrap();
+    // 值为空、下一行开始嵌套块的键
+    static ref YAML_PARENT_RE: Regex = Regex::new(r"^\s*(?:-\s+)?[\w.-]+:\s*$").unwrap();
+    // 代码片段中常见的结构：括号、运算符、赋值语句、带扩展名的文件名
+    static ref CODE_TOKEN_RE: Regex = Regex::new(r"[(){}\[\]<>;,'\x22`]|::|->|=>|==|&&|\|\||^[A-Za-z_][\w.-]*\s*[:=]|\.[A-Za-z]{1,5}$").unwrap();
+
+    // 各语言的特征，命中数最多的语言作为猜测结果
+    static ref LANGUAGE_HINTS: Vec<(&'static str, Vec<Regex>)> = vec![
+        ("rust", hints(&[r"\bfn\s+\w+\s*[<(]", r"\blet\s+mut\b", r"\bimpl\b", r"\bpub\s+(?:fn|struct|enum)\b", r"\buse\s+\w+::", r"\w+!\("])),
+        ("python", hints(&[r"(?m)^\s*def\s+\w+\(.*\):", r"(?m)^\s*(?:from\s+\w+\s+)?import\s+\w+", r"\bself\.", r"\belif\b", r"(?m)^\s*class\s+\w+.*:$", r"\bprint\("])),
+        ("typescript", hints(&[r"\binterface\s+\w+\s*\{", r":\s*(?:string|number|boolean)\b", r"\bexport\s+(?:type|interface)\b", r"\bimport\s+.*\s+from\s+'"])),
+        ("javascript", hints(&[r"\bfunction\s*\w*\s*\(", r"\b(?:const|let|var)\s+\w+\s*=", r"=>", r"\bconsole\.log\(", r"\brequire\("])),
+        ("java", hints(&[r"\bpublic\s+(?:static\s+)?(?:class|void)\b", r"\bSystem\.out\.print", r"\bprivate\s+(?:static\s+)?\w+\s+\w+", r"@Override"])),
+        ("c", hints(&[r"(?m)^\s*#include\s*[<\x22]", r"\bint\s+main\s*\(", r"\bprintf\s*\(", r"\bmalloc\s*\("])),
+        ("cpp", hints(&[r"\bstd::", r"\bcout\s*<<", r"\btemplate\s*<", r"\bnamespace\s+\w+"])),
+        ("go", hints(&[r"(?m)^package\s+\w+", r"\bfunc\s+(?:\(\w+\s+\*?\w+\)\s*)?\w+\(", r":=", r"\bfmt\.\w+\("])),
+        ("sql", hints(&[r"(?i)\bselect\b[\s\S]+\bfrom\b", r"(?i)\binsert\s+into\b", r"(?i)\bcreate\s+table\b", r"(?i)\bupdate\s+\w+\s+set\b", r"(?i)\bwhere\b"])),
+        ("shell", hints(&[r"^#!/(?:usr/)?bin/(?:env\s+)?(?:ba|z)?sh", r"(?m)^\s*(?:sudo|echo|export|cd|apt(?:-get)?|npm|cargo|git)\s", r"\$\{?\w+\}?", r"(?m)\|\s*(?:grep|awk|sed)\b"])),
+        ("css", hints(&[r"(?m)^\s*[.#]?[\w-]+(?:\s*[,>+~]?\s*[.#]?[\w-]+)*\s*\{", r"(?m)^\s*[\w-]+\s*:\s*[^;]+;\s*$", r"@media\b"])),
+    ];
+}
+
+fn hints(patterns: &[&str]) -> Vec<Regex> {
+    patterns.iter().map(|pattern| Regex::new(pattern).unwrap()).collect()
+}
+
+/// 内容的结构化信息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentMetadata {
+    pub char_count: usize,
+    pub line_count: usize,
+    /// 代码的语言或结构化数据的格式（json、xml、yaml）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// 链接的主机名或邮箱的域名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// 颜色值，十六进制和 rgb 颜色统一为 #rrggbb
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<f64>,
+}
+
+/// 分类结果
+#[derive(Debug, Clone)]
+pub struct Classification {
+    pub category: &'static str,
+    pub metadata: ContentMetadata,
+}
+
+/// 识别文本内容的分类，分类值与前端预定义分类保持一致
+pub fn classify(content: &str) -> Classification {
+    let mut metadata = ContentMetadata {
+        char_count: content.chars().count(),
+        line_count: content.lines().count(),
+        ..Default::default()
+    };
+
+    let trimmed = content.trim();
+    let single_line = !trimmed.contains('\n');
+
+    let category = if trimmed.is_empty() {
+        "text"
+    } else if is_secret_token(trimmed) {
+        "password"
+    } else {
+        let single_line_category = if single_line {
+            classify_single_line(trimmed, &mut metadata)
+        } else {
+            None
+        };
+        // 代码和结构化数据中出现 password: 之类的字段时仍按代码处理
+        single_line_category
+            .or_else(|| classify_structured(trimmed, &mut metadata))
+            .or_else(|| is_labeled_secret(trimmed).then_some("password"))
+            .unwrap_or("text")
+    };
+
+    Classification { category, metadata }
+}
+
+/// 单行内容：链接、邮箱、颜色、电话、数字、文件路径
+fn classify_single_line(line: &str, metadata: &mut ContentMetadata) -> Option<&'static str> {
+    if let Some(captures) = URL_RE.captures(line) {
+        metadata.host = Some(captures[1].to_lowercase());
+        return Some("link");
+    }
+
+    if let Some(captures) = EMAIL_RE.captures(line) {
+        metadata.host = Some(captures[1].to_lowercase());
+        return Some("email");
+    }
+
+    if let Some(color) = parse_color(line) {
+        metadata.color = Some(color);
+        return Some("color");
+    }
+
+    if is_phone(line) {
+        return Some("phone");
+    }
+
+    if NUMBER_RE.is_match(line) {
+        metadata.number = line.trim_end_matches('%').replace(',', "").parse().ok();
+        return Some("number");
+    }
+
+    // 只按形式判断，采集时不访问文件系统
+    if UNIX_PATH_RE.is_match(line) || WINDOWS_PATH_RE.is_match(line) {
+        return Some("path");
+    }
+
+    None
+}
+
+/// 结构化数据和代码
+fn classify_structured(content: &str, metadata: &mut ContentMetadata) -> Option<&'static str> {
+    let language = if is_json(content) {
+        Some("json")
+    } else if XML_RE.is_match(content) {
+        Some(if HTML_RE.is_match(content) { "html" } else { "xml" })
+    } else if is_yaml(content) {
+        Some("yaml")
+    } else {
+        guess_language(content)
+    };
+
+    match language {
+        Some(language) => {
+            metadata.language = Some(language.to_string());
+            Some("code")
+        },
+        None if looks_like_code(content) => Some("code"),
+        None => None,
+    }
+}
+
+/// 带标签的密码/密钥，例如 `password: xxx` 或几行 `用户名: xxx`、`密码: xxx`
+///
+/// 只用于较短、且每一行都是 `键: 值` 形式的内容，避免把包含这类字段的代码或配置文件当成密码。
+fn is_labeled_secret(content: &str) -> bool {
+    content.chars().count() <= MAX_LABELED_SECRET_CHARS
+        && SECRET_LABEL_RE.is_match(content)
+        && content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .all(|line| KEY_VALUE_LINE_RE.is_match(line))
+}
+
+/// 单独复制的密钥或高强度密码
+fn is_secret_token(content: &str) -> bool {
+    if SECRET_TOKEN_RE.is_match(content) {
+        return true;
+    }
+
+    // 8~64 位、不含空白且同时包含大小写字母、数字和符号的单个词。
+    // 标识符中常见的 _ . - / 不算符号，含代码结构的片段（调用、泛型、赋值等）也不算
+    let length = content.chars().count();
+    let symbols = content
+        .chars()
+        .filter(|c| c.is_ascii_punctuation() && !matches!(c, '_' | '.' | '-' | '/' | '\\'))
+        .count();
+    (8..=64).contains(&length)
+        && !content.chars().any(char::is_whitespace)
+        && content.chars().any(|c| c.is_ascii_lowercase())
+        && content.chars().any(|c| c.is_ascii_uppercase())
+        && content.chars().any(|c| c.is_ascii_digit())
+        && symbols > 0
+        && !CODE_TOKEN_RE.is_match(content)
+        && !content.contains("://")
+        && !EMAIL_RE.is_match(content)
+        && !content.starts_with(['/', '.', '~', '#', '$'])
+        && !WINDOWS_PATH_RE.is_match(content)
+}
+
+/// 解析颜色值
+fn parse_color(content: &str) -> Option<String> {
+    if HEX_COLOR_RE.is_match(content) {
+        let hex = &content[1..];
+        // #rgb 和 #rgba 展开为完整形式
+        let expanded: String = if hex.len() <= 4 {
+            hex.chars().flat_map(|c| [c, c]).collect()
+        } else {
+            hex.to_string()
+        };
+        return Some(format!("#{}", expanded.to_lowercase()));
+    }
+
+    if let Some(captures) = RGB_COLOR_RE.captures(content) {
+        let channels: Vec<u8> = (1..=3).filter_map(|i| captures[i].parse().ok()).collect();
+        if channels.len() == 3 {
+            return Some(format!("#{:02x}{:02x}{:02x}", channels[0], channels[1], channels[2]));
+        }
+    }
+
+    if HSL_COLOR_RE.is_match(content) {
+        return Some(content.to_lowercase());
+    }
+
+    None
+}
+
+/// 电话号码：国际格式、带分隔符的号码或中国大陆手机号
+fn is_phone(content: &str) -> bool {
+    if MOBILE_RE.is_match(content) {
+        return true;
+    }
+
+    if !PHONE_RE.is_match(content) {
+        return false;
+    }
+
+    // 纯数字交给数字分类，电话号码需要有 + 前缀或分隔符
+    let digits = content.chars().filter(|c| c.is_ascii_digit()).count();
+    let has_separator = content.contains(['-', ' ', '(', ')']);
+    (7..=15).contains(&digits)
+        && (content.starts_with('+') || has_separator)
+        && !content.contains('.')
+}
+
+fn is_json(content: &str) -> bool {
+    (content.starts_with('{') || content.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(content).is_ok()
+}
+
+/// 以 `---` 文档分隔符开头，或者每一行都是 `key: value`、`- item` 形式且带有嵌套结构的多行内容
+///
+/// 只有 `标签: 内容` 的普通多行文本不算 YAML，至少要有一个键下面跟着缩进块或列表。
+fn is_yaml(content: &str) -> bool {
+    if content.lines().next().is_some_and(|line| line.trim_end() == "---") {
+        return true;
+    }
+
+    let lines: Vec<&str> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .collect();
+    if lines.len() < 2 || !lines.iter().all(|line| YAML_LINE_RE.is_match(line)) {
+        return false;
+    }
+
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    lines.windows(2).any(|pair| {
+        YAML_PARENT_RE.is_match(pair[0])
+            && (indent(pair[1]) > indent(pair[0]) || pair[1].trim_start().starts_with("- "))
+    })
+}
+
+/// 按语言特征猜测代码语言
+fn guess_language(content: &str) -> Option<&'static str> {
+    LANGUAGE_HINTS
+        .iter()
+        .map(|(language, patterns)| {
+            let score = patterns.iter().filter(|pattern| pattern.is_match(content)).count();
+            (*language, score)
+        })
+        .filter(|(_, score)| *score >= 2)
+        // 得分相同时取列表中靠前的语言（例如 TypeScript 优先于 JavaScript）
+        .fold(None, |best: Option<(&'static str, usize)>, candidate| match best {
+            Some(best) if best.1 >= candidate.1 => Some(best),
+            _ => Some(candidate),
+        })
+        .map(|(language, _)| language)
+}
+
+/// 无法判断语言、但整体结构像代码的内容
+fn looks_like_code(content: &str) -> bool {
+    let lines: Vec<&str> = content.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    if lines.len() < 2 {
+        return false;
+    }
+
+    let code_lines = lines
+        .iter()
+        .filter(|line| line.ends_with([';', '{', '}', ')']) || line.starts_with("//"))
+        .count();
+    code_lines * 2 >= lines.len()
+}
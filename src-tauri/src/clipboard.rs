@@ -1,5 +1,52 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use crate::classifier::{self, ContentMetadata};
+
+/// 剪贴板内容类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    #[default]
+    Text,
+    Image,
+    Html,
+    Rtf,
+    Files,
+}
+
+/// 复制时同时存在的其它格式，content 始终保存纯文本表示
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClipboardFormats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtf: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+}
+
+impl ClipboardFormats {
+    /// 是否没有任何富文本格式
+    pub fn is_empty(&self) -> bool {
+        self.html.is_none() && self.rtf.is_none() && self.files.is_empty()
+    }
+}
+
+/// 图片条目的附加信息
+///
+/// 原图保存在图片存储中，条目只记录哈希、元数据和缩略图
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub hash: String,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub mime: String,
+    #[serde(default)]
+    pub thumbnail: String,
+}
 
 /// 剪贴板条目
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +64,21 @@ pub struct ClipboardItem {
     pub summary: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "aiAnalysisCount", alias = "aiAnalysisCount")]
     pub ai_analysis_count: Option<u32>,
+    #[serde(default, rename = "contentType", alias = "content_type")]
+    pub content_type: ContentType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageInfo>,
+    #[serde(default, skip_serializing_if = "ClipboardFormats::is_empty")]
+    pub formats: ClipboardFormats,
+    /// 自动过期时间（毫秒时间戳），由剪贴板规则设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// 内容识别得到的结构化信息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ContentMetadata>,
+    /// 是否命中了打码或过期规则，敏感内容不会发送去计算向量
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
 }
 
 /// 剪贴板操作结果
@@ -34,6 +96,8 @@ pub struct ClipboardFilter {
     pub show_favorites_only: bool,
     pub show_pinned_only: bool,
     pub category: Option<String>,
+    #[serde(default)]
+    pub content_type: Option<ContentType>,
 }
 
 impl ClipboardItem {
@@ -49,14 +113,72 @@ impl ClipboardItem {
             translation: None,
             summary: None,
             ai_analysis_count: None,
+            content_type: ContentType::Text,
+            image: None,
+            formats: ClipboardFormats::default(),
+            expires_at: None,
+            sensitive: false,
+            metadata: None,
         }
     }
     
+    /// 创建一个带有富文本格式的条目，按 文件列表 > HTML > RTF 确定内容类型
+    pub fn new_rich(content: String, formats: ClipboardFormats) -> Self {
+        let mut item = Self::new(content);
+        item.content_type = if !formats.files.is_empty() {
+            ContentType::Files
+        } else if formats.html.is_some() {
+            ContentType::Html
+        } else if formats.rtf.is_some() {
+            ContentType::Rtf
+        } else {
+            ContentType::Text
+        };
+        item.formats = formats;
+        item
+    }
+    
+    /// 创建一个新的图片条目，content 为图片的文字说明
+    pub fn new_image(image: ImageInfo) -> Self {
+        let mut item = Self::new(Self::image_label(&image));
+        item.content_type = ContentType::Image;
+        item.category = Some("image".to_string());
+        item.image = Some(image);
+        item
+    }
+    
+    /// 识别内容并记录结构化信息，没有分类时同时填入识别出的分类
+    ///
+    /// 已有的分类（例如用户手动设置的）会被保留，图片条目不做识别。
+    pub fn classify(&mut self) {
+        if self.content_type == ContentType::Image {
+            return;
+        }
+        
+        let mut classification = classifier::classify(&self.content);
+        if self.content_type == ContentType::Files {
+            classification.category = "path";
+        }
+        
+        if self.category.is_none() {
+            self.category = Some(classification.category.to_string());
+        }
+        self.metadata = Some(classification.metadata);
+    }
+    
+    /// 图片条目显示的文字说明
+    pub fn image_label(image: &ImageInfo) -> String {
+        format!("[图片 {}×{}]", image.width, image.height)
+    }
+    
     /// 检查条目是否匹配筛选条件
     pub fn matches_filter(&self, filter: &ClipboardFilter) -> bool {
         // 检查搜索文本
         if let Some(search_text) = &filter.search_text {
-            if !search_text.is_empty() && !self.content.to_lowercase().contains(&search_text.to_lowercase()) {
+            // 图片条目的 content 只是文字说明，不参与文本搜索
+            if !search_text.is_empty()
+                && (self.content_type == ContentType::Image
+                    || !self.content.to_lowercase().contains(&search_text.to_lowercase())) {
                 return false;
             }
         }
@@ -71,6 +193,13 @@ impl ClipboardItem {
             return false;
         }
         
+        // 检查内容类型
+        if let Some(content_type) = filter.content_type {
+            if self.content_type != content_type {
+                return false;
+            }
+        }
+        
         // 检查分类
         if let Some(category) = &filter.category {
             if self.category.as_ref() != Some(category) {
@@ -80,4 +209,4 @@ impl ClipboardItem {
         
         true
     }
-} 
\ No newline at end of file
+} 
\ No newline at end of file
@@ -1,70 +1,59 @@
 use arboard::Clipboard;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::Duration;
 use std::thread;
 use tauri::{AppHandle, Manager};
 use tauri::{Listener, Emitter};
-use crate::clipboard::ClipboardItem;
+use crate::clipboard::{ClipboardFormats, ClipboardItem};
+use crate::clipboard_rules::RuleMatch;
+use crate::clipboard_watcher::{self, ClipboardWatcher, PollingWatcher, WatchEvent};
+use crate::rich_clipboard;
 use crate::AppState;
-use base64::{engine::general_purpose, Engine as _};
 use image::{ImageBuffer, Rgba};
 use std::io::Cursor;
 use lazy_static;
+use serde::Serialize;
 
 // 定义一个全局变量来控制监控状态
 lazy_static::lazy_static! {
     static ref MONITOR_ENABLED: AtomicBool = AtomicBool::new(true);
+    // 轮询间隔（毫秒），使用系统变化通知时作为最长等待时间
+    static ref POLL_INTERVAL_MS: AtomicU64 = AtomicU64::new(1000);
+}
+
+// 设置剪贴板轮询间隔，下一次等待时生效
+pub fn set_poll_interval(interval_ms: u64) {
+    POLL_INTERVAL_MS.store(interval_ms, Ordering::Relaxed);
 }
 
 // 保存上一次的剪贴板内容，用于比较变化
 struct ClipboardContent {
     last_text: Option<String>,
+    last_image_hash: Option<String>,
 }
 
 impl ClipboardContent {
     fn new() -> Self {
         Self {
             last_text: None,
+            last_image_hash: None,
         }
     }
 }
 
-// 计算图片的简单哈希值（用于比较图片是否变化）
+// 计算图片的哈希值（用于比较图片是否变化以及去重）
 fn calculate_image_hash(img: &arboard::ImageData) -> String {
-    // 简单的哈希算法：使用图片的宽高和少量抽样像素
-    let width = img.width as u32;
-    let height = img.height as u32;
-    let bytes = &img.bytes;
-    
-    // 如果图片太小，直接返回
-    if width < 10 || height < 10 {
-        return format!("{}x{}", width, height);
-    }
-    
-    // 对图片进行采样
-    let mut sample_points = Vec::new();
-    for x_pct in [0, 25, 50, 75, 99] {
-        for y_pct in [0, 25, 50, 75, 99] {
-            let x = (width * x_pct as u32) / 100;
-            let y = (height * y_pct as u32) / 100;
-            
-            // 计算像素位置 - arboard的ImageData使用RGBA格式，每像素4字节
-            let stride = width as usize * 4; // 每行的字节数
-            let idx = (y as usize * stride) + (x as usize * 4); 
-            if idx + 3 < bytes.len() {
-                sample_points.push(format!("{}{}{}{}", 
-                    bytes[idx], bytes[idx+1], bytes[idx+2], bytes[idx+3]));
-            }
-        }
-    }
-    
-    // 连接采样点值形成哈希值
-    sample_points.join("")
+    // 将宽高与全部像素一起计算摘要，避免抽样导致相似截图被误判为同一张
+    let mut context = md5::Context::new();
+    context.consume((img.width as u64).to_le_bytes());
+    context.consume((img.height as u64).to_le_bytes());
+    context.consume(&img.bytes);
+    format!("{:x}", context.compute())
 }
 
-// 将图片转换为Base64编码的字符串
-fn image_to_base64(img: &arboard::ImageData) -> Result<String, Box<dyn std::error::Error>> {
+// 将图片编码为PNG数据
+fn image_to_png(img: &arboard::ImageData) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     // 将arboard::ImageData转换为image::RgbaImage
     let width = img.width as u32;
     let height = img.height as u32;
@@ -94,8 +83,25 @@ fn image_to_base64(img: &arboard::ImageData) -> Result<String, Box<dyn std::erro
         let mut cursor = Cursor::new(&mut buffer);
         rgba_image.write_to(&mut cursor, image::ImageFormat::Png)?;
     }
-    let b64 = general_purpose::STANDARD.encode(&buffer);
-    Ok(format!("data:image/png;base64,{}", b64))
+    Ok(buffer)
+}
+
+// 将PNG数据写回系统剪贴板，以图片而不是文本的形式
+pub fn write_image_to_clipboard(png: &[u8]) -> Result<(), String> {
+    let rgba_image = image::load_from_memory(png)
+        .map_err(|e| format!("解析图片失败: {}", e))?
+        .to_rgba8();
+    
+    let image_data = arboard::ImageData {
+        width: rgba_image.width() as usize,
+        height: rgba_image.height() as usize,
+        bytes: std::borrow::Cow::Owned(rgba_image.into_raw()),
+    };
+    
+    let mut clipboard = Clipboard::new().map_err(|e| format!("无法创建剪贴板实例: {}", e))?;
+    clipboard
+        .set_image(image_data)
+        .map_err(|e| format!("写入剪贴板图片失败: {}", e))
 }
 
 // 启动剪贴板监控
@@ -105,6 +111,13 @@ pub fn start_monitoring(app_handle: AppHandle) -> Result<(), Box<dyn std::error:
     // 创建一个线程安全的共享状态，用于存储最近的剪贴板内容
     let content = Arc::new(Mutex::new(ClipboardContent::new()));
     
+    // 读取保存的轮询间隔
+    if let Some(state) = app_handle.try_state::<AppState>() {
+        if let Ok(storage) = state.storage.lock() {
+            set_poll_interval(storage.get_poll_interval_ms());
+        }
+    }
+    
     // 创建一个原子布尔值，用于控制监控线程的生命周期
     let running = Arc::new(AtomicBool::new(true));
     let running_clone = running.clone();
@@ -127,6 +140,9 @@ pub fn start_monitoring(app_handle: AppHandle) -> Result<(), Box<dyn std::error:
         const MAX_ERRORS: usize = 10;
         let mut empty_clipboard_messages = 0; // 空剪贴板消息计数
         
+        // 优先使用系统的剪贴板变化通知，不可用时退回轮询
+        let mut watcher = clipboard_watcher::create_watcher();
+        
         // 循环检查剪贴板变化
         while running_clone.load(Ordering::Relaxed) {
             // 检查是否暂停监控
@@ -136,6 +152,21 @@ pub fn start_monitoring(app_handle: AppHandle) -> Result<(), Box<dyn std::error:
                 continue;
             }
             
+            // 删除规则设置了自动过期且已到期的条目
+            purge_expired_items(&app_handle_clone);
+            
+            // 等待剪贴板变化
+            let interval = Duration::from_millis(POLL_INTERVAL_MS.load(Ordering::Relaxed));
+            match watcher.wait_for_change(interval) {
+                WatchEvent::Changed => {},
+                WatchEvent::Timeout => continue,
+                WatchEvent::Closed => {
+                    eprintln!("剪贴板变化通知已失效（{}），改为轮询", watcher.name());
+                    watcher = Box::new(PollingWatcher);
+                    continue;
+                }
+            }
+            
             // 尝试读取剪贴板文本
             match clipboard.get_text() {
                 Ok(current_text) => {
@@ -151,15 +182,34 @@ pub fn start_monitoring(app_handle: AppHandle) -> Result<(), Box<dyn std::error:
                         
                         // 更新最新的内容
                         content_guard.last_text = Some(current_text.clone());
+                        drop(content_guard);
+                        
+                        // 同一次复制中可能同时带有HTML、RTF或文件列表
+                        let formats = rich_clipboard::read_formats(&mut clipboard);
                         
                         // 处理新的剪贴板内容（保存到存储）
-                        process_new_text_content(&app_handle_clone, current_text);
+                        process_new_text_content(&app_handle_clone, current_text, formats);
                     }
                 },
                 Err(e) => {
                     // 如果是空剪贴板或格式不匹配，减少日志输出
                     let error_msg = e.to_string();
                     if error_msg.contains("were not available") || error_msg.contains("empty") {
+                        // 部分平台复制文件时只提供文件列表而没有文本
+                        let files = rich_clipboard::read_file_list(&mut clipboard);
+                        let mut content_guard = content.lock().unwrap();
+                        if files.is_empty() {
+                            // 剪贴板中已没有文本（例如被图片替换），之后再次复制相同文本也应记录
+                            content_guard.last_text = None;
+                        } else {
+                            let file_list = files.join("\n");
+                            if content_guard.last_text.as_ref() != Some(&file_list) {
+                                content_guard.last_text = Some(file_list.clone());
+                                drop(content_guard);
+                                let formats = ClipboardFormats { files, ..Default::default() };
+                                process_new_text_content(&app_handle_clone, file_list, formats);
+                            }
+                        }
                         empty_clipboard_messages += 1;
                         // 只在第一次和每100次后打印一次空剪贴板消息
                         if empty_clipboard_messages == 1 || empty_clipboard_messages % 100 == 0 {
@@ -191,8 +241,22 @@ pub fn start_monitoring(app_handle: AppHandle) -> Result<(), Box<dyn std::error:
                 }
             }
             
-            // 暂停时间增加到1秒，降低CPU使用率和减少日志输出
-            thread::sleep(Duration::from_millis(1000));
+            // 尝试读取剪贴板图片
+            if let Ok(current_image) = clipboard.get_image() {
+                let hash = calculate_image_hash(&current_image);
+                let mut content_guard = content.lock().unwrap();
+                
+                if content_guard.last_image_hash.as_ref() != Some(&hash) {
+                    content_guard.last_image_hash = Some(hash.clone());
+                    drop(content_guard);
+                    
+                    // 处理新的剪贴板图片（保存到存储）
+                    process_new_image_content(&app_handle_clone, &current_image, hash);
+                }
+            } else {
+                // 剪贴板中没有图片，之后再次复制同一张图片也应记录
+                content.lock().unwrap().last_image_hash = None;
+            }
         }
         
         // println!("剪贴板监控线程结束");
@@ -212,13 +276,89 @@ pub fn start_monitoring(app_handle: AppHandle) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
+// 删除已过期的条目，有删除时通知前端刷新
+fn purge_expired_items(app_handle: &AppHandle) {
+    let purged = match app_handle.try_state::<AppState>() {
+        Some(state) => match state.storage.lock() {
+            Ok(mut storage) => storage.purge_expired().unwrap_or_else(|e| {
+                eprintln!("删除过期条目失败: {}", e);
+                0
+            }),
+            Err(_) => 0,
+        },
+        None => 0,
+    };
+    
+    if purged > 0 {
+        emit_clipboard_change(app_handle, None, false, Vec::new());
+    }
+}
+
+// clipboard-change 事件内容
+#[derive(Debug, Clone, Serialize)]
+struct ClipboardChangeEvent {
+    // 新增或更新的条目ID
+    item_id: Option<String>,
+    // 内容是否因命中过滤规则而未保存
+    skipped: bool,
+    rule_matches: Vec<RuleMatch>,
+}
+
+// 发送事件通知前端更新数据
+pub(crate) fn emit_clipboard_change(app_handle: &AppHandle, item_id: Option<String>, skipped: bool, rule_matches: Vec<RuleMatch>) {
+    let event = ClipboardChangeEvent {
+        item_id,
+        skipped,
+        rule_matches,
+    };
+    
+    match app_handle.emit("clipboard-change", event) {
+        Ok(_) => {} // println!("成功发送剪贴板更新事件"),
+        Err(_e) => {} // println!("发送剪贴板更新事件失败: {:?}", e),
+    }
+}
+
 // 处理新的文本内容
-fn process_new_text_content(app_handle: &AppHandle, content: String) {
-    // 创建新的剪贴板条目
-    let new_item = ClipboardItem::new(content);
+fn process_new_text_content(app_handle: &AppHandle, content: String, formats: ClipboardFormats) {
+    // 文件列表条目的内容统一为每行一个路径
+    let content = if formats.files.is_empty() { content } else { formats.files.join("\n") };
+    
+    let state = match app_handle.try_state::<AppState>() {
+        Some(state) => state,
+        None => {
+            // println!("获取应用状态失败");
+            return;
+        }
+    };
+    
+    // 保存前先评估过滤规则
+    let outcome = match state.clipboard_rules.lock() {
+        Ok(rules) => rules.evaluate(&content),
+        Err(e) => {
+            eprintln!("无法获取规则锁: {:?}", e);
+            return;
+        }
+    };
+    
+    // 命中跳过规则的内容不保存，只通知前端
+    if outcome.skip {
+        emit_clipboard_change(app_handle, None, true, outcome.matches);
+        return;
+    }
+    
+    // 创建新的剪贴板条目，打码后原有的富文本格式中仍含有敏感内容，只保留纯文本
+    let sensitive = outcome.is_sensitive();
+    let mut new_item = if outcome.masked {
+        ClipboardItem::new(outcome.content)
+    } else {
+        ClipboardItem::new_rich(content, formats)
+    };
+    new_item.expires_at = outcome.expires_at;
+    new_item.sensitive = sensitive;
+    new_item.classify();
     
     // 保存新条目
-    let save_result = if let Some(state) = app_handle.try_state::<AppState>() {
+    let save_result = {
         let mut storage = match state.storage.lock() {
             Ok(storage) => storage,
             Err(e) => {
@@ -227,36 +367,21 @@ fn process_new_text_content(app_handle: &AppHandle, content: String) {
             }
         };
         
-        let save_result = storage.add_item(new_item.clone());
-        
-        if save_result.is_ok() {
-            // println!("已保存剪贴板文本内容到历史记录");
-            true
-        } else {
-            // println!("保存剪贴板文本内容失败: {:?}", save_result.err());
-            false
-        }
-    } else {
-        // println!("获取应用状态失败");
-        false
+        storage.add_item(new_item.clone())
     };
     
-    // 保存成功后发送事件通知前端更新数据
-    if save_result {
-        // 发送事件通知前端更新数据
-        match app_handle.emit("clipboard-change", ()) {
-            Ok(_) => {} // println!("成功发送剪贴板更新事件"),
-            Err(e) => {} // println!("发送剪贴板更新事件失败: {:?}", e),
-        }
+    // 保存成功后发送事件通知前端更新数据，并为新条目计算向量
+    match save_result {
+        Ok(_) => {
+            state.embeddings.wake();
+            emit_clipboard_change(app_handle, Some(new_item.id), false, outcome.matches);
+        },
+        Err(_e) => {} // println!("保存剪贴板文本内容失败: {:?}", e),
     }
 }
 
 // 处理新的图片内容
-fn process_new_image_content(app_handle: &AppHandle, base64_image: String) {
-    // 创建新的剪贴板条目，标记为图片类型
-    let mut new_item = ClipboardItem::new(base64_image);
-    new_item.category = Some("image".to_string()); // 设置类别为图片
-    
+fn process_new_image_content(app_handle: &AppHandle, img: &arboard::ImageData, hash: String) {
     // 保存新条目
     let save_result = if let Some(state) = app_handle.try_state::<AppState>() {
         let mut storage = match state.storage.lock() {
@@ -267,27 +392,34 @@ fn process_new_image_content(app_handle: &AppHandle, base64_image: String) {
             }
         };
         
-        let save_result = storage.add_item(new_item.clone());
-        
-        if save_result.is_ok() {
-            // println!("已保存剪贴板图片内容到历史记录");
-            true
-        } else {
-            // println!("保存剪贴板图片内容失败: {:?}", save_result.err());
-            false
+        // 同一张图片已存在时只更新时间戳，将其移到最前
+        match storage.find_image_by_hash(&hash).cloned() {
+            Some(mut existing) => {
+                existing.timestamp = chrono::Utc::now().timestamp_millis() as u64;
+                let id = existing.id.clone();
+                storage.update_item(existing).map(|_| id)
+            },
+            None => {
+                // 编码为PNG并保存到图片存储，条目只记录引用
+                image_to_png(img)
+                    .map_err(|e| format!("编码剪贴板图片失败: {}", e))
+                    .and_then(|png| storage.store_image(&hash, &png))
+                    .and_then(|image| {
+                        let new_item = ClipboardItem::new_image(image);
+                        let id = new_item.id.clone();
+                        storage.add_item(new_item).map(|_| id)
+                    })
+            }
         }
     } else {
         // println!("获取应用状态失败");
-        false
+        return;
     };
     
     // 保存成功后发送事件通知前端更新数据
-    if save_result {
-        // 发送事件通知前端更新数据
-        match app_handle.emit("clipboard-change", ()) {
-            Ok(_) => {} // println!("成功发送剪贴板更新事件"),
-            Err(e) => {} // println!("发送剪贴板更新事件失败: {:?}", e),
-        }
+    match save_result {
+        Ok(id) => emit_clipboard_change(app_handle, Some(id), false, Vec::new()),
+        Err(_e) => {} // println!("保存剪贴板图片内容失败: {:?}", e),
     }
 }
 
@@ -321,4 +453,4 @@ pub fn toggle_clipboard_monitor() -> bool {
     // println!("切换剪贴板监控状态: {}", if new_status { "启动" } else { "暂停" });
     MONITOR_ENABLED.store(new_status, Ordering::Relaxed);
     new_status
-} 
\ No newline at end of file
+} 
\ No newline at end of file
@@ -0,0 +1,465 @@
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::persistence;
+
+// 自动过期规则未指定时长时的默认保留时间（秒）
+const DEFAULT_EXPIRE_SECS: u64 = 10 * 60;
+
+// 默认允许保存的最大字符数
+const DEFAULT_MAX_CHARS: usize = 100_000;
+
+/// 内置的敏感内容检测器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Detector {
+    /// 信用卡号（按卡号格式分组或带有发卡行前缀，并通过 Luhn 校验）
+    CreditCard,
+    /// JSON Web Token
+    Jwt,
+    /// AWS 访问密钥
+    AwsKey,
+    /// OpenAI / Anthropic 风格的 API 密钥
+    ApiKey,
+    /// PEM 格式的私钥
+    PrivateKey,
+}
+
+impl Detector {
+    fn pattern(&self) -> &'static str {
+        match self {
+            Detector::CreditCard => r"\b\d(?:[ -]?\d){12,18}\b",
+            Detector::Jwt => r"\beyJ[A-Za-z0-9_-]{5,}\.eyJ[A-Za-z0-9_-]{5,}\.[A-Za-z0-9_-]{10,}",
+            Detector::AwsKey => r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b|(?i:aws_secret_access_key)\s*[=:]\s*[A-Za-z0-9/+=]{40}",
+            Detector::ApiKey => r"\bsk-(?:proj-|ant-)?[A-Za-z0-9_-]{20,}",
+            Detector::PrivateKey => r"-----BEGIN (?:[A-Z]+ )*PRIVATE KEY-----(?s:.*?)(?:-----END (?:[A-Z]+ )*PRIVATE KEY-----|\z)",
+        }
+    }
+
+    /// 对正则匹配结果做进一步校验
+    fn verify(&self, matched: &str) -> bool {
+        match self {
+            Detector::CreditCard => card_shaped(matched) && luhn_valid(matched),
+            _ => true,
+        }
+    }
+}
+
+/// 是否具有卡号的形式，避免把订单号、证件号等长数字误判为卡号
+///
+/// 带分隔符时要求使用同一种分隔符按卡号方式分组（4-4-4-4、4-6-5 等），
+/// 连续数字则要求以已知卡组织的发卡行前缀（IIN）开头且长度符合。
+fn card_shaped(matched: &str) -> bool {
+    let separator = matched.chars().find(|c| *c == ' ' || *c == '-');
+    match separator {
+        Some(separator) => {
+            if matched.contains(if separator == ' ' { '-' } else { ' ' }) {
+                return false;
+            }
+            let groups: Vec<usize> = matched.split(separator).map(str::len).collect();
+            let (last, leading) = groups.split_last().unwrap_or((&0, &[]));
+            let grouped_by_four = leading.len() >= 3
+                && leading.iter().all(|len| *len == 4)
+                && (1..=4).contains(last);
+            grouped_by_four || groups == [4, 6, 5] || groups == [4, 6, 4]
+        },
+        None => iin_valid(matched),
+    }
+}
+
+/// 按卡组织的发卡行前缀和卡号长度校验
+fn iin_valid(digits: &str) -> bool {
+    let len = digits.len();
+    let prefix = |n: usize| digits.get(..n).and_then(|p| p.parse::<u32>().ok()).unwrap_or(0);
+
+    match prefix(1) {
+        // Visa
+        4 => matches!(len, 13 | 16 | 19),
+        // Mastercard
+        5 => (51..=55).contains(&prefix(2)) && len == 16,
+        2 => (2221..=2720).contains(&prefix(4)) && len == 16,
+        // American Express、JCB、Diners Club
+        3 => match prefix(2) {
+            34 | 37 => len == 15,
+            35 => (3528..=3589).contains(&prefix(4)) && (16..=19).contains(&len),
+            36 | 38 | 39 => (14..=19).contains(&len),
+            30 => (300..=305).contains(&prefix(3)) && (14..=19).contains(&len),
+            _ => false,
+        },
+        // Discover、银联
+        6 => {
+            (prefix(4) == 6011 || prefix(2) == 65 || (644..=649).contains(&prefix(3)) || prefix(2) == 62)
+                && (16..=19).contains(&len)
+        },
+        _ => false,
+    }
+}
+
+/// Luhn 校验，忽略空格和连字符
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| {
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                digit
+            }
+        })
+        .sum();
+
+    sum.is_multiple_of(10)
+}
+
+/// 规则的匹配方式
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleMatcher {
+    /// 自定义正则表达式
+    Regex { pattern: String },
+    /// 内置检测器
+    Detector { detector: Detector },
+    /// 内容超过指定字符数
+    MaxLength { max_chars: usize },
+}
+
+/// 规则命中后的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// 不保存
+    Skip,
+    /// 将命中的部分打码后保存
+    Mask,
+    /// 正常保存，到期后自动删除
+    Expire,
+}
+
+/// 剪贴板过滤规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipboardRule {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub matcher: RuleMatcher,
+    pub action: RuleAction,
+    /// 自动过期时长（秒），仅对 `expire` 生效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_after_secs: Option<u64>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 规则设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleSettings {
+    pub enabled: bool,
+    pub rules: Vec<ClipboardRule>,
+}
+
+impl Default for RuleSettings {
+    fn default() -> Self {
+        let detector_rule = |id: &str, name: &str, detector: Detector, action: RuleAction| ClipboardRule {
+            id: id.to_string(),
+            name: name.to_string(),
+            enabled: true,
+            matcher: RuleMatcher::Detector { detector },
+            action,
+            expire_after_secs: None,
+        };
+
+        Self {
+            enabled: true,
+            rules: vec![
+                detector_rule("private_key", "私钥", Detector::PrivateKey, RuleAction::Skip),
+                detector_rule("aws_key", "AWS 密钥", Detector::AwsKey, RuleAction::Skip),
+                detector_rule("api_key", "API 密钥", Detector::ApiKey, RuleAction::Skip),
+                detector_rule("jwt", "JWT 令牌", Detector::Jwt, RuleAction::Mask),
+                detector_rule("credit_card", "信用卡号", Detector::CreditCard, RuleAction::Mask),
+                ClipboardRule {
+                    id: "max_length".to_string(),
+                    name: "超长内容".to_string(),
+                    enabled: true,
+                    matcher: RuleMatcher::MaxLength { max_chars: DEFAULT_MAX_CHARS },
+                    action: RuleAction::Skip,
+                    expire_after_secs: None,
+                },
+            ],
+        }
+    }
+}
+
+/// 单条规则的命中情况，随 clipboard-change 事件发送给前端
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleMatch {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub action: RuleAction,
+    pub count: usize,
+}
+
+/// 规则评估结果
+#[derive(Debug, Clone)]
+pub struct RuleOutcome {
+    /// 是否跳过保存
+    pub skip: bool,
+    /// 打码后的内容，没有打码时为原内容
+    pub content: String,
+    /// 是否有内容被打码
+    pub masked: bool,
+    /// 过期时间（毫秒时间戳）
+    pub expires_at: Option<u64>,
+    pub matches: Vec<RuleMatch>,
+}
+
+impl RuleOutcome {
+    /// 命中打码或过期规则的内容视为敏感内容
+    pub fn is_sensitive(&self) -> bool {
+        self.masked || self.expires_at.is_some()
+    }
+}
+
+/// 剪贴板规则引擎，在条目保存前评估
+pub struct RuleEngine {
+    config_path: PathBuf,
+    settings: RuleSettings,
+    // 与 settings.rules 一一对应的已编译正则，最大长度规则为 None
+    compiled: Vec<Option<Regex>>,
+}
+
+impl RuleEngine {
+    /// 创建规则引擎，从配置目录加载规则
+    pub fn new(config_dir: impl AsRef<Path>) -> Self {
+        let config_path = config_dir.as_ref().join("clipboard_rules.json");
+
+        let settings = match persistence::load_json::<RuleSettings>(&config_path) {
+            Ok(Some(settings)) => settings,
+            Ok(None) => RuleSettings::default(),
+            Err(e) => {
+                eprintln!("加载剪贴板规则失败，使用默认规则: {}", e);
+                RuleSettings::default()
+            }
+        };
+
+        // 保存的规则中有无效正则时退回默认规则，保证敏感内容过滤始终有效
+        let (settings, compiled) = match Self::compile(&settings) {
+            Ok(compiled) => (settings, compiled),
+            Err(e) => {
+                eprintln!("剪贴板规则无效，使用默认规则: {}", e);
+                let settings = RuleSettings::default();
+                let compiled = Self::compile(&settings).expect("默认规则无效");
+                (settings, compiled)
+            }
+        };
+
+        Self { config_path, settings, compiled }
+    }
+
+    /// 编译全部规则的正则表达式
+    fn compile(settings: &RuleSettings) -> Result<Vec<Option<Regex>>, String> {
+        settings
+            .rules
+            .iter()
+            .map(|rule| {
+                let pattern = match &rule.matcher {
+                    RuleMatcher::Regex { pattern } => pattern.as_str(),
+                    RuleMatcher::Detector { detector } => detector.pattern(),
+                    RuleMatcher::MaxLength { .. } => return Ok(None),
+                };
+                Regex::new(pattern)
+                    .map(Some)
+                    .map_err(|e| format!("规则 {} 的正则表达式无效: {}", rule.name, e))
+            })
+            .collect()
+    }
+
+    /// 获取规则设置
+    pub fn get_settings(&self) -> RuleSettings {
+        self.settings.clone()
+    }
+
+    /// 更新并保存规则设置
+    pub fn update_settings(&mut self, settings: RuleSettings) -> Result<(), String> {
+        let compiled = Self::compile(&settings)?;
+        persistence::save_json(&self.config_path, &settings)
+            .map_err(|e| format!("保存剪贴板规则失败: {}", e))?;
+
+        self.settings = settings;
+        self.compiled = compiled;
+        Ok(())
+    }
+
+    /// 评估一段文本内容
+    pub fn evaluate(&self, content: &str) -> RuleOutcome {
+        let mut outcome = RuleOutcome {
+            skip: false,
+            content: content.to_string(),
+            masked: false,
+            expires_at: None,
+            matches: Vec::new(),
+        };
+
+        if !self.settings.enabled {
+            return outcome;
+        }
+
+        let mut mask_spans: Vec<(usize, usize)> = Vec::new();
+        let mut expire_secs: Option<u64> = None;
+
+        for (rule, regex) in self.settings.rules.iter().zip(&self.compiled) {
+            if !rule.enabled {
+                continue;
+            }
+
+            let spans: Vec<(usize, usize)> = match (&rule.matcher, regex) {
+                (RuleMatcher::MaxLength { max_chars }, _) => {
+                    if content.chars().count() > *max_chars {
+                        vec![(0, content.len())]
+                    } else {
+                        Vec::new()
+                    }
+                },
+                (RuleMatcher::Detector { detector }, Some(regex)) => regex
+                    .find_iter(content)
+                    .filter(|m| detector.verify(m.as_str()))
+                    .map(|m| (m.start(), m.end()))
+                    .collect(),
+                (_, Some(regex)) => regex.find_iter(content).map(|m| (m.start(), m.end())).collect(),
+                (_, None) => Vec::new(),
+            };
+
+            if spans.is_empty() {
+                continue;
+            }
+
+            outcome.matches.push(RuleMatch {
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                action: rule.action,
+                count: spans.len(),
+            });
+
+            match rule.action {
+                RuleAction::Skip => outcome.skip = true,
+                RuleAction::Mask => mask_spans.extend(spans),
+                RuleAction::Expire => {
+                    let secs = rule.expire_after_secs.unwrap_or(DEFAULT_EXPIRE_SECS);
+                    expire_secs = Some(expire_secs.map_or(secs, |current| current.min(secs)));
+                },
+            }
+        }
+
+        if !outcome.skip && !mask_spans.is_empty() {
+            outcome.content = mask_content(content, mask_spans);
+            outcome.masked = true;
+        }
+
+        outcome.expires_at = expire_secs
+            .map(|secs| chrono::Utc::now().timestamp_millis() as u64 + secs * 1000);
+
+        outcome
+    }
+}
+
+/// 将命中的区间替换为打码后的文本，重叠的区间会先合并
+fn mask_content(content: &str, mut spans: Vec<(usize, usize)>) -> String {
+    spans.sort();
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    let mut masked = String::with_capacity(content.len());
+    let mut cursor = 0;
+    for (start, end) in merged {
+        masked.push_str(&content[cursor..start]);
+        masked.push_str(&mask_secret(&content[start..end]));
+        cursor = end;
+    }
+    masked.push_str(&content[cursor..]);
+
+    masked
+}
+
+/// 只保留首尾各 4 个字符，便于辨认是哪一条
+fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 12 {
+        return "*".repeat(chars.len());
+    }
+
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}{}{}", head, "*".repeat(chars.len() - 8), tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 用信用卡检测器查找文本中的卡号
+    fn find_cards(text: &str) -> Vec<&str> {
+        let detector = Detector::CreditCard;
+        Regex::new(detector.pattern())
+            .unwrap()
+            .find_iter(text)
+            .map(|m| m.as_str())
+            .filter(|matched| detector.verify(matched))
+            .collect()
+    }
+
+    #[test]
+    fn grouped_card_numbers_match() {
+        assert_eq!(find_cards("卡号 4111 1111 1111 1111 请勿外传"), vec!["4111 1111 1111 1111"]);
+        assert_eq!(find_cards("5555-5555-5555-4444"), vec!["5555-5555-5555-4444"]);
+        assert_eq!(find_cards("3782 822463 10005"), vec!["3782 822463 10005"]);
+    }
+
+    #[test]
+    fn bare_card_numbers_need_an_iin_prefix() {
+        assert_eq!(find_cards("visa 4111111111111111"), vec!["4111111111111111"]);
+        assert_eq!(find_cards("amex 378282246310005"), vec!["378282246310005"]);
+    }
+
+    #[test]
+    fn long_order_numbers_do_not_match() {
+        // 通过 Luhn 校验但没有卡组织前缀
+        assert!(luhn_valid("1234567812345670"));
+        assert!(find_cards("订单号 1234567812345670").is_empty());
+        // 分隔符混用或分组不像卡号
+        assert!(find_cards("4111 1111-1111 1111").is_empty());
+        assert!(find_cards("411-11111-1111-1111").is_empty());
+        // 有前缀但未通过 Luhn 校验
+        assert!(find_cards("4111111111111112").is_empty());
+    }
+
+    #[test]
+    fn overlapping_spans_are_merged_before_masking() {
+        let content = "abcdefghijklmnopqrstuvwxyz";
+        assert_eq!(mask_content(content, vec![(10, 20), (0, 14)]), "abcd************qrstuvwxyz");
+        assert_eq!(mask_content(content, vec![(0, 4), (4, 8)]), "********ijklmnopqrstuvwxyz");
+    }
+
+    #[test]
+    fn masking_keeps_multibyte_text_around_spans() {
+        let content = "卡号：4111 1111 1111 1111。谢谢";
+        let start = content.find('4').unwrap();
+        let end = content.find('。').unwrap();
+        assert_eq!(mask_content(content, vec![(start, end)]), "卡号：4111***********1111。谢谢");
+    }
+}
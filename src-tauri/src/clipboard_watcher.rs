@@ -0,0 +1,225 @@
+use std::thread;
+use std::time::Duration;
+
+/// 一次等待的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// 剪贴板可能已经变化，需要读取一次
+    Changed,
+    /// 等待超时，期间没有收到变化通知
+    Timeout,
+    /// 通知来源已失效（例如 X 连接断开），需要换用其它监听方式
+    Closed,
+}
+
+/// 剪贴板变化监听方式
+///
+/// 监控线程只关心"何时去读剪贴板"，具体是轮询还是系统事件由实现决定。
+pub trait ClipboardWatcher: Send {
+    /// 监听方式名称，用于日志
+    fn name(&self) -> &'static str;
+
+    /// 阻塞等待下一次变化，最多等待 `timeout`
+    ///
+    /// 轮询实现把 `timeout` 当作轮询间隔，每次都返回 `Changed`。
+    fn wait_for_change(&mut self, timeout: Duration) -> WatchEvent;
+}
+
+/// 定时轮询，所有平台都可用的兜底方式
+pub struct PollingWatcher;
+
+impl ClipboardWatcher for PollingWatcher {
+    fn name(&self) -> &'static str {
+        "polling"
+    }
+
+    fn wait_for_change(&mut self, timeout: Duration) -> WatchEvent {
+        thread::sleep(timeout);
+        WatchEvent::Changed
+    }
+}
+
+/// 选择当前环境下可用的监听方式：Wayland data-control > X11 XFixes > 轮询
+pub fn create_watcher() -> Box<dyn ClipboardWatcher> {
+    #[cfg(target_os = "linux")]
+    {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            match linux::WaylandWatcher::new() {
+                Ok(watcher) => return Box::new(watcher),
+                Err(e) => eprintln!("无法使用 Wayland 剪贴板通知: {}", e),
+            }
+        }
+
+        if std::env::var_os("DISPLAY").is_some() {
+            match linux::X11Watcher::new() {
+                Ok(watcher) => return Box::new(watcher),
+                Err(e) => eprintln!("无法使用 X11 剪贴板通知: {}", e),
+            }
+        }
+    }
+
+    Box::new(PollingWatcher)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+    use x11rb::protocol::xproto::{ConnectionExt as _, CreateWindowAux, WindowClass};
+    use x11rb::protocol::Event;
+    use super::{ClipboardWatcher, WatchEvent};
+
+    /// 从通知通道等待一次变化，并合并期间堆积的多次通知
+    fn wait_on_channel(receiver: &Receiver<()>, timeout: Duration) -> WatchEvent {
+        match receiver.recv_timeout(timeout) {
+            Ok(()) => {
+                while receiver.try_recv().is_ok() {}
+                WatchEvent::Changed
+            },
+            Err(RecvTimeoutError::Timeout) => WatchEvent::Timeout,
+            Err(RecvTimeoutError::Disconnected) => WatchEvent::Closed,
+        }
+    }
+
+    /// 创建通知通道，并预先放入一次变化，让监控启动时先读取一次当前内容
+    fn notify_channel() -> (Sender<()>, Receiver<()>) {
+        let (sender, receiver) = mpsc::channel();
+        let _ = sender.send(());
+        (sender, receiver)
+    }
+
+    /// 通过 XFixes 扩展监听 CLIPBOARD 选区所有者的变化
+    pub struct X11Watcher {
+        receiver: Receiver<()>,
+    }
+
+    impl X11Watcher {
+        pub fn new() -> Result<Self, String> {
+            let (conn, screen_num) = x11rb::connect(None).map_err(|e| format!("连接 X 服务器失败: {}", e))?;
+
+            conn.xfixes_query_version(5, 0)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| format!("X 服务器不支持 XFixes: {}", e))?;
+
+            let clipboard_atom = conn
+                .intern_atom(false, b"CLIPBOARD")
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| format!("获取 CLIPBOARD 原子失败: {}", e))?
+                .atom;
+
+            // 创建一个不可见的窗口用于接收事件
+            let screen = &conn.setup().roots[screen_num];
+            let window = conn.generate_id().map_err(|e| e.to_string())?;
+            conn.create_window(
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                window,
+                screen.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                x11rb::COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .map_err(|e| format!("创建 X 窗口失败: {}", e))?;
+
+            let event_mask = SelectionEventMask::SET_SELECTION_OWNER
+                | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                | SelectionEventMask::SELECTION_CLIENT_CLOSE;
+            conn.xfixes_select_selection_input(window, clipboard_atom, event_mask)
+                .map_err(|e| format!("订阅选区事件失败: {}", e))?;
+            conn.flush().map_err(|e| e.to_string())?;
+
+            let (sender, receiver) = notify_channel();
+            thread::spawn(move || loop {
+                match conn.wait_for_event() {
+                    Ok(Event::XfixesSelectionNotify(_)) => {
+                        if sender.send(()).is_err() {
+                            break;
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(e) => {
+                        eprintln!("X11 剪贴板事件连接中断: {}", e);
+                        break;
+                    },
+                }
+            });
+
+            Ok(Self { receiver })
+        }
+    }
+
+    impl ClipboardWatcher for X11Watcher {
+        fn name(&self) -> &'static str {
+            "x11-xfixes"
+        }
+
+        fn wait_for_change(&mut self, timeout: Duration) -> WatchEvent {
+            wait_on_channel(&self.receiver, timeout)
+        }
+    }
+
+    /// 通过 `wl-paste --watch` 使用合成器的 data-control 协议监听变化
+    ///
+    /// wl-clipboard-rs 只提供一次性读取（`paste::get_contents`）和写入的接口，data-control
+    /// 的事件处理是私有模块，没有监听选区变化的 API。自己实现需要直接基于 wayland-client
+    /// 绑定 data-control 协议，因此这里借用 wl-clipboard 软件包中的 wl-paste；
+    /// 未安装或合成器不支持时由 `create_watcher` 退回其他方式。
+    pub struct WaylandWatcher {
+        child: Child,
+        receiver: Receiver<()>,
+    }
+
+    impl WaylandWatcher {
+        pub fn new() -> Result<Self, String> {
+            let mut child = Command::new("wl-paste")
+                .args(["--watch", "echo"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("启动 wl-paste 失败: {}", e))?;
+
+            let stdout = child.stdout.take().ok_or("无法读取 wl-paste 输出")?;
+
+            // 合成器不支持 data-control 时 wl-paste 会立即退出，通道随之关闭
+            let (sender, receiver) = notify_channel();
+            thread::spawn(move || {
+                for line in BufReader::new(stdout).lines() {
+                    if line.is_err() || sender.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            Ok(Self { child, receiver })
+        }
+    }
+
+    impl ClipboardWatcher for WaylandWatcher {
+        fn name(&self) -> &'static str {
+            "wayland-data-control"
+        }
+
+        fn wait_for_change(&mut self, timeout: Duration) -> WatchEvent {
+            wait_on_channel(&self.receiver, timeout)
+        }
+    }
+
+    impl Drop for WaylandWatcher {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
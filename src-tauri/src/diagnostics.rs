@@ -0,0 +1,266 @@
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use crate::ai::{APITestResult, ChatCompletionMessage, ChatCompletionRequest};
+use crate::provider::{self, ChatProvider, ResolvedProvider};
+
+// 解析域名和建立TCP连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 诊断步骤的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    /// 有问题但不影响使用，例如后续步骤已证明服务可用
+    Warning,
+    /// 前置步骤失败或服务不支持，未执行
+    Skipped,
+}
+
+/// 一个诊断步骤的报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticStep {
+    /// 步骤标识：connect、auth、model、completion
+    pub name: String,
+    pub label: String,
+    pub status: StepStatus,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl DiagnosticStep {
+    fn new(name: &str, label: &str, status: StepStatus, message: String, latency: Option<Duration>) -> Self {
+        Self {
+            name: name.to_string(),
+            label: label.to_string(),
+            status,
+            message,
+            latency_ms: latency.map(|latency| latency.as_millis() as u64),
+        }
+    }
+
+    fn skipped(name: &str, label: &str, message: &str) -> Self {
+        Self::new(name, label, StepStatus::Skipped, message.to_string(), None)
+    }
+}
+
+/// 依次检查网络连接、密钥、模型和一次最小的补全请求
+pub async fn run(resolved: &ResolvedProvider, model: &str) -> APITestResult {
+    let started = Instant::now();
+    let mut steps = Vec::new();
+
+    // 1. 域名解析与TCP连接
+    let connect = check_connect(resolved.provider.endpoint()).await;
+    let connected = connect.status != StepStatus::Failed;
+    steps.push(connect);
+
+    // 2. 通过模型列表接口验证密钥
+    let mut models = None;
+    if connected {
+        let timer = Instant::now();
+        steps.push(match resolved.provider.list_models().await {
+            Ok(Some(ids)) => {
+                let message = format!("密钥有效，共 {} 个可用模型", ids.len());
+                models = Some(ids);
+                DiagnosticStep::new("auth", "密钥验证", StepStatus::Passed, message, Some(timer.elapsed()))
+            },
+            Ok(None) => DiagnosticStep::new(
+                "auth",
+                "密钥验证",
+                StepStatus::Skipped,
+                "该服务不提供模型列表接口，将通过补全请求验证密钥".to_string(),
+                Some(timer.elapsed()),
+            ),
+            Err(e) => DiagnosticStep::new("auth", "密钥验证", StepStatus::Failed, e, Some(timer.elapsed())),
+        });
+    } else {
+        steps.push(DiagnosticStep::skipped("auth", "密钥验证", "无法连接到服务"));
+    }
+    let authorized = steps.last().is_some_and(|step| step.status != StepStatus::Failed);
+
+    // 3. 检查所选模型是否可用
+    steps.push(match &models {
+        _ if model.is_empty() => DiagnosticStep::new(
+            "model",
+            "模型检查",
+            StepStatus::Failed,
+            "未选择模型".to_string(),
+            None,
+        ),
+        Some(ids) if ids.iter().any(|id| same_model(id, model)) => DiagnosticStep::new(
+            "model",
+            "模型检查",
+            StepStatus::Passed,
+            format!("模型 {} 可用", model),
+            None,
+        ),
+        Some(_) => DiagnosticStep::new(
+            "model",
+            "模型检查",
+            StepStatus::Failed,
+            format!("模型 {} 不在可用模型列表中", model),
+            None,
+        ),
+        None => DiagnosticStep::skipped("model", "模型检查", "无法获取模型列表，将通过补全请求验证模型"),
+    });
+
+    // 4. 发送一次最小的补全请求
+    if connected && authorized && !model.is_empty() {
+        let completion = check_completion(resolved, model).await;
+
+        // 补全请求成功说明模型可用，模型列表中没有它（例如别名或未列出的部署）只作为警告
+        if completion.status == StepStatus::Passed {
+            if let Some(step) = steps.iter_mut().find(|step| step.name == "model" && step.status == StepStatus::Failed) {
+                step.status = StepStatus::Warning;
+                step.message = format!("模型 {} 不在可用模型列表中，但补全请求成功", model);
+            }
+        }
+        steps.push(completion);
+    } else {
+        steps.push(DiagnosticStep::skipped("completion", "补全请求", "前置检查未通过"));
+    }
+
+    let response_time = started.elapsed().as_millis() as u64;
+    let failed = steps.iter().find(|step| step.status == StepStatus::Failed);
+    let (success, message) = match failed {
+        Some(step) => (false, format!("{}失败: {}", step.label, step.message)),
+        None => (true, format!("连接成功 ({}ms)", response_time)),
+    };
+
+    APITestResult {
+        success,
+        message,
+        response_time: Some(response_time),
+        steps,
+    }
+}
+
+/// 是否为该协议配置了代理，与 reqwest 读取的环境变量一致
+fn proxy_configured(scheme: &str) -> bool {
+    let names: &[&str] = match scheme {
+        "https" => &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"],
+        _ => &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"],
+    };
+    names.iter().any(|name| std::env::var(name).is_ok_and(|value| !value.trim().is_empty()))
+}
+
+/// 解析域名并建立TCP连接
+///
+/// 配置了代理时实际请求经过代理发送，改为通过同一个 HTTP 客户端请求服务地址，
+/// 收到任何 HTTP 响应即视为连通。
+async fn check_connect(endpoint: &str) -> DiagnosticStep {
+    let url = match reqwest::Url::parse(endpoint) {
+        Ok(url) => url,
+        Err(e) => return DiagnosticStep::new("connect", "网络连接", StepStatus::Failed, format!("API地址无效: {}", e), None),
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return DiagnosticStep::new("connect", "网络连接", StepStatus::Failed, "API地址缺少主机名".to_string(), None);
+    };
+
+    if proxy_configured(url.scheme()) {
+        return check_connect_via_proxy(&url, host).await;
+    }
+
+    let timer = Instant::now();
+    let address = match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::lookup_host((host, port))).await {
+        Ok(Ok(mut addresses)) => addresses.next(),
+        Ok(Err(e)) => {
+            return DiagnosticStep::new("connect", "网络连接", StepStatus::Failed, format!("无法解析域名 {}: {}", host, e), Some(timer.elapsed()));
+        },
+        Err(_) => None,
+    };
+    let Some(address) = address else {
+        return DiagnosticStep::new("connect", "网络连接", StepStatus::Failed, format!("无法解析域名 {}", host), Some(timer.elapsed()));
+    };
+
+    match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => DiagnosticStep::new(
+            "connect",
+            "网络连接",
+            StepStatus::Passed,
+            format!("已连接到 {} ({})", host, address),
+            Some(timer.elapsed()),
+        ),
+        Ok(Err(e)) => DiagnosticStep::new(
+            "connect",
+            "网络连接",
+            StepStatus::Failed,
+            format!("无法连接到 {}: {}", address, e),
+            Some(timer.elapsed()),
+        ),
+        Err(_) => DiagnosticStep::new(
+            "connect",
+            "网络连接",
+            StepStatus::Failed,
+            format!("连接 {} 超时", address),
+            Some(timer.elapsed()),
+        ),
+    }
+}
+
+/// 通过代理请求服务地址
+async fn check_connect_via_proxy(url: &reqwest::Url, host: &str) -> DiagnosticStep {
+    let client = match provider::http_client() {
+        Ok(client) => client,
+        Err(e) => return DiagnosticStep::new("connect", "网络连接", StepStatus::Failed, e, None),
+    };
+
+    let timer = Instant::now();
+    match client.get(url.clone()).timeout(CONNECT_TIMEOUT).send().await {
+        Ok(response) => DiagnosticStep::new(
+            "connect",
+            "网络连接",
+            StepStatus::Passed,
+            format!("已通过代理连接到 {} (HTTP {})", host, response.status().as_u16()),
+            Some(timer.elapsed()),
+        ),
+        Err(e) => DiagnosticStep::new(
+            "connect",
+            "网络连接",
+            StepStatus::Failed,
+            format!("通过代理无法连接到 {}: {}", host, e),
+            Some(timer.elapsed()),
+        ),
+    }
+}
+
+/// 比较模型 ID，Ollama 的模型列表带有 `:latest` 标签，而配置中通常省略
+fn same_model(listed: &str, configured: &str) -> bool {
+    fn normalize(id: &str) -> &str {
+        id.strip_suffix(":latest").unwrap_or(id)
+    }
+    normalize(listed) == normalize(configured)
+}
+
+/// 发送一次只生成少量内容的补全请求
+async fn check_completion(resolved: &ResolvedProvider, model: &str) -> DiagnosticStep {
+    let request = ChatCompletionRequest {
+        model: model.to_string(),
+        messages: vec![ChatCompletionMessage { role: "user".to_string(), content: "ping".to_string() }],
+        temperature: Some(0.0),
+        max_tokens: Some(8),
+        stream: Some(false),
+    };
+
+    let timer = Instant::now();
+    match resolved.provider.complete(&request).await {
+        Ok(response) if response["choices"][0]["message"].is_object() => DiagnosticStep::new(
+            "completion",
+            "补全请求",
+            StepStatus::Passed,
+            "模型已正常响应".to_string(),
+            Some(timer.elapsed()),
+        ),
+        Ok(response) => DiagnosticStep::new(
+            "completion",
+            "补全请求",
+            StepStatus::Failed,
+            format!("无法解析响应: {}", response),
+            Some(timer.elapsed()),
+        ),
+        Err(e) => DiagnosticStep::new("completion", "补全请求", StepStatus::Failed, e, Some(timer.elapsed())),
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+use crate::persistence;
+use crate::provider::{self, ResolvedProvider};
+
+type AppState = crate::AppState;
+
+// 每次请求计算向量的条目数
+const BATCH_SIZE: usize = 16;
+
+// 计算失败后等待多久再重试
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// 语义搜索设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddingSettings {
+    /// 是否在后台为剪贴板条目计算向量
+    pub enabled: bool,
+    /// 提供向量接口的AI提供商，为空时使用当前选择的提供商
+    #[serde(default)]
+    pub provider_id: String,
+    /// 向量模型，例如 text-embedding-3-small 或 nomic-embed-text
+    #[serde(default)]
+    pub model: String,
+}
+
+/// 向量计算进度
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingStatus {
+    pub enabled: bool,
+    pub model: String,
+    /// 可以计算向量的条目数
+    pub total: usize,
+    /// 已计算向量的条目数
+    pub embedded: usize,
+}
+
+/// 语义搜索的设置与后台计算任务的唤醒信号
+pub struct EmbeddingService {
+    settings: Mutex<EmbeddingSettings>,
+    config_path: PathBuf,
+    wake: Notify,
+}
+
+impl EmbeddingService {
+    pub fn new(data_dir: &Path) -> Self {
+        let config_path = data_dir.join("embedding_settings.json");
+        let settings = persistence::load_json(&config_path)
+            .unwrap_or_else(|e| {
+                eprintln!("加载语义搜索设置失败: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            settings: Mutex::new(settings),
+            config_path,
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn get_settings(&self) -> Result<EmbeddingSettings, String> {
+        Ok(self.settings.lock().map_err(|e| e.to_string())?.clone())
+    }
+
+    pub fn update_settings(&self, settings: EmbeddingSettings) -> Result<(), String> {
+        persistence::save_json(&self.config_path, &settings)?;
+        *self.settings.lock().map_err(|e| e.to_string())? = settings;
+        self.wake();
+        Ok(())
+    }
+
+    /// 已开启并选择了模型时返回设置
+    fn active_settings(&self) -> Result<Option<EmbeddingSettings>, String> {
+        let settings = self.get_settings()?;
+        Ok((settings.enabled && !settings.model.trim().is_empty()).then_some(settings))
+    }
+
+    /// 有新增或修改的条目时唤醒后台任务
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+}
+
+fn resolve(state: &AppState, settings: &EmbeddingSettings) -> Result<ResolvedProvider, String> {
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    provider::resolve(&ai_settings_manager, Some(&settings.provider_id), None, None)
+}
+
+/// 计算查询文本的向量，返回使用的模型和向量
+pub async fn embed_query(state: &AppState, query: &str) -> Result<(String, Vec<f32>), String> {
+    let settings = state.embeddings
+        .active_settings()?
+        .ok_or_else(|| "未开启语义搜索或未选择向量模型".to_string())?;
+    let resolved = resolve(state, &settings)?;
+
+    let vector = resolved.provider
+        .embed(&settings.model, &[query.to_string()])
+        .await?
+        .pop()
+        .ok_or_else(|| "向量接口未返回结果".to_string())?;
+    Ok((settings.model, vector))
+}
+
+/// 为一批还没有向量的条目计算向量，返回处理的条目数
+async fn embed_pending(state: &AppState) -> Result<usize, String> {
+    let Some(settings) = state.embeddings.active_settings()? else {
+        return Ok(0);
+    };
+
+    let batch = state.storage
+        .lock()
+        .map_err(|e| e.to_string())?
+        .pending_embeddings(&settings.model, BATCH_SIZE);
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    let resolved = resolve(state, &settings)?;
+    let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+    let vectors = resolved.provider.embed(&settings.model, &texts).await?;
+
+    let mut storage = state.storage.lock().map_err(|e| e.to_string())?;
+    for ((id, text), vector) in batch.iter().zip(vectors) {
+        storage.save_embedding(id, text, &settings.model, vector)?;
+    }
+
+    Ok(batch.len())
+}
+
+/// 启动后台任务，持续为新增或修改的条目计算向量
+pub fn spawn_worker(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let Some(state) = app_handle.try_state::<AppState>() else {
+                return;
+            };
+
+            match embed_pending(&state).await {
+                // 没有待处理的条目，等待新条目或设置变化
+                Ok(0) => state.embeddings.wake.notified().await,
+                Ok(_) => {},
+                Err(e) => {
+                    eprintln!("计算剪贴板向量失败: {}", e);
+                    let _ = tokio::time::timeout(RETRY_DELAY, state.embeddings.wake.notified()).await;
+                },
+            }
+        }
+    });
+}
@@ -10,12 +10,39 @@ mod clipboard_monitor;
 mod role;
 mod role_storage;
 mod translation;
+mod search;
+mod persistence;
+mod blob_store;
+mod rich_clipboard;
+mod clipboard_watcher;
+mod clipboard_rules;
+mod classifier;
+mod category;
+mod category_storage;
+mod stream_registry;
+mod anthropic;
+mod provider;
+mod secrets;
+mod diagnostics;
+mod vector_index;
+mod embeddings;
+mod retrieval;
+mod token_budget;
+mod retry;
+mod usage;
+mod chat_storage;
+mod prompt_template;
 
-use clipboard::{ClipboardItem, ClipboardOperationResult, ClipboardFilter};
+use clipboard::{ClipboardItem, ClipboardOperationResult, ClipboardFilter, ClipboardFormats, ContentType};
 use storage::Storage;
+use search::SearchHit;
 use ai::{AIManager, AIRole, AIConfig, AISettings, AIProviderSetting, AISettingsManager, ModelInfo, APITestResult};
-use role::{Role, RoleOperationResult};
+use role::{NewRole, Role, RoleBinding, RoleOperationResult};
 use role_storage::RoleStorage;
+use category::Category;
+use category_storage::CategoryStorage;
+use stream_registry::{StreamInfo, StreamRegistry};
+use embeddings::{EmbeddingService, EmbeddingSettings, EmbeddingStatus};
 use translation::{TranslationManager, TranslationState, TranslationSettings, TranslationResult, LanguageInfo};
 use std::sync::Mutex;
 use tauri::{Manager, State, Emitter, AppHandle};
@@ -25,15 +52,32 @@ use tauri_plugin_clipboard_manager::ClipboardExt;
 use tauri::Listener;
 use tauri_plugin_global_shortcut::GlobalShortcutExt;
 use sysinfo::{System, SystemExt, CpuExt};
-use ai::chat_completion;
+use ai::{chat_completion, stream_chat, AIProvider, AzureDeployment, ChatCompletionMessage};
+use provider::ChatProvider;
+use clipboard_rules::{RuleEngine, RuleSettings};
+use secrets::{SecretStore, SecretStoreStatus};
+use token_budget::{ContextStrategy, TokenReport};
+use retry::RetryPolicy;
+use usage::{BudgetStatus, UsageGroup, UsageLedger, UsageSettings, UsageSource, UsageSummary};
+use chat_storage::{ChatExportFormat, ChatMessage, ChatSearchHit, ChatSession, ChatSessionPage, ChatSessionUpdate, ChatStorage};
+use prompt_template::{PromptInput, RenderedPrompt};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 // 应用状态
 struct AppState {
     storage: Mutex<Storage>,
     ai_manager: Mutex<AIManager>,
     role_storage: Mutex<RoleStorage>,
+    category_storage: Mutex<CategoryStorage>,
     ai_settings_manager: Mutex<AISettingsManager>,
     translation_manager: Mutex<TranslationManager>,
+    clipboard_rules: Mutex<RuleEngine>,
+    streams: Mutex<StreamRegistry>,
+    secrets: Arc<SecretStore>,
+    embeddings: EmbeddingService,
+    usage: UsageLedger,
+    chat_storage: Mutex<ChatStorage>,
 }
 
 // 获取最大历史记录数量
@@ -82,6 +126,119 @@ fn set_max_history_items(state: State<AppState>, max_items: usize) -> Result<(),
     }
 }
 
+// 获取剪贴板轮询间隔（毫秒）
+#[tauri::command]
+fn get_clipboard_poll_interval(state: State<AppState>) -> Result<u64, String> {
+    let storage = state.storage.lock().map_err(|e| e.to_string())?;
+    Ok(storage.get_poll_interval_ms())
+}
+
+// 设置剪贴板轮询间隔（毫秒），仅在系统不支持剪贴板变化通知时使用
+#[tauri::command]
+fn set_clipboard_poll_interval(state: State<AppState>, interval_ms: u64) -> Result<(), String> {
+    let mut storage = state.storage.lock().map_err(|e| format!("获取存储锁失败: {}", e))?;
+    storage
+        .set_poll_interval_ms(interval_ms)
+        .map_err(|e| format!("设置轮询间隔失败: {}", e))?;
+    
+    // 立即应用到正在运行的监控线程
+    clipboard_monitor::set_poll_interval(storage.get_poll_interval_ms());
+    Ok(())
+}
+
+// 获取剪贴板过滤规则
+#[tauri::command]
+fn get_clipboard_rules(state: State<AppState>) -> Result<RuleSettings, String> {
+    let rules = state.clipboard_rules.lock().map_err(|e| e.to_string())?;
+    Ok(rules.get_settings())
+}
+
+// 更新剪贴板过滤规则，正则表达式无效时返回错误
+#[tauri::command]
+fn update_clipboard_rules(state: State<AppState>, settings: RuleSettings) -> Result<(), String> {
+    let mut rules = state.clipboard_rules.lock().map_err(|e| e.to_string())?;
+    rules.update_settings(settings)
+}
+
+// 取消进行中的流式请求，前端以 stream_id 传参
+#[tauri::command(rename_all = "snake_case")]
+fn cancel_stream(stream_id: String, state: State<AppState>) -> Result<(), String> {
+    let streams = state.streams.lock().map_err(|e| e.to_string())?;
+    streams.cancel(&stream_id)
+}
+
+// 查询流式请求的状态
+#[tauri::command(rename_all = "snake_case")]
+fn get_stream_status(stream_id: String, state: State<AppState>) -> Result<StreamInfo, String> {
+    let streams = state.streams.lock().map_err(|e| e.to_string())?;
+    streams.get_status(&stream_id).ok_or_else(|| format!("流不存在: {}", stream_id))
+}
+
+// 获取所有流式请求的状态
+#[tauri::command]
+fn list_streams(state: State<AppState>) -> Result<Vec<StreamInfo>, String> {
+    let streams = state.streams.lock().map_err(|e| e.to_string())?;
+    Ok(streams.list())
+}
+
+// 数据库的保存状态
+#[derive(Debug, Clone, serde::Serialize)]
+struct StorageStatus {
+    /// 数据库未能打开、只保存在内存中的数据，退出后丢失
+    in_memory: Vec<String>,
+}
+
+// 获取数据库的保存状态，前端在数据库未能打开时提示用户
+#[tauri::command]
+fn get_storage_status(state: State<AppState>) -> Result<StorageStatus, String> {
+    let mut in_memory = Vec::new();
+    if !state.storage.lock().map_err(|e| e.to_string())?.is_persistent() {
+        in_memory.push("剪贴板历史".to_string());
+    }
+    if !state.chat_storage.lock().map_err(|e| e.to_string())?.is_persistent() {
+        in_memory.push("聊天记录".to_string());
+    }
+    if !state.usage.is_persistent() {
+        in_memory.push("用量记录".to_string());
+    }
+    Ok(StorageStatus { in_memory })
+}
+
+// 获取密钥存储的状态
+#[tauri::command]
+fn get_secret_store_status(state: State<AppState>) -> Result<SecretStoreStatus, String> {
+    state.secrets.status()
+}
+
+// 重新检查系统钥匙串（启动时钥匙串尚未就绪的情况），可用时改用钥匙串并迁移明文保存的密钥
+#[tauri::command]
+fn recheck_secret_store(
+    state: State<AppState>,
+    translation_state: State<TranslationState>,
+) -> Result<SecretStoreStatus, String> {
+    if state.secrets.is_available() {
+        state.ai_settings_manager.lock().map_err(|e| e.to_string())?.migrate_secrets()?;
+        translation_state.manager.lock().map_err(|e| e.to_string())?.migrate_secrets()?;
+    }
+    
+    state.secrets.status()
+}
+
+// 用口令解锁加密的密钥文件（首次使用时设置口令），并迁移明文保存的密钥
+#[tauri::command]
+fn unlock_secret_store(
+    passphrase: String,
+    state: State<AppState>,
+    translation_state: State<TranslationState>,
+) -> Result<SecretStoreStatus, String> {
+    state.secrets.unlock(&passphrase)?;
+    
+    state.ai_settings_manager.lock().map_err(|e| e.to_string())?.migrate_secrets()?;
+    translation_state.manager.lock().map_err(|e| e.to_string())?.migrate_secrets()?;
+    
+    state.secrets.status()
+}
+
 // 初始化应用
 fn main() {
     tauri::Builder::default()
@@ -125,8 +282,8 @@ fn main() {
                 }
             }
             
-            // 初始化存储
-            let storage_file = app_dir.join("clipboard_history.json");
+            // 初始化存储（首次启动时会自动迁移旧的 clipboard_history.json）
+            let storage_file = app_dir.join("clipboard_history.db");
             let storage = Storage::new(storage_file);
             
             // 初始化AI管理器
@@ -136,11 +293,20 @@ fn main() {
             let role_storage_file = app_dir.join("roles.json");
             let role_storage = RoleStorage::new(role_storage_file);
             
+            // 初始化分类存储
+            let category_storage = CategoryStorage::new(app_dir.join("categories.json"));
+            
+            // 初始化密钥存储，API密钥等保存在系统钥匙串或加密文件中
+            let secrets = Arc::new(SecretStore::new(&app_dir));
+            
             // 初始化AI设置管理器
-            let ai_settings_manager = AISettingsManager::new(app_dir.clone());
+            let ai_settings_manager = AISettingsManager::new(app_dir.clone(), secrets.clone());
             
             // 初始化翻译管理器
-            let translation_manager = TranslationManager::new(app_dir.clone());
+            let translation_manager = TranslationManager::new(app_dir.clone(), secrets.clone());
+            
+            // 初始化剪贴板过滤规则
+            let clipboard_rules = RuleEngine::new(app_dir.clone());
             
             // 为TranslationState创建一个克隆的实例
             let translation_manager_clone = translation_manager.clone();
@@ -150,8 +316,26 @@ fn main() {
                 storage: Mutex::new(storage),
                 ai_manager: Mutex::new(ai_manager),
                 role_storage: Mutex::new(role_storage),
+                category_storage: Mutex::new(category_storage),
                 ai_settings_manager: Mutex::new(ai_settings_manager),
                 translation_manager: Mutex::new(translation_manager),
+                clipboard_rules: Mutex::new(clipboard_rules),
+                streams: Mutex::new(StreamRegistry::new()),
+                secrets,
+                embeddings: EmbeddingService::new(&app_dir),
+                usage: UsageLedger::new(&app_dir),
+                chat_storage: Mutex::new(ChatStorage::new(&app_dir)),
+            });
+            
+            // 在后台为剪贴板条目计算语义搜索使用的向量
+            embeddings::spawn_worker(app_handle.clone());
+            
+            // 应用关闭时中止所有进行中的AI请求
+            let app_handle_for_streams = app_handle.clone();
+            app_handle.listen("tauri://close-requested", move |_| {
+                if let Ok(mut streams) = app_handle_for_streams.state::<AppState>().streams.lock() {
+                    streams.cancel_all();
+                }
             });
             
             // 注册翻译状态（使用克隆的实例）
@@ -189,6 +373,10 @@ fn main() {
             get_clipboard_history,
             get_max_history_items,
             set_max_history_items,
+            get_clipboard_poll_interval,
+            set_clipboard_poll_interval,
+            get_clipboard_rules,
+            update_clipboard_rules,
             add_clipboard_item,
             update_clipboard_item,
             remove_clipboard_item,
@@ -209,7 +397,15 @@ fn main() {
             update_role,
             delete_role,
             reset_role,
+            get_categories,
+            add_category,
+            update_category,
+            delete_category,
+            reorder_categories,
+            reset_categories,
             get_role,
+            render_role_prompt,
+            validate_prompt_template,
             update_shortcut,
             get_ai_settings,
             update_ai_settings,
@@ -218,11 +414,47 @@ fn main() {
             fetch_models,
             test_api_connection,
             chat_with_ai,
+            estimate_chat_tokens,
+            update_context_strategy,
+            update_share_passwords_in_context,
+            update_fallback_providers,
+            update_retry_policy,
+            get_usage_settings,
+            update_usage_settings,
+            get_usage_summary,
+            get_budget_status,
+            list_chat_sessions,
+            get_chat_session,
+            create_chat_session,
+            update_chat_session,
+            delete_chat_session,
+            clear_chat_session,
+            add_chat_message,
+            update_chat_message,
+            delete_chat_message,
+            search_chat_messages,
+            export_chat_session,
+            import_chat_session,
             get_clipboard_count,
+            search_clipboard,
+            semantic_search,
+            get_embedding_settings,
+            update_embedding_settings,
+            get_embedding_status,
+            get_clipboard_image,
             get_system_info,
             chat_completion,
+            stream_chat,
+            cancel_stream,
+            get_stream_status,
+            list_streams,
+            get_storage_status,
+            get_secret_store_status,
+            recheck_secret_store,
+            unlock_secret_store,
             translation::get_supported_languages,
             translation::get_translation_settings,
+            translation::update_translation_settings,
             translation::test_translation_api,
             translation::translate_text,
         ])
@@ -459,16 +691,118 @@ fn get_clipboard_count(
     Ok(storage.get_filtered_count(filter.as_ref()))
 }
 
+// 获取图片条目的原图（data URL），列表中只包含缩略图
+#[tauri::command]
+fn get_clipboard_image(state: State<AppState>, id: String) -> Result<String, String> {
+    let storage = state.storage.lock().map_err(|e| e.to_string())?;
+    let png = storage.read_image(&id)?;
+    Ok(blob_store::to_data_url(&png))
+}
+
+// 全文搜索剪贴板历史
+#[tauri::command]
+fn search_clipboard(
+    state: State<AppState>,
+    query: String,
+    limit: Option<usize>,
+    filter_options: Option<serde_json::Value>
+) -> Result<Vec<SearchHit>, String> {
+    let storage = state.storage.lock().map_err(|e| e.to_string())?;
+    
+    // 解析过滤器
+    let filter = filter_options.and_then(|filter_value| {
+        serde_json::from_value::<ClipboardFilter>(filter_value).ok()
+    });
+    
+    Ok(storage.search(&query, filter.as_ref(), limit.unwrap_or(50)))
+}
+
+// 语义搜索剪贴板历史，按与查询的向量相似度排序
+#[tauri::command]
+async fn semantic_search(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<usize>,
+    filter_options: Option<serde_json::Value>
+) -> Result<Vec<SearchHit>, String> {
+    let (model, vector) = embeddings::embed_query(&state, &query).await?;
+    
+    let filter = filter_options.and_then(|filter_value| {
+        serde_json::from_value::<ClipboardFilter>(filter_value).ok()
+    });
+    
+    let storage = state.storage.lock().map_err(|e| e.to_string())?;
+    Ok(storage.semantic_search(&vector, &model, filter.as_ref(), limit.unwrap_or(10)))
+}
+
+// 获取语义搜索设置
+#[tauri::command]
+fn get_embedding_settings(state: State<AppState>) -> Result<EmbeddingSettings, String> {
+    state.embeddings.get_settings()
+}
+
+// 更新语义搜索设置
+#[tauri::command]
+fn update_embedding_settings(settings: EmbeddingSettings, state: State<AppState>) -> Result<(), String> {
+    state.embeddings.update_settings(settings)
+}
+
+// 获取向量计算进度
+#[tauri::command]
+fn get_embedding_status(state: State<AppState>) -> Result<EmbeddingStatus, String> {
+    let settings = state.embeddings.get_settings()?;
+    let storage = state.storage.lock().map_err(|e| e.to_string())?;
+    let (total, embedded) = storage.embedding_progress(&settings.model);
+    
+    Ok(EmbeddingStatus {
+        enabled: settings.enabled,
+        model: settings.model,
+        total,
+        embedded,
+    })
+}
+
+// 与监听到的内容一样评估过滤规则，命中跳过规则时返回拒绝保存的结果
+// 否则按规则打码，并根据新内容重新计算敏感标记和过期时间
+fn apply_clipboard_rules(state: &AppState, item: &mut ClipboardItem) -> Result<(), ClipboardOperationResult> {
+    let outcome = state.clipboard_rules.lock().map_err(|e| e.to_string()).unwrap().evaluate(&item.content);
+    if outcome.skip {
+        return Err(ClipboardOperationResult {
+            success: false,
+            message: Some("内容命中过滤规则，未保存".to_string()),
+            data: serde_json::to_value(&outcome.matches).ok(),
+        });
+    }
+    
+    // 打码后富文本格式中仍含有敏感内容，只保留纯文本
+    item.sensitive = outcome.is_sensitive();
+    item.expires_at = outcome.expires_at;
+    if outcome.masked {
+        item.content = outcome.content;
+        item.formats = ClipboardFormats::default();
+        item.content_type = ContentType::Text;
+    }
+    Ok(())
+}
+
 // 添加剪贴板条目
 #[tauri::command]
-fn add_clipboard_item(item: ClipboardItem, state: State<AppState>) -> ClipboardOperationResult {
+fn add_clipboard_item(mut item: ClipboardItem, state: State<AppState>) -> ClipboardOperationResult {
+    if let Err(rejected) = apply_clipboard_rules(&state, &mut item) {
+        return rejected;
+    }
+    
     let mut storage = state.storage.lock().map_err(|e| e.to_string()).unwrap();
     
+    // 由后端识别分类，返回识别后的条目供前端使用
+    item.classify();
+    let saved_item = serde_json::to_value(&item).ok();
+    
     match storage.add_item(item) {
         Ok(_) => ClipboardOperationResult {
             success: true,
             message: Some("添加成功".to_string()),
-            data: None,
+            data: saved_item,
         },
         Err(e) => ClipboardOperationResult {
             success: false,
@@ -499,9 +833,22 @@ fn remove_clipboard_item(id: String, state: State<AppState>) -> ClipboardOperati
 
 // 更新剪贴板条目
 #[tauri::command]
-fn update_clipboard_item(item: ClipboardItem, state: State<AppState>) -> ClipboardOperationResult {
+fn update_clipboard_item(mut item: ClipboardItem, state: State<AppState>) -> ClipboardOperationResult {
     let mut storage = state.storage.lock().map_err(|e| e.to_string()).unwrap();
     
+    // 内容变化时重新评估过滤规则，未变化时保留已有的敏感标记和过期时间
+    match storage.get_item(&item.id) {
+        Some(existing) if existing.content == item.content => {
+            item.sensitive = existing.sensitive;
+            item.expires_at = existing.expires_at;
+        },
+        _ => {
+            if let Err(rejected) = apply_clipboard_rules(&state, &mut item) {
+                return rejected;
+            }
+        },
+    }
+    
     match storage.update_item(item) {
         Ok(_) => ClipboardOperationResult {
             success: true,
@@ -517,10 +864,48 @@ fn update_clipboard_item(item: ClipboardItem, state: State<AppState>) -> Clipboa
 }
 
 // 复制内容到系统剪贴板
+// 传入条目ID时按条目的原始格式复制（图片、HTML、RTF、文件列表），plain_text 为 true 时只复制纯文本
 #[tauri::command]
-async fn copy_to_clipboard(content: String, app_handle: tauri::AppHandle) -> ClipboardOperationResult {
-    let manager = app_handle.clipboard();
-    match manager.write_text(content) {
+async fn copy_to_clipboard(
+    content: String,
+    id: Option<String>,
+    plain_text: Option<bool>,
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>
+) -> Result<ClipboardOperationResult, String> {
+    let item = match id {
+        Some(id) if !plain_text.unwrap_or(false) => {
+            let storage = state.storage.lock().map_err(|e| e.to_string())?;
+            match storage.get_item(&id) {
+                // 图片条目从图片存储读取原图
+                Some(item) if item.content_type == ContentType::Image => {
+                    let png = storage.read_image(&id);
+                    drop(storage);
+                    let result = png.and_then(|png| clipboard_monitor::write_image_to_clipboard(&png));
+                    return Ok(copy_result(result));
+                },
+                Some(item) => Some(item.clone()),
+                None => None,
+            }
+        },
+        _ => None,
+    };
+    
+    // 富文本格式写入失败时退回到纯文本
+    if let Some(item) = item.filter(|item| item.content_type != ContentType::Text) {
+        match rich_clipboard::write_item(&item) {
+            Ok(_) => return Ok(copy_result(Ok(()))),
+            Err(e) => eprintln!("按原始格式复制失败，改为复制纯文本: {}", e),
+        }
+    }
+    
+    let result = app_handle.clipboard().write_text(content).map_err(|e| e.to_string());
+    Ok(copy_result(result))
+}
+
+// 将复制结果转换为操作结果
+fn copy_result(result: Result<(), String>) -> ClipboardOperationResult {
+    match result {
         Ok(_) => ClipboardOperationResult {
             success: true,
             message: Some("复制成功".to_string()),
@@ -553,115 +938,194 @@ fn clear_clipboard_history(state: State<AppState>) -> ClipboardOperationResult {
     }
 }
 
-// 翻译内容
+// 使用当前选择的AI提供商，失败时依次改用备用提供商
+fn resolve_selected_provider(state: &AppState) -> Result<provider::ProviderChain, String> {
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    provider::resolve_chain(&ai_settings_manager, None, None, None)
+}
+
+// 已配置百度翻译时优先使用，否则使用当前选择的AI提供商
+async fn translate_with_configured_service(
+    content: &str,
+    item_id: &str,
+    state: &AppState,
+    translation_state: &TranslationState,
+) -> Result<String, String> {
+    let (translator, target_language) = {
+        let manager = translation_state.manager.lock().map_err(|e| e.to_string())?;
+        (manager.detached(), manager.target_language_name())
+    };
+    
+    if translator.has_credentials() {
+        let result = translator.translate(content).await?;
+        return Ok(result.trans_result
+            .into_iter()
+            .map(|item| item.dst)
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+    
+    let chain = resolve_selected_provider(state)?;
+    let source = UsageSource::for_operation(Some(UsageSource { item_id: Some(item_id.to_string()), ..Default::default() }), "translate_content");
+    ai::translate_text(state, chain, source, content, &target_language).await
+}
+
+// 将AI处理结果保存到条目中并通知前端刷新，条目已被删除时忽略
+fn save_ai_result(
+    id: &str,
+    app_handle: &AppHandle,
+    state: &AppState,
+    apply: impl FnOnce(&mut ClipboardItem),
+) -> Result<(), String> {
+    {
+        let mut storage = state.storage.lock().map_err(|e| e.to_string())?;
+        let Some(mut item) = storage.get_item(id).cloned() else {
+            return Ok(());
+        };
+        
+        apply(&mut item);
+        item.ai_analysis_count = Some(item.ai_analysis_count.unwrap_or(0) + 1);
+        storage.update_item(item)?;
+    }
+    
+    clipboard_monitor::emit_clipboard_change(app_handle, Some(id.to_string()), false, Vec::new());
+    Ok(())
+}
+
+// 翻译内容，结果保存到条目中
 #[tauri::command]
-async fn translate_content(_id: String, content: String) -> ClipboardOperationResult {
-    // 模拟实现
-    ClipboardOperationResult {
+async fn translate_content(
+    id: String,
+    content: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    translation_state: State<'_, TranslationState>,
+) -> Result<ClipboardOperationResult, String> {
+    let translation = match translate_with_configured_service(&content, &id, &state, &translation_state).await {
+        Ok(translation) => translation,
+        Err(e) => return Ok(ClipboardOperationResult {
+            success: false,
+            message: Some(format!("翻译失败: {}", e)),
+            data: None,
+        }),
+    };
+    
+    let saved = translation.clone();
+    save_ai_result(&id, &app_handle, &state, |item| item.translation = Some(saved))?;
+    
+    Ok(ClipboardOperationResult {
         success: true,
         message: Some("翻译成功".to_string()),
         data: Some(serde_json::json!({
-            "translation": format!("这是 {} 的翻译结果", content)
+            "translation": translation
         })),
-    }
+    })
 }
 
-// 总结内容
+// 总结内容，结果保存到条目中
 #[tauri::command]
-async fn summarize_content(_id: String, content: String) -> ClipboardOperationResult {
-    // 模拟实现
-    ClipboardOperationResult {
+async fn summarize_content(
+    id: String,
+    content: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ClipboardOperationResult, String> {
+    let summary = match resolve_selected_provider(&state) {
+        Ok(chain) => {
+            let source = UsageSource::for_operation(Some(UsageSource { item_id: Some(id.clone()), ..Default::default() }), "summarize_content");
+            ai::summarize_text(&state, chain, source, &content).await
+        },
+        Err(e) => Err(e),
+    };
+    let summary = match summary {
+        Ok(summary) => summary,
+        Err(e) => return Ok(ClipboardOperationResult {
+            success: false,
+            message: Some(format!("总结失败: {}", e)),
+            data: None,
+        }),
+    };
+    
+    let saved = summary.clone();
+    save_ai_result(&id, &app_handle, &state, |item| item.summary = Some(saved))?;
+    
+    Ok(ClipboardOperationResult {
         success: true,
         message: Some("总结成功".to_string()),
         data: Some(serde_json::json!({
-            "summary": format!("这是 {} 的总结内容", content)
+            "summary": summary
         })),
-    }
+    })
+}
+
+// AI分析的请求参数
+//
+// 发起的角色和剪贴板条目只通过 usage_source 传入，既用于渲染提示词也用于用量统计
+#[derive(Debug, serde::Deserialize)]
+struct AnalyzeRequest {
+    content: String,
+    system_prompt: Option<String>,
+    inputs: Option<HashMap<String, String>>,
+    provider_id: Option<String>,
+    api_key: Option<String>,
+    api_base_url: Option<String>,
+    model: Option<String>,
+    #[serde(default)]
+    usage_source: UsageSource,
 }
 
 // AI分析功能
+//
+// usage_source 带有 role_id 时使用角色的提示词模板，否则 system_prompt 作为模板，都在发送前按剪贴板内容渲染。
+// 未传入的提供商、模型和参数优先使用角色绑定的值，其次使用AI设置中的值
 #[tauri::command]
-async fn analyze_with_ai(
-    content: String, 
-    system_prompt: String, 
-    _provider_id: Option<String>, 
-    api_key: Option<String>, 
-    api_base_url: Option<String>, 
-    model: Option<String>
-) -> Result<String, String> {
+async fn analyze_with_ai(request: AnalyzeRequest, state: State<'_, AppState>) -> Result<String, String> {
     // println!("开始AI分析...");
     
-    // 如果未提供提供商参数，尝试从环境变量获取
-    let actual_api_key = match api_key {
-        Some(key) if !key.is_empty() => key,
-        _ => match std::env::var("OPENAI_API_KEY") {
-            Ok(key) => key,
-            Err(_) => return Err("未提供API密钥且环境变量OPENAI_API_KEY未设置".to_string()),
-        },
+    let AnalyzeRequest { content, system_prompt, inputs, provider_id, api_key, api_base_url, model, usage_source } = request;
+    let item_id = usage_source.item_id.clone();
+    let role = usage_source.role_id.as_deref().map(|id| get_role(id.to_string(), state.clone())).transpose()?;
+    let binding = role.as_ref().map(|role| role.binding.clone()).unwrap_or_default();
+    
+    // 使用角色绑定的提供商时，传入的地址和密钥属于其他提供商，改用该提供商的设置
+    let (provider_id, api_base_url, api_key) = match (provider_id, binding.provider_id) {
+        (None, Some(bound)) => (Some(bound), None, None),
+        (provider_id, _) => (provider_id, api_base_url, api_key),
+    };
+    let chain = {
+        let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+        provider::resolve_chain(&ai_settings_manager, provider_id.as_deref(), api_base_url, api_key)?
     };
     
-    let actual_api_base_url = match api_base_url {
-        Some(url) if !url.is_empty() => url,
-        _ => "https://api.openai.com/v1".to_string(),
+    let rendered = match &role {
+        Some(role) => prompt_template::render_role(&state, role, Some(content.clone()), item_id.as_deref(), &inputs.unwrap_or_default())?,
+        None => {
+            let template = system_prompt.ok_or("缺少系统提示词或角色")?;
+            prompt_template::render(&state, &template, &[], Some(content.clone()), item_id.as_deref(), &inputs.unwrap_or_default())?
+        },
     };
     
-    let actual_model = match model {
-        Some(m) if !m.is_empty() => m,
-        _ => "gpt-3.5-turbo".to_string(),
+    // 模板已经包含剪贴板内容时，渲染结果整体作为用户消息发送
+    let messages = if rendered.uses_clipboard {
+        vec![ChatCompletionMessage { role: "user".to_string(), content: rendered.prompt }]
+    } else {
+        vec![
+            ChatCompletionMessage { role: "system".to_string(), content: rendered.prompt },
+            ChatCompletionMessage { role: "user".to_string(), content },
+        ]
     };
+    let source = UsageSource::for_operation(Some(usage_source), "analyze_with_ai");
+    let model = model.or(binding.model);
+    let (json, _) = usage::complete(&state, chain, source, model, messages, binding.temperature, binding.max_tokens).await?;
     
-    // println!("使用参数: 基础URL: {}, 模型: {}", actual_api_base_url, actual_model);
+    // 从响应中提取内容
+    let result = json["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or("无法解析结果")
+        .to_string();
     
-    // 构建API请求
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/chat/completions", actual_api_base_url))
-        .header("Authorization", format!("Bearer {}", actual_api_key))
-        .header("Content-Type", "application/json")
-        .json(&serde_json::json!({
-            "model": actual_model,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": system_prompt
-                },
-                {
-                    "role": "user",
-                    "content": content
-                }
-            ],
-            "temperature": 0.7,
-            "max_tokens": 1000
-        }))
-        .send()
-        .await;
-    
-    match response {
-        Ok(res) => {
-            if res.status().is_success() {
-                match res.json::<serde_json::Value>().await {
-                    Ok(json) => {
-                        // 从响应中提取内容
-                        let result = json["choices"][0]["message"]["content"]
-                            .as_str()
-                            .unwrap_or("无法解析结果")
-                            .to_string();
-                        
-                        // println!("AI分析完成");
-                        Ok(result)
-                    }
-                    Err(e) => Err(format!("解析响应失败: {}", e)),
-                }
-            } else {
-                let status = res.status();
-                match res.text().await {
-                    Ok(error_text) => Err(format!("API请求失败 ({}): {}", status, error_text)),
-                    Err(_) => Err(format!("API请求失败1: 状态码 {}", status)),
-                }
-            }
-        }
-        Err(e) => Err(format!("请求失败: {}", e)),
-    }
+    // println!("AI分析完成");
+    Ok(result)
 }
 
 // 获取所有AI角色
@@ -723,8 +1187,17 @@ fn edit_clipboard_item(id: String, new_content: String, state: State<AppState>)
                 },
             };
             
+            // 图片条目没有可编辑的文本内容
+            if existing_item.content_type == ContentType::Image {
+                return ClipboardOperationResult {
+                    success: false,
+                    message: Some(String::from("图片条目不支持编辑")),
+                    data: None,
+                };
+            }
+            
             // 创建更新后的条目
-            let updated_item = ClipboardItem {
+            let mut updated_item = ClipboardItem {
                 id: existing_item.id, // 保持ID不变
                 content: new_content,
                 timestamp: existing_item.timestamp, // 保持时间戳不变
@@ -734,14 +1207,29 @@ fn edit_clipboard_item(id: String, new_content: String, state: State<AppState>)
                 translation: None, // 清除翻译，因为内容已更改
                 summary: None, // 清除摘要，因为内容已更改
                 ai_analysis_count: existing_item.ai_analysis_count, // 保持AI分析次数不变
+                // 内容已更改，原有的富文本格式不再对应，编辑后按纯文本保存
+                content_type: ContentType::Text,
+                image: None,
+                formats: ClipboardFormats::default(),
+                expires_at: existing_item.expires_at,
+                metadata: None,
+                sensitive: existing_item.sensitive,
             };
+            if let Err(rejected) = apply_clipboard_rules(&state, &mut updated_item) {
+                return rejected;
+            }
+            // 保留原有分类，重新识别编辑后内容的结构化信息
+            updated_item.classify();
             
-            // 更新条目
+            // 更新条目，内容改变后旧的向量已失效，唤醒后台任务重新计算
             match storage.update_item(updated_item) {
-                Ok(_) => ClipboardOperationResult {
-                    success: true,
-                    message: None,
-                    data: None,
+                Ok(_) => {
+                    state.embeddings.wake();
+                    ClipboardOperationResult {
+                        success: true,
+                        message: None,
+                        data: None,
+                    }
                 },
                 Err(e) => ClipboardOperationResult {
                     success: false,
@@ -767,32 +1255,13 @@ fn get_roles(state: State<AppState>) -> Result<Vec<Role>, String> {
 
 // 添加角色
 #[tauri::command]
-fn add_role(
-    name: String, 
-    description: String, 
-    systemPrompt: String, 
-    icon: String, 
-    isCustom: bool, 
-    avatar: Option<String>,
-    state: State<AppState>
-) -> RoleOperationResult {
-    // println!("添加角色: {}", name);
+fn add_role(role: NewRole, state: State<AppState>) -> RoleOperationResult {
+    // println!("添加角色: {}", role.name);
     
     let mut role_storage = state.role_storage.lock().map_err(|e| e.to_string()).unwrap();
     
     // 创建角色对象
-    let now = chrono::Utc::now().timestamp_millis() as u64;
-    let role = Role {
-        id: uuid::Uuid::new_v4().to_string(),
-        name,
-        description,
-        system_prompt: systemPrompt,
-        icon,
-        avatar,
-        is_custom: isCustom,
-        created_at: now,
-        updated_at: now,
-    };
+    let role = Role::from_new(role);
     
     match role_storage.add_role(role) {
         Ok(_) => RoleOperationResult {
@@ -866,6 +1335,119 @@ fn reset_role(id: String, state: State<AppState>) -> RoleOperationResult {
     }
 }
 
+// 通知所有窗口分类已变化，主窗口和悬浮窗会重新加载分类和剪贴板历史
+fn emit_categories_changed(app_handle: &AppHandle) {
+    if let Err(e) = app_handle.emit("categories-changed", ()) {
+        eprintln!("发送分类变化事件失败: {}", e);
+    }
+}
+
+// 获取所有分类
+#[tauri::command]
+fn get_categories(state: State<AppState>) -> Result<Vec<Category>, String> {
+    let category_storage = state.category_storage.lock().map_err(|e| e.to_string())?;
+    Ok(category_storage.get_all_categories())
+}
+
+// 添加分类
+#[tauri::command]
+fn add_category(
+    label: String,
+    value: String,
+    color: String,
+    app_handle: AppHandle,
+    state: State<AppState>,
+) -> Result<Category, String> {
+    let mut category_storage = state.category_storage.lock().map_err(|e| e.to_string())?;
+    let category = category_storage.add_category(label, value, color)?;
+    
+    emit_categories_changed(&app_handle);
+    Ok(category)
+}
+
+// 更新分类，标识改变时同步修改已有条目的分类
+#[tauri::command]
+fn update_category(
+    id: String,
+    label: String,
+    value: String,
+    color: String,
+    app_handle: AppHandle,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let mut category_storage = state.category_storage.lock().map_err(|e| e.to_string())?;
+    let renamed_from = category_storage.update_category(&id, label, value.clone(), color)?;
+    
+    if let Some(old_value) = renamed_from {
+        let mut storage = state.storage.lock().map_err(|e| e.to_string())?;
+        storage.retag_category(&old_value, Some(&value))?;
+    }
+    
+    emit_categories_changed(&app_handle);
+    Ok(())
+}
+
+// 删除分类，原分类下的条目改为 reassign_to 指定的分类，未指定时重新识别分类
+//
+// 先改写条目再删除分类，改写失败时分类保留，条目不会指向已删除的分类
+#[tauri::command]
+fn delete_category(
+    id: String,
+    reassign_to: Option<String>,
+    app_handle: AppHandle,
+    state: State<AppState>,
+) -> Result<usize, String> {
+    let mut category_storage = state.category_storage.lock().map_err(|e| e.to_string())?;
+    let value = category_storage.deletable_value(&id)?;
+    if let Some(target) = &reassign_to {
+        if *target == value {
+            return Err("不能将条目改为要删除的分类".to_string());
+        }
+        if !category_storage.contains_value(target) {
+            return Err(format!("目标分类不存在: {}", target));
+        }
+    }
+    
+    let count = {
+        let mut storage = state.storage.lock().map_err(|e| e.to_string())?;
+        storage.retag_category(&value, reassign_to.as_deref())?
+    };
+    category_storage.delete_category(&id)?;
+    
+    emit_categories_changed(&app_handle);
+    if count > 0 {
+        clipboard_monitor::emit_clipboard_change(&app_handle, None, false, Vec::new());
+    }
+    Ok(count)
+}
+
+// 调整分类的显示顺序
+#[tauri::command]
+fn reorder_categories(ids: Vec<String>, app_handle: AppHandle, state: State<AppState>) -> Result<(), String> {
+    let mut category_storage = state.category_storage.lock().map_err(|e| e.to_string())?;
+    category_storage.reorder_categories(&ids)?;
+    
+    emit_categories_changed(&app_handle);
+    Ok(())
+}
+
+// 重置为默认分类，被移除分类下的条目重新识别分类
+#[tauri::command]
+fn reset_categories(app_handle: AppHandle, state: State<AppState>) -> Result<(), String> {
+    let mut category_storage = state.category_storage.lock().map_err(|e| e.to_string())?;
+    let removed = category_storage.reset_categories()?;
+    
+    let mut storage = state.storage.lock().map_err(|e| e.to_string())?;
+    for value in removed {
+        storage.retag_category(&value, None)?;
+    }
+    drop(storage);
+    
+    emit_categories_changed(&app_handle);
+    clipboard_monitor::emit_clipboard_change(&app_handle, None, false, Vec::new());
+    Ok(())
+}
+
 // 获取AI设置
 #[tauri::command]
 fn get_ai_settings(state: State<AppState>) -> Result<AISettings, String> {
@@ -894,11 +1476,27 @@ fn update_provider_setting(provider_id: String, setting: AIProviderSetting, stat
     ai_settings_manager.update_provider_setting(&provider_id, setting)
 }
 
-// 获取模型列表
+// 获取模型列表，并记录接口返回的上下文长度
 #[tauri::command]
-async fn fetch_models(url: String, api_key: Option<String>, api_base_url: Option<String>) -> Result<Vec<ModelInfo>, String> {
-    // println!("尝试从 {} 获取模型列表", url);
+async fn fetch_models(url: String, api_key: Option<String>, api_base_url: Option<String>, state: State<'_, AppState>) -> Result<Vec<ModelInfo>, String> {
+    let models = request_models(url, api_key, api_base_url).await?;
     
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    if let Err(e) = ai_settings_manager.record_context_limits(&models) {
+        eprintln!("保存模型上下文长度失败: {}", e);
+    }
+    Ok(models)
+}
+
+async fn request_models(url: String, api_key: Option<String>, api_base_url: Option<String>) -> Result<Vec<ModelInfo>, String> {
+    // println!("尝试从 {} 获取模型列表", url);
+
+    // Azure OpenAI 返回资源下的部署列表，地址可以是资源地址或其下的任意接口地址
+    if url.contains(".openai.azure.com") {
+        let endpoint = url.split("/openai").next().unwrap_or(&url);
+        return fetch_azure_deployments(&AzureDeployment::from_endpoint(endpoint), api_key.unwrap_or_default()).await;
+    }
+
     let is_local_service = url.contains("localhost") || url.contains("127.0.0.1");
     let is_ollama = url.contains("open.bigmodel.cn/api/paas/v4");
     
@@ -962,12 +1560,17 @@ async fn fetch_models(url: String, api_key: Option<String>, api_base_url: Option
                 let models = data.iter()
                     .filter_map(|model| {
                         let id = model.get("id")?.as_str()?.to_string();
+                        // 部分服务（如 OpenRouter、Moonshot）会返回模型的上下文长度
+                        let max_tokens = ["context_length", "context_window"]
+                            .iter()
+                            .find_map(|key| model.get(*key).and_then(|t| t.as_u64()))
+                            .map(|t| t as u32);
                         
                         Some(ModelInfo {
                             id: id.clone(),
                             name: Some(id),
                             description: None,
-                            max_tokens: None,
+                            max_tokens,
                         })
                     })
                     .collect::<Vec<_>>();
@@ -1161,197 +1764,48 @@ fn parse_ollama_response(body: &str) -> Result<Vec<ModelInfo>, String> {
     }
 }
 
-// 测试API连接
+// 测试API连接：依次检查网络连接、密钥、模型和一次最小的补全请求，结果保存到提供商设置
 #[tauri::command]
-async fn test_api_connection(api_base_url: String, api_key: Option<String>, _model: String) -> Result<APITestResult, String> {
-    // println!("测试API连接: {}", api_base_url);
-    
-    let is_local_service = api_base_url.contains("localhost") || api_base_url.contains("127.0.0.1");
-    let is_ollama = api_base_url.contains("open.bigmodel.cn/api/paas/v4");
-    
-    // 构建客户端
-    let client = reqwest::Client::new();
-    
-    // 开始计时
-    let start_time = std::time::Instant::now();
-    
-    // 构建测试请求URL
-    let test_url = if is_ollama {
-        // Ollama特殊处理，确保使用正确的API端点
-        // 尝试/v1/models而不是/models
-        format!("{}/", api_base_url)
-    } else {
-        format!("{}/models", api_base_url)
+async fn test_api_connection(
+    provider_id: Option<String>,
+    api_base_url: Option<String>,
+    api_key: Option<String>,
+    model: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<APITestResult, String> {
+    let resolved = {
+        let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+        provider::resolve(&ai_settings_manager, provider_id.as_deref(), api_base_url, api_key)
     };
     
-    // println!("测试URL: {}", test_url);
-    
-    // 发送请求
-    let mut request = client.get(&test_url);
-    
-    // 只有非本地服务才强制验证API密钥
-    if !is_local_service {
-        let api_key = match &api_key {
-            Some(ref key) if !key.trim().is_empty() => key,
-            _ => return Err("API密钥不能为空".into()),
-        };
-        
-        request = request.header("Authorization", format!("Bearer {}", api_key));
-    } else if let Some(ref key) = api_key {
-        // 如果是本地服务且提供了API密钥，也添加
-        if !key.trim().is_empty() {
-            request = request.header("Authorization", format!("Bearer {}", key));
-        }
-    }
-    
-    // 添加通用头部
-    request = request.header("Content-Type", "application/json");
-    
-    let response = request.send().await;
-    
-    // 计算响应时间
-    let response_time = start_time.elapsed().as_millis() as u64;
-    
-    match response {
-        Ok(resp) => {
-            if resp.status().is_success() {
-                // 尝试读取响应内容，验证是否包含模型列表
-                match resp.text().await {
-                    Ok(text) => {
-                        // 简单检查是否包含模型列表相关关键字
-                        let contains_models = text.contains("models") || text.contains("data") || text.contains("id");
-                        
-                        if contains_models {
-                            Ok(APITestResult {
-                                success: true,
-                                message: format!("连接成功，已获取模型列表 ({}ms)", response_time),
-                                response_time: Some(response_time),
-                            })
-                        } else {
-                            Ok(APITestResult {
-                                success: true,
-                                message: format!("连接成功，但无法确认是否为有效的模型列表接口 ({}ms)", response_time),
-                                response_time: Some(response_time),
-                            })
-                        }
-                    }
-                    Err(_) => {
-                        Ok(APITestResult {
-                            success: true,
-                            message: format!("连接成功 ({}ms)", response_time),
-                            response_time: Some(response_time),
-                        })
-                    }
-                }
-            } else {
-                let status = resp.status();
-                let error_text = resp.text().await.unwrap_or_else(|_| "无法读取错误详情".to_string());
-                
-                // 如果是Ollama且报404，尝试回退到原始API端点
-                if is_ollama && status.as_u16() == 404 {
-                    // println!("Ollama 404错误，尝试备用端点...");
-                    return test_ollama_fallback(api_base_url, api_key, _model).await;
-                }
-                
-                Ok(APITestResult {
-                    success: false,
-                    message: format!("API请求失败: 状态码 {}, 错误信息: {}", status, error_text),
-                    response_time: Some(response_time),
-                })
-            }
-        }
+    let result = match resolved {
+        Ok(resolved) => {
+            let model = model.filter(|model| !model.trim().is_empty()).unwrap_or_else(|| resolved.model.clone());
+            let result = diagnostics::run(&resolved, &model).await;
+            
+            let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+            ai_settings_manager.record_test_result(&resolved.provider_id, result.success)?;
+            result
+        },
+        // 地址或密钥未配置时直接返回失败，同样记录为测试失败
         Err(e) => {
-            // 如果是Ollama且连接失败，尝试回退到原始API端点
-            if is_ollama {
-                // println!("Ollama连接失败，尝试备用端点...");
-                return test_ollama_fallback(api_base_url, api_key, _model).await;
-            }
+            let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+            let provider_id = match provider_id.filter(|id| !id.is_empty()) {
+                Some(id) => id,
+                None => ai_settings_manager.get_global_settings()?.selected_provider_id,
+            };
+            ai_settings_manager.record_test_result(&provider_id, false)?;
             
-            Ok(APITestResult {
+            APITestResult {
                 success: false,
-                message: format!("连接失败: {}", e),
-                response_time: Some(response_time),
-            })
-        }
-    }
-}
-
-// Ollama备用测试方法，尝试原始API端点
-async fn test_ollama_fallback(api_base_url: String, api_key: Option<String>, _model: String) -> Result<APITestResult, String> {
-    // println!("尝试Ollama备用API端点");
-    
-    // 构建备用URL，使用原始API端点
-    let base_url = api_base_url.replace("/v1", "/api");
-    let test_url = format!("{}/chat/completions", base_url);
-    
-    // println!("备用测试URL: {}", test_url);
-    
-    // 开始计时
-    let start_time = std::time::Instant::now();
-    
-    // 发送请求
-    let client = reqwest::Client::new();
-    let mut request = client.get(&test_url)
-        .header("Content-Type", "application/json");
-    
-    // 添加认证头（如果提供了API密钥）
-    if let Some(key) = &api_key {
-        if !key.trim().is_empty() {
-            // println!("添加备用端点授权头: Bearer {}", key.chars().take(5).collect::<String>() + "...");
-            request = request.header("Authorization", format!("Bearer {}", key));
-        }
-    }
-    
-    let response = request.send().await;
-    
-    // 计算响应时间
-    let response_time = start_time.elapsed().as_millis() as u64;
-    
-    match response {
-        Ok(resp) => {
-            if resp.status().is_success() {
-                match resp.text().await {
-                    Ok(text) => {
-                        if text.contains("models") || text.contains("name") {
-                            Ok(APITestResult {
-                                success: true,
-                                message: format!("连接成功 (使用备用API端点) ({}ms)", response_time),
-                                response_time: Some(response_time),
-                            })
-                        } else {
-                            Ok(APITestResult {
-                                success: true,
-                                message: format!("连接成功，但响应格式可能不正确 ({}ms)", response_time),
-                                response_time: Some(response_time),
-                            })
-                        }
-                    }
-                    Err(_) => {
-                        Ok(APITestResult {
-                            success: true,
-                            message: format!("连接成功 (使用备用API端点) ({}ms)", response_time),
-                            response_time: Some(response_time),
-                        })
-                    }
-                }
-            } else {
-                let status = resp.status();
-                let error_text = resp.text().await.unwrap_or_else(|_| "无法读取错误详情".to_string());
-                Ok(APITestResult {
-                    success: false,
-                    message: format!("API请求失败 (备用端点): 状态码 {}, 错误信息: {}", status, error_text),
-                    response_time: Some(response_time),
-                })
+                message: e,
+                response_time: None,
+                steps: Vec::new(),
             }
-        }
-        Err(e) => {
-            Ok(APITestResult {
-                success: false,
-                message: format!("连接备用端点失败: {}。请检查Ollama是否正在运行", e),
-                response_time: Some(response_time),
-            })
-        }
-    }
+        },
+    };
+    
+    Ok(result)
 }
 
 #[tauri::command]
@@ -1361,51 +1815,195 @@ async fn chat_with_ai(
     temperature: f32,
     max_tokens: u32,
     stream: bool,
-    _provider_id: String,
+    provider_id: String,
     api_key: String,
-    api_base_url: String
+    api_base_url: String,
+    state: State<'_, AppState>,
 ) -> Result<serde_json::Value, String> {
     // println!("开始发送聊天请求...");
     // println!("提供商: {}, 模型: {}, 流式输出: {}", provider_id, model, stream);
     
     // 构建请求体
-    let request_body = serde_json::json!({
-        "model": model,
-        "messages": messages,
-        "temperature": temperature,
-        "max_tokens": max_tokens,
-        "stream": stream
-    });
+    let messages = messages
+        .into_iter()
+        .map(serde_json::from_value::<ChatCompletionMessage>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("消息格式无效: {}", e))?;
+    let chain = {
+        let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+        provider::resolve_chain(&ai_settings_manager, Some(&provider_id), Some(api_base_url), Some(api_key))?
+    };
     
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/chat/completions", api_base_url))
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&request_body)
-        .send()
-        .await;
-    
-    match response {
-        Ok(res) => {
-            if res.status().is_success() {
-                match res.json::<serde_json::Value>().await {
-                    Ok(json) => {
-                        // println!("AI响应成功");
-                        Ok(json)
-                    }
-                    Err(e) => Err(format!("解析响应失败: {}", e)),
-                }
-            } else {
-                let status = res.status();
-                match res.text().await {
-                    Ok(error_text) => Err(format!("API请求失败 ({}): {}", status, error_text)),
-                    Err(_) => Err(format!("API请求失败: 状态码 {}", status)),
-                }
-            }
-        }
-        Err(e) => Err(format!("请求失败: {}", e)),
+    // 按提供商的接口格式发送，响应统一为 OpenAI 格式
+    if stream {
+        // 该命令一次返回完整结果，流式输出请使用 stream_chat
+        eprintln!("chat_with_ai 不支持流式输出，已按非流式请求处理");
     }
+    let source = UsageSource::for_operation(None, "chat_with_ai");
+    let (mut response, answered_by) = usage::complete(&state, chain, source, Some(model), messages, Some(temperature), Some(max_tokens as i32)).await?;
+    response["provider_id"] = serde_json::json!(answered_by);
+    Ok(response)
+}
+
+// 获取用量统计设置（价格表与预算）
+#[tauri::command]
+fn get_usage_settings(state: State<AppState>) -> Result<UsageSettings, String> {
+    state.usage.get_settings()
+}
+
+// 更新用量统计设置
+#[tauri::command]
+fn update_usage_settings(settings: UsageSettings, state: State<AppState>) -> Result<(), String> {
+    state.usage.update_settings(settings)
+}
+
+// 按天、提供商、模型或角色汇总 [from, to) 时间范围内的用量
+#[tauri::command]
+fn get_usage_summary(group_by: UsageGroup, from: u64, to: u64, state: State<AppState>) -> Result<Vec<UsageSummary>, String> {
+    state.usage.summary(group_by, from, to)
+}
+
+// 获取本月费用与预算
+#[tauri::command]
+fn get_budget_status(state: State<AppState>) -> Result<BudgetStatus, String> {
+    state.usage.budget_status()
+}
+
+// 分页获取聊天会话，按更新时间从新到旧排列，不包含消息
+#[tauri::command]
+fn list_chat_sessions(offset: Option<usize>, limit: Option<usize>, state: State<AppState>) -> Result<ChatSessionPage, String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.list_sessions(offset.unwrap_or(0), limit.unwrap_or(50))
+}
+
+// 获取聊天会话及其消息
+#[tauri::command]
+fn get_chat_session(id: String, state: State<AppState>) -> Result<Option<ChatSession>, String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.get_session(&id)
+}
+
+// 创建聊天会话
+#[tauri::command]
+fn create_chat_session(session: ChatSession, state: State<AppState>) -> Result<ChatSession, String> {
+    let mut chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.create_session(session)
+}
+
+// 修改聊天会话的标题、提供商或提示词
+#[tauri::command]
+fn update_chat_session(id: String, update: ChatSessionUpdate, state: State<AppState>) -> Result<(), String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.update_session(&id, update)
+}
+
+// 删除聊天会话及其消息
+#[tauri::command]
+fn delete_chat_session(id: String, state: State<AppState>) -> Result<bool, String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.delete_session(&id)
+}
+
+// 清空聊天会话中的消息
+#[tauri::command]
+fn clear_chat_session(id: String, state: State<AppState>) -> Result<(), String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.clear_session(&id)
+}
+
+// 向聊天会话添加消息
+#[tauri::command]
+fn add_chat_message(session_id: String, message: ChatMessage, state: State<AppState>) -> Result<(), String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.add_message(&session_id, &message)
+}
+
+// 修改消息内容，回复结束后保存完整内容和回答的提供商
+#[tauri::command]
+fn update_chat_message(
+    session_id: String,
+    message_id: String,
+    content: String,
+    provider_id: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.update_message(&session_id, &message_id, &content, provider_id.as_deref())
+}
+
+// 删除聊天消息
+#[tauri::command]
+fn delete_chat_message(session_id: String, message_id: String, state: State<AppState>) -> Result<(), String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.delete_message(&session_id, &message_id)
+}
+
+// 在所有聊天会话的消息中搜索
+#[tauri::command]
+fn search_chat_messages(query: String, limit: Option<usize>, state: State<AppState>) -> Result<Vec<ChatSearchHit>, String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.search_messages(&query, limit.unwrap_or(50))
+}
+
+// 导出聊天会话为 JSON 或 Markdown
+#[tauri::command]
+fn export_chat_session(id: String, format: Option<ChatExportFormat>, state: State<AppState>) -> Result<String, String> {
+    let chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.export_session(&id, format.unwrap_or_default())
+}
+
+// 导入之前导出的 JSON 会话，ID 冲突时使用新的 ID
+#[tauri::command]
+fn import_chat_session(data: String, state: State<AppState>) -> Result<ChatSession, String> {
+    let session: ChatSession = serde_json::from_str(&data).map_err(|e| format!("解析会话失败: {}", e))?;
+    let mut chat_storage = state.chat_storage.lock().map_err(|e| e.to_string())?;
+    chat_storage.create_session(session)
+}
+
+// 设置请求失败时依次尝试的备用提供商
+#[tauri::command]
+fn update_fallback_providers(provider_ids: Vec<String>, state: State<AppState>) -> Result<(), String> {
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    ai_settings_manager.set_fallback_providers(provider_ids)
+}
+
+// 设置限流或服务暂时不可用时的重试方式
+#[tauri::command]
+fn update_retry_policy(retry: RetryPolicy, state: State<AppState>) -> Result<(), String> {
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    ai_settings_manager.set_retry_policy(retry)
+}
+
+// 估算聊天请求的 token 数，不发起网络请求
+//
+// 与 chat_completion 一致，未指定的提供商、模型和参数使用角色绑定的值
+#[tauri::command]
+fn estimate_chat_tokens(
+    messages: Vec<ChatCompletionMessage>,
+    model: Option<String>,
+    max_tokens: Option<i32>,
+    provider_id: Option<String>,
+    role_id: Option<String>,
+    state: State<AppState>,
+) -> Result<TokenReport, String> {
+    let binding = ai::role_binding(&state, role_id.as_deref())?;
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    let resolved = provider::resolve(&ai_settings_manager, provider_id.or(binding.provider_id).as_deref(), None, None)?;
+    resolved.estimate(model.or(binding.model), messages, max_tokens.or(binding.max_tokens))
+}
+
+// 设置请求超出上下文长度时的处理方式
+#[tauri::command]
+fn update_context_strategy(strategy: ContextStrategy, state: State<AppState>) -> Result<(), String> {
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    ai_settings_manager.set_context_strategy(strategy)
+}
+
+// 设置基于剪贴板回答时是否允许发送归为密码的条目
+#[tauri::command]
+fn update_share_passwords_in_context(enabled: bool, state: State<AppState>) -> Result<(), String> {
+    let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
+    ai_settings_manager.set_share_passwords_in_context(enabled)
 }
 
 #[tauri::command]
@@ -1414,6 +2012,65 @@ fn get_role(id: String, state: State<AppState>) -> Result<Role, String> {
     role_storage.get_role(&id).ok_or_else(|| format!("未找到角色: {}", id)).cloned()
 }
 
+// 按剪贴板内容和自定义输入渲染角色的提示词模板
+#[tauri::command]
+fn render_role_prompt(
+    role_id: String,
+    clipboard: Option<String>,
+    item_id: Option<String>,
+    inputs: Option<HashMap<String, String>>,
+    state: State<AppState>,
+) -> Result<RenderedPrompt, String> {
+    let role = get_role(role_id, state.clone())?;
+    prompt_template::render_role(&state, &role, clipboard, item_id.as_deref(), &inputs.unwrap_or_default())
+}
+
+// 检查提示词模板和自定义输入，保存角色前由前端调用以提示错误
+#[tauri::command]
+fn validate_prompt_template(template: String, inputs: Option<Vec<PromptInput>>) -> Result<(), String> {
+    prompt_template::validate(&template, &inputs.unwrap_or_default())
+}
+
+// 获取Azure OpenAI资源下的部署列表
+async fn fetch_azure_deployments(azure: &AzureDeployment, api_key: String) -> Result<Vec<ModelInfo>, String> {
+    if api_key.trim().is_empty() {
+        return Err("API密钥不能为空".into());
+    }
+    
+    let response = reqwest::Client::new()
+        .get(azure.deployments_url())
+        .header("api-key", api_key)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+    
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_else(|_| "无法读取响应内容".to_string());
+        return Err(format!("获取Azure部署列表失败: 状态码 {}, 错误信息: {}", status, body));
+    }
+    
+    let json = response.json::<serde_json::Value>().await
+        .map_err(|e| format!("解析响应失败: {}", e))?;
+    
+    let deployments = json["data"]
+        .as_array()
+        .ok_or_else(|| "无法解析Azure部署列表".to_string())?
+        .iter()
+        .filter_map(|deployment| {
+            let id = deployment["id"].as_str()?;
+            Some(ModelInfo {
+                id: id.to_string(),
+                name: deployment["model"].as_str().map(|model| format!("{} ({})", id, model)),
+                description: None,
+                max_tokens: None,
+            })
+        })
+        .collect();
+    
+    Ok(deployments)
+}
+
 #[tauri::command]
 async fn get_ai_models(provider_id: String, state: State<'_, AppState>) -> Result<Vec<ModelInfo>, String> {
     // println!("获取AI模型列表: {}", provider_id);
@@ -1423,6 +2080,17 @@ async fn get_ai_models(provider_id: String, state: State<'_, AppState>) -> Resul
         let ai_settings_manager = state.ai_settings_manager.lock().map_err(|e| e.to_string())?;
         ai_settings_manager.get_provider_setting(&provider_id)?
     };
+    if provider_setting.api_key.as_deref().is_some_and(secrets::is_locked_marker) {
+        return Err(format!("密钥存储未解锁，无法读取提供商 '{}' 的API密钥", provider_id));
+    }
+    
+    // Azure 返回资源下的部署列表，部署名称即请求时使用的模型
+    if let Some(azure) = provider_setting.azure.clone().or_else(|| {
+        let base_url = provider_setting.custom_api_base_url.as_deref().unwrap_or_default();
+        (AIProvider::detect(&provider_id, base_url) == AIProvider::Azure).then(|| AzureDeployment::from_endpoint(base_url))
+    }) {
+        return fetch_azure_deployments(&azure, provider_setting.api_key.unwrap_or_default()).await;
+    }
     
     let api_key = provider_setting.api_key;
     let api_base_url = provider_setting.custom_api_base_url.unwrap_or_else(|| {
@@ -1442,18 +2110,18 @@ async fn get_ai_models(provider_id: String, state: State<'_, AppState>) -> Resul
     } else if provider_id == "OpenAI" {
         // 标准OpenAI API
         let url = format!("{}/models", api_base_url);
-        return fetch_models(url, api_key, Some(api_base_url)).await;
+        return request_models(url, api_key, Some(api_base_url)).await;
     } else if provider_id == "ZhipuAI" {
         // 智普AI特殊处理
         return fetch_ollama_fallback(api_base_url, api_key).await;
     } else if provider_id == "Copy2AI" {
         // 兼容性处理，自建服务
         let url = format!("{}/models", api_base_url);
-        return fetch_models(url, api_key, Some(api_base_url)).await;
+        return request_models(url, api_key, Some(api_base_url)).await;
     } else {
         // 通用处理
         let url = format!("{}/models", api_base_url);
-        return fetch_models(url, api_key, Some(api_base_url)).await;
+        return request_models(url, api_key, Some(api_base_url)).await;
     }
 }
 
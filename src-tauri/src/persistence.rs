@@ -0,0 +1,155 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// 保留的备份代数
+const BACKUP_GENERATIONS: usize = 3;
+
+/// 在文件名后追加后缀，例如 `roles.json` -> `roles.json.bak.1`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// 第 N 代备份文件路径（1 为最新）
+fn backup_path(path: &Path, generation: usize) -> PathBuf {
+    with_suffix(path, &format!(".bak.{}", generation))
+}
+
+/// 将目录项的变更刷到磁盘，保证重命名在崩溃后依然有效
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// 轮转备份：.bak.N-1 -> .bak.N，当前文件复制为 .bak.1
+fn rotate_backups(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for generation in (1..BACKUP_GENERATIONS).rev() {
+        let from = backup_path(path, generation);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, generation + 1))
+                .map_err(|e| format!("轮转备份文件失败: {:?} - {}", from, e))?;
+        }
+    }
+
+    // 使用复制而不是重命名，保证正式文件在任何时刻都存在
+    fs::copy(path, backup_path(path, 1))
+        .map_err(|e| format!("创建备份文件失败: {:?} - {}", path, e))?;
+
+    Ok(())
+}
+
+/// 原子写入文件
+///
+/// 先写入同目录下的临时文件并 fsync，再轮转备份，最后重命名覆盖正式文件。
+/// 写入过程中崩溃只会留下临时文件，不会破坏原有数据。
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    // 确保目录存在
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("创建目录失败: {:?} - {}", parent, e))?;
+    }
+
+    // 写入临时文件
+    let tmp_path = with_suffix(path, ".tmp");
+    {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)
+            .map_err(|e| format!("创建临时文件失败: {:?} - {}", tmp_path, e))?;
+        file.write_all(contents)
+            .map_err(|e| format!("写入临时文件失败: {:?} - {}", tmp_path, e))?;
+        file.sync_all()
+            .map_err(|e| format!("同步临时文件失败: {:?} - {}", tmp_path, e))?;
+    }
+
+    rotate_backups(path)?;
+
+    // 重命名为正式文件
+    fs::rename(&tmp_path, path)
+        .map_err(|e| format!("替换文件失败: {:?} - {}", path, e))?;
+    sync_parent_dir(path);
+
+    Ok(())
+}
+
+/// 序列化为格式化的JSON并原子写入
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("序列化数据失败: {}", e))?;
+    write_atomic(path, json.as_bytes())
+}
+
+/// 读取并解析JSON文件，空文件视为无数据
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let mut file = File::open(path).map_err(|e| format!("打开文件失败: {:?} - {}", path, e))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| format!("读取文件失败: {:?} - {}", path, e))?;
+
+    if contents.trim().is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|e| format!("解析JSON失败: {:?} - {}", path, e))
+}
+
+/// 加载JSON文件，损坏时从最新的有效备份恢复
+///
+/// - 文件不存在时返回 `Ok(None)`
+/// - 文件为空或无法解析时依次尝试 `.bak.1`、`.bak.2` …，
+///   恢复成功后将损坏的文件另存为 `.corrupt` 并写回恢复的数据
+/// - 没有可用备份时返回原始错误（空文件则返回 `Ok(None)`）
+pub fn load_json<T: Serialize + DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let primary = match read_json::<T>(path) {
+        Ok(Some(value)) => return Ok(Some(value)),
+        result => result,
+    };
+
+    // 正式文件为空或已损坏，尝试从备份恢复
+    for generation in 1..=BACKUP_GENERATIONS {
+        let backup = backup_path(path, generation);
+        if !backup.exists() {
+            continue;
+        }
+
+        match read_json::<T>(&backup) {
+            Ok(Some(value)) => {
+                eprintln!("已从备份恢复数据: {:?}", backup);
+
+                // 保留损坏的文件以便排查
+                let _ = fs::rename(path, with_suffix(path, ".corrupt"));
+                if let Err(e) = save_json(path, &value) {
+                    eprintln!("写回恢复的数据失败: {}", e);
+                }
+
+                return Ok(Some(value));
+            },
+            Ok(None) => continue,
+            Err(e) => eprintln!("备份文件不可用: {}", e),
+        }
+    }
+
+    primary
+}
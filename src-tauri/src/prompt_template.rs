@@ -0,0 +1,552 @@
+use std::collections::{BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::classifier;
+use crate::clipboard::{ClipboardItem, ContentType};
+use crate::role::Role;
+
+type AppState = crate::AppState;
+
+/// 模板中可以直接使用的内置变量
+pub const BUILTIN_VARIABLES: &[&str] = &[
+    "clipboard",
+    "selection_language",
+    "date",
+    "time",
+    "previous_clip",
+    "category",
+];
+
+/// 角色提示词中用户自定义的输入，在模板中按名称引用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptInput {
+    pub name: String,
+    /// 填写时显示的名称，为空时显示 name
+    #[serde(default)]
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// 渲染后的提示词
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedPrompt {
+    pub prompt: String,
+    /// 模板引用了剪贴板内容，调用方不需要再单独发送剪贴板内容
+    pub uses_clipboard: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    /// 按字符数截断，超出部分用省略号代替
+    Truncate(usize),
+    /// 变量为空时使用的值
+    Default(String),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Variable { name: String, filters: Vec<Filter> },
+    /// {{#if name}} 或 {{#unless name}}（negate 为 true）
+    Condition { name: String, negate: bool, then: Vec<Node>, otherwise: Vec<Node> },
+}
+
+/// 解析后的提示词模板
+///
+/// 支持 `{{name}}`、过滤器 `{{name | truncate:200 | upper}}`，
+/// 以及条件 `{{#if name}}...{{else}}...{{/if}}` 和 `{{#unless name}}...{{/unless}}`。
+/// 需要原样输出 `{{` 时写作 `{{{{`，整段原样输出（如代码示例）时放在 `{{#raw}}...{{/raw}}` 中。
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+// 解析中的条件块
+struct Block {
+    keyword: &'static str,
+    name: String,
+    line: usize,
+    nodes: Vec<Node>,
+    then: Option<Vec<Node>>,
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 按不在引号内的 | 拆分表达式
+fn split_pipes(expression: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (index, c) in expression.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '|' if !in_quotes => {
+                parts.push(expression[start..index].trim());
+                start = index + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(expression[start..].trim());
+    parts
+}
+
+fn parse_filter(text: &str, line: usize) -> Result<Filter, String> {
+    let (name, argument) = match text.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument.trim())),
+        None => (text, None),
+    };
+
+    match (name, argument) {
+        ("upper", None) => Ok(Filter::Upper),
+        ("lower", None) => Ok(Filter::Lower),
+        ("trim", None) => Ok(Filter::Trim),
+        ("truncate", Some(argument)) => argument
+            .parse()
+            .map(Filter::Truncate)
+            .map_err(|_| format!("第 {} 行：truncate 的参数应为字符数，例如 truncate:200", line)),
+        ("default", Some(argument)) => {
+            let value = argument
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(argument);
+            Ok(Filter::Default(value.to_string()))
+        },
+        ("truncate" | "default", None) => Err(format!("第 {} 行：过滤器 {} 缺少参数", line, name)),
+        ("upper" | "lower" | "trim", Some(_)) => Err(format!("第 {} 行：过滤器 {} 不需要参数", line, name)),
+        _ => Err(format!("第 {} 行：不支持的过滤器 {}", line, name)),
+    }
+}
+
+fn parse_variable(expression: &str, line: usize) -> Result<Node, String> {
+    let mut parts = split_pipes(expression).into_iter();
+    let name = parts.next().unwrap_or_default();
+    if !is_identifier(name) {
+        return Err(format!("第 {} 行：无效的变量名 \"{}\"", line, name));
+    }
+    let filters = parts
+        .map(|filter| parse_filter(filter, line))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Node::Variable { name: name.to_string(), filters })
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut root: Vec<Node> = Vec::new();
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut rest = source;
+        let mut offset = 0;
+
+        loop {
+            let text_end = rest.find("{{").unwrap_or(rest.len());
+            if text_end > 0 {
+                let text = Node::Text(rest[..text_end].to_string());
+                blocks.last_mut().map_or(&mut root, |block| &mut block.nodes).push(text);
+            }
+            if text_end == rest.len() {
+                break;
+            }
+
+            let line = source[..offset + text_end].matches('\n').count() + 1;
+
+            // {{{{ 输出字面的 {{
+            if rest[text_end..].starts_with("{{{{") {
+                let text = Node::Text("{{".to_string());
+                blocks.last_mut().map_or(&mut root, |block| &mut block.nodes).push(text);
+                rest = &rest[text_end + 4..];
+                offset += text_end + 4;
+                continue;
+            }
+
+            let tag_start = text_end + 2;
+            let tag_len = rest[tag_start..]
+                .find("}}")
+                .ok_or_else(|| format!("第 {} 行：标签缺少结尾的 }}}}", line))?;
+            let tag = rest[tag_start..tag_start + tag_len].trim();
+            let consumed = tag_start + tag_len + 2;
+            rest = &rest[consumed..];
+            offset += consumed;
+
+            if tag == "#raw" {
+                // 原样输出到 {{/raw}} 为止的内容
+                let raw_len = rest
+                    .find("{{/raw}}")
+                    .ok_or_else(|| format!("第 {} 行的 #raw 缺少 {{{{/raw}}}}", line))?;
+                let text = Node::Text(rest[..raw_len].to_string());
+                blocks.last_mut().map_or(&mut root, |block| &mut block.nodes).push(text);
+                rest = &rest[raw_len + "{{/raw}}".len()..];
+                offset += raw_len + "{{/raw}}".len();
+            } else if let Some(opening) = tag.strip_prefix('#') {
+                let (keyword, name) = opening.split_once(char::is_whitespace).unwrap_or((opening, ""));
+                let keyword = match keyword {
+                    "if" => "if",
+                    "unless" => "unless",
+                    _ => return Err(format!("第 {} 行：不支持的标签 #{}", line, keyword)),
+                };
+                let name = name.trim();
+                if !is_identifier(name) {
+                    return Err(format!("第 {} 行：#{} 后应为变量名", line, keyword));
+                }
+                blocks.push(Block { keyword, name: name.to_string(), line, nodes: Vec::new(), then: None });
+            } else if tag == "else" {
+                let block = blocks.last_mut().ok_or_else(|| format!("第 {} 行：else 没有对应的 #if", line))?;
+                if block.then.is_some() {
+                    return Err(format!("第 {} 行：#{} 中有多个 else", line, block.keyword));
+                }
+                block.then = Some(std::mem::take(&mut block.nodes));
+            } else if let Some(closing) = tag.strip_prefix('/') {
+                let block = blocks.pop().ok_or_else(|| format!("第 {} 行：{{{{/{}}}}} 没有对应的开始标签", line, closing))?;
+                if closing.trim() != block.keyword {
+                    return Err(format!("第 {} 行：应为 {{{{/{}}}}}，却是 {{{{/{}}}}}", line, block.keyword, closing.trim()));
+                }
+                let (then, otherwise) = match block.then {
+                    Some(then) => (then, block.nodes),
+                    None => (block.nodes, Vec::new()),
+                };
+                let condition = Node::Condition { name: block.name, negate: block.keyword == "unless", then, otherwise };
+                blocks.last_mut().map_or(&mut root, |block| &mut block.nodes).push(condition);
+            } else {
+                let variable = parse_variable(tag, line)?;
+                blocks.last_mut().map_or(&mut root, |block| &mut block.nodes).push(variable);
+            }
+        }
+
+        if let Some(block) = blocks.pop() {
+            return Err(format!("第 {} 行的 #{} 缺少 {{{{/{}}}}}", block.line, block.keyword, block.keyword));
+        }
+        Ok(Self { nodes: root })
+    }
+
+    /// 模板引用的全部变量
+    pub fn variables(&self) -> BTreeSet<&str> {
+        fn collect<'a>(nodes: &'a [Node], names: &mut BTreeSet<&'a str>) {
+            for node in nodes {
+                match node {
+                    Node::Text(_) => {},
+                    Node::Variable { name, .. } => {
+                        names.insert(name);
+                    },
+                    Node::Condition { name, then, otherwise, .. } => {
+                        names.insert(name);
+                        collect(then, names);
+                        collect(otherwise, names);
+                    },
+                }
+            }
+        }
+
+        let mut names = BTreeSet::new();
+        collect(&self.nodes, &mut names);
+        names
+    }
+
+    /// 渲染模板，未提供的变量视为空字符串
+    pub fn render(&self, variables: &HashMap<String, String>) -> String {
+        let mut output = String::new();
+        render_nodes(&self.nodes, variables, &mut output);
+        output
+    }
+}
+
+fn render_nodes(nodes: &[Node], variables: &HashMap<String, String>, output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Variable { name, filters } => {
+                let value = variables.get(name).cloned().unwrap_or_default();
+                output.push_str(&filters.iter().fold(value, apply_filter));
+            },
+            Node::Condition { name, negate, then, otherwise } => {
+                let present = variables.get(name).is_some_and(|value| !value.trim().is_empty());
+                let branch = if present != *negate { then } else { otherwise };
+                render_nodes(branch, variables, output);
+            },
+        }
+    }
+}
+
+fn apply_filter(value: String, filter: &Filter) -> String {
+    match filter {
+        Filter::Upper => value.to_uppercase(),
+        Filter::Lower => value.to_lowercase(),
+        Filter::Trim => value.trim().to_string(),
+        Filter::Truncate(max_chars) => {
+            if value.chars().count() <= *max_chars {
+                value
+            } else {
+                let mut truncated: String = value.chars().take(*max_chars).collect();
+                truncated.push('…');
+                truncated
+            }
+        },
+        Filter::Default(default) => {
+            if value.trim().is_empty() {
+                default.clone()
+            } else {
+                value
+            }
+        },
+    }
+}
+
+/// 保存角色前检查提示词模板和自定义输入
+pub fn validate(template: &str, inputs: &[PromptInput]) -> Result<(), String> {
+    let mut input_names = BTreeSet::new();
+    for input in inputs {
+        if !is_identifier(&input.name) {
+            return Err(format!("输入名称 \"{}\" 只能包含字母、数字和下划线，且不能以数字开头", input.name));
+        }
+        if BUILTIN_VARIABLES.contains(&input.name.as_str()) {
+            return Err(format!("输入名称 \"{}\" 与内置变量重复", input.name));
+        }
+        if !input_names.insert(input.name.as_str()) {
+            return Err(format!("输入名称 \"{}\" 重复", input.name));
+        }
+    }
+
+    let template = Template::parse(template).map_err(|e| format!("提示词模板有误，{}", e))?;
+    let unknown: Vec<&str> = template
+        .variables()
+        .into_iter()
+        .filter(|name| !BUILTIN_VARIABLES.contains(name) && !input_names.contains(name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!("提示词模板引用了未定义的变量: {}", unknown.join(", ")));
+    }
+    Ok(())
+}
+
+/// 按文字的书写系统猜测自然语言
+fn natural_language(text: &str) -> Option<&'static str> {
+    let mut counts = [0usize; 7];
+    for c in text.chars() {
+        let index = match c {
+            '\u{3040}'..='\u{30ff}' => 0,
+            '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' => 1,
+            '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' => 2,
+            '\u{0400}'..='\u{04ff}' => 3,
+            '\u{0600}'..='\u{06ff}' => 4,
+            '\u{0e00}'..='\u{0e7f}' => 5,
+            c if c.is_ascii_alphabetic() => 6,
+            _ => continue,
+        };
+        counts[index] += 1;
+    }
+
+    // 日文中夹杂汉字，出现假名即视为日语
+    if counts[0] > 0 {
+        return Some("日语");
+    }
+    let names = ["日语", "韩语", "中文", "俄语", "阿拉伯语", "泰语", "英语"];
+    let (index, count) = counts.iter().enumerate().skip(1).max_by_key(|(_, count)| **count)?;
+    (*count > 0).then_some(names[index])
+}
+
+/// 计算模板用到的内置变量
+///
+/// 传入 item_id 时按该剪贴板条目计算分类、语言和上一条内容，否则按 clipboard 内容识别。
+fn builtin_variables(
+    state: &AppState,
+    names: &BTreeSet<&str>,
+    clipboard: Option<String>,
+    item_id: Option<&str>,
+) -> Result<HashMap<String, String>, String> {
+    let mut variables = HashMap::new();
+    let now = chrono::Local::now();
+    variables.insert("date".to_string(), now.format("%Y-%m-%d").to_string());
+    variables.insert("time".to_string(), now.format("%H:%M").to_string());
+
+    let (item, previous) = {
+        let storage = state.storage.lock().map_err(|e| e.to_string())?;
+        let item = item_id.and_then(|id| storage.get_item(id)).cloned();
+        let previous = if names.contains("previous_clip") {
+            // 当前条目之前最近的一条文本记录
+            let before = item.as_ref().map_or(u64::MAX, |item| item.timestamp);
+            let current = item.as_ref().map(|item| item.content.as_str()).or(clipboard.as_deref());
+            storage
+                .get_all_items()
+                .into_iter()
+                .filter(|other: &ClipboardItem| {
+                    other.content_type == ContentType::Text
+                        && other.timestamp < before
+                        && Some(other.content.as_str()) != current
+                })
+                .max_by_key(|other| other.timestamp)
+                .map(|other| other.content)
+        } else {
+            None
+        };
+        (item, previous)
+    };
+
+    let clipboard = clipboard.or_else(|| item.as_ref().map(|item| item.content.clone())).unwrap_or_default();
+    let classification = classifier::classify(&clipboard);
+
+    if names.contains("category") {
+        let value = item
+            .as_ref()
+            .and_then(|item| item.category.clone())
+            .unwrap_or_else(|| classification.category.to_string());
+        let category_storage = state.category_storage.lock().map_err(|e| e.to_string())?;
+        let label = category_storage
+            .get_all_categories()
+            .into_iter()
+            .find(|category| category.value == value)
+            .map_or(value, |category| category.label);
+        variables.insert("category".to_string(), label);
+    }
+
+    if names.contains("selection_language") {
+        // 代码和结构化数据使用识别出的格式，其余按书写系统猜测
+        let language = item
+            .as_ref()
+            .and_then(|item| item.metadata.as_ref())
+            .and_then(|metadata| metadata.language.clone())
+            .or(classification.metadata.language)
+            .or_else(|| natural_language(&clipboard).map(String::from))
+            .unwrap_or_default();
+        variables.insert("selection_language".to_string(), language);
+    }
+
+    variables.insert("previous_clip".to_string(), previous.unwrap_or_default());
+    variables.insert("clipboard".to_string(), clipboard);
+    Ok(variables)
+}
+
+/// 渲染提示词模板
+///
+/// 自定义输入未传入时使用默认值，必填输入缺失时报错。模板无法解析时报错，
+/// 早于模板功能保存的提示词中的 `{{` 需要改写为 `{{{{` 或放入 `{{#raw}}` 中。
+pub fn render(
+    state: &AppState,
+    template: &str,
+    declared_inputs: &[PromptInput],
+    clipboard: Option<String>,
+    item_id: Option<&str>,
+    inputs: &HashMap<String, String>,
+) -> Result<RenderedPrompt, String> {
+    let parsed = Template::parse(template).map_err(|e| format!("提示词模板有误，{}", e))?;
+    let names = parsed.variables();
+
+    let mut variables = builtin_variables(state, &names, clipboard, item_id)?;
+    for input in declared_inputs {
+        let value = inputs
+            .get(&input.name)
+            .filter(|value| !value.trim().is_empty())
+            .or(input.default.as_ref())
+            .cloned()
+            .unwrap_or_default();
+        if input.required && value.trim().is_empty() {
+            let label = if input.label.is_empty() { &input.name } else { &input.label };
+            return Err(format!("请填写 {}", label));
+        }
+        variables.insert(input.name.clone(), value);
+    }
+
+    Ok(RenderedPrompt {
+        prompt: parsed.render(&variables),
+        uses_clipboard: names.contains("clipboard"),
+    })
+}
+
+/// 按角色的提示词模板渲染
+pub fn render_role(
+    state: &AppState,
+    role: &Role,
+    clipboard: Option<String>,
+    item_id: Option<&str>,
+    inputs: &HashMap<String, String>,
+) -> Result<RenderedPrompt, String> {
+    render(state, &role.system_prompt, &role.inputs, clipboard, item_id, inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_with(source: &str, pairs: &[(&str, &str)]) -> String {
+        let variables = pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        Template::parse(source).expect("模板应能解析").render(&variables)
+    }
+
+    #[test]
+    fn renders_variables_and_filters() {
+        assert_eq!(render_with("你好，{{name | trim | upper}}！", &[("name", "  bob ")]), "你好，BOB！");
+        assert_eq!(render_with("{{name | default:\"匿名\"}}", &[]), "匿名");
+        assert_eq!(render_with("{{ missing }}", &[]), "");
+    }
+
+    #[test]
+    fn renders_if_else_and_unless() {
+        let source = "{{#if a}}有{{else}}无{{/if}}";
+        assert_eq!(render_with(source, &[("a", "x")]), "有");
+        assert_eq!(render_with(source, &[("a", "  ")]), "无");
+
+        let source = "{{#unless a}}无{{else}}有{{/unless}}";
+        assert_eq!(render_with(source, &[]), "无");
+        assert_eq!(render_with(source, &[("a", "x")]), "有");
+    }
+
+    #[test]
+    fn renders_nested_conditions() {
+        let source = "{{#if a}}A{{#unless b}}-没有B{{else}}-{{b}}{{/unless}}{{/if}}.";
+        assert_eq!(render_with(source, &[("a", "1")]), "A-没有B.");
+        assert_eq!(render_with(source, &[("a", "1"), ("b", "乙")]), "A-乙.");
+        assert_eq!(render_with(source, &[("b", "乙")]), ".");
+
+        let template = Template::parse(source).unwrap();
+        assert_eq!(template.variables().into_iter().collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn reports_unclosed_and_mismatched_tags() {
+        assert!(Template::parse("第一行\n{{name").unwrap_err().contains("第 2 行"));
+        assert!(Template::parse("{{#if a}}内容").unwrap_err().contains("缺少 {{/if}}"));
+        assert!(Template::parse("{{#if a}}内容{{/unless}}").unwrap_err().contains("应为 {{/if}}"));
+        assert!(Template::parse("内容{{/if}}").is_err());
+        assert!(Template::parse("{{else}}").is_err());
+        assert!(Template::parse("{{#if a}}1{{else}}2{{else}}3{{/if}}").is_err());
+        assert!(Template::parse("{{#raw}}未结束").is_err());
+    }
+
+    #[test]
+    fn reports_invalid_filters() {
+        assert!(Template::parse("{{a | truncate}}").is_err());
+        assert!(Template::parse("{{a | truncate:abc}}").is_err());
+        assert!(Template::parse("{{a | upper:1}}").is_err());
+        assert!(Template::parse("{{a | shout}}").is_err());
+    }
+
+    #[test]
+    fn truncates_multibyte_text_by_characters() {
+        assert_eq!(render_with("{{text | truncate:2}}", &[("text", "你好世界")]), "你好…");
+        assert_eq!(render_with("{{text | truncate:4}}", &[("text", "你好世界")]), "你好世界");
+        assert_eq!(render_with("{{text | truncate:1}}", &[("text", "😀😀")]), "😀…");
+    }
+
+    #[test]
+    fn escapes_literal_braces() {
+        assert_eq!(render_with("示例：{{{{name}}", &[("name", "x")]), "示例：{{name}}");
+        assert_eq!(
+            render_with("{{#raw}}fn f() { {{x}} }{{/raw}} {{x}}", &[("x", "1")]),
+            "fn f() { {{x}} } 1"
+        );
+    }
+
+    #[test]
+    fn validates_undefined_variables() {
+        let inputs = vec![PromptInput { name: "topic".to_string(), label: String::new(), default: None, required: false }];
+        assert!(validate("{{topic}} {{clipboard}}", &inputs).is_ok());
+        assert!(validate("{{unknown}}", &inputs).unwrap_err().contains("unknown"));
+        assert!(validate("{{{{unknown}} {{#raw}}{{other}}{{/raw}}", &inputs).is_ok());
+    }
+}
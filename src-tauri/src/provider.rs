@@ -0,0 +1,982 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
+use crate::ai::{
+    AIProvider, AISettingsManager, AzureDeployment, ChatCompletionChoice, ChatCompletionDelta,
+    ChatCompletionRequest, ChatCompletionResponse,
+};
+use crate::anthropic;
+use crate::retry::{self, RetryPolicy};
+use crate::secrets;
+use crate::token_budget::{self, ContextStrategy, FittedRequest, TokenReport};
+
+// 建立连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 非流式请求的总超时时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+// 流式请求两次收到数据之间允许的最长间隔
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// 提供商设置中未指定时使用的默认参数
+const DEFAULT_TEMPERATURE: f32 = 0.7;
+const DEFAULT_MAX_TOKENS: u32 = 2048;
+
+/// 流式数据块的处理函数
+pub type ChunkHandler<'a> = dyn FnMut(&ChatCompletionResponse) -> Result<(), String> + Send + 'a;
+
+/// 聊天接口提供商，各提供商的请求与响应统一为 OpenAI 格式
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+    /// 非流式请求，返回 OpenAI chat.completion 格式的响应
+    async fn complete(&self, request: &ChatCompletionRequest) -> Result<Value, String>;
+
+    /// 流式请求，数据块转换为 OpenAI 格式后交给 on_chunk
+    async fn stream(&self, request: &ChatCompletionRequest, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String>;
+
+    /// 获取可用的模型ID，服务不提供模型列表接口时返回 None
+    async fn list_models(&self) -> Result<Option<Vec<String>>, String>;
+
+    /// 计算文本的向量，返回值与 inputs 一一对应
+    async fn embed(&self, _model: &str, _inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        Err("该提供商不支持向量接口".to_string())
+    }
+
+    /// 接口地址，用于连接诊断
+    fn endpoint(&self) -> &str;
+}
+
+/// 从设置中解析出的提供商及其默认参数
+pub struct ResolvedProvider {
+    pub provider_id: String,
+    pub provider: Box<dyn ChatProvider>,
+    /// 设置中选择的模型，未设置时为空
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    /// 设置中是否使用流式输出
+    pub stream: bool,
+    pub context_strategy: ContextStrategy,
+    /// 模型列表接口返回的上下文长度
+    pub context_limits: HashMap<String, u32>,
+}
+
+impl ResolvedProvider {
+    /// 构建请求，未指定的参数使用提供商设置中的值
+    pub fn request(
+        &self,
+        model: Option<String>,
+        messages: Vec<crate::ai::ChatCompletionMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<i32>,
+    ) -> Result<ChatCompletionRequest, String> {
+        let model = model
+            .filter(|model| !model.trim().is_empty())
+            .unwrap_or_else(|| self.model.clone());
+        if model.trim().is_empty() {
+            return Err(format!("提供商 '{}' 未选择模型", self.provider_id));
+        }
+
+        Ok(ChatCompletionRequest {
+            model,
+            messages,
+            temperature: Some(temperature.unwrap_or(self.temperature)),
+            max_tokens: Some(max_tokens.unwrap_or(self.max_tokens as i32)),
+            stream: None,
+        })
+    }
+
+    /// 估算请求的 token 数以及超出上下文长度时将如何调整，不发起网络请求
+    pub fn estimate(
+        &self,
+        model: Option<String>,
+        messages: Vec<crate::ai::ChatCompletionMessage>,
+        max_tokens: Option<i32>,
+    ) -> Result<TokenReport, String> {
+        let request = self.request(model, messages, None, max_tokens)?;
+        let limit = token_budget::context_limit(&request.model, &self.context_limits);
+        Ok(token_budget::fit(request, limit, self.context_strategy).report)
+    }
+
+    /// 构建请求并按模型的上下文长度调整，使用摘要策略时会先请求一次摘要
+    pub async fn prepare(
+        &self,
+        model: Option<String>,
+        messages: Vec<crate::ai::ChatCompletionMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<i32>,
+    ) -> Result<(ChatCompletionRequest, TokenReport), String> {
+        let request = self.request(model, messages, temperature, max_tokens)?;
+        let limit = token_budget::context_limit(&request.model, &self.context_limits);
+        let FittedRequest { mut request, mut report, summarize } = token_budget::fit(request, limit, self.context_strategy);
+
+        if !summarize.is_empty() {
+            let summary_request = ChatCompletionRequest {
+                model: request.model.clone(),
+                messages: token_budget::summary_messages(&request.model, &summarize, limit),
+                temperature: Some(0.3),
+                max_tokens: Some(token_budget::summary_max_tokens()),
+                stream: None,
+            };
+            let summary = self.provider.complete(&summary_request).await.and_then(|response| {
+                response["choices"][0]["message"]["content"]
+                    .as_str()
+                    .map(|content| content.trim().to_string())
+                    .filter(|content| !content.is_empty())
+                    .ok_or_else(|| format!("无法解析摘要响应: {}", response))
+            });
+            match summary {
+                Ok(summary) => {
+                    token_budget::insert_summary(&mut request.messages, &summary);
+                    report.summarized = true;
+                    report.final_prompt_tokens = token_budget::count_messages(&request.model, &request.messages);
+                },
+                Err(e) => eprintln!("总结早期对话失败，已直接丢弃: {}", e),
+            }
+        }
+
+        if !report.fits {
+            eprintln!(
+                "请求超出模型 {} 的上下文长度 {}，提示词 {} -> {} tokens，丢弃 {} 条消息",
+                report.model, report.context_limit, report.prompt_tokens, report.final_prompt_tokens, report.dropped_messages
+            );
+        }
+        Ok((request, report))
+    }
+}
+
+/// 根据 AI 设置解析聊天提供商
+///
+/// provider_id 为空时使用当前选择的提供商；传入的地址和密钥优先于已保存的设置。
+pub fn resolve(
+    manager: &AISettingsManager,
+    provider_id: Option<&str>,
+    base_url: Option<String>,
+    api_key: Option<String>,
+) -> Result<ResolvedProvider, String> {
+    // 只读取所用提供商的密钥
+    let settings = manager.get_global_settings()?;
+    let provider_id = provider_id
+        .filter(|id| !id.is_empty())
+        .unwrap_or(&settings.selected_provider_id)
+        .to_string();
+    let setting = manager.get_provider_setting(&provider_id).ok();
+    let setting = setting.as_ref();
+
+    let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+    let base_url = non_empty(base_url)
+        .or_else(|| non_empty(setting.and_then(|setting| setting.custom_api_base_url.clone())))
+        .or_else(|| default_base_url(&provider_id).map(String::from))
+        .unwrap_or_default();
+    let api_key = non_empty(api_key)
+        .or_else(|| non_empty(setting.and_then(|setting| setting.api_key.clone())))
+        .unwrap_or_default();
+
+    let azure = setting.and_then(|setting| setting.azure.clone());
+    let kind = if azure.is_some() { AIProvider::Azure } else { AIProvider::detect(&provider_id, &base_url) };
+    if base_url.is_empty() && kind != AIProvider::Anthropic && azure.is_none() {
+        return Err(format!("未配置提供商 '{}' 的API地址", provider_id));
+    }
+    if api_key.is_empty() && kind != AIProvider::Ollama && !is_local_url(&base_url) {
+        return Err(format!("未配置提供商 '{}' 的API密钥", provider_id));
+    }
+    if secrets::is_locked_marker(&api_key) {
+        return Err(format!("密钥存储未解锁，无法读取提供商 '{}' 的API密钥", provider_id));
+    }
+
+    let provider: Box<dyn ChatProvider> = match kind {
+        AIProvider::Anthropic => Box::new(Anthropic::new(base_url, api_key, settings.retry)),
+        AIProvider::Azure => {
+            // 资源地址为空时请求会发往相对地址，提前报错
+            let deployment = azure.unwrap_or_else(|| AzureDeployment::from_endpoint(&base_url)).with_defaults(&base_url);
+            if !deployment.endpoint.trim().starts_with("http") {
+                return Err(format!("未配置提供商 '{}' 的 Azure 资源地址", provider_id));
+            }
+            Box::new(Azure { deployment, api_key, retry: settings.retry })
+        },
+        AIProvider::Zhipu => Box::new(Zhipu(OpenAICompatible::new(base_url, api_key, settings.retry))),
+        AIProvider::Ollama => Box::new(Ollama::new(base_url, api_key, settings.retry)),
+        _ => Box::new(OpenAICompatible::new(base_url, api_key, settings.retry)),
+    };
+
+    Ok(ResolvedProvider {
+        provider_id,
+        provider,
+        model: setting.map(|setting| setting.selected_model.clone()).unwrap_or_default(),
+        temperature: setting.map_or(DEFAULT_TEMPERATURE, |setting| setting.temperature),
+        max_tokens: setting.map_or(DEFAULT_MAX_TOKENS, |setting| setting.max_tokens),
+        stream: setting.is_none_or(|setting| setting.use_stream),
+        context_strategy: settings.context_strategy,
+        context_limits: manager.context_limits()?,
+    })
+}
+
+/// 请求的提供商及设置中的备用提供商，按顺序尝试
+pub struct ProviderChain {
+    providers: Vec<ResolvedProvider>,
+}
+
+/// 带提供商ID的流式数据块处理函数
+pub type ProviderChunkHandler<'a> = dyn FnMut(&str, &ChatCompletionResponse) -> Result<(), String> + Send + 'a;
+
+impl ProviderChain {
+    /// 第一个尝试的提供商
+    pub fn primary(&self) -> &ResolvedProvider {
+        &self.providers[0]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// 只保留满足条件的提供商
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.providers.retain(|resolved| keep(&resolved.provider_id));
+    }
+
+    /// 非流式请求，失败时依次改用备用提供商，返回响应和实际回答的提供商ID
+    ///
+    /// 指定的模型只用于第一个提供商，备用提供商使用各自设置中的模型。
+    pub async fn complete(
+        &self,
+        model: Option<String>,
+        messages: Vec<crate::ai::ChatCompletionMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<i32>,
+    ) -> Result<(Value, &str), String> {
+        let mut errors = Vec::new();
+        for (index, resolved) in self.providers.iter().enumerate() {
+            let model = if index == 0 { model.clone() } else { None };
+            let result = async {
+                let (request, _) = resolved.prepare(model, messages.clone(), temperature, max_tokens).await?;
+                resolved.provider.complete(&request).await
+            }
+            .await;
+
+            match result {
+                Ok(response) => return Ok((response, resolved.provider_id.as_str())),
+                Err(e) => {
+                    eprintln!("提供商 '{}' 请求失败: {}", resolved.provider_id, e);
+                    errors.push((resolved.provider_id.as_str(), e));
+                },
+            }
+        }
+        Err(Self::describe_errors(errors))
+    }
+
+    /// 流式请求，在收到第一个数据块之前失败时依次改用备用提供商，返回实际回答的提供商ID
+    pub async fn stream(
+        &self,
+        model: Option<String>,
+        messages: Vec<crate::ai::ChatCompletionMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<i32>,
+        on_chunk: &mut ProviderChunkHandler<'_>,
+    ) -> Result<&str, String> {
+        let mut errors = Vec::new();
+        for (index, resolved) in self.providers.iter().enumerate() {
+            let model = if index == 0 { model.clone() } else { None };
+            let mut started = false;
+            let result = async {
+                let (request, _) = resolved.prepare(model, messages.clone(), temperature, max_tokens).await?;
+                resolved.provider.stream(&request, &mut |response| {
+                    started = true;
+                    on_chunk(&resolved.provider_id, response)
+                })
+                .await
+            }
+            .await;
+
+            match result {
+                Ok(()) => return Ok(resolved.provider_id.as_str()),
+                // 已经输出了部分内容，无法再换用其他提供商
+                Err(e) if started => return Err(e),
+                Err(e) => {
+                    eprintln!("提供商 '{}' 流式请求失败: {}", resolved.provider_id, e);
+                    errors.push((resolved.provider_id.as_str(), e));
+                },
+            }
+        }
+        Err(Self::describe_errors(errors))
+    }
+
+    fn describe_errors(mut errors: Vec<(&str, String)>) -> String {
+        if errors.len() == 1 {
+            return errors.remove(0).1;
+        }
+        errors
+            .into_iter()
+            .map(|(provider_id, e)| format!("{}: {}", provider_id, e))
+            .collect::<Vec<_>>()
+            .join("；")
+    }
+}
+
+/// 解析请求的提供商及设置中的备用提供商
+///
+/// 未配置完整的备用提供商会被跳过；只有全部无法解析时才返回错误。
+pub fn resolve_chain(
+    manager: &AISettingsManager,
+    provider_id: Option<&str>,
+    base_url: Option<String>,
+    api_key: Option<String>,
+) -> Result<ProviderChain, String> {
+    let fallback_ids = manager.get_global_settings()?.fallback_provider_ids;
+    let mut providers = Vec::new();
+    let mut first_error = None;
+
+    match resolve(manager, provider_id, base_url, api_key) {
+        Ok(resolved) => providers.push(resolved),
+        Err(e) => first_error = Some(e),
+    }
+    for fallback_id in fallback_ids {
+        if providers.iter().any(|resolved: &ResolvedProvider| resolved.provider_id == fallback_id) {
+            continue;
+        }
+        match resolve(manager, Some(&fallback_id), None, None) {
+            Ok(resolved) => providers.push(resolved),
+            Err(e) => eprintln!("跳过备用提供商 '{}': {}", fallback_id, e),
+        }
+    }
+
+    match first_error {
+        Some(e) if providers.is_empty() => Err(e),
+        Some(e) => {
+            eprintln!("{}，改用备用提供商", e);
+            Ok(ProviderChain { providers })
+        },
+        None => Ok(ProviderChain { providers }),
+    }
+}
+
+/// 内置提供商的默认地址，与前端的提供商列表一致
+fn default_base_url(provider_id: &str) -> Option<&'static str> {
+    match provider_id {
+        "Copy2AI" | "zhipu" => Some("https://open.bigmodel.cn/api/paas/v4"),
+        "openai" => Some("https://api.openai.com/v1"),
+        "anthropic" => Some(anthropic::DEFAULT_BASE_URL),
+        "ollama" => Some("http://localhost:11434/v1"),
+        "ollama-legacy" => Some("http://localhost:11434/api"),
+        "kimi" => Some("https://api.moonshot.cn/v1"),
+        "deepseek" => Some("https://api.deepseek.com/v1"),
+        "qwen" => Some("https://dashscope.aliyuncs.com/compatible-mode/v1"),
+        "baichuan" => Some("https://api.baichuan-ai.com/v1"),
+        "302ai" => Some("https://api.302ai.cn/v1"),
+        "vllm" => Some("http://localhost:8000/v1"),
+        "localai" | "LMStudio" => Some("http://localhost:8080/v1"),
+        _ => None,
+    }
+}
+
+fn is_local_url(url: &str) -> bool {
+    url.contains("localhost") || url.contains("127.0.0.1")
+}
+
+/// 所有提供商共用的HTTP客户端配置
+pub fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .map_err(|e| format!("创建HTTP客户端失败: {}", e))
+}
+
+/// 发送请求并检查响应状态，错误统一转换为中文说明
+///
+/// 遇到限流、服务端临时错误或连接失败时按重试设置退避后重发，优先遵循 Retry-After。
+async fn send(builder: reqwest::RequestBuilder, stream: bool, retry: &RetryPolicy) -> Result<reqwest::Response, String> {
+    // 流式请求的耗时取决于生成长度，改为在读取时检查间隔
+    let mut builder = if stream { builder } else { builder.timeout(REQUEST_TIMEOUT) };
+
+    let mut attempt = 0;
+    loop {
+        let next = builder.try_clone();
+        let result = builder.send().await;
+
+        let (retry_after, reason) = match &result {
+            Ok(response) if retry::is_retryable_status(response.status().as_u16()) => {
+                (retry::retry_after(response.headers()), format!("状态码 {}", response.status().as_u16()))
+            },
+            Err(e) if e.is_timeout() || e.is_connect() => (None, e.to_string()),
+            _ => return check_status(result.map_err(describe_send_error)?).await,
+        };
+        let (Some(next), Some(delay)) = (next, retry.delay(attempt, retry_after)) else {
+            return check_status(result.map_err(describe_send_error)?).await;
+        };
+
+        eprintln!("请求失败（{}），{} 毫秒后第 {} 次重试", reason, delay.as_millis(), attempt + 1);
+        tokio::time::sleep(delay).await;
+        builder = next;
+        attempt += 1;
+    }
+}
+
+fn describe_send_error(e: reqwest::Error) -> String {
+    if e.is_timeout() {
+        "请求超时，请稍后重试".to_string()
+    } else if e.is_connect() {
+        format!("无法连接到服务: {}", e)
+    } else {
+        format!("网络请求失败: {}", e)
+    }
+}
+
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_else(|_| "未知错误".to_string());
+    Err(format!("API请求失败 ({}): {}", status.as_u16(), describe_error(status.as_u16(), &body)))
+}
+
+/// 从错误响应中提取说明，各提供商的错误格式不同
+fn describe_error(status: u16, body: &str) -> String {
+    let reason = match status {
+        400 => "请求参数错误",
+        401 | 403 => "API密钥无效或没有权限",
+        404 => "接口地址或模型不存在",
+        408 => "请求超时",
+        413 => "请求内容过长",
+        429 => "请求过于频繁或额度不足",
+        500..=599 => "服务暂时不可用",
+        _ => "请求失败",
+    };
+
+    let detail = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|json| {
+            json["error"]["message"]
+                .as_str()
+                .or_else(|| json["error"].as_str())
+                .or_else(|| json["message"].as_str())
+                .map(String::from)
+        })
+        .unwrap_or_else(|| body.to_string());
+
+    if detail.trim().is_empty() {
+        reason.to_string()
+    } else {
+        format!("{}: {}", reason, detail)
+    }
+}
+
+/// 请求模型列表接口，返回 data 或 models 数组中的模型ID；接口不存在时返回 None
+async fn fetch_model_ids(builder: reqwest::RequestBuilder) -> Result<Option<Vec<String>>, String> {
+    let response = builder.timeout(REQUEST_TIMEOUT).send().await.map_err(describe_send_error)?;
+    if matches!(response.status().as_u16(), 404 | 405) {
+        return Ok(None);
+    }
+
+    let json = read_json(check_status(response).await?).await?;
+    let ids = json["data"]
+        .as_array()
+        .or_else(|| json["models"].as_array())
+        .ok_or_else(|| "无法解析模型列表".to_string())?
+        .iter()
+        .filter_map(|model| {
+            model["id"].as_str()
+                .or_else(|| model["name"].as_str())
+                .or_else(|| model["model"].as_str())
+                .map(String::from)
+        })
+        .collect();
+
+    Ok(Some(ids))
+}
+
+/// 读取非流式响应的JSON内容
+async fn read_json(response: reqwest::Response) -> Result<Value, String> {
+    response.json::<Value>().await.map_err(|e| format!("解析响应失败: {}", e))
+}
+
+/// 解析 OpenAI 格式的 /embeddings 响应，按 index 排序
+fn parse_embeddings(json: &Value, count: usize) -> Result<Vec<Vec<f32>>, String> {
+    let mut data: Vec<&Value> = json["data"]
+        .as_array()
+        .ok_or_else(|| format!("无法解析向量响应: {}", json))?
+        .iter()
+        .collect();
+    data.sort_by_key(|entry| entry["index"].as_u64().unwrap_or(0));
+
+    let vectors = data.iter().map(|entry| parse_vector(&entry["embedding"])).collect::<Result<Vec<_>, _>>()?;
+    if vectors.len() != count {
+        return Err(format!("向量数量与输入不一致: {} != {}", vectors.len(), count));
+    }
+    Ok(vectors)
+}
+
+fn parse_vector(value: &Value) -> Result<Vec<f32>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| "向量格式无效".to_string())?
+        .iter()
+        .map(|x| x.as_f64().map(|x| x as f32).ok_or_else(|| "向量格式无效".to_string()))
+        .collect()
+}
+
+/// 将响应体包装为可按行读取的流
+fn body_reader(response: reqwest::Response) -> impl AsyncRead + Unpin + Send {
+    let stream = response.bytes_stream()
+        .map_err(std::io::Error::other);
+    StreamReader::new(stream)
+}
+
+// 逐行读取响应，对每个非空行调用 on_line，返回 false 时结束读取
+async fn read_lines<R, F>(reader: &mut R, mut on_line: F) -> Result<(), String>
+where
+    R: AsyncRead + Unpin + Send,
+    F: FnMut(&str) -> Result<bool, String>,
+{
+    let mut buffer = Vec::new();
+    let mut chunk = vec![0; 1024];
+
+    loop {
+        let n = tokio::time::timeout(STREAM_IDLE_TIMEOUT, reader.read(&mut chunk))
+            .await
+            .map_err(|_| "等待响应数据超时".to_string())?
+            .map_err(|e| format!("读取响应失败: {}", e))?;
+
+        if n == 0 {
+            break;
+        }
+
+        buffer.extend_from_slice(&chunk[..n]);
+
+        // 处理buffer中的每一行
+        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+            let line = buffer.drain(..=pos).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if !line.is_empty() && !on_line(line)? {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// 读取SSE响应，对每个数据行调用 on_data，返回 false 时结束读取
+async fn read_sse_data<R, F>(reader: &mut R, mut on_data: F) -> Result<(), String>
+where
+    R: AsyncRead + Unpin + Send,
+    F: FnMut(&str) -> Result<bool, String>,
+{
+    read_lines(reader, |line| match line.strip_prefix("data:") {
+        Some(data) => on_data(data.trim()),
+        None => Ok(true),
+    })
+    .await
+}
+
+/// 读取 OpenAI 格式的流式响应
+async fn read_openai_stream(response: reqwest::Response, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String> {
+    let mut reader = body_reader(response);
+    read_sse_data(&mut reader, |data| {
+        if data == "[DONE]" {
+            // 流结束
+            return Ok(false);
+        }
+
+        match serde_json::from_str::<ChatCompletionResponse>(data) {
+            Ok(response) => on_chunk(&response)?,
+            Err(e) => eprintln!("解析JSON失败: {} (数据: {})", e, data),
+        }
+        Ok(true)
+    })
+    .await
+}
+
+/// OpenAI 格式的请求体
+fn openai_body(request: &ChatCompletionRequest, stream: bool) -> Value {
+    json!({
+        "model": request.model,
+        "messages": request.messages,
+        "temperature": request.temperature,
+        "max_tokens": request.max_tokens,
+        "stream": stream,
+    })
+}
+
+/// OpenAI 兼容接口，适用于 OpenAI、Kimi、DeepSeek 等大多数提供商
+pub struct OpenAICompatible {
+    base_url: String,
+    api_key: String,
+    retry: RetryPolicy,
+}
+
+impl OpenAICompatible {
+    pub fn new(base_url: String, api_key: String, retry: RetryPolicy) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            retry,
+        }
+    }
+
+    fn with_auth(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        // 本地服务可以不设置密钥
+        if self.api_key.is_empty() {
+            builder
+        } else {
+            builder.header("Authorization", format!("Bearer {}", self.api_key))
+        }
+    }
+
+    fn post(&self, body: &Value) -> Result<reqwest::RequestBuilder, String> {
+        Ok(self.with_auth(http_client()?
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Content-Type", "application/json")
+            .json(body)))
+    }
+
+    async fn complete_body(&self, body: &Value) -> Result<Value, String> {
+        read_json(send(self.post(body)?, false, &self.retry).await?).await
+    }
+
+    async fn stream_body(&self, body: &Value, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String> {
+        let response = send(self.post(body)?, true, &self.retry).await?;
+        read_openai_stream(response, on_chunk).await
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OpenAICompatible {
+    async fn complete(&self, request: &ChatCompletionRequest) -> Result<Value, String> {
+        self.complete_body(&openai_body(request, false)).await
+    }
+
+    async fn stream(&self, request: &ChatCompletionRequest, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String> {
+        self.stream_body(&openai_body(request, true), on_chunk).await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, String> {
+        fetch_model_ids(self.with_auth(http_client()?.get(format!("{}/models", self.base_url)))).await
+    }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let builder = self.with_auth(http_client()?
+            .post(format!("{}/embeddings", self.base_url))
+            .header("Content-Type", "application/json")
+            .json(&json!({ "model": model, "input": inputs })));
+        parse_embeddings(&read_json(send(builder, false, &self.retry).await?).await?, inputs.len())
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+}
+
+/// 智谱 AI，接口与 OpenAI 兼容，但温度不能取 0 或 1
+pub struct Zhipu(OpenAICompatible);
+
+impl Zhipu {
+    fn body(request: &ChatCompletionRequest, stream: bool) -> Value {
+        let mut body = openai_body(request, stream);
+        if let Some(temperature) = request.temperature {
+            body["temperature"] = json!(temperature.clamp(0.01, 0.99));
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl ChatProvider for Zhipu {
+    async fn complete(&self, request: &ChatCompletionRequest) -> Result<Value, String> {
+        self.0.complete_body(&Self::body(request, false)).await
+    }
+
+    async fn stream(&self, request: &ChatCompletionRequest, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String> {
+        self.0.stream_body(&Self::body(request, true), on_chunk).await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, String> {
+        self.0.list_models().await
+    }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        self.0.embed(model, inputs).await
+    }
+
+    fn endpoint(&self) -> &str {
+        self.0.endpoint()
+    }
+}
+
+/// Azure OpenAI，按部署地址请求，使用 api-key 请求头
+pub struct Azure {
+    deployment: AzureDeployment,
+    api_key: String,
+    retry: RetryPolicy,
+}
+
+impl Azure {
+    fn post(&self, request: &ChatCompletionRequest, stream: bool) -> Result<reqwest::RequestBuilder, String> {
+        Ok(http_client()?
+            .post(self.deployment.chat_url(&request.model))
+            .header("Content-Type", "application/json")
+            .header("api-key", &self.api_key)
+            .json(&openai_body(request, stream)))
+    }
+}
+
+#[async_trait]
+impl ChatProvider for Azure {
+    async fn complete(&self, request: &ChatCompletionRequest) -> Result<Value, String> {
+        read_json(send(self.post(request, false)?, false, &self.retry).await?).await
+    }
+
+    async fn stream(&self, request: &ChatCompletionRequest, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String> {
+        let response = send(self.post(request, true)?, true, &self.retry).await?;
+        read_openai_stream(response, on_chunk).await
+    }
+
+    // Azure 的模型即资源下的部署
+    async fn list_models(&self) -> Result<Option<Vec<String>>, String> {
+        fetch_model_ids(http_client()?.get(self.deployment.deployments_url()).header("api-key", &self.api_key)).await
+    }
+
+    // 向量模型使用单独的部署，部署名即模型名
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let builder = http_client()?
+            .post(self.deployment.embeddings_url(model))
+            .header("Content-Type", "application/json")
+            .header("api-key", &self.api_key)
+            .json(&json!({ "input": inputs }));
+        parse_embeddings(&read_json(send(builder, false, &self.retry).await?).await?, inputs.len())
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.deployment.endpoint
+    }
+}
+
+/// Anthropic Messages 接口
+pub struct Anthropic {
+    base_url: String,
+    api_key: String,
+    retry: RetryPolicy,
+}
+
+impl Anthropic {
+    pub fn new(base_url: String, api_key: String, retry: RetryPolicy) -> Self {
+        let base_url = if base_url.is_empty() { anthropic::DEFAULT_BASE_URL.to_string() } else { base_url };
+        Self { base_url, api_key, retry }
+    }
+
+    fn post(&self, request: &ChatCompletionRequest, stream: bool) -> Result<reqwest::RequestBuilder, String> {
+        let mut body = anthropic::request_body(request);
+        body["stream"] = json!(stream);
+        Ok(anthropic::with_headers(http_client()?.post(anthropic::messages_url(&self.base_url)), &self.api_key)
+            .json(&body))
+    }
+}
+
+#[async_trait]
+impl ChatProvider for Anthropic {
+    async fn complete(&self, request: &ChatCompletionRequest) -> Result<Value, String> {
+        let json = read_json(send(self.post(request, false)?, false, &self.retry).await?).await?;
+        anthropic::normalize_response(&json)
+    }
+
+    async fn stream(&self, request: &ChatCompletionRequest, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String> {
+        let response = send(self.post(request, true)?, true, &self.retry).await?;
+        let mut reader = body_reader(response);
+        let mut parser = anthropic::StreamParser::new();
+        read_sse_data(&mut reader, |data| match parser.parse(data)? {
+            anthropic::StreamStep::Chunk(response) => on_chunk(&response).map(|_| true),
+            anthropic::StreamStep::Skip => Ok(true),
+            anthropic::StreamStep::Done => Ok(false),
+        })
+        .await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, String> {
+        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+        fetch_model_ids(anthropic::with_headers(http_client()?.get(url), &self.api_key)).await
+    }
+
+    fn endpoint(&self) -> &str {
+        &self.base_url
+    }
+}
+
+/// Ollama 的 /api/chat 响应，流式时每行一个
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    model: String,
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+    content: String,
+}
+
+/// Ollama 本地服务
+///
+/// 地址以 /api 结尾时使用原生的 /api/chat 接口，否则使用其 OpenAI 兼容接口。
+pub struct Ollama {
+    native: bool,
+    inner: OpenAICompatible,
+}
+
+impl Ollama {
+    pub fn new(base_url: String, api_key: String, retry: RetryPolicy) -> Self {
+        let inner = OpenAICompatible::new(base_url, api_key, retry);
+        Self { native: inner.base_url.ends_with("/api"), inner }
+    }
+
+    fn post_native(&self, request: &ChatCompletionRequest, stream: bool) -> Result<reqwest::RequestBuilder, String> {
+        let body = json!({
+            "model": request.model,
+            "messages": request.messages,
+            "stream": stream,
+            "options": {
+                "temperature": request.temperature,
+                "num_predict": request.max_tokens,
+            },
+        });
+        Ok(http_client()?
+            .post(format!("{}/chat", self.inner.base_url))
+            .header("Content-Type", "application/json")
+            .json(&body))
+    }
+
+    fn finish_reason(response: &OllamaChatResponse) -> Option<String> {
+        response.done.then(|| match response.done_reason.as_deref() {
+            Some("length") => "length".to_string(),
+            _ => "stop".to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl ChatProvider for Ollama {
+    async fn complete(&self, request: &ChatCompletionRequest) -> Result<Value, String> {
+        if !self.native {
+            return self.inner.complete(request).await;
+        }
+
+        let json = read_json(send(self.post_native(request, false)?, false, &self.inner.retry).await?).await?;
+        let response = serde_json::from_value::<OllamaChatResponse>(json)
+            .map_err(|e| format!("无法解析Ollama响应: {}", e))?;
+        if let Some(error) = response.error {
+            return Err(format!("Ollama返回错误: {}", error));
+        }
+
+        Ok(json!({
+            "id": format!("ollama-{}", uuid::Uuid::new_v4()),
+            "object": "chat.completion",
+            "created": chrono::Utc::now().timestamp(),
+            "model": response.model,
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": response.message.as_ref().map(|message| message.content.as_str()).unwrap_or_default(),
+                },
+                "finish_reason": Self::finish_reason(&response),
+            }],
+            "usage": {
+                "prompt_tokens": response.prompt_eval_count,
+                "completion_tokens": response.eval_count,
+            },
+        }))
+    }
+
+    async fn stream(&self, request: &ChatCompletionRequest, on_chunk: &mut ChunkHandler<'_>) -> Result<(), String> {
+        if !self.native {
+            return self.inner.stream(request, on_chunk).await;
+        }
+
+        let response = send(self.post_native(request, true)?, true, &self.inner.retry).await?;
+        let mut reader = body_reader(response);
+        let id = format!("ollama-{}", uuid::Uuid::new_v4());
+        let created = chrono::Utc::now().timestamp() as u64;
+
+        // 原生接口每行返回一个JSON对象，最后一行的 done 为 true
+        read_lines(&mut reader, |line| {
+            let response = match serde_json::from_str::<OllamaChatResponse>(line) {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("解析JSON失败: {} (数据: {})", e, line);
+                    return Ok(true);
+                }
+            };
+            if let Some(error) = response.error {
+                return Err(format!("Ollama返回错误: {}", error));
+            }
+
+            on_chunk(&ChatCompletionResponse {
+                id: id.clone(),
+                object: "chat.completion.chunk".to_string(),
+                created,
+                model: response.model.clone(),
+                choices: vec![ChatCompletionChoice {
+                    index: 0,
+                    delta: ChatCompletionDelta {
+                        content: response.message.as_ref().map(|message| message.content.clone()),
+                    },
+                    finish_reason: Self::finish_reason(&response),
+                }],
+            })?;
+            Ok(!response.done)
+        })
+        .await
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, String> {
+        if !self.native {
+            return self.inner.list_models().await;
+        }
+
+        // 原生接口的模型列表为 /api/tags
+        fetch_model_ids(http_client()?.get(format!("{}/tags", self.inner.base_url))).await
+    }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        if !self.native {
+            return self.inner.embed(model, inputs).await;
+        }
+
+        // 原生接口为 /api/embed，返回 embeddings 数组
+        let builder = http_client()?
+            .post(format!("{}/embed", self.inner.base_url))
+            .header("Content-Type", "application/json")
+            .json(&json!({ "model": model, "input": inputs }));
+        let json = read_json(send(builder, false, &self.inner.retry).await?).await?;
+        let vectors = json["embeddings"]
+            .as_array()
+            .ok_or_else(|| format!("无法解析Ollama向量响应: {}", json))?
+            .iter()
+            .map(parse_vector)
+            .collect::<Result<Vec<_>, _>>()?;
+        if vectors.len() != inputs.len() {
+            return Err(format!("向量数量与输入不一致: {} != {}", vectors.len(), inputs.len()));
+        }
+        Ok(vectors)
+    }
+
+    fn endpoint(&self) -> &str {
+        self.inner.endpoint()
+    }
+}
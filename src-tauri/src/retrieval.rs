@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+use serde::Serialize;
+use crate::ai::ChatCompletionMessage;
+use crate::clipboard::ClipboardItem;
+use crate::embeddings;
+use crate::token_budget;
+
+type AppState = crate::AppState;
+
+// 每种检索方式最多取回的条目数
+const MAX_CANDIDATES: usize = 8;
+
+// 注入的剪贴板内容最多占用模型上下文长度的比例（分母），其余留给对话和回复
+const CONTEXT_SHARE_DIVISOR: u32 = 4;
+
+// 单个条目最多占用的 token 数，超出部分截断
+const MAX_ITEM_TOKENS: usize = 800;
+
+/// 回答引用的剪贴板条目，index 对应回答中的 [编号]
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipboardReference {
+    pub index: usize,
+    pub item_id: String,
+    pub score: f32,
+    pub timestamp: u64,
+    pub preview: String,
+}
+
+/// 检索与问题相关的条目：开启语义搜索时优先使用向量检索，再用关键字检索补充
+async fn retrieve(state: &AppState, question: &str) -> Result<Vec<(ClipboardItem, f32)>, String> {
+    let semantic = match embeddings::embed_query(state, question).await {
+        Ok(query) => Some(query),
+        Err(e) => {
+            if state.embeddings.get_settings()?.enabled {
+                eprintln!("语义检索失败，改用关键字检索: {}", e);
+            }
+            None
+        },
+    };
+
+    let storage = state.storage.lock().map_err(|e| e.to_string())?;
+    let mut hits = match &semantic {
+        Some((model, vector)) => storage.semantic_search(vector, model, None, MAX_CANDIDATES),
+        None => Vec::new(),
+    };
+    hits.extend(storage.search_question(question, MAX_CANDIDATES));
+
+    let mut seen = HashSet::new();
+    Ok(hits
+        .into_iter()
+        .filter(|hit| seen.insert(hit.item.id.clone()))
+        .map(|hit| (hit.item, hit.score))
+        .collect())
+}
+
+/// 条目是否可以发送给远程模型
+///
+/// 与 `vector_index::embedding_text` 保持一致：命中打码、过期规则的条目不发送，
+/// 归为密码的条目只在用户明确允许时发送。
+fn shareable(item: &ClipboardItem, include_passwords: bool) -> bool {
+    !item.sensitive && (include_passwords || item.category.as_deref() != Some("password"))
+}
+
+/// 在 token 预算内拼接检索到的条目，返回注入的提示词和对应的引用
+fn build_context(
+    model: &str,
+    hits: Vec<(ClipboardItem, f32)>,
+    budget: usize,
+    include_passwords: bool,
+) -> (String, Vec<ClipboardReference>) {
+    let mut sections = Vec::new();
+    let mut references = Vec::new();
+    let mut used = 0;
+
+    for (item, score) in hits.into_iter().filter(|(item, _)| shareable(item, include_passwords)) {
+        let content = token_budget::truncate_tokens(model, &item.content, MAX_ITEM_TOKENS);
+        let copied_at = chrono::DateTime::from_timestamp_millis(item.timestamp as i64)
+            .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let index = references.len() + 1;
+        let section = format!("[{}] 复制于 {}\n{}", index, copied_at, content);
+
+        let tokens = token_budget::count_tokens(model, &section);
+        if used + tokens > budget {
+            break;
+        }
+        used += tokens;
+
+        sections.push(section);
+        references.push(ClipboardReference {
+            index,
+            item_id: item.id,
+            score,
+            timestamp: item.timestamp,
+            preview: token_budget::truncate_tokens(model, &item.content, 40),
+        });
+    }
+
+    let prompt = format!(
+        "以下是从用户剪贴板历史中检索到的、可能与问题相关的记录。请优先依据这些记录回答，\
+         并在用到的内容后用 [编号] 标注来源；记录中没有相关信息时请直接说明，不要编造。\n\n{}",
+        sections.join("\n\n")
+    );
+    (prompt, references)
+}
+
+/// 根据最后一条用户消息检索剪贴板历史，将相关条目注入系统提示词
+///
+/// 注入的内容按 model 计算 token 数，最多占 context_limit 的四分之一。
+/// 没有检索到可以发送的相关条目时不修改消息，返回空的引用列表。
+pub async fn augment(
+    state: &AppState,
+    messages: &mut Vec<ChatCompletionMessage>,
+    model: &str,
+    context_limit: u32,
+) -> Result<Vec<ClipboardReference>, String> {
+    let Some(question) = messages.iter().rev().find(|message| message.role == "user").map(|message| message.content.clone()) else {
+        return Ok(Vec::new());
+    };
+
+    let hits = retrieve(state, &question).await?;
+    if hits.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let include_passwords = state.ai_settings_manager.lock().map_err(|e| e.to_string())?.share_passwords_in_context()?;
+    let budget = (context_limit / CONTEXT_SHARE_DIVISOR) as usize;
+    let (context, references) = build_context(model, hits, budget, include_passwords);
+    if references.is_empty() {
+        return Ok(Vec::new());
+    }
+    match messages.iter_mut().find(|message| message.role == "system") {
+        Some(system) => system.content = format!("{}\n\n{}", system.content, context),
+        None => messages.insert(0, ChatCompletionMessage { role: "system".to_string(), content: context }),
+    }
+
+    Ok(references)
+}
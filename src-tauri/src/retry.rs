@@ -0,0 +1,64 @@
+use std::time::Duration;
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+
+/// 请求失败后的重试设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// 最多重试次数，0 表示不重试
+    pub max_retries: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub base_delay_ms: u64,
+    /// 单次等待的上限；服务要求等待更久（Retry-After）时不再重试
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay_ms: 1000,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第 attempt 次重试（从 0 开始）前的等待时间，返回 None 表示不再重试
+    ///
+    /// 服务返回了 Retry-After 时按其等待，否则使用带随机抖动的指数退避。
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+
+        let max_delay = Duration::from_millis(self.max_delay_ms);
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= max_delay).then_some(retry_after);
+        }
+
+        let backoff = self.base_delay_ms.saturating_mul(1u64 << attempt.min(16)).min(self.max_delay_ms);
+        // 在后一半区间内随机，避免多个请求同时重试
+        let jitter = rand::thread_rng().gen_range(backoff / 2..=backoff);
+        Some(Duration::from_millis(jitter))
+    }
+}
+
+/// 限流和服务端的临时错误可以重试
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// 解析 Retry-After 响应头，支持秒数和 HTTP 日期两种格式
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
@@ -0,0 +1,376 @@
+use arboard::Clipboard;
+use crate::clipboard::{ClipboardFormats, ClipboardItem, ContentType};
+
+/// 读取剪贴板中除纯文本外的其它格式（HTML、RTF、文件列表）
+///
+/// 只在检测到剪贴板变化时调用，读取失败的格式视为不存在。
+pub fn read_formats(clipboard: &mut Clipboard) -> ClipboardFormats {
+    let html = clipboard.get().html().ok().filter(|html| !html.trim().is_empty());
+
+    ClipboardFormats {
+        html,
+        rtf: platform::read_rtf().filter(|rtf| rtf.starts_with("{\\rtf")),
+        files: read_file_list(clipboard),
+    }
+}
+
+/// 读取剪贴板中的文件列表，没有时返回空列表
+pub fn read_file_list(clipboard: &mut Clipboard) -> Vec<String> {
+    clipboard
+        .get()
+        .file_list()
+        .map(|paths| paths.iter().map(|path| path.to_string_lossy().into_owned()).collect())
+        .unwrap_or_default()
+}
+
+/// 按条目的原始格式写回剪贴板
+///
+/// 纯文本条目或当前平台不支持的格式返回错误，由调用方退回到纯文本复制。
+pub fn write_item(item: &ClipboardItem) -> Result<(), String> {
+    match item.content_type {
+        ContentType::Html => {
+            let html = item.formats.html.as_deref().ok_or("条目缺少HTML内容")?;
+            let mut clipboard = Clipboard::new().map_err(|e| format!("无法创建剪贴板实例: {}", e))?;
+            clipboard
+                .set_html(html, Some(item.content.as_str()))
+                .map_err(|e| format!("写入HTML失败: {}", e))
+        },
+        ContentType::Rtf => {
+            let rtf = item.formats.rtf.as_deref().ok_or("条目缺少RTF内容")?;
+            platform::write_rtf(rtf, &item.content)
+        },
+        ContentType::Files => platform::write_files(&item.formats.files),
+        _ => Err("条目没有富文本格式".to_string()),
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use clipboard_win::{options::NoClear, raw, register_format, Clipboard};
+
+    // 打开剪贴板的重试次数
+    const OPEN_ATTEMPTS: usize = 10;
+
+    fn rtf_format() -> Result<u32, String> {
+        register_format("Rich Text Format")
+            .map(|format| format.get())
+            .ok_or_else(|| "注册RTF格式失败".to_string())
+    }
+
+    pub fn read_rtf() -> Option<String> {
+        let format = rtf_format().ok()?;
+        let _clipboard = Clipboard::new_attempts(OPEN_ATTEMPTS).ok()?;
+        if !raw::is_format_avail(format) {
+            return None;
+        }
+
+        let mut data = Vec::new();
+        raw::get_vec(format, &mut data).ok()?;
+        Some(String::from_utf8_lossy(&data).trim_end_matches('\0').to_string())
+    }
+
+    pub fn write_rtf(rtf: &str, plain_text: &str) -> Result<(), String> {
+        let format = rtf_format()?;
+        let _clipboard = Clipboard::new_attempts(OPEN_ATTEMPTS)
+            .map_err(|e| format!("打开剪贴板失败: {}", e))?;
+
+        // 同时写入纯文本，保证不支持RTF的程序也能粘贴
+        raw::empty().map_err(|e| format!("清空剪贴板失败: {}", e))?;
+        raw::set_string_with(plain_text, NoClear).map_err(|e| format!("写入文本失败: {}", e))?;
+
+        let mut data = rtf.as_bytes().to_vec();
+        data.push(0);
+        raw::set_without_clear(format, &data).map_err(|e| format!("写入RTF失败: {}", e))
+    }
+
+    pub fn write_files(paths: &[String]) -> Result<(), String> {
+        let _clipboard = Clipboard::new_attempts(OPEN_ATTEMPTS)
+            .map_err(|e| format!("打开剪贴板失败: {}", e))?;
+
+        raw::empty().map_err(|e| format!("清空剪贴板失败: {}", e))?;
+        raw::set_file_list(paths).map_err(|e| format!("写入文件列表失败: {}", e))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    pub fn read_rtf() -> Option<String> {
+        let output = Command::new("pbpaste").args(["-Prefer", "rtf"]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        // 没有RTF时 pbpaste 会返回纯文本，由调用方按文件头过滤
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    pub fn write_rtf(rtf: &str, _plain_text: &str) -> Result<(), String> {
+        // pbcopy 会根据RTF文件头自动以RTF类型写入，系统负责提供纯文本表示
+        let mut child = Command::new("pbcopy")
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("启动 pbcopy 失败: {}", e))?;
+
+        if let Some(stdin) = child.stdin.as_mut() {
+            stdin.write_all(rtf.as_bytes()).map_err(|e| format!("写入RTF失败: {}", e))?;
+        }
+
+        let status = child.wait().map_err(|e| format!("写入RTF失败: {}", e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("pbcopy 退出异常: {}", status))
+        }
+    }
+
+    pub fn write_files(_paths: &[String]) -> Result<(), String> {
+        Err("当前平台暂不支持写入文件列表".to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io::Read;
+    use std::process::Command;
+    use wl_clipboard_rs::{copy, paste};
+
+    /// 当前是否运行在 Wayland 会话中
+    fn is_wayland() -> bool {
+        std::env::var_os("WAYLAND_DISPLAY").is_some()
+    }
+
+    pub fn read_rtf() -> Option<String> {
+        if is_wayland() {
+            let (mut pipe, _) = paste::get_contents(
+                paste::ClipboardType::Regular,
+                paste::Seat::Unspecified,
+                paste::MimeType::Specific("text/rtf"),
+            )
+            .ok()?;
+
+            let mut rtf = String::new();
+            pipe.read_to_string(&mut rtf).ok()?;
+            return Some(rtf);
+        }
+
+        // X11 下借助 xclip 读取指定的目标格式
+        let output = Command::new("xclip")
+            .args(["-selection", "clipboard", "-t", "text/rtf", "-o"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// 以多种 MIME 类型同时写入剪贴板
+    fn copy_multi(sources: Vec<(String, Vec<u8>)>) -> Result<(), String> {
+        if !is_wayland() {
+            return x11::copy_multi(sources);
+        }
+
+        let sources = sources
+            .into_iter()
+            .map(|(mime_type, data)| copy::MimeSource {
+                source: copy::Source::Bytes(data.into_boxed_slice()),
+                mime_type: copy::MimeType::Specific(mime_type),
+            })
+            .collect();
+
+        copy::Options::new()
+            .copy_multi(sources)
+            .map_err(|e| format!("写入剪贴板失败: {}", e))
+    }
+
+    pub fn write_rtf(rtf: &str, plain_text: &str) -> Result<(), String> {
+        copy_multi(vec![
+            ("text/rtf".to_string(), rtf.as_bytes().to_vec()),
+            ("text/plain;charset=utf-8".to_string(), plain_text.as_bytes().to_vec()),
+            ("text/plain".to_string(), plain_text.as_bytes().to_vec()),
+        ])
+    }
+
+    pub fn write_files(paths: &[String]) -> Result<(), String> {
+        let uris: Vec<String> = paths.iter().map(|path| file_uri(path)).collect();
+        let plain_text = paths.join("\n");
+
+        copy_multi(vec![
+            ("text/uri-list".to_string(), uris.join("\r\n").into_bytes()),
+            // GNOME 系文件管理器通过该类型识别复制的文件
+            ("x-special/gnome-copied-files".to_string(), format!("copy\n{}", uris.join("\n")).into_bytes()),
+            ("text/plain;charset=utf-8".to_string(), plain_text.clone().into_bytes()),
+            ("text/plain".to_string(), plain_text.into_bytes()),
+        ])
+    }
+
+    /// X11 下的多格式写入
+    ///
+    /// X11 的剪贴板数据由选区所有者按需提供，xclip 一次只能提供一种目标格式，
+    /// 因此这里自己创建窗口持有 CLIPBOARD 选区，由后台线程应答其他程序的请求，
+    /// 直到其他程序取得选区为止。
+    mod x11 {
+        use std::thread;
+        use x11rb::connection::{Connection, RequestConnection};
+        use x11rb::protocol::xproto::{
+            Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode,
+            SelectionNotifyEvent, SelectionRequestEvent, WindowClass, SELECTION_NOTIFY_EVENT,
+        };
+        use x11rb::protocol::Event;
+        use x11rb::rust_connection::RustConnection;
+        use x11rb::wrapper::ConnectionExt as _;
+        use x11rb::CURRENT_TIME;
+
+        // ChangeProperty 请求头的长度，数据超过单个请求上限时不提供（未实现 INCR 分段传输）
+        const CHANGE_PROPERTY_HEADER: usize = 24;
+
+        fn intern(conn: &RustConnection, name: &str) -> Result<Atom, String> {
+            conn.intern_atom(false, name.as_bytes())
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map(|reply| reply.atom)
+                .map_err(|e| format!("获取 {} 原子失败: {}", name, e))
+        }
+
+        pub fn copy_multi(sources: Vec<(String, Vec<u8>)>) -> Result<(), String> {
+            let (conn, screen_num) = x11rb::connect(None).map_err(|e| format!("连接 X 服务器失败: {}", e))?;
+            let clipboard = intern(&conn, "CLIPBOARD")?;
+            let targets = intern(&conn, "TARGETS")?;
+
+            let mut offers = Vec::new();
+            for (mime_type, data) in sources {
+                // 多数 X11 程序按 UTF8_STRING 请求纯文本
+                if mime_type == "text/plain;charset=utf-8" {
+                    offers.push((intern(&conn, "UTF8_STRING")?, data.clone()));
+                }
+                offers.push((intern(&conn, &mime_type)?, data));
+            }
+
+            let screen = &conn.setup().roots[screen_num];
+            let window = conn.generate_id().map_err(|e| e.to_string())?;
+            conn.create_window(
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                window,
+                screen.root,
+                0,
+                0,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_ONLY,
+                x11rb::COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .map_err(|e| format!("创建 X 窗口失败: {}", e))?;
+
+            conn.set_selection_owner(window, clipboard, CURRENT_TIME)
+                .map_err(|e| format!("设置选区所有者失败: {}", e))?;
+            let owner = conn
+                .get_selection_owner(clipboard)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map_err(|e| format!("查询选区所有者失败: {}", e))?
+                .owner;
+            if owner != window {
+                return Err("未能取得剪贴板选区".to_string());
+            }
+
+            thread::spawn(move || loop {
+                match conn.wait_for_event() {
+                    Ok(Event::SelectionRequest(request)) => {
+                        if let Err(e) = answer(&conn, &request, targets, &offers) {
+                            eprintln!("应答剪贴板请求失败: {}", e);
+                        }
+                    },
+                    // 其他程序取得了选区，数据不再需要提供
+                    Ok(Event::SelectionClear(_)) => break,
+                    Ok(_) => {},
+                    Err(e) => {
+                        eprintln!("X11 剪贴板连接中断: {}", e);
+                        break;
+                    },
+                }
+            });
+
+            Ok(())
+        }
+
+        /// 将请求的格式写入请求方窗口的属性，并通知请求方
+        fn answer(
+            conn: &RustConnection,
+            request: &SelectionRequestEvent,
+            targets: Atom,
+            offers: &[(Atom, Vec<u8>)],
+        ) -> Result<(), String> {
+            // 旧式客户端的 property 为 None，按规范使用 target 作为属性名
+            let property = if request.property == u32::from(AtomEnum::NONE) {
+                request.target
+            } else {
+                request.property
+            };
+            let max_bytes = conn.maximum_request_bytes().saturating_sub(CHANGE_PROPERTY_HEADER);
+
+            let provided = if request.target == targets {
+                let atoms: Vec<Atom> = std::iter::once(targets)
+                    .chain(offers.iter().map(|(atom, _)| *atom))
+                    .collect();
+                conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &atoms)
+                    .map_err(|e| e.to_string())?;
+                true
+            } else {
+                match offers.iter().find(|(atom, _)| *atom == request.target) {
+                    Some((atom, data)) if data.len() <= max_bytes => {
+                        conn.change_property8(PropMode::REPLACE, request.requestor, property, *atom, data)
+                            .map_err(|e| e.to_string())?;
+                        true
+                    },
+                    _ => false,
+                }
+            };
+
+            let notify = SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property: if provided { property } else { AtomEnum::NONE.into() },
+            };
+            conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)
+                .map_err(|e| e.to_string())?;
+            conn.flush().map_err(|e| e.to_string())
+        }
+    }
+
+    /// 将本地路径转换为 file:// URI，对保留字符进行百分号编码
+    fn file_uri(path: &str) -> String {
+        let mut uri = String::from("file://");
+        for byte in path.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(byte as char),
+                _ => uri.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        uri
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    pub fn read_rtf() -> Option<String> {
+        None
+    }
+
+    pub fn write_rtf(_rtf: &str, _plain_text: &str) -> Result<(), String> {
+        Err("当前平台暂不支持写入RTF".to_string())
+    }
+
+    pub fn write_files(_paths: &[String]) -> Result<(), String> {
+        Err("当前平台暂不支持写入文件列表".to_string())
+    }
+}
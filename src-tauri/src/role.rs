@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use crate::prompt_template::PromptInput;
 
 /// AI角色定义
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,15 +8,74 @@ pub struct Role {
     pub id: String,
     pub name: String,
     pub description: String,
+    /// 提示词模板，可以引用剪贴板变量和自定义输入
     pub system_prompt: String,
     pub icon: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub avatar: Option<String>,
     pub is_custom: bool,
+    /// 提示词模板中用到的自定义输入
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<PromptInput>,
+    /// 角色使用的提供商、模型和参数
+    #[serde(flatten)]
+    pub binding: RoleBinding,
     pub created_at: u64,
     pub updated_at: u64,
 }
 
+/// 新建角色时前端传入的字段，ID和时间戳由后端生成
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewRole {
+    pub name: String,
+    pub description: String,
+    pub system_prompt: String,
+    pub icon: String,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    pub is_custom: bool,
+    #[serde(default)]
+    pub inputs: Vec<PromptInput>,
+    #[serde(flatten)]
+    pub binding: RoleBinding,
+}
+
+/// 角色绑定的提供商、模型和参数，未设置的项使用全局AI设置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoleBinding {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+    /// 是否使用流式输出
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+impl RoleBinding {
+    /// 检查参数范围，空字符串视为未设置
+    pub fn normalize(&mut self) -> Result<(), String> {
+        self.provider_id = self.provider_id.take().filter(|id| !id.trim().is_empty());
+        self.model = self.model.take().filter(|model| !model.trim().is_empty());
+        
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err("温度应在 0 到 2 之间".to_string());
+            }
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            if max_tokens <= 0 {
+                return Err("最大 token 数应大于 0".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
 /// 角色操作结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoleOperationResult {
@@ -36,6 +96,8 @@ impl Role {
             icon,
             avatar: None,
             is_custom: true,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         }
@@ -52,6 +114,26 @@ impl Role {
             icon,
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+    
+    /// 根据前端传入的字段创建角色
+    pub fn from_new(role: NewRole) -> Self {
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: role.name,
+            description: role.description,
+            system_prompt: role.system_prompt,
+            icon: role.icon,
+            avatar: role.avatar,
+            is_custom: role.is_custom,
+            inputs: role.inputs,
+            binding: role.binding,
             created_at: now,
             updated_at: now,
         }
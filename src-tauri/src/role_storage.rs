@@ -1,8 +1,8 @@
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::collections::HashMap;
-use crate::role::Role;
+use crate::role::{Role, RoleBinding};
+use crate::persistence;
+use crate::prompt_template;
 
 /// 角色存储管理器
 pub struct RoleStorage {
@@ -28,27 +28,15 @@ impl RoleStorage {
     
     /// 从文件加载数据
     fn load(&mut self) -> Result<(), String> {
-        // 如果文件不存在，返回空数据
-        if !self.file_path.exists() {
-            // 初始化预设角色
-            self.initialize_preset_roles();
-            return self.save(); // 保存预设角色
-        }
-        
-        // 读取文件内容
-        let mut file = File::open(&self.file_path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).map_err(|e| e.to_string())?;
-        
-        // 如果文件为空，返回空数据
-        if contents.trim().is_empty() {
-            // 初始化预设角色
-            self.initialize_preset_roles();
-            return self.save(); // 保存预设角色
-        }
-        
-        // 解析JSON数据
-        let roles: Vec<Role> = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        // 文件损坏时会从最近的有效备份恢复
+        let roles: Vec<Role> = match persistence::load_json(&self.file_path)? {
+            Some(roles) => roles,
+            None => {
+                // 文件不存在或为空，初始化预设角色
+                self.initialize_preset_roles();
+                return self.save(); // 保存预设角色
+            }
+        };
         
         // 转换为HashMap
         for role in roles {
@@ -71,6 +59,8 @@ impl RoleStorage {
             icon: "💬".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -84,6 +74,8 @@ impl RoleStorage {
             icon: "🔍".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -97,6 +89,8 @@ impl RoleStorage {
             icon: "📎".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -110,6 +104,8 @@ impl RoleStorage {
             icon: "📝".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -123,6 +119,8 @@ impl RoleStorage {
             icon: "✨".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -136,6 +134,8 @@ impl RoleStorage {
             icon: "🌐".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -149,6 +149,8 @@ impl RoleStorage {
             icon: "✍️".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -162,6 +164,8 @@ impl RoleStorage {
             icon: "🧠".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -175,6 +179,8 @@ impl RoleStorage {
             icon: "📱".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -188,6 +194,8 @@ impl RoleStorage {
             icon: "💻".to_string(),
             avatar: None,
             is_custom: false,
+            inputs: Vec::new(),
+            binding: RoleBinding::default(),
             created_at: now,
             updated_at: now,
         };
@@ -207,22 +215,11 @@ impl RoleStorage {
     
     /// 保存数据到文件
     fn save(&self) -> Result<(), String> {
-        // 确保目录存在
-        if let Some(parent) = self.file_path.parent() {
-            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-        }
-        
         // 将HashMap转换为Vec
         let roles: Vec<Role> = self.roles.values().cloned().collect();
         
-        // 序列化为JSON
-        let json = serde_json::to_string_pretty(&roles).map_err(|e| e.to_string())?;
-        
-        // 写入文件
-        let mut file = File::create(&self.file_path).map_err(|e| e.to_string())?;
-        file.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
-        
-        Ok(())
+        // 原子写入并保留备份
+        persistence::save_json(&self.file_path, &roles)
     }
     
     /// 获取所有角色
@@ -248,8 +245,17 @@ impl RoleStorage {
         self.roles.get(id)
     }
     
+    /// 获取角色绑定的提供商和参数，角色不存在时返回空的绑定
+    pub fn get_binding(&self, id: &str) -> RoleBinding {
+        self.roles.get(id).map(|role| role.binding.clone()).unwrap_or_default()
+    }
+    
     /// 添加角色
-    pub fn add_role(&mut self, role: Role) -> Result<(), String> {
+    pub fn add_role(&mut self, mut role: Role) -> Result<(), String> {
+        // 检查提示词模板和模型参数
+        prompt_template::validate(&role.system_prompt, &role.inputs)?;
+        role.binding.normalize()?;
+        
         // 添加角色
         self.roles.insert(role.id.clone(), role);
         
@@ -258,7 +264,7 @@ impl RoleStorage {
     }
     
     /// 更新角色
-    pub fn update_role(&mut self, role: Role) -> Result<(), String> {
+    pub fn update_role(&mut self, mut role: Role) -> Result<(), String> {
         // 检查角色是否存在
         if !self.roles.contains_key(&role.id) {
             return Err(format!("角色不存在: {}", role.id));
@@ -270,6 +276,10 @@ impl RoleStorage {
             return Err("不能将预设角色改为自定义角色".to_string());
         }
         
+        // 检查提示词模板和模型参数
+        prompt_template::validate(&role.system_prompt, &role.inputs)?;
+        role.binding.normalize()?;
+        
         // 更新角色
         self.roles.insert(role.id.clone(), role);
         
@@ -326,6 +336,8 @@ impl RoleStorage {
                     icon: "💬".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at, // 保留原始创建时间
                     updated_at: now,
                 };
@@ -341,6 +353,8 @@ impl RoleStorage {
                     icon: "🔍".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -356,6 +370,8 @@ impl RoleStorage {
                     icon: "📎".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -371,6 +387,8 @@ impl RoleStorage {
                     icon: "📝".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -386,6 +404,8 @@ impl RoleStorage {
                     icon: "✨".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -401,6 +421,8 @@ impl RoleStorage {
                     icon: "🌐".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -416,6 +438,8 @@ impl RoleStorage {
                     icon: "🧠".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -431,6 +455,8 @@ impl RoleStorage {
                     icon: "📱".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -446,6 +472,8 @@ impl RoleStorage {
                     icon: "💻".to_string(),
                     avatar: None,
                     is_custom: false,
+                    inputs: Vec::new(),
+                    binding: RoleBinding::default(),
                     created_at: role.created_at,
                     updated_at: now,
                 };
@@ -459,4 +487,4 @@ impl RoleStorage {
         // 保存到文件
         self.save()
     }
-} 
\ No newline at end of file
+} 
\ No newline at end of file
@@ -0,0 +1,672 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::clipboard::{ClipboardItem, ContentType};
+
+// BM25 参数
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+// 片段前后保留的字符数
+const SNIPPET_CONTEXT_CHARS: usize = 30;
+
+// 自然语言提问中不携带信息的英文词
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "the", "what", "which", "who", "where", "when", "why", "how", "is", "are", "was", "were",
+    "be", "been", "do", "did", "does", "i", "me", "my", "you", "your", "it", "its", "this", "that",
+    "these", "those", "of", "in", "on", "at", "to", "for", "from", "with", "about", "and", "or", "can",
+    "could", "would", "should", "have", "has", "had", "any", "some", "there", "please", "copy", "copied",
+    "clipboard", "paste", "pasted",
+];
+
+// 含有这些字的中文二元组不参与自然语言检索（助词、代词、疑问词）
+const STOP_CHARS: &[char] = &[
+    '的', '了', '是', '我', '你', '他', '她', '它', '们', '那', '这', '个', '么', '吗', '呢', '吧', '啊',
+    '哪', '怎', '在', '和', '把', '被', '过',
+];
+
+// 自然语言检索中与剪贴板操作本身有关、不携带信息的中文词
+const STOP_BIGRAMS: &[&str] = &["复制", "粘贴", "剪贴", "贴板", "之前", "刚才", "一下"];
+
+/// 可搜索的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchField {
+    Content,
+    Translation,
+    Summary,
+}
+
+impl SearchField {
+    const ALL: [SearchField; 3] = [SearchField::Content, SearchField::Translation, SearchField::Summary];
+
+    /// 字段在排序中的权重
+    fn weight(&self) -> f32 {
+        match self {
+            SearchField::Content => 1.0,
+            SearchField::Summary => 0.8,
+            SearchField::Translation => 0.6,
+        }
+    }
+
+    /// 从条目中取出字段文本
+    fn text<'a>(&self, item: &'a ClipboardItem) -> Option<&'a str> {
+        match self {
+            // 图片条目的 content 只是文字说明，不建立索引
+            SearchField::Content if item.content_type == ContentType::Image => None,
+            SearchField::Content => Some(item.content.as_str()),
+            SearchField::Translation => item.translation.as_deref(),
+            SearchField::Summary => item.summary.as_deref(),
+        }
+    }
+}
+
+/// 高亮的匹配片段
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchSnippet {
+    pub field: SearchField,
+    pub text: String,
+    /// 需要高亮的区间（按字符计算，左闭右开）
+    pub highlights: Vec<(usize, usize)>,
+}
+
+/// 搜索结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub item: ClipboardItem,
+    pub score: f32,
+    pub snippets: Vec<SearchSnippet>,
+}
+
+/// 分词结果
+#[derive(Debug, Clone)]
+struct Token {
+    term: String,
+    position: u32,
+    // 在原文中的字符区间
+    start: usize,
+    end: usize,
+}
+
+/// 判断是否为中日韩字符，这类字符按二元组切分
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF |   // 平假名、片假名
+        0x3400..=0x4DBF |   // 扩展A
+        0x4E00..=0x9FFF |   // 基本汉字
+        0xAC00..=0xD7AF |   // 韩文音节
+        0xF900..=0xFAFF |   // 兼容汉字
+        0x20000..=0x2A6DF)  // 扩展B
+}
+
+/// 查询词为单个中日韩字符时返回该字符
+///
+/// 连续的中日韩文字只按二元组建立索引，单字查询需要匹配所有包含它的二元组。
+fn single_cjk(term: &str) -> Option<char> {
+    let mut chars = term.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if is_cjk(c) => Some(c),
+        _ => None,
+    }
+}
+
+/// 将文本切分为词元
+///
+/// 拉丁字母与数字按连续片段切分并转小写；中日韩文字按相邻二元组切分，
+/// 单个孤立的中日韩字符作为一个词元。
+fn tokenize(text: &str) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0u32;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if is_cjk(c) {
+            // 找到连续的中日韩字符片段
+            let start = i;
+            while i < chars.len() && is_cjk(chars[i]) {
+                i += 1;
+            }
+            let run = &chars[start..i];
+
+            if run.len() == 1 {
+                tokens.push(Token { term: run[0].to_string(), position, start, end: start + 1 });
+                position += 1;
+            } else {
+                for offset in 0..run.len() - 1 {
+                    let term: String = run[offset..offset + 2].iter().collect();
+                    tokens.push(Token { term, position, start: start + offset, end: start + offset + 2 });
+                    position += 1;
+                }
+            }
+        } else if c.is_alphanumeric() {
+            let start = i;
+            while i < chars.len() && chars[i].is_alphanumeric() && !is_cjk(chars[i]) {
+                i += 1;
+            }
+            let term: String = chars[start..i].iter().collect::<String>().to_lowercase();
+            tokens.push(Token { term, position, start, end: i });
+            position += 1;
+        } else {
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+/// 查询子句
+#[derive(Debug, Clone)]
+struct QueryClause {
+    terms: Vec<String>,
+    // 最后一个词按前缀匹配
+    prefix: bool,
+}
+
+/// 解析查询语句
+///
+/// 支持的语法：
+/// - `foo bar`：所有词都必须出现
+/// - `"foo bar"`：短语，词必须相邻且有序
+/// - `foo*`：前缀匹配
+///
+/// 连续的中文会被切成多个二元组，并作为短语匹配。
+fn parse_query(query: &str) -> Vec<QueryClause> {
+    let mut clauses = Vec::new();
+    let mut rest = query.trim();
+
+    while !rest.is_empty() {
+        let (raw, remaining) = if let Some(stripped) = rest.strip_prefix('"') {
+            match stripped.find('"') {
+                Some(end) => (&stripped[..end], &stripped[end + 1..]),
+                None => (stripped, ""),
+            }
+        } else {
+            match rest.find(char::is_whitespace) {
+                Some(end) => (&rest[..end], &rest[end..]),
+                None => (rest, ""),
+            }
+        };
+        rest = remaining.trim_start();
+
+        let prefix = raw.ends_with('*');
+        let terms: Vec<String> = tokenize(raw).into_iter().map(|t| t.term).collect();
+        if !terms.is_empty() {
+            clauses.push(QueryClause { terms, prefix });
+        }
+    }
+
+    clauses
+}
+
+/// 将自然语言问题拆成独立的查询词，去掉停用词和重复的词
+fn question_terms(question: &str) -> Vec<QueryClause> {
+    let mut seen = HashSet::new();
+    tokenize(question)
+        .into_iter()
+        .map(|token| token.term)
+        .filter(|term| {
+            let cjk = term.chars().any(is_cjk);
+            if cjk {
+                !term.chars().any(|c| STOP_CHARS.contains(&c)) && !STOP_BIGRAMS.contains(&term.as_str())
+            } else {
+                !STOP_WORDS.contains(&term.as_str())
+            }
+        })
+        .filter(|term| seen.insert(term.clone()))
+        .map(|term| QueryClause { terms: vec![term], prefix: false })
+        .collect()
+}
+
+/// 单个字段的倒排索引
+#[derive(Default)]
+struct FieldIndex {
+    // 词 -> 条目ID -> 出现位置
+    postings: BTreeMap<String, HashMap<String, Vec<u32>>>,
+    // 条目ID -> 包含的词，用于删除
+    doc_terms: HashMap<String, Vec<String>>,
+    doc_lengths: HashMap<String, u32>,
+    total_length: u64,
+}
+
+impl FieldIndex {
+    fn insert(&mut self, id: &str, text: &str) {
+        let tokens = tokenize(text);
+        let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+        for token in &tokens {
+            positions.entry(token.term.clone()).or_default().push(token.position);
+        }
+
+        let terms: Vec<String> = positions.keys().cloned().collect();
+        for (term, term_positions) in positions {
+            self.postings.entry(term).or_default().insert(id.to_string(), term_positions);
+        }
+
+        self.doc_terms.insert(id.to_string(), terms);
+        self.doc_lengths.insert(id.to_string(), tokens.len() as u32);
+        self.total_length += tokens.len() as u64;
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(terms) = self.doc_terms.remove(id) {
+            for term in terms {
+                if let Some(docs) = self.postings.get_mut(&term) {
+                    docs.remove(id);
+                    if docs.is_empty() {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+        }
+        if let Some(length) = self.doc_lengths.remove(id) {
+            self.total_length -= length as u64;
+        }
+    }
+
+    /// 取出与词（或前缀）匹配的所有倒排表，以及需要加到位置上的偏移
+    ///
+    /// 单个中日韩字符匹配以它开头或结尾的二元组。以它结尾的二元组位置加一，
+    /// 使同一个字出现在两个相邻二元组中时只计一次。
+    fn expand(&self, term: &str, prefix: bool) -> Vec<(&HashMap<String, Vec<u32>>, u32)> {
+        let starting_with = self.postings
+            .range(term.to_string()..)
+            .take_while(|(key, _)| key.starts_with(term))
+            .map(|(_, docs)| (docs, 0));
+
+        if prefix {
+            return starting_with.collect();
+        }
+        match single_cjk(term) {
+            Some(c) => starting_with
+                .chain(self.postings.iter().filter_map(|(key, docs)| {
+                    let mut chars = key.chars();
+                    (chars.next().is_some() && chars.next() == Some(c) && chars.next().is_none()).then_some((docs, 1))
+                }))
+                .collect(),
+            None => self.postings.get(term).map(|docs| (docs, 0)).into_iter().collect(),
+        }
+    }
+
+    /// 计算子句在每个条目中的命中次数
+    fn match_clause(&self, clause: &QueryClause) -> HashMap<String, u32> {
+        let last = clause.terms.len() - 1;
+
+        // 每个词对应的 条目ID -> 位置集合
+        let mut per_term: Vec<HashMap<&str, HashSet<u32>>> = Vec::with_capacity(clause.terms.len());
+        for (i, term) in clause.terms.iter().enumerate() {
+            let mut merged: HashMap<&str, HashSet<u32>> = HashMap::new();
+            for (docs, shift) in self.expand(term, clause.prefix && i == last) {
+                for (id, positions) in docs {
+                    merged.entry(id.as_str()).or_default().extend(positions.iter().map(|position| position + shift));
+                }
+            }
+            if merged.is_empty() {
+                return HashMap::new();
+            }
+            per_term.push(merged);
+        }
+
+        let mut matches = HashMap::new();
+        for (id, first_positions) in &per_term[0] {
+            // 统计短语出现次数：每个起始位置后续的词都要依次相邻
+            let count = first_positions
+                .iter()
+                .filter(|&&start| {
+                    per_term[1..].iter().enumerate().all(|(offset, docs)| {
+                        docs.get(id)
+                            .map(|positions| positions.contains(&(start + offset as u32 + 1)))
+                            .unwrap_or(false)
+                    })
+                })
+                .count() as u32;
+
+            if count > 0 {
+                matches.insert(id.to_string(), count);
+            }
+        }
+
+        matches
+    }
+
+    fn average_length(&self) -> f32 {
+        if self.doc_lengths.is_empty() {
+            0.0
+        } else {
+            self.total_length as f32 / self.doc_lengths.len() as f32
+        }
+    }
+}
+
+/// 剪贴板历史的全文索引
+#[derive(Default)]
+pub struct SearchIndex {
+    fields: HashMap<SearchField, FieldIndex>,
+    doc_count: usize,
+    indexed: HashSet<String>,
+}
+
+impl SearchIndex {
+    /// 创建空索引
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 索引（或重新索引）一个条目
+    pub fn index_item(&mut self, item: &ClipboardItem) {
+        self.remove_item(&item.id);
+
+        for field in SearchField::ALL {
+            if let Some(text) = field.text(item) {
+                self.fields.entry(field).or_default().insert(&item.id, text);
+            }
+        }
+
+        self.indexed.insert(item.id.clone());
+        self.doc_count += 1;
+    }
+
+    /// 从索引中移除条目
+    pub fn remove_item(&mut self, id: &str) {
+        if !self.indexed.remove(id) {
+            return;
+        }
+
+        for index in self.fields.values_mut() {
+            index.remove(id);
+        }
+        self.doc_count -= 1;
+    }
+
+    /// 清空索引
+    pub fn clear(&mut self) {
+        self.fields.clear();
+        self.indexed.clear();
+        self.doc_count = 0;
+    }
+
+    /// 执行查询，返回按相关度排序的条目ID与得分
+    pub fn query(&self, query: &str) -> Vec<(String, f32)> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() {
+            return Vec::new();
+        }
+
+        // 所有子句都必须命中
+        let (scores, matched_clauses) = self.score_clauses(&clauses);
+        let results = scores
+            .into_iter()
+            .filter(|(id, _)| matched_clauses.get(id) == Some(&clauses.len()))
+            .collect();
+        sort_by_score(results)
+    }
+
+    /// 按自然语言问题检索，只要求命中部分查询词
+    ///
+    /// 问题中的停用词会被去掉，其余每个词单独匹配，至少命中三分之一的词才算相关。
+    /// 得分按命中词的比例加权，命中越多的条目越靠前。
+    pub fn query_any(&self, question: &str) -> Vec<(String, f32)> {
+        let clauses = question_terms(question);
+        if clauses.is_empty() {
+            return Vec::new();
+        }
+
+        let minimum = clauses.len().div_ceil(3);
+        let (scores, matched_clauses) = self.score_clauses(&clauses);
+        let results = scores
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let matched = *matched_clauses.get(&id)?;
+                (matched >= minimum).then(|| (id, score * matched as f32 / clauses.len() as f32))
+            })
+            .collect();
+        sort_by_score(results)
+    }
+
+    /// 计算每个条目的 BM25 得分和命中的子句数
+    fn score_clauses(&self, clauses: &[QueryClause]) -> (HashMap<String, f32>, HashMap<String, usize>) {
+        let n = self.doc_count as f32;
+        let mut scores: HashMap<String, f32> = HashMap::new();
+        let mut matched_clauses: HashMap<String, usize> = HashMap::new();
+
+        for clause in clauses {
+            let mut clause_scores: HashMap<String, f32> = HashMap::new();
+
+            for (field, index) in &self.fields {
+                let matches = index.match_clause(clause);
+                if matches.is_empty() {
+                    continue;
+                }
+
+                let df = matches.len() as f32;
+                let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+                let avg_length = index.average_length().max(1.0);
+
+                for (id, tf) in matches {
+                    let length = *index.doc_lengths.get(&id).unwrap_or(&0) as f32;
+                    let tf = tf as f32;
+                    let score = idf * tf * (BM25_K1 + 1.0)
+                        / (tf + BM25_K1 * (1.0 - BM25_B + BM25_B * length / avg_length));
+                    *clause_scores.entry(id).or_insert(0.0) += score * field.weight();
+                }
+            }
+
+            for (id, score) in clause_scores {
+                *scores.entry(id.clone()).or_insert(0.0) += score;
+                *matched_clauses.entry(id).or_insert(0) += 1;
+            }
+        }
+
+        (scores, matched_clauses)
+    }
+
+    /// 为条目生成高亮片段
+    pub fn snippets(&self, item: &ClipboardItem, query: &str) -> Vec<SearchSnippet> {
+        self.build_snippets(item, &parse_query(query))
+    }
+
+    /// 为按自然语言问题检索到的条目生成高亮片段
+    pub fn question_snippets(&self, item: &ClipboardItem, question: &str) -> Vec<SearchSnippet> {
+        self.build_snippets(item, &question_terms(question))
+    }
+
+    fn build_snippets(&self, item: &ClipboardItem, clauses: &[QueryClause]) -> Vec<SearchSnippet> {
+        SearchField::ALL
+            .iter()
+            .filter_map(|field| {
+                let text = field.text(item)?;
+                build_snippet(*field, text, clauses)
+            })
+            .collect()
+    }
+}
+
+/// 按得分从高到低排序
+fn sort_by_score(mut results: Vec<(String, f32)>) -> Vec<(String, f32)> {
+    results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    results
+}
+
+/// 在字段文本中定位匹配词并截取上下文
+fn build_snippet(field: SearchField, text: &str, clauses: &[QueryClause]) -> Option<SearchSnippet> {
+    let tokens = tokenize(text);
+
+    // 找出所有与查询词匹配的区间，单个中日韩字符只高亮二元组中的这个字
+    let mut ranges: Vec<(usize, usize)> = tokens
+        .iter()
+        .flat_map(|token| {
+            clauses.iter().flat_map(move |clause| {
+                let last = clause.terms.len() - 1;
+                clause.terms.iter().enumerate().filter_map(move |(i, term)| {
+                    if clause.prefix && i == last {
+                        token.term.starts_with(term.as_str()).then_some((token.start, token.end))
+                    } else if let Some(c) = single_cjk(term) {
+                        token.term.chars().position(|t| t == c).map(|offset| (token.start + offset, token.start + offset + 1))
+                    } else {
+                        (&token.term == term).then_some((token.start, token.end))
+                    }
+                })
+            })
+        })
+        .collect();
+
+    if ranges.is_empty() {
+        return None;
+    }
+
+    // 合并相邻或重叠的区间（中文二元组会互相重叠）
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    // 以第一个匹配为中心截取片段
+    let chars: Vec<char> = text.chars().collect();
+    let window_start = merged[0].0.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let window_end = (merged[0].1 + SNIPPET_CONTEXT_CHARS * 2).min(chars.len());
+
+    let mut snippet: String = chars[window_start..window_end].iter().collect();
+    let mut shift = window_start;
+    if window_start > 0 {
+        snippet.insert(0, '…');
+        shift = shift.saturating_sub(1);
+    }
+    if window_end < chars.len() {
+        snippet.push('…');
+    }
+
+    let highlights = merged
+        .into_iter()
+        .filter(|(start, end)| *start >= window_start && *end <= window_end)
+        .map(|(start, end)| (start - shift, end - shift))
+        .collect();
+
+    Some(SearchSnippet { field, text: snippet, highlights })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 依次索引给定内容，返回索引和各条目的ID
+    fn build_index(contents: &[&str]) -> (SearchIndex, Vec<String>) {
+        let mut index = SearchIndex::new();
+        let ids = contents
+            .iter()
+            .map(|content| {
+                let item = ClipboardItem::new(content.to_string());
+                index.index_item(&item);
+                item.id
+            })
+            .collect();
+        (index, ids)
+    }
+
+    fn result_ids(results: Vec<(String, f32)>) -> Vec<String> {
+        results.into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// 子句的词以空格连接，便于比较
+    fn clause_terms(query: &str) -> Vec<(String, bool)> {
+        parse_query(query)
+            .into_iter()
+            .map(|clause| (clause.terms.join(" "), clause.prefix))
+            .collect()
+    }
+
+    #[test]
+    fn parse_query_splits_words_phrases_and_prefixes() {
+        assert_eq!(
+            clause_terms(r#"Foo "bar baz" qu*"#),
+            [("foo", false), ("bar baz", false), ("qu", true)].map(|(terms, prefix)| (terms.to_string(), prefix))
+        );
+    }
+
+    #[test]
+    fn parse_query_splits_chinese_into_bigrams() {
+        assert_eq!(clause_terms("数据库"), [("数据 据库".to_string(), false)]);
+        assert_eq!(clause_terms("库"), [("库".to_string(), false)]);
+    }
+
+    #[test]
+    fn words_must_all_match_but_phrases_must_be_adjacent() {
+        let (index, ids) = build_index(&["quick brown fox", "brown dog is quick"]);
+
+        let mut all_words = result_ids(index.query("quick brown"));
+        all_words.sort();
+        let mut expected = ids.clone();
+        expected.sort();
+        assert_eq!(all_words, expected);
+
+        assert_eq!(result_ids(index.query(r#""quick brown""#)), vec![ids[0].clone()]);
+        assert!(index.query("quick cat").is_empty());
+    }
+
+    #[test]
+    fn prefix_matches_longer_words() {
+        let (index, ids) = build_index(&["configuration file", "confirm order", "unrelated"]);
+
+        let mut results = result_ids(index.query("conf*"));
+        results.sort();
+        let mut expected = vec![ids[0].clone(), ids[1].clone()];
+        expected.sort();
+        assert_eq!(results, expected);
+        assert!(index.query("conf").is_empty());
+    }
+
+    #[test]
+    fn single_cjk_character_matches_inside_bigrams() {
+        let (index, ids) = build_index(&["数据库密码", "库", "今天天气"]);
+
+        let mut results = result_ids(index.query("库"));
+        results.sort();
+        let mut expected = vec![ids[0].clone(), ids[1].clone()];
+        expected.sort();
+        assert_eq!(results, expected);
+
+        let snippet = build_snippet(SearchField::Content, "数据库密码", &parse_query("库")).unwrap();
+        assert_eq!(snippet.highlights, vec![(2, 3)]);
+    }
+
+    #[test]
+    fn more_occurrences_in_shorter_text_rank_first() {
+        let (index, ids) = build_index(&[
+            "rust appears once in this rather long sentence about other things",
+            "rust rust",
+            "nothing here",
+        ]);
+
+        assert_eq!(result_ids(index.query("rust")), vec![ids[1].clone(), ids[0].clone()]);
+    }
+
+    #[test]
+    fn question_finds_item_with_some_terms() {
+        let (index, ids) = build_index(&[
+            "staging db password: Stg-2024!xK",
+            "lunch order for the team",
+            "production db host: 10.0.0.12",
+        ]);
+
+        let results = result_ids(index.query_any("what was the staging DB password format I copied?"));
+        assert_eq!(results.first(), Some(&ids[0]));
+        assert!(!results.contains(&ids[1]));
+    }
+
+    #[test]
+    fn chinese_question_finds_item() {
+        let (index, ids) = build_index(&["测试环境数据库密码：Test#123", "周五下午开会"]);
+
+        let results = result_ids(index.query_any("我之前复制的测试环境数据库密码是什么？"));
+        assert_eq!(results, vec![ids[0].clone()]);
+    }
+
+    #[test]
+    fn question_of_stop_words_matches_nothing() {
+        let (index, _) = build_index(&["what was that"]);
+        assert!(index.query_any("what was the").is_empty());
+    }
+}
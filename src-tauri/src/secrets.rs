@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use base64::{engine::general_purpose, Engine as _};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::persistence;
+
+// 系统钥匙串中使用的服务名
+const KEYRING_SERVICE: &str = "copy2ai";
+
+// 设置文件中密钥引用的前缀，例如 "secret:ai.Copy2AI"
+const REFERENCE_PREFIX: &str = "secret:";
+
+// 密钥存储无法读取时返回给前端的占位值，保存设置时遇到它会保留原有引用
+pub const LOCKED_MARKER: &str = "secret-locked";
+
+// 用于校验口令是否正确的明文
+const CHECK_PLAINTEXT: &[u8] = b"copy2ai-secrets";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// 密钥在设置文件中的引用
+pub fn reference(name: &str) -> String {
+    format!("{}{}", REFERENCE_PREFIX, name)
+}
+
+/// 解析引用中的密钥名称，不是引用时返回 None
+pub fn parse_reference(value: &str) -> Option<&str> {
+    value.strip_prefix(REFERENCE_PREFIX)
+}
+
+/// 是否为密钥无法读取时的占位值
+pub fn is_locked_marker(value: &str) -> bool {
+    value == LOCKED_MARKER
+}
+
+/// 加密文件的内容，所有值均为 base64 编码
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EncryptedFileData {
+    salt: String,
+    /// 加密后的校验值，用于判断口令是否正确
+    check: String,
+    /// 密钥名称 -> 随机数与密文
+    #[serde(default)]
+    entries: HashMap<String, String>,
+}
+
+/// 口令保护的加密文件，系统钥匙串不可用时使用
+struct EncryptedFile {
+    path: PathBuf,
+    data: Option<EncryptedFileData>,
+    /// 解锁后由口令派生的密钥
+    cipher: Option<Aes256Gcm>,
+}
+
+impl EncryptedFile {
+    fn open(path: PathBuf) -> Self {
+        let data = persistence::load_json(&path).unwrap_or_else(|e| {
+            eprintln!("加载密钥文件失败: {}", e);
+            None
+        });
+        Self { path, data, cipher: None }
+    }
+
+    fn derive_cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, String> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| format!("派生密钥失败: {}", e))?;
+        Aes256Gcm::new_from_slice(&key).map_err(|e| format!("创建加密器失败: {}", e))
+    }
+
+    fn encrypt(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| "加密失败".to_string())?;
+
+        let mut combined = nonce.to_vec();
+        combined.extend_from_slice(&ciphertext);
+        Ok(general_purpose::STANDARD.encode(combined))
+    }
+
+    fn decrypt(cipher: &Aes256Gcm, encoded: &str) -> Result<Vec<u8>, String> {
+        let combined = general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("密文格式无效: {}", e))?;
+        if combined.len() < NONCE_LEN {
+            return Err("密文格式无效".to_string());
+        }
+
+        let (nonce, ciphertext) = combined.split_at(NONCE_LEN);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "解密失败".to_string())
+    }
+
+    /// 用口令解锁，首次使用时以该口令创建加密文件
+    fn unlock(&mut self, passphrase: &str) -> Result<(), String> {
+        if passphrase.is_empty() {
+            return Err("口令不能为空".to_string());
+        }
+
+        match &self.data {
+            Some(data) => {
+                let salt = general_purpose::STANDARD
+                    .decode(&data.salt)
+                    .map_err(|e| format!("密钥文件已损坏: {}", e))?;
+                let cipher = Self::derive_cipher(passphrase, &salt)?;
+                if Self::decrypt(&cipher, &data.check).ok().as_deref() != Some(CHECK_PLAINTEXT) {
+                    return Err("口令错误".to_string());
+                }
+                self.cipher = Some(cipher);
+            },
+            None => {
+                let mut salt = [0u8; SALT_LEN];
+                rand::thread_rng().fill(&mut salt);
+                let cipher = Self::derive_cipher(passphrase, &salt)?;
+                self.data = Some(EncryptedFileData {
+                    salt: general_purpose::STANDARD.encode(salt),
+                    check: Self::encrypt(&cipher, CHECK_PLAINTEXT)?,
+                    entries: HashMap::new(),
+                });
+                self.cipher = Some(cipher);
+                self.save()?;
+            },
+        }
+
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        match &self.data {
+            Some(data) => persistence::save_json(&self.path, data),
+            None => Ok(()),
+        }
+    }
+
+    fn unlocked(&mut self) -> Result<(&Aes256Gcm, &mut EncryptedFileData), String> {
+        match (&self.cipher, &mut self.data) {
+            (Some(cipher), Some(data)) => Ok((cipher, data)),
+            _ => Err("密钥文件未解锁，请先输入口令".to_string()),
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Result<Option<String>, String> {
+        let (cipher, data) = self.unlocked()?;
+        data.entries
+            .get(name)
+            .map(|encoded| {
+                let plaintext = Self::decrypt(cipher, encoded)?;
+                String::from_utf8(plaintext).map_err(|e| format!("密钥内容无效: {}", e))
+            })
+            .transpose()
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let (cipher, data) = self.unlocked()?;
+        let encrypted = Self::encrypt(cipher, value.as_bytes())?;
+        data.entries.insert(name.to_string(), encrypted);
+        self.save()
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), String> {
+        let (_, data) = self.unlocked()?;
+        if data.entries.remove(name).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+enum Backend {
+    /// 系统钥匙串（Linux 上为 Secret Service）
+    Keyring,
+    EncryptedFile(Box<EncryptedFile>),
+}
+
+/// 密钥存储的状态，供前端判断是否需要输入口令
+#[derive(Debug, Clone, Serialize)]
+pub struct SecretStoreStatus {
+    /// "keyring" 或 "encrypted_file"
+    pub backend: &'static str,
+    /// 是否可以读写密钥
+    pub unlocked: bool,
+    /// 加密文件是否已设置口令，使用系统钥匙串时始终为 true
+    pub initialized: bool,
+}
+
+/// API 密钥等敏感信息的存储
+///
+/// 优先使用系统钥匙串，不可用时改用口令保护的加密文件。设置文件中只保存
+/// `reference` 生成的引用，读取时再通过 `resolve` 取回明文。
+pub struct SecretStore {
+    backend: Mutex<Backend>,
+}
+
+impl SecretStore {
+    pub fn new(data_dir: &Path) -> Self {
+        let file_path = data_dir.join("secrets.enc.json");
+
+        // 已经使用加密文件的继续使用，避免密钥分散在两处
+        let backend = if !file_path.exists() && Self::keyring_available() {
+            Backend::Keyring
+        } else {
+            eprintln!("使用加密文件保存密钥: {:?}", file_path);
+            Backend::EncryptedFile(Box::new(EncryptedFile::open(file_path)))
+        };
+
+        Self { backend: Mutex::new(backend) }
+    }
+
+    /// 检查系统钥匙串能否访问
+    fn keyring_available() -> bool {
+        match keyring::Entry::new(KEYRING_SERVICE, "availability-check").and_then(|entry| entry.get_password()) {
+            Ok(_) | Err(keyring::Error::NoEntry) => true,
+            Err(e) => {
+                eprintln!("无法访问系统钥匙串: {}", e);
+                false
+            },
+        }
+    }
+
+    fn keyring_entry(name: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYRING_SERVICE, name).map_err(|e| format!("访问系统钥匙串失败: {}", e))
+    }
+
+    /// 当前状态
+    pub fn status(&self) -> Result<SecretStoreStatus, String> {
+        let backend = self.backend.lock().map_err(|e| e.to_string())?;
+        Ok(match &*backend {
+            Backend::Keyring => SecretStoreStatus { backend: "keyring", unlocked: true, initialized: true },
+            Backend::EncryptedFile(file) => SecretStoreStatus {
+                backend: "encrypted_file",
+                unlocked: file.cipher.is_some(),
+                initialized: file.data.is_some(),
+            },
+        })
+    }
+
+    /// 是否可以读写密钥
+    ///
+    /// 启动时系统钥匙串尚未就绪而改用了加密文件、且加密文件还没有设置口令时，
+    /// 重新检查系统钥匙串，可用则改用系统钥匙串，使明文密钥能尽早迁移。
+    pub fn is_available(&self) -> bool {
+        let Ok(mut backend) = self.backend.lock() else {
+            return false;
+        };
+        if let Backend::EncryptedFile(file) = &*backend {
+            if file.data.is_none() && Self::keyring_available() {
+                *backend = Backend::Keyring;
+            }
+        }
+        match &*backend {
+            Backend::Keyring => true,
+            Backend::EncryptedFile(file) => file.cipher.is_some(),
+        }
+    }
+
+    /// 解锁加密文件，首次使用时设置口令；使用系统钥匙串时无需解锁
+    pub fn unlock(&self, passphrase: &str) -> Result<(), String> {
+        let mut backend = self.backend.lock().map_err(|e| e.to_string())?;
+        match &mut *backend {
+            Backend::Keyring => Ok(()),
+            Backend::EncryptedFile(file) => file.unlock(passphrase),
+        }
+    }
+
+    /// 读取密钥，不存在时返回 None
+    pub fn get(&self, name: &str) -> Result<Option<String>, String> {
+        let mut backend = self.backend.lock().map_err(|e| e.to_string())?;
+        match &mut *backend {
+            Backend::Keyring => match Self::keyring_entry(name)?.get_password() {
+                Ok(value) => Ok(Some(value)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(format!("读取系统钥匙串失败: {}", e)),
+            },
+            Backend::EncryptedFile(file) => file.get(name),
+        }
+    }
+
+    /// 写入密钥
+    pub fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        let mut backend = self.backend.lock().map_err(|e| e.to_string())?;
+        match &mut *backend {
+            Backend::Keyring => Self::keyring_entry(name)?
+                .set_password(value)
+                .map_err(|e| format!("写入系统钥匙串失败: {}", e)),
+            Backend::EncryptedFile(file) => file.set(name, value),
+        }
+    }
+
+    /// 删除密钥，不存在时忽略
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        let mut backend = self.backend.lock().map_err(|e| e.to_string())?;
+        match &mut *backend {
+            Backend::Keyring => match Self::keyring_entry(name)?.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(format!("删除系统钥匙串中的密钥失败: {}", e)),
+            },
+            Backend::EncryptedFile(file) => file.delete(name),
+        }
+    }
+
+    /// 保存设置中的密钥，返回应写入设置文件的值
+    ///
+    /// 已经是引用的值原样返回，空值会删除已保存的密钥。占位值应由调用方换回原有引用。
+    pub fn store(&self, name: &str, value: &str) -> Result<String, String> {
+        if is_locked_marker(value) {
+            return Err("密钥存储未解锁，无法保存密钥".to_string());
+        }
+        if parse_reference(value).is_some() {
+            return Ok(value.to_string());
+        }
+        if value.is_empty() {
+            self.delete(name)?;
+            return Ok(String::new());
+        }
+
+        self.set(name, value)?;
+        Ok(reference(name))
+    }
+
+    /// 将设置中的值解析为明文，不是引用的值原样返回
+    pub fn resolve(&self, value: &str) -> Result<String, String> {
+        match parse_reference(value) {
+            Some(name) => self.get(name)?.ok_or_else(|| format!("密钥不存在: {}", name)),
+            None => Ok(value.to_string()),
+        }
+    }
+}
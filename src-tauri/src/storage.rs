@@ -1,41 +1,87 @@
-use std::fs::{self, File};
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
-use std::collections::HashMap;
-use crate::clipboard::{ClipboardItem, ClipboardFilter};
+use std::fs;
+use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use crate::blob_store::{self, BlobStore};
+use crate::clipboard::{ClipboardItem, ClipboardFilter, ContentType, ImageInfo};
+use crate::search::{SearchHit, SearchIndex};
+use crate::vector_index::{self, VectorIndex};
+use crate::persistence;
 use serde::{Serialize, Deserialize};
+use rusqlite::{params, Connection};
 
 /// 存储配置
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct StorageConfig {
     max_items: usize,
+    #[serde(default = "default_poll_interval_ms")]
+    poll_interval_ms: u64,
+}
+
+// 默认每秒轮询一次剪贴板
+fn default_poll_interval_ms() -> u64 {
+    1000
 }
 
 impl Default for StorageConfig {
     fn default() -> Self {
         Self {
             max_items: 404, // 默认最大保存404条记录
+            poll_interval_ms: default_poll_interval_ms(),
         }
     }
 }
 
 /// 存储管理器
+///
+/// 剪贴板历史保存在 SQLite 数据库中，每次增删改都是一个独立的小事务；
+/// 内存中保留一份条目副本，用于排序、筛选和分页。
+/// 图片原始数据保存在 `blobs/` 目录中，条目被删除或淘汰后一并清理。
+/// 语义搜索使用的向量保存在 `clipboard_embeddings` 表中，内容修改后删除，等待重新计算。
 pub struct Storage {
-    file_path: PathBuf,
+    db_path: PathBuf,
     config_path: PathBuf,
+    conn: Connection,
+    /// 数据库是否成功打开，为 false 时使用的是内存数据库
+    persistent: bool,
+    blobs: BlobStore,
     items: HashMap<String, ClipboardItem>,
+    search_index: SearchIndex,
+    vector_index: VectorIndex,
     config: StorageConfig,
 }
 
 impl Storage {
     /// 创建一个新的存储管理器
-    pub fn new(file_path: PathBuf) -> Self {
-        let config_path = file_path.with_file_name("clipboard_config.json");
+    pub fn new(db_path: PathBuf) -> Self {
+        let config_path = db_path.with_file_name("clipboard_config.json");
+        
+        // 确保目录存在
+        if let Some(parent) = db_path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                eprintln!("创建历史记录目录失败: {:?} - {}", parent, e);
+            }
+        }
+        
+        // 打开数据库，失败时退回内存数据库，保证应用仍可运行
+        let (conn, persistent) = match Connection::open(&db_path) {
+            Ok(conn) => (conn, true),
+            Err(e) => {
+                eprintln!("打开剪贴板数据库失败: {:?} - {}", db_path, e);
+                (Connection::open_in_memory().expect("无法创建内存数据库"), false)
+            }
+        };
+        
+        let blobs = BlobStore::new(db_path.with_file_name("blobs"));
         
         let mut storage = Self {
-            file_path,
+            db_path,
             config_path,
+            conn,
+            persistent,
+            blobs,
             items: HashMap::new(),
+            search_index: SearchIndex::new(),
+            vector_index: VectorIndex::new(),
             config: StorageConfig::default(),
         };
         
@@ -44,146 +90,247 @@ impl Storage {
             // eprintln!("加载存储配置失败: {}", e);
         });
         
+        // 初始化数据库结构
+        if let Err(e) = storage.init_schema() {
+            eprintln!("初始化剪贴板数据库失败: {}", e);
+        }
+        
+        // 首次启动时从旧的JSON文件迁移历史记录
+        if let Err(e) = storage.migrate_from_json() {
+            eprintln!("迁移剪贴板历史失败: {}", e);
+        }
+        
         // 加载现有数据
-        storage.load().unwrap_or_else(|_e| {
-            // eprintln!("加载剪贴板历史失败: {}", e);
-        });
+        let fully_loaded = match storage.load() {
+            Ok(skipped) => skipped == 0,
+            Err(e) => {
+                eprintln!("加载剪贴板历史失败: {}", e);
+                false
+            }
+        };
+        
+        // 加载语义搜索使用的向量
+        if let Err(e) = storage.load_embeddings() {
+            eprintln!("加载剪贴板向量失败: {}", e);
+        }
+        
+        // 将旧版内嵌在条目中的图片移入图片存储
+        if let Err(e) = storage.migrate_inline_images() {
+            eprintln!("迁移图片数据失败: {}", e);
+        }
+        
+        // 为旧版本保存的、尚未识别过内容的条目补充分类
+        if let Err(e) = storage.classify_legacy_items() {
+            eprintln!("识别历史记录分类失败: {}", e);
+        }
+        
+        // 清理没有条目引用的图片文件；数据库未能打开或有条目未能加载时无法判断哪些图片仍被引用，跳过清理
+        if persistent && fully_loaded {
+            let live_hashes: HashSet<&str> = storage.items
+                .values()
+                .filter_map(|item| item.image.as_ref().map(|image| image.hash.as_str()))
+                .collect();
+            storage.blobs.collect_garbage(&live_hashes);
+        } else {
+            eprintln!("剪贴板历史未完整加载，跳过图片清理");
+        }
         
         storage
     }
     
-    /// 从文件加载配置
-    fn load_config(&mut self) -> Result<(), String> {
-        // 如果文件不存在，使用默认配置
-        if !self.config_path.exists() {
+    /// 初始化数据库表结构
+    fn init_schema(&self) -> Result<(), String> {
+        self.conn
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 PRAGMA synchronous = NORMAL;
+                 CREATE TABLE IF NOT EXISTS clipboard_items (
+                     id TEXT PRIMARY KEY,
+                     timestamp INTEGER NOT NULL,
+                     pinned INTEGER NOT NULL DEFAULT 0,
+                     favorite INTEGER NOT NULL DEFAULT 0,
+                     category TEXT,
+                     data TEXT NOT NULL
+                 );
+                 CREATE INDEX IF NOT EXISTS idx_clipboard_items_timestamp
+                     ON clipboard_items (timestamp);
+                 CREATE TABLE IF NOT EXISTS clipboard_embeddings (
+                     id TEXT PRIMARY KEY,
+                     model TEXT NOT NULL,
+                     vector BLOB NOT NULL
+                 );",
+            )
+            .map_err(|e| format!("创建数据表失败: {}", e))
+    }
+    
+    /// 将旧版 clipboard_history.json 中的数据导入数据库
+    fn migrate_from_json(&mut self) -> Result<(), String> {
+        let legacy_path = self.db_path.with_file_name("clipboard_history.json");
+        if !legacy_path.exists() {
             return Ok(());
         }
         
-        // 读取文件内容
-        let mut file = File::open(&self.config_path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).map_err(|e| e.to_string())?;
-        
-        // 如果文件为空，使用默认配置
-        if contents.trim().is_empty() {
+        // 数据库中已有数据时不再重复导入
+        let count: i64 = self.conn
+            .query_row("SELECT COUNT(*) FROM clipboard_items", [], |row| row.get(0))
+            .map_err(|e| format!("查询历史记录数量失败: {}", e))?;
+        if count > 0 {
             return Ok(());
         }
         
-        // 解析JSON数据
-        self.config = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        // 读取旧数据，文件损坏时尝试从备份恢复
+        let items: Vec<ClipboardItem> = persistence::load_json(&legacy_path)
+            .map_err(|e| format!("读取旧版历史记录失败: {}", e))?
+            .unwrap_or_default();
+        
+        // 在一个事务中导入全部条目
+        let tx = self.conn.transaction().map_err(|e| format!("开启事务失败: {}", e))?;
+        for item in &items {
+            Self::write_item(&tx, item)?;
+        }
+        tx.commit().map_err(|e| format!("提交迁移事务失败: {}", e))?;
+        
+        // 导入成功后重命名旧文件，避免再次迁移
+        let migrated_path = legacy_path.with_extension("json.migrated");
+        fs::rename(&legacy_path, &migrated_path)
+            .map_err(|e| format!("重命名旧版历史记录文件失败: {}", e))?;
         
         Ok(())
     }
     
-    /// 保存配置到文件
-    fn save_config(&self) -> Result<(), String> {
-        // 确保目录存在
-        if let Some(parent) = self.config_path.parent() {
-            match fs::create_dir_all(parent) {
-                Ok(_) => {},
-                Err(e) => {
-                    let error_msg = format!("创建配置目录失败: {:?} - {}", parent, e);
-                    return Err(error_msg);
-                }
-            }
-        }
+    /// 将 content 中内嵌 data URL 的图片条目转存到图片存储
+    fn migrate_inline_images(&mut self) -> Result<(), String> {
+        let inline_ids: Vec<String> = self.items
+            .values()
+            .filter(|item| item.content_type == ContentType::Image && item.content.starts_with("data:"))
+            .map(|item| item.id.clone())
+            .collect();
         
-        // 序列化为JSON
-        let json = match serde_json::to_string_pretty(&self.config) {
-            Ok(json) => json,
-            Err(e) => {
-                let error_msg = format!("序列化配置数据失败: {}", e);
-                return Err(error_msg);
-            }
-        };
+        for id in inline_ids {
+            let mut item = self.items[&id].clone();
+            let png = blob_store::from_data_url(&item.content)?;
+            
+            // 沿用原有哈希，保证去重仍然有效
+            let hash = item.image
+                .as_ref()
+                .map(|image| image.hash.clone())
+                .unwrap_or_else(|| format!("{:x}", md5::compute(&png)));
+            let image = self.blobs.put_png(&hash, &png)?;
+            
+            item.content = ClipboardItem::image_label(&image);
+            item.image = Some(image);
+            self.update_item(item)?;
+        }
         
-        // 写入文件
-        match File::create(&self.config_path) {
-            Ok(mut file) => {
-                match file.write_all(json.as_bytes()) {
-                    Ok(_) => Ok(()),
-                    Err(e) => {
-                        let error_msg = format!("写入配置文件失败: {}", e);
-                        Err(error_msg)
-                    }
-                }
-            },
-            Err(e) => {
-                let error_msg = format!("创建配置文件失败: {:?} - {}", self.config_path, e);
-                Err(error_msg)
+        Ok(())
+    }
+    
+    /// 识别没有结构化信息的条目，在一个事务中写回
+    fn classify_legacy_items(&mut self) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(|e| format!("开启事务失败: {}", e))?;
+        for item in self.items.values_mut() {
+            if item.metadata.is_some() || item.content_type == ContentType::Image {
+                continue;
             }
+            
+            item.classify();
+            Self::write_item(&tx, item)?;
         }
+        tx.commit().map_err(|e| format!("提交分类事务失败: {}", e))
     }
     
-    /// 从文件加载数据
-    fn load(&mut self) -> Result<(), String> {
-        // 如果文件不存在，返回空数据
-        if !self.file_path.exists() {
-            return Ok(());
-        }
-        
-        // 读取文件内容
-        let mut file = File::open(&self.file_path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).map_err(|e| e.to_string())?;
-        
-        // 如果文件为空，返回空数据
-        if contents.trim().is_empty() {
-            return Ok(());
-        }
-        
-        // 解析JSON数据
-        let items: Vec<ClipboardItem> = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
-        
-        // 转换为HashMap
-        for item in items {
-            self.items.insert(item.id.clone(), item);
+    /// 从文件加载配置
+    fn load_config(&mut self) -> Result<(), String> {
+        // 文件不存在或为空时使用默认配置，损坏时从备份恢复
+        if let Some(config) = persistence::load_json(&self.config_path)? {
+            self.config = config;
         }
         
         Ok(())
     }
     
-    /// 保存数据到文件
-    fn save(&self) -> Result<(), String> {
-        // 确保目录存在
-        if let Some(parent) = self.file_path.parent() {
-            match fs::create_dir_all(parent) {
-                Ok(_) => {},
+    /// 保存配置到文件
+    fn save_config(&self) -> Result<(), String> {
+        persistence::save_json(&self.config_path, &self.config)
+            .map_err(|e| format!("保存存储配置失败: {}", e))
+    }
+    
+    /// 从数据库加载数据
+    ///
+    /// 返回无法解析而跳过的条目数
+    fn load(&mut self) -> Result<usize, String> {
+        let mut stmt = self.conn
+            .prepare("SELECT id, data FROM clipboard_items")
+            .map_err(|e| format!("查询历史记录失败: {}", e))?;
+        
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| format!("查询历史记录失败: {}", e))?;
+        
+        let mut skipped = 0;
+        for row in rows {
+            let (id, data) = row.map_err(|e| format!("读取历史记录失败: {}", e))?;
+            match serde_json::from_str::<ClipboardItem>(&data) {
+                Ok(item) => {
+                    self.search_index.index_item(&item);
+                    self.items.insert(item.id.clone(), item);
+                },
                 Err(e) => {
-                    let error_msg = format!("创建历史记录目录失败: {:?} - {}", parent, e);
-                    return Err(error_msg);
-                }
+                    eprintln!("解析历史记录失败: {} - {}", id, e);
+                    skipped += 1;
+                },
             }
         }
         
-        // 将HashMap转换为Vec
-        let items: Vec<ClipboardItem> = self.items.values().cloned().collect();
-        
-        // 序列化为JSON
-        let json = match serde_json::to_string_pretty(&items) {
-            Ok(json) => json,
-            Err(e) => {
-                let error_msg = format!("序列化历史记录数据失败: {}", e);
-                return Err(error_msg);
-            }
-        };
-        
-        // 写入文件
-        match File::create(&self.file_path) {
-            Ok(mut file) => {
-                match file.write_all(json.as_bytes()) {
-                    Ok(_) => Ok(()),
-                    Err(e) => {
-                        let error_msg = format!("写入历史记录文件失败: {}", e);
-                        Err(error_msg)
-                    }
-                }
-            },
-            Err(e) => {
-                let error_msg = format!("创建历史记录文件失败: {:?} - {}", self.file_path, e);
-                Err(error_msg)
+        Ok(skipped)
+    }
+    
+    /// 从数据库加载向量，条目已不存在的向量一并删除
+    fn load_embeddings(&mut self) -> Result<(), String> {
+        let mut stmt = self.conn
+            .prepare("SELECT id, model, vector FROM clipboard_embeddings")
+            .map_err(|e| format!("查询向量失败: {}", e))?;
+        
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Vec<u8>>(2)?)))
+            .map_err(|e| format!("查询向量失败: {}", e))?;
+        
+        for row in rows {
+            let (id, model, vector) = row.map_err(|e| format!("读取向量失败: {}", e))?;
+            // 已不再计算向量的条目（如后来被归为密码）不载入旧向量
+            if self.items.get(&id).is_some_and(|item| vector_index::embedding_text(item).is_some()) {
+                self.vector_index.insert(&id, &model, vector_index::decode(&vector));
             }
         }
+        drop(stmt);
+        
+        self.conn
+            .execute("DELETE FROM clipboard_embeddings WHERE id NOT IN (SELECT id FROM clipboard_items)", [])
+            .map_err(|e| format!("清理向量失败: {}", e))?;
+        
+        Ok(())
+    }
+    
+    /// 写入（插入或替换）单个条目
+    fn write_item(conn: &Connection, item: &ClipboardItem) -> Result<(), String> {
+        let data = serde_json::to_string(item)
+            .map_err(|e| format!("序列化历史记录数据失败: {}", e))?;
+        
+        conn.execute(
+            "INSERT OR REPLACE INTO clipboard_items (id, timestamp, pinned, favorite, category, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                item.id,
+                item.timestamp as i64,
+                item.pinned,
+                item.favorite,
+                item.category,
+                data,
+            ],
+        )
+        .map_err(|e| format!("写入历史记录失败: {}", e))?;
+        
+        Ok(())
     }
     
     /// 获取所有剪贴板条目
@@ -213,6 +360,34 @@ impl Storage {
         self.items.get(id)
     }
     
+    /// 按图片哈希查找已保存的图片条目
+    pub fn find_image_by_hash(&self, hash: &str) -> Option<&ClipboardItem> {
+        self.items
+            .values()
+            .find(|item| item.image.as_ref().map(|image| image.hash.as_str()) == Some(hash))
+    }
+    
+    /// 保存图片原始数据，返回图片条目的元数据
+    pub fn store_image(&self, hash: &str, png: &[u8]) -> Result<ImageInfo, String> {
+        self.blobs.put_png(hash, png)
+    }
+    
+    /// 读取图片条目的原始 PNG 数据
+    pub fn read_image(&self, id: &str) -> Result<Vec<u8>, String> {
+        let item = self.items.get(id).ok_or_else(|| format!("条目不存在: {}", id))?;
+        let image = item.image.as_ref().ok_or_else(|| format!("条目不是图片: {}", id))?;
+        self.blobs.read(&image.hash)
+    }
+    
+    /// 条目被删除后，如果没有其他条目引用同一张图片则删除图片文件
+    fn release_image(&self, removed: &ClipboardItem) {
+        if let Some(image) = &removed.image {
+            if self.find_image_by_hash(&image.hash).is_none() {
+                self.blobs.remove(&image.hash);
+            }
+        }
+    }
+    
     /// 获取符合筛选条件的剪贴板条目
     pub fn get_filtered_items(&self, filter: &ClipboardFilter) -> Vec<ClipboardItem> {
         let mut items: Vec<ClipboardItem> = self.items
@@ -286,26 +461,173 @@ impl Storage {
         }
     }
     
+    /// 全文搜索剪贴板条目，结果按相关度排序
+    pub fn search(&self, query: &str, filter: Option<&ClipboardFilter>, limit: usize) -> Vec<SearchHit> {
+        self.search_index
+            .query(query)
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let item = self.items.get(&id)?;
+                
+                // 除搜索文本外，其余筛选条件仍然生效
+                if let Some(filter) = filter {
+                    let filter = ClipboardFilter { search_text: None, ..filter.clone() };
+                    if !item.matches_filter(&filter) {
+                        return None;
+                    }
+                }
+                
+                Some(SearchHit {
+                    item: item.clone(),
+                    score,
+                    snippets: self.search_index.snippets(item, query),
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+    
+    /// 按自然语言问题检索剪贴板条目，只要求命中问题中的部分关键词
+    pub fn search_question(&self, question: &str, limit: usize) -> Vec<SearchHit> {
+        self.search_index
+            .query_any(question)
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let item = self.items.get(&id)?;
+                Some(SearchHit {
+                    item: item.clone(),
+                    score,
+                    snippets: self.search_index.question_snippets(item, question),
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+    
+    /// 按向量相似度搜索剪贴板条目，query 须与已保存的向量使用同一模型计算
+    pub fn semantic_search(&self, query: &[f32], model: &str, filter: Option<&ClipboardFilter>, limit: usize) -> Vec<SearchHit> {
+        self.vector_index
+            .nearest(query, model)
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let item = self.items.get(&id)?;
+                if let Some(filter) = filter {
+                    let filter = ClipboardFilter { search_text: None, ..filter.clone() };
+                    if !item.matches_filter(&filter) {
+                        return None;
+                    }
+                }
+                
+                Some(SearchHit {
+                    item: item.clone(),
+                    score,
+                    snippets: Vec::new(),
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+    
+    /// 还没有指定模型向量的条目，返回 (条目ID, 用于计算向量的文本)，新条目优先
+    pub fn pending_embeddings(&self, model: &str, limit: usize) -> Vec<(String, String)> {
+        let mut pending: Vec<&ClipboardItem> = self.items
+            .values()
+            .filter(|item| !self.vector_index.contains(&item.id, model))
+            .collect();
+        pending.sort_by_key(|item| std::cmp::Reverse(item.timestamp));
+        
+        pending
+            .into_iter()
+            .filter_map(|item| vector_index::embedding_text(item).map(|text| (item.id.clone(), text)))
+            .take(limit)
+            .collect()
+    }
+    
+    /// 可以计算向量的条目总数，以及其中已有指定模型向量的数量
+    pub fn embedding_progress(&self, model: &str) -> (usize, usize) {
+        self.items
+            .values()
+            .filter(|item| vector_index::embedding_text(item).is_some())
+            .fold((0, 0), |(total, embedded), item| {
+                (total + 1, embedded + usize::from(self.vector_index.contains(&item.id, model)))
+            })
+    }
+    
+    /// 保存条目的向量，条目在计算期间被删除或修改时忽略，返回是否已保存
+    pub fn save_embedding(&mut self, id: &str, text: &str, model: &str, vector: Vec<f32>) -> Result<bool, String> {
+        let current = self.items.get(id).and_then(vector_index::embedding_text);
+        if current.as_deref() != Some(text) {
+            return Ok(false);
+        }
+        
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO clipboard_embeddings (id, model, vector) VALUES (?1, ?2, ?3)",
+                params![id, model, vector_index::encode(&vector)],
+            )
+            .map_err(|e| format!("保存向量失败: {}", e))?;
+        self.vector_index.insert(id, model, vector);
+        
+        Ok(true)
+    }
+    
+    /// 已保存的同ID条目的向量是否失效：内容已改变，或条目不再计算向量（如被改为密码分类）
+    fn embedding_stale(&self, item: &ClipboardItem) -> bool {
+        self.items.get(&item.id).is_some_and(|existing| {
+            existing.content != item.content || vector_index::embedding_text(item).is_none()
+        })
+    }
+    
+    /// 删除单个条目的向量
+    fn delete_embedding(conn: &Connection, id: &str) -> Result<(), String> {
+        conn.execute("DELETE FROM clipboard_embeddings WHERE id = ?1", params![id])
+            .map_err(|e| format!("删除向量失败: {}", e))?;
+        Ok(())
+    }
+    
     /// 添加剪贴板条目
     pub fn add_item(&mut self, item: ClipboardItem) -> Result<(), String> {
-        // 添加条目
-        self.items.insert(item.id.clone(), item);
-        
         // 如果超过最大条目数，删除最旧的非固定、非收藏条目
-        if self.items.len() > self.config.max_items {
-            let mut items: Vec<ClipboardItem> = self.items.values().cloned().collect();
-            
-            // 按时间戳排序（从旧到新）
-            items.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-            
-            // 找到最旧的非固定、非收藏条目
-            if let Some(oldest) = items.iter().find(|item| !item.pinned && !item.favorite) {
-                self.items.remove(&oldest.id);
+        let evicted_id = if !self.items.contains_key(&item.id) && self.items.len() + 1 > self.config.max_items {
+            self.items
+                .values()
+                .filter(|existing| !existing.pinned && !existing.favorite)
+                .min_by_key(|existing| existing.timestamp)
+                .map(|existing| existing.id.clone())
+        } else {
+            None
+        };
+        
+        let embedding_stale = self.embedding_stale(&item);
+        
+        // 写入新条目与淘汰旧条目放在同一个事务中
+        let tx = self.conn.transaction().map_err(|e| format!("开启事务失败: {}", e))?;
+        Self::write_item(&tx, &item)?;
+        if embedding_stale {
+            Self::delete_embedding(&tx, &item.id)?;
+        }
+        if let Some(id) = &evicted_id {
+            tx.execute("DELETE FROM clipboard_items WHERE id = ?1", params![id])
+                .map_err(|e| format!("删除历史记录失败: {}", e))?;
+            Self::delete_embedding(&tx, id)?;
+        }
+        tx.commit().map_err(|e| format!("提交事务失败: {}", e))?;
+        
+        // 数据库写入成功后再更新内存与索引
+        self.search_index.index_item(&item);
+        if embedding_stale {
+            self.vector_index.remove(&item.id);
+        }
+        self.items.insert(item.id.clone(), item);
+        if let Some(id) = &evicted_id {
+            if let Some(evicted) = self.items.remove(id) {
+                self.release_image(&evicted);
             }
+            self.search_index.remove_item(id);
+            self.vector_index.remove(id);
         }
         
-        // 保存到文件
-        self.save()
+        Ok(())
     }
     
     /// 更新剪贴板条目
@@ -315,11 +637,46 @@ impl Storage {
             return Err(format!("条目不存在: {}", item.id));
         }
         
+        // 内容被修改或不再计算向量时删除旧的向量
+        let embedding_stale = self.embedding_stale(&item);
+        
+        // 更新数据库
+        let tx = self.conn.transaction().map_err(|e| format!("开启事务失败: {}", e))?;
+        Self::write_item(&tx, &item)?;
+        if embedding_stale {
+            Self::delete_embedding(&tx, &item.id)?;
+        }
+        tx.commit().map_err(|e| format!("提交事务失败: {}", e))?;
+        
         // 更新条目
+        self.search_index.index_item(&item);
+        if embedding_stale {
+            self.vector_index.remove(&item.id);
+        }
         self.items.insert(item.id.clone(), item);
         
-        // 保存到文件
-        self.save()
+        Ok(())
+    }
+    
+    /// 将指定分类下的条目改为另一个分类，返回修改的条目数量
+    ///
+    /// 没有指定新分类时清除原分类，重新按内容识别。
+    pub fn retag_category(&mut self, from: &str, to: Option<&str>) -> Result<usize, String> {
+        let tx = self.conn.transaction().map_err(|e| format!("开启事务失败: {}", e))?;
+        let mut count = 0;
+        for item in self.items.values_mut() {
+            if item.category.as_deref() != Some(from) {
+                continue;
+            }
+            
+            item.category = to.map(|to| to.to_string());
+            item.classify();
+            Self::write_item(&tx, item)?;
+            count += 1;
+        }
+        tx.commit().map_err(|e| format!("提交事务失败: {}", e))?;
+        
+        Ok(count)
     }
     
     /// 删除剪贴板条目
@@ -329,20 +686,63 @@ impl Storage {
             return Err(format!("条目不存在: {}", id));
         }
         
+        // 从数据库删除
+        let tx = self.conn.transaction().map_err(|e| format!("开启事务失败: {}", e))?;
+        tx.execute("DELETE FROM clipboard_items WHERE id = ?1", params![id])
+            .map_err(|e| format!("删除历史记录失败: {}", e))?;
+        Self::delete_embedding(&tx, id)?;
+        tx.commit().map_err(|e| format!("提交事务失败: {}", e))?;
+        
         // 删除条目
-        self.items.remove(id);
+        if let Some(removed) = self.items.remove(id) {
+            self.release_image(&removed);
+        }
+        self.search_index.remove_item(id);
+        self.vector_index.remove(id);
+        
+        Ok(())
+    }
+    
+    /// 删除已过期的条目，固定或收藏的条目不会过期，返回删除的数量
+    pub fn purge_expired(&mut self) -> Result<usize, String> {
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        let expired_ids: Vec<String> = self.items
+            .values()
+            .filter(|item| !item.pinned && !item.favorite)
+            .filter(|item| item.expires_at.is_some_and(|expires_at| expires_at <= now))
+            .map(|item| item.id.clone())
+            .collect();
+        
+        for id in &expired_ids {
+            self.remove_item(id)?;
+        }
         
-        // 保存到文件
-        self.save()
+        Ok(expired_ids.len())
     }
     
     /// 清空所有剪贴板条目
     pub fn clear_all(&mut self) -> Result<(), String> {
+        // 清空数据表
+        self.conn
+            .execute_batch("DELETE FROM clipboard_items; DELETE FROM clipboard_embeddings;")
+            .map_err(|e| format!("清空历史记录失败: {}", e))?;
+        
         // 清空集合
         self.items.clear();
+        self.search_index.clear();
+        self.vector_index.clear();
+        
+        // 删除全部图片文件；使用内存数据库时磁盘上的图片属于未能打开的历史记录，保留不动
+        if self.persistent {
+            self.blobs.collect_garbage(&HashSet::new());
+        }
         
-        // 保存到文件
-        self.save()
+        Ok(())
+    }
+    
+    /// 数据库是否成功打开；打开失败时历史记录只保存在内存中，退出后丢失
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
     }
     
     /// 设置最大保存条目数
@@ -357,4 +757,15 @@ impl Storage {
     pub fn get_max_items(&self) -> usize {
         self.config.max_items
     }
-} 
\ No newline at end of file
+    
+    /// 设置剪贴板轮询间隔（毫秒），仅在无法使用系统变化通知时生效
+    pub fn set_poll_interval_ms(&mut self, interval_ms: u64) -> Result<(), String> {
+        self.config.poll_interval_ms = interval_ms.clamp(100, 10000);
+        self.save_config()
+    }
+    
+    /// 获取剪贴板轮询间隔（毫秒）
+    pub fn get_poll_interval_ms(&self) -> u64 {
+        self.config.poll_interval_ms
+    }
+} 
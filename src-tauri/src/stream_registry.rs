@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::future::Future;
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+type AppState = crate::AppState;
+
+// 保留的已结束流的数量，超出后删除最早结束的记录
+const MAX_FINISHED_STREAMS: usize = 50;
+
+/// 流式请求的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// 流式请求的信息，供前端查询
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamInfo {
+    pub stream_id: String,
+    pub status: StreamStatus,
+    pub started_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct StreamEntry {
+    info: StreamInfo,
+    token: CancellationToken,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// 进行中的AI流式请求登记表，按流ID保存取消令牌和任务句柄
+#[derive(Default)]
+pub struct StreamRegistry {
+    streams: HashMap<String, StreamEntry>,
+}
+
+impl StreamRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记一个新的流，同一ID的流仍在进行时返回错误
+    fn register(&mut self, stream_id: &str) -> Result<CancellationToken, String> {
+        if self.streams.get(stream_id).is_some_and(|entry| entry.info.status == StreamStatus::Running) {
+            return Err(format!("流已在进行中: {}", stream_id));
+        }
+
+        let token = CancellationToken::new();
+        self.streams.insert(stream_id.to_string(), StreamEntry {
+            info: StreamInfo {
+                stream_id: stream_id.to_string(),
+                status: StreamStatus::Running,
+                started_at: chrono::Utc::now().timestamp_millis() as u64,
+                finished_at: None,
+                error: None,
+            },
+            token: token.clone(),
+            handle: None,
+        });
+
+        Ok(token)
+    }
+
+    /// 记录流的任务句柄，任务已经结束时忽略
+    fn set_handle(&mut self, stream_id: &str, handle: JoinHandle<()>) {
+        if let Some(entry) = self.streams.get_mut(stream_id) {
+            if entry.info.status == StreamStatus::Running {
+                entry.handle = Some(handle);
+            }
+        }
+    }
+
+    /// 标记流已结束，返回最终状态
+    fn finish(&mut self, stream_id: &str, status: StreamStatus, error: Option<String>) -> Option<StreamInfo> {
+        let entry = self.streams.get_mut(stream_id)?;
+        entry.info.status = status;
+        entry.info.finished_at = Some(chrono::Utc::now().timestamp_millis() as u64);
+        entry.info.error = error;
+        entry.handle = None;
+        let info = entry.info.clone();
+
+        self.prune_finished();
+        Some(info)
+    }
+
+    /// 删除多余的已结束记录
+    fn prune_finished(&mut self) {
+        let mut finished: Vec<(u64, String)> = self.streams
+            .values()
+            .filter_map(|entry| entry.info.finished_at.map(|at| (at, entry.info.stream_id.clone())))
+            .collect();
+        if finished.len() <= MAX_FINISHED_STREAMS {
+            return;
+        }
+
+        finished.sort();
+        let excess = finished.len() - MAX_FINISHED_STREAMS;
+        for (_, stream_id) in finished.into_iter().take(excess) {
+            self.streams.remove(&stream_id);
+        }
+    }
+
+    /// 取消一个进行中的流
+    pub fn cancel(&self, stream_id: &str) -> Result<(), String> {
+        let entry = self.streams.get(stream_id).ok_or_else(|| format!("流不存在: {}", stream_id))?;
+        if entry.info.status != StreamStatus::Running {
+            return Err(format!("流已结束: {}", stream_id));
+        }
+
+        entry.token.cancel();
+        Ok(())
+    }
+
+    /// 立即中止所有进行中的流，用于应用退出
+    pub fn cancel_all(&mut self) {
+        for entry in self.streams.values_mut() {
+            entry.token.cancel();
+            if let Some(handle) = entry.handle.take() {
+                handle.abort();
+            }
+        }
+    }
+
+    /// 查询流的状态
+    pub fn get_status(&self, stream_id: &str) -> Option<StreamInfo> {
+        self.streams.get(stream_id).map(|entry| entry.info.clone())
+    }
+
+    /// 所有流的状态，按开始时间从新到旧排列
+    pub fn list(&self) -> Vec<StreamInfo> {
+        let mut streams: Vec<StreamInfo> = self.streams.values().map(|entry| entry.info.clone()).collect();
+        streams.sort_by_key(|info| std::cmp::Reverse(info.started_at));
+        streams
+    }
+}
+
+/// 启动一个登记在案、可以取消的流式请求
+///
+/// 取消时 `work` 会被直接丢弃，其中进行中的HTTP请求随之中止。无论以何种方式结束，
+/// 都会调用 `on_finish` 发送最终事件，返回的通道在结束后收到最终状态。
+pub fn spawn_stream<F, C>(
+    app: &AppHandle,
+    stream_id: String,
+    work: F,
+    on_finish: C,
+) -> Result<oneshot::Receiver<StreamInfo>, String>
+where
+    F: Future<Output = Result<(), String>> + Send + 'static,
+    C: FnOnce(&StreamInfo) + Send + 'static,
+{
+    let state = app.state::<AppState>();
+    let token = state.streams.lock().map_err(|e| e.to_string())?.register(&stream_id)?;
+
+    let (sender, receiver) = oneshot::channel();
+    let app_for_task = app.clone();
+    let id = stream_id.clone();
+    let handle = tauri::async_runtime::spawn(async move {
+        let (status, error) = tokio::select! {
+            biased;
+            _ = token.cancelled() => (StreamStatus::Cancelled, None),
+            result = work => match result {
+                Ok(()) => (StreamStatus::Completed, None),
+                Err(e) => (StreamStatus::Failed, Some(e)),
+            },
+        };
+
+        let info = match app_for_task.state::<AppState>().streams.lock() {
+            Ok(mut streams) => streams.finish(&id, status, error),
+            Err(_) => None,
+        };
+        if let Some(info) = info {
+            on_finish(&info);
+            let _ = sender.send(info);
+        }
+    });
+
+    state.streams.lock().map_err(|e| e.to_string())?.set_handle(&stream_id, handle);
+    Ok(receiver)
+}
@@ -0,0 +1,508 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use crate::ai::{ChatCompletionMessage, ChatCompletionRequest};
+
+// 每条消息的格式开销（角色、分隔符等）
+const TOKENS_PER_MESSAGE: usize = 4;
+
+// 回复开头的固定开销
+const REPLY_PRIMING_TOKENS: usize = 3;
+
+// 无法得知模型上下文长度时使用的保守值
+const DEFAULT_CONTEXT_LIMIT: u32 = 8192;
+
+// 为回复至少保留的 token 数
+const MIN_COMPLETION_TOKENS: u32 = 256;
+
+// 为历史摘要预留的 token 数
+const SUMMARY_TOKENS: u32 = 400;
+
+// 截取消息时为末尾的省略说明预留的 token 数
+const OMISSION_NOTE_TOKENS: usize = 32;
+
+// 常见模型的上下文长度，按名称前缀匹配（小写），多个前缀匹配时取最长的
+const KNOWN_CONTEXT_LIMITS: &[(&str, u32)] = &[
+    ("moonshot-v1-8k", 8_192),
+    ("moonshot-v1-32k", 32_768),
+    ("moonshot-v1-128k", 131_072),
+    ("kimi", 131_072),
+    ("deepseek", 65_536),
+    ("glm-4", 128_000),
+    ("glm-4-long", 1_000_000),
+    ("glm-4-airx", 8_192),
+    ("glm-4v", 8_192),
+    ("glm-4.5v", 65_536),
+    ("claude", 200_000),
+    ("qwen", 32_768),
+    ("qwen-long", 1_000_000),
+    ("qwen-plus", 131_072),
+    ("qwen-max", 131_072),
+    ("qwen-turbo", 131_072),
+    ("qwen-vl", 32_768),
+    ("baichuan", 32_768),
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_000_000),
+    ("o1", 128_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("llama3", 8_192),
+    ("llama3.1", 131_072),
+    ("llama3.2", 131_072),
+    ("llama3.3", 131_072),
+];
+
+/// 请求超出上下文长度时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// 丢弃最早的对话
+    #[default]
+    DropOldest,
+    /// 将较早的对话总结为一段摘要
+    Summarize,
+    /// 截取过长的消息（通常是粘贴的剪贴板内容），只保留放得下的前几段
+    Chunk,
+}
+
+/// 请求的 token 估算结果，发送前返回给前端
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenReport {
+    pub model: String,
+    pub context_limit: u32,
+    /// 调整前的提示词 token 数
+    pub prompt_tokens: usize,
+    /// 调整后的提示词 token 数
+    pub final_prompt_tokens: usize,
+    /// 为回复保留的 token 数，即实际发送的 max_tokens
+    pub max_tokens: u32,
+    /// 原始请求是否能直接放入上下文
+    pub fits: bool,
+    pub strategy: ContextStrategy,
+    /// 被丢弃的消息数（使用摘要时为被总结的消息数）
+    pub dropped_messages: usize,
+    /// 是否生成了历史摘要
+    pub summarized: bool,
+    /// 被截取的消息中省略的段数
+    pub omitted_chunks: usize,
+}
+
+/// 调整后的请求，summarize 中是需要总结后放回的早期对话
+pub struct FittedRequest {
+    pub request: ChatCompletionRequest,
+    pub report: TokenReport,
+    pub summarize: Vec<ChatCompletionMessage>,
+}
+
+/// 选择模型对应的分词器，非 OpenAI 模型使用 cl100k 近似估算
+fn tokenizer(model: &str) -> Tokenizer {
+    match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => Tokenizer::O200kBase,
+        _ => Tokenizer::Cl100kBase,
+    }
+}
+
+/// 计算文本的 token 数
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    let bpe = match tokenizer(model) {
+        Tokenizer::O200kBase => tiktoken_rs::o200k_base_singleton(),
+        _ => tiktoken_rs::cl100k_base_singleton(),
+    };
+    let bpe = bpe.lock();
+    bpe.encode_with_special_tokens(text).len()
+}
+
+fn count_message(model: &str, message: &ChatCompletionMessage) -> usize {
+    TOKENS_PER_MESSAGE + count_tokens(model, &message.role) + count_tokens(model, &message.content)
+}
+
+/// 计算消息列表的 token 数，包含每条消息的格式开销
+pub fn count_messages(model: &str, messages: &[ChatCompletionMessage]) -> usize {
+    messages.iter().map(|message| count_message(model, message)).sum::<usize>() + REPLY_PRIMING_TOKENS
+}
+
+/// 模型的上下文长度：优先使用模型列表接口返回的值，其次是内置的常见模型，最后使用保守的默认值
+pub fn context_limit(model: &str, known: &HashMap<String, u32>) -> u32 {
+    if let Some(limit) = known.get(model) {
+        return *limit;
+    }
+
+    let lower = model.to_lowercase();
+    let known_limit = KNOWN_CONTEXT_LIMITS
+        .iter()
+        .filter(|(prefix, _)| lower.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, limit)| *limit);
+    if let Some(limit) = known_limit {
+        return limit;
+    }
+    if lower.starts_with("gpt-") {
+        return tiktoken_rs::model::get_context_size(&lower) as u32;
+    }
+    DEFAULT_CONTEXT_LIMIT
+}
+
+/// 截取文本开头不超过 max_tokens 的部分，有截断时末尾加省略号
+pub fn truncate_tokens(model: &str, text: &str, max_tokens: usize) -> String {
+    if count_tokens(model, text) <= max_tokens {
+        return text.to_string();
+    }
+
+    // 二分查找放得下的最长前缀
+    let chars: Vec<char> = text.chars().collect();
+    let (mut low, mut high) = (0, chars.len());
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        let prefix: String = chars[..mid].iter().collect();
+        if count_tokens(model, &prefix) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    let mut truncated: String = chars[..low].iter().collect();
+    truncated.push('…');
+    truncated
+}
+
+/// 按行（行过长时按字符）将文本切分为不超过 max_tokens 的片段
+fn split_chunks(model: &str, text: &str, max_tokens: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+
+    for line in text.split_inclusive('\n') {
+        let line_tokens = count_tokens(model, line);
+        if line_tokens > max_tokens {
+            // 单行过长时按字符切分
+            let chars: Vec<char> = line.chars().collect();
+            let step = (chars.len() * max_tokens / line_tokens).max(1);
+            for piece in chars.chunks(step) {
+                if !current.is_empty() {
+                    chunks.push(std::mem::take(&mut current));
+                    current_tokens = 0;
+                }
+                chunks.push(piece.iter().collect());
+            }
+            continue;
+        }
+
+        if current_tokens + line_tokens > max_tokens && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        current.push_str(line);
+        current_tokens += line_tokens;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// 只保留消息中放得下的前几段，返回省略的段数
+///
+/// 放不下时末尾附加省略说明，说明占用的 token 也计入 budget。
+fn chunk_message(model: &str, message: &mut ChatCompletionMessage, budget: usize) -> usize {
+    if count_tokens(model, &message.content) <= budget {
+        return 0;
+    }
+
+    let budget = budget.saturating_sub(OMISSION_NOTE_TOKENS);
+    let chunk_tokens = (budget / 4).max(64);
+    let chunks = split_chunks(model, &message.content, chunk_tokens);
+    let total = chunks.len();
+
+    let mut kept = String::new();
+    let mut used = 0;
+    let mut count = 0;
+    for chunk in chunks {
+        let tokens = count_tokens(model, &chunk);
+        if used + tokens > budget {
+            break;
+        }
+        used += tokens;
+        kept.push_str(&chunk);
+        count += 1;
+    }
+
+    let omitted = total - count;
+    if omitted > 0 {
+        message.content = format!("{}\n\n（内容过长，共 {} 段，已省略后 {} 段）", kept.trim_end(), total, omitted);
+    }
+    omitted
+}
+
+/// 可以丢弃的最早一条消息的位置：系统消息和最后一条消息始终保留
+fn oldest_droppable(messages: &[ChatCompletionMessage]) -> Option<usize> {
+    let last = messages.len().checked_sub(1)?;
+    messages[..last].iter().position(|message| message.role != "system")
+}
+
+/// 消息中最长的一条的位置，include_system 为 false 时不考虑系统消息
+fn longest_message(model: &str, messages: &[ChatCompletionMessage], include_system: bool) -> Option<usize> {
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| include_system || message.role != "system")
+        .max_by_key(|(_, message)| count_tokens(model, &message.content))
+        .map(|(index, _)| index)
+}
+
+/// 按上下文长度调整请求，不发起网络请求
+///
+/// 回复预留的 token 先在不低于最小值的前提下缩减；仍然放不下时按策略处理消息。
+/// 任何策略处理后仍放不下时，依次丢弃最早的对话、截取最长的消息，保证请求可以发送。
+pub fn fit(mut request: ChatCompletionRequest, limit: u32, strategy: ContextStrategy) -> FittedRequest {
+    let model = request.model.clone();
+    let requested = request.max_tokens.map_or(MIN_COMPLETION_TOKENS, |tokens| tokens.max(1) as u32);
+    let prompt_tokens = count_messages(&model, &request.messages);
+    let fits = prompt_tokens as u64 + requested as u64 <= limit as u64;
+
+    let mut report = TokenReport {
+        model: model.clone(),
+        context_limit: limit,
+        prompt_tokens,
+        final_prompt_tokens: prompt_tokens,
+        max_tokens: requested,
+        fits,
+        strategy,
+        dropped_messages: 0,
+        summarized: false,
+        omitted_chunks: 0,
+    };
+    let mut summarize = Vec::new();
+
+    if !fits {
+        // 回复预留不超过上下文的四分之一，也不少于最小值
+        let reserved = requested.min(MIN_COMPLETION_TOKENS.max(limit / 4)).min(limit / 2);
+        if prompt_tokens as u64 + reserved as u64 <= limit as u64 {
+            report.max_tokens = limit - prompt_tokens as u32;
+        } else {
+            report.max_tokens = reserved;
+            let mut budget = (limit - reserved) as usize;
+
+            match strategy {
+                ContextStrategy::Summarize => {
+                    budget = budget.saturating_sub(SUMMARY_TOKENS as usize);
+                    while count_messages(&model, &request.messages) > budget {
+                        let Some(index) = oldest_droppable(&request.messages) else { break };
+                        summarize.push(request.messages.remove(index));
+                    }
+                    report.dropped_messages = summarize.len();
+                },
+                ContextStrategy::Chunk => {
+                    if let Some(index) = longest_message(&model, &request.messages, false) {
+                        let others = count_messages(&model, &request.messages) - count_message(&model, &request.messages[index]);
+                        let available = budget.saturating_sub(others + TOKENS_PER_MESSAGE * 2);
+                        report.omitted_chunks = chunk_message(&model, &mut request.messages[index], available);
+                    }
+                },
+                ContextStrategy::DropOldest => {},
+            }
+
+            // 兜底：丢弃最早的对话
+            while count_messages(&model, &request.messages) > budget {
+                let Some(index) = oldest_droppable(&request.messages) else { break };
+                request.messages.remove(index);
+                report.dropped_messages += 1;
+            }
+
+            // 兜底：只剩系统消息和最后一条消息时截取最长的消息，只有系统消息时截取系统消息
+            let total = count_messages(&model, &request.messages);
+            if total > budget {
+                if let Some(index) = longest_message(&model, &request.messages, true) {
+                    let current = count_message(&model, &request.messages[index]);
+                    let available = budget.saturating_sub(total - current + TOKENS_PER_MESSAGE * 2);
+                    report.omitted_chunks += chunk_message(&model, &mut request.messages[index], available);
+                }
+            }
+        }
+    }
+
+    report.final_prompt_tokens = count_messages(&model, &request.messages);
+    request.max_tokens = Some(report.max_tokens as i32);
+    FittedRequest { request, report, summarize }
+}
+
+/// 历史摘要请求的消息
+pub fn summary_messages(model: &str, history: &[ChatCompletionMessage], limit: u32) -> Vec<ChatCompletionMessage> {
+    let transcript = history
+        .iter()
+        .map(|message| format!("{}: {}", message.role, message.content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let mut content = ChatCompletionMessage { role: "user".to_string(), content: transcript };
+    let budget = limit.saturating_sub(SUMMARY_TOKENS + MIN_COMPLETION_TOKENS) as usize;
+    chunk_message(model, &mut content, budget);
+
+    vec![
+        ChatCompletionMessage {
+            role: "system".to_string(),
+            content: "请将以下对话总结为一段简洁的摘要，保留关键事实、结论和用户的要求，只输出摘要内容。".to_string(),
+        },
+        content,
+    ]
+}
+
+/// 将历史摘要放到系统消息之后
+pub fn insert_summary(messages: &mut Vec<ChatCompletionMessage>, summary: &str) {
+    let index = messages.iter().position(|message| message.role != "system").unwrap_or(messages.len());
+    messages.insert(index, ChatCompletionMessage {
+        role: "system".to_string(),
+        content: format!("此前对话的摘要：{}", summary),
+    });
+}
+
+/// 摘要的 token 上限
+pub fn summary_max_tokens() -> i32 {
+    SUMMARY_TOKENS as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = "gpt-4o";
+
+    fn message(role: &str, content: String) -> ChatCompletionMessage {
+        ChatCompletionMessage { role: role.to_string(), content }
+    }
+
+    fn request(messages: Vec<ChatCompletionMessage>, max_tokens: i32) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: MODEL.to_string(),
+            messages,
+            temperature: None,
+            max_tokens: Some(max_tokens),
+            stream: None,
+        }
+    }
+
+    /// 多轮对话，每条消息约 200 token
+    fn long_conversation() -> Vec<ChatCompletionMessage> {
+        let mut messages = vec![message("system", "你是一个助手。".to_string())];
+        for i in 0..20 {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            messages.push(message(role, format!("第 {} 条消息 ", i) + &"hello world ".repeat(100)));
+        }
+        messages
+    }
+
+    /// 按行排列的长文本，约 5000 token
+    fn long_paste() -> String {
+        (0..500).map(|i| format!("line {} of the pasted document\n", i)).collect()
+    }
+
+    fn assert_fits(fitted: &FittedRequest, limit: u32) {
+        let report = &fitted.report;
+        assert_eq!(report.final_prompt_tokens, count_messages(MODEL, &fitted.request.messages));
+        assert!(
+            report.final_prompt_tokens as u64 + report.max_tokens as u64 <= limit as u64,
+            "{} + {} > {}",
+            report.final_prompt_tokens,
+            report.max_tokens,
+            limit
+        );
+        assert_eq!(fitted.request.max_tokens, Some(report.max_tokens as i32));
+    }
+
+    #[test]
+    fn request_that_fits_is_unchanged() {
+        let messages = vec![message("user", "你好".to_string())];
+        let fitted = fit(request(messages, 512), 4096, ContextStrategy::DropOldest);
+
+        assert!(fitted.report.fits);
+        assert_eq!(fitted.report.max_tokens, 512);
+        assert_eq!(fitted.request.messages.len(), 1);
+        assert_fits(&fitted, 4096);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_system_and_last_message() {
+        let limit = 2048;
+        let fitted = fit(request(long_conversation(), 1024), limit, ContextStrategy::DropOldest);
+
+        assert!(!fitted.report.fits);
+        assert!(fitted.report.dropped_messages > 0);
+        assert_eq!(fitted.request.messages.first().unwrap().role, "system");
+        assert!(fitted.request.messages.last().unwrap().content.starts_with("第 19 条消息"));
+        assert!(fitted.summarize.is_empty());
+        assert_fits(&fitted, limit);
+    }
+
+    #[test]
+    fn summarize_leaves_room_for_the_summary() {
+        let limit = 2048;
+        let fitted = fit(request(long_conversation(), 1024), limit, ContextStrategy::Summarize);
+
+        assert!(!fitted.summarize.is_empty());
+        assert_eq!(fitted.report.dropped_messages, fitted.summarize.len());
+        assert_fits(&fitted, limit);
+        assert!(
+            fitted.report.final_prompt_tokens + SUMMARY_TOKENS as usize + fitted.report.max_tokens as usize
+                <= limit as usize
+        );
+    }
+
+    #[test]
+    fn chunk_truncates_the_longest_message() {
+        let limit = 2048;
+        let messages = vec![
+            message("system", "你是一个助手。".to_string()),
+            message("user", long_paste()),
+        ];
+        let fitted = fit(request(messages, 1024), limit, ContextStrategy::Chunk);
+
+        assert!(fitted.report.omitted_chunks > 0);
+        assert_eq!(fitted.request.messages.len(), 2);
+        assert!(fitted.request.messages[1].content.starts_with("line 0 of"));
+        assert_fits(&fitted, limit);
+    }
+
+    #[test]
+    fn single_oversized_message_is_truncated_with_every_strategy() {
+        let limit = 1024;
+        for strategy in [ContextStrategy::DropOldest, ContextStrategy::Summarize, ContextStrategy::Chunk] {
+            let messages = vec![message("user", long_paste())];
+            let fitted = fit(request(messages, 4096), limit, strategy);
+
+            assert_eq!(fitted.request.messages.len(), 1, "{:?}", strategy);
+            assert!(fitted.report.omitted_chunks > 0, "{:?}", strategy);
+            assert_fits(&fitted, limit);
+        }
+    }
+
+    #[test]
+    fn single_line_message_is_split_by_characters() {
+        let limit = 1024;
+        let messages = vec![message("user", "word ".repeat(5000))];
+        let fitted = fit(request(messages, 256), limit, ContextStrategy::Chunk);
+
+        assert!(fitted.report.omitted_chunks > 0);
+        assert_fits(&fitted, limit);
+    }
+
+    #[test]
+    fn system_only_conversation_is_truncated() {
+        let limit = 1024;
+        for strategy in [ContextStrategy::DropOldest, ContextStrategy::Summarize, ContextStrategy::Chunk] {
+            let messages = vec![message("system", long_paste())];
+            let fitted = fit(request(messages, 256), limit, strategy);
+
+            assert_eq!(fitted.request.messages.len(), 1, "{:?}", strategy);
+            assert_fits(&fitted, limit);
+        }
+    }
+
+    #[test]
+    fn context_limit_prefers_longest_prefix() {
+        let known = HashMap::new();
+        assert_eq!(context_limit("glm-4-airx", &known), 8_192);
+        assert_eq!(context_limit("glm-4-plus", &known), 128_000);
+        assert_eq!(context_limit("unknown-model", &known), DEFAULT_CONTEXT_LIMIT);
+        assert_eq!(context_limit("glm-4-plus", &HashMap::from([("glm-4-plus".to_string(), 1000)])), 1000);
+    }
+}
@@ -1,14 +1,17 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use md5;
 use reqwest::Client;
 use rand::Rng;
 use tauri::AppHandle;
 use std::time::Duration;
+use crate::persistence;
+use crate::secrets::{self, SecretStore};
+
+// 密钥存储中保存百度翻译密钥的名称
+const SECRET_NAME: &str = "translation.baidu";
 
 // 翻译设置结构体
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -64,11 +67,12 @@ pub struct TranslationManager {
     settings: TranslationSettings,
     config_path: PathBuf,
     http_client: Client,
+    secrets: Arc<SecretStore>,
 }
 
 impl TranslationManager {
     // 创建一个新的翻译管理器实例
-    pub fn new(config_dir: impl AsRef<Path>) -> Self {
+    pub fn new(config_dir: impl AsRef<Path>, secrets: Arc<SecretStore>) -> Self {
         let config_path = config_dir.as_ref().join("translation_settings.json");
         let mut manager = Self {
             settings: TranslationSettings::default(),
@@ -77,6 +81,7 @@ impl TranslationManager {
                 .timeout(Duration::from_secs(10))
                 .build()
                 .expect("Failed to create HTTP client"),
+            secrets,
         };
         
         // 尝试加载配置，如果失败则使用默认值
@@ -84,49 +89,80 @@ impl TranslationManager {
             eprintln!("Failed to load translation settings: {}", e);
         }
         
+        // 迁移旧版本以明文保存的密钥
+        if let Err(e) = manager.migrate_secrets() {
+            eprintln!("迁移翻译密钥失败: {}", e);
+        }
+        
         manager
     }
     
-    // 加载翻译设置
-    fn load_settings(&mut self) -> Result<(), String> {
-        if !self.config_path.exists() {
+    // 将明文保存的密钥迁移到密钥存储，密钥存储不可用时跳过
+    pub fn migrate_secrets(&mut self) -> Result<(), String> {
+        if self.settings.key.is_empty() || secrets::parse_reference(&self.settings.key).is_some() || !self.secrets.is_available() {
             return Ok(());
         }
         
-        let mut file = File::open(&self.config_path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).map_err(|e| e.to_string())?;
-        
-        if contents.trim().is_empty() {
-            return Ok(());
+        self.settings.key = self.secrets.store(SECRET_NAME, &self.settings.key)?;
+        self.save_settings()
+    }
+    
+    // 加载翻译设置
+    fn load_settings(&mut self) -> Result<(), String> {
+        if let Some(settings) = persistence::load_json(&self.config_path)? {
+            self.settings = settings;
         }
         
-        self.settings = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
-        
         Ok(())
     }
     
     // 保存翻译设置
     fn save_settings(&self) -> Result<(), String> {
-        if let Some(parent) = self.config_path.parent() {
-            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-        }
-        
-        let json = serde_json::to_string_pretty(&self.settings).map_err(|e| e.to_string())?;
-        
-        let mut file = File::create(&self.config_path).map_err(|e| e.to_string())?;
-        file.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
-        
-        Ok(())
+        persistence::save_json(&self.config_path, &self.settings)
     }
     
-    // 获取当前翻译设置
+    // 获取当前翻译设置，密钥从密钥存储中取回，无法读取时返回占位值
     pub fn get_settings(&self) -> TranslationSettings {
-        self.settings.clone()
+        let mut settings = self.settings.clone();
+        settings.key = self.secrets.resolve(&settings.key).unwrap_or_else(|e| {
+            eprintln!("读取翻译密钥失败: {}", e);
+            secrets::LOCKED_MARKER.to_string()
+        });
+        settings
+    }
+    
+    // 是否已配置百度翻译的凭证
+    pub fn has_credentials(&self) -> bool {
+        !self.settings.appid.is_empty() && !self.settings.key.is_empty()
+    }
+    
+    // 目标语言的名称，用于提示AI翻译
+    pub fn target_language_name(&self) -> String {
+        let code = &self.settings.translation_to;
+        self.get_supported_languages()
+            .into_iter()
+            .find(|language| &language.code == code)
+            .map(|language| language.name)
+            .unwrap_or_else(|| code.clone())
+    }
+    
+    // 创建一个用于单次请求的翻译管理器，避免请求期间持有锁
+    pub fn detached(&self) -> Self {
+        Self {
+            settings: self.get_settings(),
+            config_path: PathBuf::new(), // 不需要保存
+            http_client: self.http_client.clone(),
+            secrets: self.secrets.clone(),
+        }
     }
     
-    // 更新翻译设置
-    pub fn update_settings(&mut self, settings: TranslationSettings) -> Result<(), String> {
+    // 更新翻译设置，密钥保存到密钥存储，设置文件中只保留引用
+    pub fn update_settings(&mut self, mut settings: TranslationSettings) -> Result<(), String> {
+        // 传回占位值说明密钥未被修改，沿用已保存的引用
+        if secrets::is_locked_marker(&settings.key) {
+            settings.key = self.settings.key.clone();
+        }
+        settings.key = self.secrets.store(SECRET_NAME, &settings.key)?;
         self.settings = settings;
         self.save_settings()?;
         Ok(())
@@ -151,6 +187,9 @@ impl TranslationManager {
         if self.settings.appid.is_empty() || self.settings.key.is_empty() {
             return Err("翻译API凭证未设置".to_string());
         }
+        if secrets::is_locked_marker(&self.settings.key) {
+            return Err("密钥存储未解锁，无法读取翻译密钥".to_string());
+        }
         
         // 添加字符限制检查
         const MAX_CHARS: usize = 2000;
@@ -342,21 +381,8 @@ pub async fn translate_text(
     state: tauri::State<'_, TranslationState>,
     text: String,
 ) -> Result<TranslationResult, String> {
-    // 先从锁中克隆我们需要的数据，然后释放锁
-    let settings = {
-        let manager = state.manager.lock().map_err(|e| e.to_string())?;
-        manager.get_settings()
-    };
-    
-    // 创建一个临时的翻译管理器用于此次请求
-    let temp_manager = TranslationManager {
-        settings,
-        config_path: PathBuf::new(), // 不需要保存
-        http_client: Client::builder()
-            .timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to create HTTP client"),
-    };
+    // 创建一个临时的翻译管理器用于此次请求，然后释放锁
+    let temp_manager = state.manager.lock().map_err(|e| e.to_string())?.detached();
     
     // 执行翻译
     temp_manager.translate(&text).await
@@ -365,9 +391,11 @@ pub async fn translate_text(
 // 测试API连接
 #[tauri::command]
 pub async fn test_translation_api(
-    _state: tauri::State<'_, TranslationState>,
+    state: tauri::State<'_, TranslationState>,
     settings: TranslationSettings,
 ) -> Result<bool, String> {
+    let secrets = state.manager.lock().map_err(|e| e.to_string())?.secrets.clone();
+    
     // 创建临时翻译管理器
     let temp_manager = TranslationManager {
         settings,
@@ -376,12 +404,16 @@ pub async fn test_translation_api(
             .timeout(Duration::from_secs(10))
             .build()
             .expect("Failed to create HTTP client"),
+        secrets,
     };
     
     // 检查API凭证是否为空
     if temp_manager.settings.appid.is_empty() || temp_manager.settings.key.is_empty() {
         return Err("翻译API凭证未设置".to_string());
     }
+    if secrets::is_locked_marker(&temp_manager.settings.key) {
+        return Err("密钥存储未解锁，无法读取翻译密钥".to_string());
+    }
     
     // 生成随机数
     let salt = rand::thread_rng()
@@ -447,4 +479,4 @@ pub async fn test_translation_api(
         },
         Err(e) => Err(format!("无效的JSON响应: {}, 响应内容: {}", e, response_text)),
     }
-} 
\ No newline at end of file
+} 
\ No newline at end of file
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use chrono::{Datelike, Local, TimeZone};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::ai::{ChatCompletionMessage, ChatCompletionResponse};
+use crate::persistence;
+use crate::provider::ProviderChain;
+use crate::token_budget;
+
+type AppState = crate::AppState;
+
+/// 模型价格，单位为每百万 token 的费用
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// 用量统计设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageSettings {
+    /// 价格使用的货币，仅用于显示
+    pub currency: String,
+    /// 模型价格表，键为模型ID或其前缀，匹配最长的前缀
+    pub prices: HashMap<String, ModelPrice>,
+    /// 每月总费用上限，达到后拒绝新的请求
+    pub monthly_budget: Option<f64>,
+    /// 各提供商每月的费用上限，达到后跳过该提供商
+    pub provider_budgets: HashMap<String, f64>,
+}
+
+impl Default for UsageSettings {
+    fn default() -> Self {
+        let prices = [
+            ("gpt-4o-mini", 0.15, 0.6),
+            ("gpt-4o", 2.5, 10.0),
+            ("gpt-4.1-mini", 0.4, 1.6),
+            ("gpt-4.1", 2.0, 8.0),
+            ("claude-3-5-haiku", 0.8, 4.0),
+            ("claude-3-5-sonnet", 3.0, 15.0),
+            ("claude-3-7-sonnet", 3.0, 15.0),
+            ("deepseek-chat", 0.27, 1.1),
+            ("deepseek-reasoner", 0.55, 2.19),
+            ("glm-4-flash", 0.0, 0.0),
+        ]
+        .into_iter()
+        .map(|(model, input, output)| (model.to_string(), ModelPrice { input, output }))
+        .collect();
+
+        Self {
+            currency: "USD".to_string(),
+            prices,
+            monthly_budget: None,
+            provider_budgets: HashMap::new(),
+        }
+    }
+}
+
+/// 请求的来源，由前端传入
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UsageSource {
+    /// 发起请求的命令，由后端填写
+    #[serde(skip)]
+    pub operation: &'static str,
+    #[serde(default)]
+    pub item_id: Option<String>,
+    #[serde(default)]
+    pub role_id: Option<String>,
+}
+
+impl UsageSource {
+    pub fn for_operation(source: Option<UsageSource>, operation: &'static str) -> Self {
+        Self { operation, ..source.unwrap_or_default() }
+    }
+}
+
+/// 一次AI请求的记录
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRecord {
+    pub timestamp: u64,
+    pub operation: String,
+    pub provider_id: String,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// token 数是否为估算值（流式响应或服务未返回 usage）
+    pub estimated: bool,
+    pub latency_ms: u64,
+    pub success: bool,
+    pub error: Option<String>,
+    pub item_id: Option<String>,
+    pub role_id: Option<String>,
+    pub cost: f64,
+}
+
+/// 汇总的维度
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroup {
+    Day,
+    Provider,
+    Model,
+    Role,
+}
+
+impl UsageGroup {
+    fn column(self) -> &'static str {
+        match self {
+            UsageGroup::Day => "strftime('%Y-%m-%d', timestamp / 1000, 'unixepoch', 'localtime')",
+            UsageGroup::Provider => "provider_id",
+            UsageGroup::Model => "model",
+            UsageGroup::Role => "COALESCE(role_id, '')",
+        }
+    }
+}
+
+/// 按维度汇总的用量
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummary {
+    pub key: String,
+    pub requests: u64,
+    pub failed: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+    pub avg_latency_ms: f64,
+}
+
+/// 本月费用与预算
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    pub currency: String,
+    pub month_cost: f64,
+    pub monthly_budget: Option<f64>,
+    /// 各提供商本月的费用
+    pub provider_costs: HashMap<String, f64>,
+    pub provider_budgets: HashMap<String, f64>,
+}
+
+/// AI请求的用量账本，保存在本地数据库中
+pub struct UsageLedger {
+    conn: Mutex<Connection>,
+    /// 数据库是否成功打开，为 false 时使用的是内存数据库
+    persistent: bool,
+    settings: Mutex<UsageSettings>,
+    config_path: PathBuf,
+}
+
+impl UsageLedger {
+    pub fn new(data_dir: &Path) -> Self {
+        let db_path = data_dir.join("usage.db");
+        let (conn, persistent) = match Connection::open(&db_path) {
+            Ok(conn) => (conn, true),
+            Err(e) => {
+                eprintln!("打开用量数据库失败: {:?} - {}", db_path, e);
+                (Connection::open_in_memory().expect("无法创建内存数据库"), false)
+            }
+        };
+        if let Err(e) = Self::init_schema(&conn) {
+            eprintln!("初始化用量数据库失败: {}", e);
+        }
+
+        let config_path = data_dir.join("usage_settings.json");
+        let settings = persistence::load_json(&config_path)
+            .unwrap_or_else(|e| {
+                eprintln!("加载用量设置失败: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            conn: Mutex::new(conn),
+            persistent,
+            settings: Mutex::new(settings),
+            config_path,
+        }
+    }
+
+    /// 数据库是否成功打开；打开失败时用量记录只保存在内存中，退出后丢失
+    pub fn is_persistent(&self) -> bool {
+        self.persistent
+    }
+
+    fn init_schema(conn: &Connection) -> Result<(), String> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS usage_records (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 timestamp INTEGER NOT NULL,
+                 operation TEXT NOT NULL,
+                 provider_id TEXT NOT NULL,
+                 model TEXT NOT NULL,
+                 prompt_tokens INTEGER NOT NULL,
+                 completion_tokens INTEGER NOT NULL,
+                 estimated INTEGER NOT NULL,
+                 latency_ms INTEGER NOT NULL,
+                 success INTEGER NOT NULL,
+                 error TEXT,
+                 item_id TEXT,
+                 role_id TEXT,
+                 cost REAL NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS idx_usage_records_timestamp
+                 ON usage_records (timestamp);",
+        )
+        .map_err(|e| format!("创建数据表失败: {}", e))
+    }
+
+    pub fn get_settings(&self) -> Result<UsageSettings, String> {
+        Ok(self.settings.lock().map_err(|e| e.to_string())?.clone())
+    }
+
+    pub fn update_settings(&self, settings: UsageSettings) -> Result<(), String> {
+        persistence::save_json(&self.config_path, &settings)?;
+        *self.settings.lock().map_err(|e| e.to_string())? = settings;
+        Ok(())
+    }
+
+    /// 按价格表计算费用，没有对应价格的模型记为 0
+    fn cost(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> Result<f64, String> {
+        let settings = self.settings.lock().map_err(|e| e.to_string())?;
+        let model = model.to_lowercase();
+        let price = settings.prices
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(&prefix.to_lowercase()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price);
+
+        Ok(price.map_or(0.0, |price| {
+            (prompt_tokens as f64 * price.input + completion_tokens as f64 * price.output) / 1_000_000.0
+        }))
+    }
+
+    /// 保存一条记录
+    pub fn record(&self, record: &UsageRecord) -> Result<(), String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO usage_records (timestamp, operation, provider_id, model, prompt_tokens, completion_tokens,
+                 estimated, latency_ms, success, error, item_id, role_id, cost)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                record.timestamp as i64,
+                record.operation,
+                record.provider_id,
+                record.model,
+                record.prompt_tokens as i64,
+                record.completion_tokens as i64,
+                record.estimated,
+                record.latency_ms as i64,
+                record.success,
+                record.error,
+                record.item_id,
+                record.role_id,
+                record.cost,
+            ],
+        )
+        .map_err(|e| format!("保存用量记录失败: {}", e))?;
+        Ok(())
+    }
+
+    /// 按维度汇总 [from, to) 时间范围内的用量，时间为毫秒时间戳
+    pub fn summary(&self, group: UsageGroup, from: u64, to: u64) -> Result<Vec<UsageSummary>, String> {
+        let conn = self.conn.lock().map_err(|e| e.to_string())?;
+        let sql = format!(
+            "SELECT {0}, COUNT(*), SUM(success = 0), SUM(prompt_tokens), SUM(completion_tokens), SUM(cost), AVG(latency_ms)
+             FROM usage_records WHERE timestamp >= ?1 AND timestamp < ?2
+             GROUP BY {0} ORDER BY {0}",
+            group.column()
+        );
+        let mut stmt = conn.prepare(&sql).map_err(|e| format!("查询用量失败: {}", e))?;
+        let rows = stmt
+            .query_map(params![from as i64, to as i64], |row| {
+                Ok(UsageSummary {
+                    key: row.get(0)?,
+                    requests: row.get::<_, i64>(1)? as u64,
+                    failed: row.get::<_, i64>(2)? as u64,
+                    prompt_tokens: row.get::<_, i64>(3)? as u64,
+                    completion_tokens: row.get::<_, i64>(4)? as u64,
+                    cost: row.get(5)?,
+                    avg_latency_ms: row.get(6)?,
+                })
+            })
+            .map_err(|e| format!("查询用量失败: {}", e))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| format!("读取用量失败: {}", e))
+    }
+
+    /// 本月的费用与预算
+    pub fn budget_status(&self) -> Result<BudgetStatus, String> {
+        let month_start = Local
+            .with_ymd_and_hms(Local::now().year(), Local::now().month(), 1, 0, 0, 0)
+            .earliest()
+            .map_or(0, |start| start.timestamp_millis() as u64);
+        let provider_costs: HashMap<String, f64> = self
+            .summary(UsageGroup::Provider, month_start, u64::MAX >> 1)?
+            .into_iter()
+            .map(|summary| (summary.key, summary.cost))
+            .collect();
+
+        let settings = self.get_settings()?;
+        Ok(BudgetStatus {
+            currency: settings.currency,
+            month_cost: provider_costs.values().sum(),
+            monthly_budget: settings.monthly_budget,
+            provider_costs,
+            provider_budgets: settings.provider_budgets,
+        })
+    }
+
+    /// 检查本月预算：总费用超出时拒绝请求，单个提供商超出时将其从候选中移除
+    pub fn enforce_budget(&self, chain: &mut ProviderChain) -> Result<(), String> {
+        let status = self.budget_status()?;
+        if let Some(budget) = status.monthly_budget.filter(|budget| status.month_cost >= *budget) {
+            return Err(format!(
+                "本月AI费用 {:.2} {} 已达到预算上限 {:.2} {}，请在用量设置中调整预算",
+                status.month_cost, status.currency, budget, status.currency
+            ));
+        }
+
+        chain.retain(|provider_id| {
+            let over = status.provider_budgets.get(provider_id).is_some_and(|budget| {
+                status.provider_costs.get(provider_id).copied().unwrap_or_default() >= *budget
+            });
+            if over {
+                eprintln!("提供商 '{}' 本月费用已达到预算上限，已跳过", provider_id);
+            }
+            !over
+        });
+        if chain.is_empty() {
+            return Err("所有可用的提供商本月费用均已达到预算上限".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn now_millis() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+/// 发送非流式请求并记录用量，返回响应和实际回答的提供商ID
+pub async fn complete(
+    state: &AppState,
+    mut chain: ProviderChain,
+    source: UsageSource,
+    model: Option<String>,
+    messages: Vec<ChatCompletionMessage>,
+    temperature: Option<f32>,
+    max_tokens: Option<i32>,
+) -> Result<(Value, String), String> {
+    state.usage.enforce_budget(&mut chain)?;
+
+    let started = Instant::now();
+    let timestamp = now_millis();
+    let requested_model = model.clone().unwrap_or_else(|| chain.primary().model.clone());
+    let prompt = messages.clone();
+    let result = chain.complete(model, messages, temperature, max_tokens).await;
+
+    let (provider_id, model, usage, error) = match &result {
+        Ok((response, provider_id)) => {
+            let model = response["model"].as_str().unwrap_or(&requested_model).to_string();
+            let usage = response["usage"]["prompt_tokens"]
+                .as_u64()
+                .zip(response["usage"]["completion_tokens"].as_u64());
+            let usage = usage.map(|(prompt_tokens, completion_tokens)| (prompt_tokens, completion_tokens, false)).unwrap_or_else(|| {
+                let content = response["choices"][0]["message"]["content"].as_str().unwrap_or_default();
+                (
+                    token_budget::count_messages(&model, &prompt) as u64,
+                    token_budget::count_tokens(&model, content) as u64,
+                    true,
+                )
+            });
+            (provider_id.to_string(), model, usage, None)
+        },
+        Err(e) => (chain.primary().provider_id.clone(), requested_model, (0, 0, false), Some(e.clone())),
+    };
+
+    let (prompt_tokens, completion_tokens, estimated) = usage;
+    let record = UsageRecord {
+        timestamp,
+        operation: source.operation.to_string(),
+        cost: state.usage.cost(&model, prompt_tokens, completion_tokens)?,
+        provider_id,
+        model,
+        prompt_tokens,
+        completion_tokens,
+        estimated,
+        latency_ms: started.elapsed().as_millis() as u64,
+        success: error.is_none(),
+        error,
+        item_id: source.item_id,
+        role_id: source.role_id,
+    };
+    if let Err(e) = state.usage.record(&record) {
+        eprintln!("{}", e);
+    }
+
+    result.map(|(response, provider_id)| (response, provider_id.to_string()))
+}
+
+/// 流式请求的用量，边接收数据块边累计，结束时记录
+pub struct StreamUsage {
+    started: Instant,
+    timestamp: u64,
+    source: UsageSource,
+    prompt: Vec<ChatCompletionMessage>,
+    provider_id: String,
+    model: String,
+    completion: String,
+}
+
+impl StreamUsage {
+    /// 检查预算并开始计时
+    pub fn start(
+        state: &AppState,
+        chain: &mut ProviderChain,
+        source: UsageSource,
+        model: Option<&str>,
+        prompt: &[ChatCompletionMessage],
+    ) -> Result<Arc<Mutex<Self>>, String> {
+        state.usage.enforce_budget(chain)?;
+
+        let primary = chain.primary();
+        Ok(Arc::new(Mutex::new(Self {
+            started: Instant::now(),
+            timestamp: now_millis(),
+            source,
+            prompt: prompt.to_vec(),
+            provider_id: primary.provider_id.clone(),
+            model: model.filter(|model| !model.is_empty()).unwrap_or(&primary.model).to_string(),
+            completion: String::new(),
+        })))
+    }
+
+    pub fn on_chunk(&mut self, provider_id: &str, response: &ChatCompletionResponse) {
+        if self.completion.is_empty() {
+            self.provider_id = provider_id.to_string();
+            self.model = response.model.clone();
+        }
+        for choice in &response.choices {
+            if let Some(content) = &choice.delta.content {
+                self.completion.push_str(content);
+            }
+        }
+    }
+
+    /// 请求结束（包括取消）时记录，token 数为估算值
+    pub fn finish(&self, state: &AppState, error: Option<String>) {
+        // 没有收到任何内容就失败的请求不计费
+        let (prompt_tokens, completion_tokens) = if error.is_some() && self.completion.is_empty() {
+            (0, 0)
+        } else {
+            (
+                token_budget::count_messages(&self.model, &self.prompt) as u64,
+                token_budget::count_tokens(&self.model, &self.completion) as u64,
+            )
+        };
+        let cost = match state.usage.cost(&self.model, prompt_tokens, completion_tokens) {
+            Ok(cost) => cost,
+            Err(e) => return eprintln!("计算费用失败: {}", e),
+        };
+
+        let record = UsageRecord {
+            timestamp: self.timestamp,
+            operation: self.source.operation.to_string(),
+            provider_id: self.provider_id.clone(),
+            model: self.model.clone(),
+            prompt_tokens,
+            completion_tokens,
+            estimated: true,
+            latency_ms: self.started.elapsed().as_millis() as u64,
+            success: error.is_none(),
+            error,
+            item_id: self.source.item_id.clone(),
+            role_id: self.source.role_id.clone(),
+            cost,
+        };
+        if let Err(e) = state.usage.record(&record) {
+            eprintln!("{}", e);
+        }
+    }
+}
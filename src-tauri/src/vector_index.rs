@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use crate::clipboard::{ClipboardItem, ContentType};
+
+// 每个条目最多取前若干字符计算向量，避免超出向量模型的输入长度
+const MAX_EMBEDDING_CHARS: usize = 4000;
+
+/// 条目用于计算向量的文本，图片、空白条目和敏感内容返回 None
+///
+/// 向量由远程接口计算，归为密码或命中打码、过期规则的条目不发送出去。
+pub fn embedding_text(item: &ClipboardItem) -> Option<String> {
+    if item.content_type == ContentType::Image || item.content.trim().is_empty() {
+        return None;
+    }
+    if item.sensitive || item.category.as_deref() == Some("password") {
+        return None;
+    }
+    Some(item.content.chars().take(MAX_EMBEDDING_CHARS).collect())
+}
+
+/// 向量在数据库中保存为小端序的 f32 序列
+pub fn encode(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn decode(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+fn norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// 单个条目的向量
+struct StoredVector {
+    /// 计算向量时使用的模型，不同模型的向量不能互相比较
+    model: String,
+    vector: Vec<f32>,
+    norm: f32,
+}
+
+/// 剪贴板条目的向量索引，按余弦相似度查找最接近的条目
+pub struct VectorIndex {
+    vectors: HashMap<String, StoredVector>,
+}
+
+impl VectorIndex {
+    pub fn new() -> Self {
+        Self { vectors: HashMap::new() }
+    }
+
+    /// 添加或替换条目的向量
+    pub fn insert(&mut self, id: &str, model: &str, vector: Vec<f32>) {
+        let norm = norm(&vector);
+        self.vectors.insert(id.to_string(), StoredVector { model: model.to_string(), vector, norm });
+    }
+
+    /// 删除条目的向量
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.vectors.remove(id).is_some()
+    }
+
+    /// 清空索引
+    pub fn clear(&mut self) {
+        self.vectors.clear();
+    }
+
+    /// 条目是否已有指定模型计算的向量
+    pub fn contains(&self, id: &str, model: &str) -> bool {
+        self.vectors.get(id).is_some_and(|stored| stored.model == model)
+    }
+
+    /// 按余弦相似度从高到低返回 (条目ID, 相似度)
+    pub fn nearest(&self, query: &[f32], model: &str) -> Vec<(String, f32)> {
+        let query_norm = norm(query);
+        if query_norm == 0.0 {
+            return Vec::new();
+        }
+
+        let mut scored: Vec<(String, f32)> = self.vectors
+            .iter()
+            .filter(|(_, stored)| stored.model == model && stored.norm > 0.0 && stored.vector.len() == query.len())
+            .map(|(id, stored)| {
+                let dot: f32 = stored.vector.iter().zip(query).map(|(a, b)| a * b).sum();
+                (id.clone(), dot / (stored.norm * query_norm))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+    }
+}
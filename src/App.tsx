@@ -1,6 +1,6 @@
-import React from 'react';
+import React, { useEffect } from 'react';
 import { Routes, Route, useSearchParams, Navigate } from 'react-router-dom';
-import { Layout } from 'antd';
+import { Layout, notification } from 'antd';
 import Sidebar from './components/Sidebar';
 import Home from './pages/Home';
 import Settings from './pages/Settings';
@@ -9,6 +9,7 @@ import Chat from './pages/Chat';
 import AISettings from './pages/AISettings';
 import FloatClipboard from './pages/FloatClipboard';
 import Dashboard from './pages/index';
+import { getStorageStatus } from './api/apiClient';
 
 const { Content } = Layout;
 
@@ -16,6 +17,24 @@ const App: React.FC = () => {
   const [searchParams] = useSearchParams();
   const isFloatWindow = searchParams.get('window') === 'float_clipboard';
 
+  // 数据库未能打开时数据只保存在内存中，提示用户退出后会丢失
+  useEffect(() => {
+    if (isFloatWindow) {
+      return;
+    }
+    getStorageStatus()
+      .then(status => {
+        if (status.in_memory.length > 0) {
+          notification.warning({
+            message: '数据无法保存',
+            description: `${status.in_memory.join('、')}的数据库无法打开，本次运行的记录只保存在内存中，退出后会丢失。请检查数据目录的权限和磁盘空间后重启应用。`,
+            duration: 0
+          });
+        }
+      })
+      .catch(error => console.error('获取数据库状态失败:', error));
+  }, [isFloatWindow]);
+
   if (isFloatWindow) {
     // 如果是浮动窗口，只显示浮动剪贴板组件
     return <FloatClipboard />;
@@ -3,6 +3,11 @@
  */
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
+import { v4 as uuidv4 } from 'uuid';
+import { ClipboardItem } from '../models/clipboard';
+import { RetryPolicy } from '../models/ai';
+import { ChatSession, ClipboardSource, Message, StreamResponse } from '../models/chat';
+import { PromptInput } from '../models/role';
 
 // 定义简化版的 ChatMessage 接口，与 Rust 后端接口匹配
 interface ChatMessage {
@@ -10,84 +15,531 @@ interface ChatMessage {
   content: string;
 }
 
+// 流式请求的状态，与 Rust 后端 StreamInfo 对应
+export interface StreamInfo {
+  stream_id: string;
+  status: 'running' | 'completed' | 'cancelled' | 'failed';
+  started_at: number;
+  finished_at?: number;
+  error?: string;
+}
+
+// 密钥存储的状态，与 Rust 后端 SecretStoreStatus 对应
+export interface SecretStoreStatus {
+  backend: 'keyring' | 'encrypted_file';
+  unlocked: boolean;
+  initialized: boolean;
+}
+
+// 数据库的保存状态，与 Rust 后端 StorageStatus 对应
+export interface StorageStatus {
+  // 数据库未能打开、只保存在内存中的数据，退出后丢失
+  in_memory: string[];
+}
+
+// 语义搜索设置，与 Rust 后端 EmbeddingSettings 对应
+export interface EmbeddingSettings {
+  enabled: boolean;
+  // 为空时使用当前选择的AI提供商
+  provider_id: string;
+  model: string;
+}
+
+// 向量计算进度，与 Rust 后端 EmbeddingStatus 对应
+export interface EmbeddingStatus {
+  enabled: boolean;
+  model: string;
+  total: number;
+  embedded: number;
+}
+
+// 搜索结果，与 Rust 后端 SearchHit 对应
+export interface SearchHit {
+  item: ClipboardItem;
+  score: number;
+  snippets: {
+    field: 'content' | 'translation' | 'summary';
+    text: string;
+    highlights: [number, number][];
+  }[];
+}
+
+// 请求超出模型上下文长度时的处理方式，与 Rust 后端 ContextStrategy 对应
+export type ContextStrategy = 'drop_oldest' | 'summarize' | 'chunk';
+
+// 请求的 token 估算结果，与 Rust 后端 TokenReport 对应
+export interface TokenReport {
+  model: string;
+  context_limit: number;
+  // 调整前后的提示词 token 数
+  prompt_tokens: number;
+  final_prompt_tokens: number;
+  // 为回复保留的 token 数
+  max_tokens: number;
+  // 原始请求是否能直接放入上下文
+  fits: boolean;
+  strategy: ContextStrategy;
+  dropped_messages: number;
+  summarized: boolean;
+  omitted_chunks: number;
+}
+
+// 用量统计设置，与 Rust 后端 UsageSettings 对应
+export interface UsageSettings {
+  // 价格使用的货币，仅用于显示
+  currency: string;
+  // 模型价格表，键为模型ID或其前缀，单位为每百万 token 的费用
+  prices: Record<string, { input: number; output: number }>;
+  // 每月总费用上限，达到后拒绝新的请求
+  monthly_budget: number | null;
+  // 各提供商每月的费用上限
+  provider_budgets: Record<string, number>;
+}
+
+// 发起请求的剪贴板条目或角色，与 Rust 后端 UsageSource 对应
+export interface UsageSource {
+  item_id?: string;
+  role_id?: string;
+}
+
+// 按维度汇总的用量，与 Rust 后端 UsageSummary 对应
+export interface UsageSummary {
+  key: string;
+  requests: number;
+  failed: number;
+  prompt_tokens: number;
+  completion_tokens: number;
+  cost: number;
+  avg_latency_ms: number;
+}
+
+// 本月费用与预算，与 Rust 后端 BudgetStatus 对应
+export interface BudgetStatus {
+  currency: string;
+  month_cost: number;
+  monthly_budget: number | null;
+  provider_costs: Record<string, number>;
+  provider_budgets: Record<string, number>;
+}
+
+// 分页的聊天会话列表，与 Rust 后端 ChatSessionPage 对应
+export interface ChatSessionPage {
+  sessions: ChatSession[];
+  total: number;
+}
+
+// 可修改的会话字段，与 Rust 后端 ChatSessionUpdate 对应
+export type ChatSessionUpdate = Partial<Pick<ChatSession, 'title' | 'aiProvider' | 'systemPrompt' | 'rolePrompt' | 'roleId'>>;
+
+// 聊天消息搜索结果，与 Rust 后端 ChatSearchHit 对应
+export interface ChatSearchHit {
+  sessionId: string;
+  sessionTitle: string;
+  message: Message;
+  snippet: string;
+}
+
+// 渲染后的角色提示词，与 Rust 后端 RenderedPrompt 对应
+export interface RenderedPrompt {
+  prompt: string;
+  // 模板已包含剪贴板内容，不需要再单独发送
+  uses_clipboard: boolean;
+}
+
+// chat_completion 的请求参数，未指定的提供商、模型和参数使用角色绑定的值，其次使用提供商设置
+export interface ChatCompletionOptions {
+  providerId?: string;
+  model?: string;
+  messages: ChatMessage[];
+  temperature?: number;
+  maxTokens?: number;
+  stream?: boolean;
+  // 先检索剪贴板历史，将相关条目作为上下文
+  clipboardContext?: boolean;
+  // 发起请求的剪贴板条目或角色，用于用量统计
+  usageSource?: UsageSource;
+}
+
+// chat_completion 的结果
+export interface ChatCompletionResult {
+  content: string;
+  // 实际回答的提供商，主提供商失败时可能是备用提供商
+  providerId?: string;
+  references: ClipboardSource[];
+  // 被停止时为 true，content 只包含停止前收到的内容
+  cancelled: boolean;
+}
+
 /**
- * 请求流式聊天响应
- * @param url API基础URL
- * @param apiKey API密钥
- * @param model 模型名称
- * @param messages 消息列表
- * @param temperature 温度参数
- * @param maxTokens 最大令牌数
- * @param onData 数据回调函数
- */
-export async function streamChat(
-  url: string,
-  apiKey: string,
-  model: string,
-  messages: ChatMessage[],
-  temperature: number,
-  maxTokens: number,
-  onData: (data: any) => void
-): Promise<void> {
-  // 注册监听器接收流式响应
-  const unlisten = await listen('stream-response', (event) => {
-    onData(event.payload);
+ * 通过后端发送聊天请求，各提供商的接口格式、超时、重试和备用提供商都由后端处理
+ * @param options 请求参数
+ * @param onChunk 流式请求收到数据块时的回调
+ * @param signal 用于停止流式请求，停止后后端取消请求
+ */
+export async function chatCompletion(
+  options: ChatCompletionOptions,
+  onChunk?: (chunk: StreamResponse) => void,
+  signal?: AbortSignal
+): Promise<ChatCompletionResult> {
+  const streamId = uuidv4();
+  const eventName = `chat_response_${streamId}`;
+  const result: ChatCompletionResult = { content: '', references: [], cancelled: false };
+
+  // 流式数据块和结束标记（[DONE]、[CANCELLED] 或 ERROR: ...）都通过事件发送
+  let finish: (error?: Error) => void = () => {};
+  const finished = new Promise<void>((resolve, reject) => {
+    finish = error => error ? reject(error) : resolve();
   });
+  // 请求返回前就已结束时，避免出现未处理的拒绝
+  finished.catch(() => {});
+  // 停止时取消后端的流，后端随后发送 [CANCELLED]；请求返回前流还未登记，返回后再取消
+  let registered = false;
+  const onAbort = () => {
+    if (registered) {
+      cancelStream(streamId).catch(error => console.warn('取消流式请求失败:', error));
+    }
+  };
+
+  // 先注册监听器再发起请求，避免错过第一个数据块
+  const unlisten = await listen<StreamResponse & { references?: ClipboardSource[] } | string>(eventName, (event) => {
+    const payload = event.payload;
+    if (typeof payload === 'string') {
+      result.cancelled = payload === '[CANCELLED]';
+      finish(payload.startsWith('ERROR: ') ? new Error(payload.slice('ERROR: '.length)) : undefined);
+      return;
+    }
+    result.providerId = result.providerId ?? payload.provider_id;
+    result.references = payload.references ?? result.references;
+    result.content += payload.choices[0]?.delta.content || '';
+    onChunk?.(payload);
+  });
+  signal?.addEventListener('abort', onAbort);
 
   try {
-    // 调用后端命令发送请求
-    await invoke('stream_chat', {
-      request: {
-        url,
-        api_key: apiKey,
-        model,
-        messages,
-        temperature,
-        max_tokens: maxTokens,
-        stream: true
+    const response = await invoke<string>('chat_completion', {
+      options: {
+        provider_id: options.providerId,
+        messages: options.messages,
+        model: options.model,
+        temperature: options.temperature,
+        max_tokens: options.maxTokens,
+        stream: options.stream,
+        clipboard_context: options.clipboardContext,
+        usage_source: options.usageSource,
+        stream_id: streamId
       }
     });
+
+    // 非流式请求直接返回完整响应
+    if (response !== eventName) {
+      const json = JSON.parse(response);
+      return {
+        content: json.choices?.[0]?.message?.content ?? '',
+        providerId: json.provider_id,
+        references: json.references ?? [],
+        cancelled: false
+      };
+    }
+
+    registered = true;
+    if (signal?.aborted) {
+      onAbort();
+    }
+    await finished;
+    return result;
   } finally {
-    // 不管是成功还是失败，都需要取消监听
+    signal?.removeEventListener('abort', onAbort);
     unlisten();
   }
 }
 
 /**
- * 请求非流式聊天响应
- * @param url API基础URL
- * @param apiKey API密钥
- * @param model 模型名称
+ * 估算聊天请求的 token 数，不发起网络请求
+ * @param providerId 提供商ID，为空时使用当前选择的提供商
+ * @param model 模型名称，为空时使用提供商设置中的模型
  * @param messages 消息列表
- * @param temperature 温度参数
- * @param maxTokens 最大令牌数
+ * @param maxTokens 为回复保留的 token 数
+ * @param roleId 发起请求的角色，未指定的提供商、模型和参数使用角色绑定的值
  */
-export async function chatCompletion(
-  url: string,
-  apiKey: string,
-  model: string,
+export async function estimateChatTokens(
+  providerId: string | undefined,
+  model: string | undefined,
   messages: ChatMessage[],
-  temperature: number,
-  maxTokens: number
-): Promise<any> {
-  // 使用Rust后端命令发送请求，完全避免跨域问题
-  return invoke('chat_completion', {
-    request: {
-      url,
-      api_key: apiKey,
-      model,
-      messages,
-      temperature,
-      max_tokens: maxTokens,
-      stream: false
-    }
+  maxTokens?: number,
+  roleId?: string
+): Promise<TokenReport> {
+  return invoke<TokenReport>('estimate_chat_tokens', {
+    providerId,
+    model,
+    messages,
+    maxTokens,
+    roleId
   });
 }
 
+/**
+ * 设置请求超出上下文长度时的处理方式
+ */
+export async function updateContextStrategy(strategy: ContextStrategy): Promise<void> {
+  return invoke('update_context_strategy', { strategy });
+}
+
+/**
+ * 设置基于剪贴板回答时是否允许把归为密码的条目发送给模型
+ */
+export async function updateSharePasswordsInContext(enabled: boolean): Promise<void> {
+  return invoke('update_share_passwords_in_context', { enabled });
+}
+
+/**
+ * 设置请求失败时依次尝试的备用提供商
+ * @param providerIds 备用提供商ID，按尝试顺序排列
+ */
+export async function updateFallbackProviders(providerIds: string[]): Promise<void> {
+  return invoke('update_fallback_providers', { providerIds });
+}
+
+/**
+ * 设置限流或服务暂时不可用时的重试方式
+ */
+export async function updateRetryPolicy(retry: RetryPolicy): Promise<void> {
+  return invoke('update_retry_policy', { retry });
+}
+
+/**
+ * 获取用量统计设置（价格表与预算）
+ */
+export async function getUsageSettings(): Promise<UsageSettings> {
+  return invoke<UsageSettings>('get_usage_settings');
+}
+
+/**
+ * 保存用量统计设置
+ */
+export async function updateUsageSettings(settings: UsageSettings): Promise<void> {
+  return invoke('update_usage_settings', { settings });
+}
+
+/**
+ * 按维度汇总时间范围内的用量
+ * @param groupBy 汇总维度
+ * @param from 开始时间（毫秒时间戳，包含）
+ * @param to 结束时间（毫秒时间戳，不包含）
+ */
+export async function getUsageSummary(
+  groupBy: 'day' | 'provider' | 'model' | 'role',
+  from: number,
+  to: number
+): Promise<UsageSummary[]> {
+  return invoke<UsageSummary[]>('get_usage_summary', { groupBy, from, to });
+}
+
+/**
+ * 获取本月费用与预算
+ */
+export async function getBudgetStatus(): Promise<BudgetStatus> {
+  return invoke<BudgetStatus>('get_budget_status');
+}
+
+/**
+ * 分页获取聊天会话，按更新时间从新到旧排列，不包含消息
+ */
+export async function listChatSessions(offset?: number, limit?: number): Promise<ChatSessionPage> {
+  return invoke<ChatSessionPage>('list_chat_sessions', { offset, limit });
+}
+
+/**
+ * 获取聊天会话及其消息
+ */
+export async function getChatSession(id: string): Promise<ChatSession | null> {
+  return invoke<ChatSession | null>('get_chat_session', { id });
+}
+
+/**
+ * 创建聊天会话
+ */
+export async function createChatSession(session: ChatSession): Promise<ChatSession> {
+  return invoke<ChatSession>('create_chat_session', { session });
+}
+
+/**
+ * 修改聊天会话
+ */
+export async function updateChatSession(id: string, update: ChatSessionUpdate): Promise<void> {
+  return invoke('update_chat_session', { id, update });
+}
+
+/**
+ * 删除聊天会话
+ */
+export async function deleteChatSession(id: string): Promise<boolean> {
+  return invoke<boolean>('delete_chat_session', { id });
+}
+
+/**
+ * 清空聊天会话中的消息
+ */
+export async function clearChatSession(id: string): Promise<void> {
+  return invoke('clear_chat_session', { id });
+}
+
+/**
+ * 向聊天会话添加消息
+ */
+export async function addChatMessage(sessionId: string, message: Message): Promise<void> {
+  return invoke('add_chat_message', { sessionId, message });
+}
+
+/**
+ * 修改聊天消息内容
+ * @param providerId 回答的提供商
+ */
+export async function updateChatMessage(sessionId: string, messageId: string, content: string, providerId?: string): Promise<void> {
+  return invoke('update_chat_message', { sessionId, messageId, content, providerId });
+}
+
+/**
+ * 删除聊天消息
+ */
+export async function deleteChatMessage(sessionId: string, messageId: string): Promise<void> {
+  return invoke('delete_chat_message', { sessionId, messageId });
+}
+
+/**
+ * 在所有聊天会话的消息中搜索
+ */
+export async function searchChatMessages(query: string, limit?: number): Promise<ChatSearchHit[]> {
+  return invoke<ChatSearchHit[]>('search_chat_messages', { query, limit });
+}
+
+/**
+ * 导出聊天会话
+ * @param format json 可以再导入，markdown 便于阅读
+ */
+export async function exportChatSession(id: string, format: 'json' | 'markdown' = 'json'): Promise<string> {
+  return invoke<string>('export_chat_session', { id, format });
+}
+
+/**
+ * 导入之前导出的 JSON 会话
+ */
+export async function importChatSession(data: string): Promise<ChatSession> {
+  return invoke<ChatSession>('import_chat_session', { data });
+}
+
+/**
+ * 在后端渲染角色的提示词模板
+ * @param roleId 角色ID
+ * @param clipboard 剪贴板内容
+ * @param itemId 剪贴板条目ID，用于计算分类、语言和上一条内容
+ * @param inputs 自定义输入的值
+ */
+export async function renderRolePrompt(
+  roleId: string,
+  clipboard?: string,
+  itemId?: string,
+  inputs?: Record<string, string>
+): Promise<RenderedPrompt> {
+  return invoke<RenderedPrompt>('render_role_prompt', { roleId, clipboard, itemId, inputs });
+}
+
+/**
+ * 检查提示词模板，模板有误时返回错误信息
+ */
+export async function validatePromptTemplate(template: string, inputs?: PromptInput[]): Promise<void> {
+  return invoke('validate_prompt_template', { template, inputs });
+}
+
 /**
  * 取消流式请求
  * @param streamId 流ID
  */
 export async function cancelStream(streamId: string): Promise<void> {
   await invoke('cancel_stream', { stream_id: streamId });
-}
\ No newline at end of file
+}
+
+/**
+ * 查询流式请求的状态
+ * @param streamId 流ID
+ */
+export async function getStreamStatus(streamId: string): Promise<StreamInfo> {
+  return invoke<StreamInfo>('get_stream_status', { stream_id: streamId });
+}
+
+/**
+ * 查询数据库的保存状态
+ */
+export async function getStorageStatus(): Promise<StorageStatus> {
+  return invoke<StorageStatus>('get_storage_status');
+}
+
+/**
+ * 查询密钥存储的状态
+ */
+export async function getSecretStoreStatus(): Promise<SecretStoreStatus> {
+  return invoke<SecretStoreStatus>('get_secret_store_status');
+}
+
+/**
+ * 重新检查系统钥匙串，可用时改用钥匙串并迁移明文保存的密钥
+ */
+export async function recheckSecretStore(): Promise<SecretStoreStatus> {
+  return invoke<SecretStoreStatus>('recheck_secret_store');
+}
+
+/**
+ * 解锁加密的密钥文件，首次使用时设置口令
+ * @param passphrase 口令
+ */
+export async function unlockSecretStore(passphrase: string): Promise<SecretStoreStatus> {
+  return invoke<SecretStoreStatus>('unlock_secret_store', { passphrase });
+}
+
+/**
+ * 获取图片条目的原图
+ * @param id 剪贴板条目ID
+ * @returns PNG 格式的 data URL
+ */
+export async function getClipboardImage(id: string): Promise<string> {
+  return invoke<string>('get_clipboard_image', { id });
+}
+
+/**
+ * 全文搜索剪贴板历史，结果按相关度排序
+ * @param query 查询内容，支持 "短语" 和 前缀* 语法
+ * @param limit 返回的最大条目数
+ */
+export async function searchClipboard(query: string, limit?: number): Promise<SearchHit[]> {
+  return invoke<SearchHit[]>('search_clipboard', { query, limit });
+}
+
+/**
+ * 按语义搜索剪贴板历史
+ * @param query 查询内容
+ * @param limit 返回的最大条目数
+ */
+export async function semanticSearch(query: string, limit?: number): Promise<SearchHit[]> {
+  return invoke<SearchHit[]>('semantic_search', { query, limit });
+}
+
+/**
+ * 获取语义搜索设置
+ */
+export async function getEmbeddingSettings(): Promise<EmbeddingSettings> {
+  return invoke<EmbeddingSettings>('get_embedding_settings');
+}
+
+/**
+ * 保存语义搜索设置
+ * @param settings 语义搜索设置
+ */
+export async function updateEmbeddingSettings(settings: EmbeddingSettings): Promise<void> {
+  await invoke('update_embedding_settings', { settings });
+}
+
+/**
+ * 查询向量计算进度
+ */
+export async function getEmbeddingStatus(): Promise<EmbeddingStatus> {
+  return invoke<EmbeddingStatus>('get_embedding_status');
+}
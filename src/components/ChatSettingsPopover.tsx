@@ -100,7 +100,7 @@ const ChatSettingsPopover: React.FC<ChatSettingsPopoverProps> = ({
         // 更新会话
         updateSession(currentSession.id, {
           aiProvider: provider,
-          ...(rolePrompt && { rolePrompt }) // 仅当获取到提示词时才更新
+          ...(rolePrompt && { rolePrompt, roleId: selectedRole }) // 仅当获取到提示词时才更新
         });
         
         message.success('设置已更新');
@@ -120,6 +120,26 @@ export const DEFAULT_AI_PROVIDERS: AIProvider[] = [
     description: 'OpenAI 提供的 GPT 系列模型 API',
     website: 'https://openai.com'
   },
+  {
+    id: 'anthropic',
+    name: 'Anthropic',
+    apiBaseUrl: 'https://api.anthropic.com/v1',
+    defaultModels: ['claude-3-5-sonnet-latest', 'claude-3-5-haiku-latest', 'claude-3-opus-latest'],
+    supportsModelsList: false,
+    requiresApiKey: true,
+    description: 'Anthropic 提供的 Claude 系列模型 API（原生 Messages 接口）',
+    website: 'https://www.anthropic.com'
+  },
+  {
+    id: 'azure',
+    name: 'Azure OpenAI',
+    apiBaseUrl: '',
+    defaultModels: [],
+    supportsModelsList: true,
+    requiresApiKey: true,
+    description: 'Azure OpenAI 服务，API地址填写资源地址，模型填写部署名称',
+    website: 'https://azure.microsoft.com/products/ai-services/openai-service'
+  },
   {
     id: 'ollama-legacy',
     name: 'Ollama (经典API)',
@@ -1,3 +1,5 @@
+import { invoke } from '@tauri-apps/api/core';
+
 /**
  * 预定义的内容分类
  */
@@ -7,6 +9,7 @@ export interface CategoryItem {
   value: string;
   color: string;
   isSystem?: boolean;
+  order?: number;
   createdAt?: number;
 }
 
@@ -17,6 +20,11 @@ export const PREDEFINED_CATEGORIES: CategoryItem[] = [
   { label: '链接', value: 'link', color: 'purple' },
   { label: '邮箱', value: 'email', color: 'gold' },
   { label: '密码', value: 'password', color: 'red' },
+  { label: '文件路径', value: 'path', color: 'cyan' },
+  { label: '电话', value: 'phone', color: 'orange' },
+  { label: '颜色', value: 'color', color: 'magenta' },
+  { label: '数字', value: 'number', color: 'geekblue' },
+  { label: '图片', value: 'image', color: 'volcano' },
   { label: '其他', value: 'other', color: 'default' }
 ];
 
@@ -37,30 +45,13 @@ export const getAllCategories = (): CategoryItem[] => {
 };
 
 /**
- * 基于内容自动检测类别
- * @param content 内容文本
- * @returns 检测到的类别
+ * 从后端同步分类到本地缓存，供同步读取的 getAllCategories 使用
+ * @returns 后端保存的分类
  */
-export const detectCategory = (content: string): string => {
-  // 链接检测
-  if (content.match(/^https?:\/\//i)) return 'link';
-  
-  // 邮箱检测
-  if (content.match(/^[\w-\.]+@([\w-]+\.)+[\w-]{2,4}$/g)) return 'email';
-  
-  // 代码检测
-  if (content.match(/<[^>]*>/) || 
-      content.match(/function\s*\(/) || 
-      content.match(/^\s*import\s+/) || 
-      content.match(/\{\s*[\w\d_]+:\s*[\w\d_]+\s*\}/)) {
-    return 'code';
-  }
-  
-  // 密码检测
-  if (content.match(/\b(?:password|pwd|密码)\b/i)) return 'password';
-  
-  // 默认为文本
-  return 'text';
+export const syncCategories = async (): Promise<CategoryItem[]> => {
+  const categories = await invoke<CategoryItem[]>('get_categories');
+  localStorage.setItem('custom_categories', JSON.stringify(categories));
+  return categories;
 };
 
 /**
@@ -23,12 +23,40 @@ export interface AIProviderSettings {
   dynamic_models?: string[]; // 动态加载的模型列表
   last_test_time?: number;    // 最后测试时间
   test_success?: boolean;    // 测试是否成功
+  azure?: AzureDeployment;    // Azure OpenAI 部署配置
+}
+
+// Azure OpenAI 部署配置
+export interface AzureDeployment {
+  endpoint: string;          // 资源地址，如 https://<资源名>.openai.azure.com
+  deployment: string;        // 部署名称，留空时使用所选模型作为部署名称
+  api_version: string;       // 接口版本
 }
 
 // 全局AI设置
 export interface AISettings {
   selected_provider_id: string; // 当前选择的提供商ID
   providers: Record<string, AIProviderSettings>; // 各提供商的具体设置
+  context_strategy?: 'drop_oldest' | 'summarize' | 'chunk'; // 超出上下文长度时的处理方式
+  fallback_provider_ids?: string[]; // 请求失败时依次尝试的备用提供商
+  retry?: RetryPolicy; // 限流或服务暂时不可用时的重试设置
+  share_passwords_in_context?: boolean; // 基于剪贴板回答时是否允许发送归为密码的条目
+}
+
+// 请求失败后的重试设置
+export interface RetryPolicy {
+  max_retries: number; // 最多重试次数，0 表示不重试
+  base_delay_ms: number; // 第一次重试前的等待时间，之后每次翻倍
+  max_delay_ms: number; // 单次等待的上限
+}
+
+// 连接诊断步骤
+export interface DiagnosticStep {
+  name: 'connect' | 'auth' | 'model' | 'completion';
+  label: string;
+  status: 'passed' | 'failed' | 'warning' | 'skipped';
+  message: string;
+  latency_ms?: number;
 }
 
 // API测试结果
@@ -36,6 +64,7 @@ export interface APITestResult {
   success: boolean;
   message: string;
   response_time?: number;
+  steps?: DiagnosticStep[];  // 各诊断步骤的结果
 }
 
 // 模型信息
@@ -6,6 +6,20 @@ export interface Message {
   content: string;
   timestamp: number;
   clipboardRef?: ClipboardReference;
+  // 回答的提供商，主提供商失败时可能是备用提供商
+  providerId?: string;
+  // 基于剪贴板历史回答时引用的条目，只保存在内存中
+  sources?: ClipboardSource[];
+}
+
+// 基于剪贴板历史回答时引用的条目，与 Rust 后端 ClipboardReference 对应
+export interface ClipboardSource {
+  // 对应回答中的 [编号]
+  index: number;
+  item_id: string;
+  score: number;
+  timestamp: number;
+  preview: string;
 }
 
 export interface ClipboardReference {
@@ -23,8 +37,14 @@ export interface ChatSession {
   aiProvider: AIProvider;
   systemPrompt?: string;
   rolePrompt?: string;
+  // 创建会话时使用的角色
+  roleId?: string;
+  // 会话引用的第一个剪贴板条目
+  clipboardItemId?: string;
   createdAt: number;
   updatedAt: number;
+  // 会话列表只返回消息数，messages 为空
+  messageCount?: number;
 }
 
 export interface StreamResponse {
@@ -38,4 +58,6 @@ export interface StreamResponse {
     finish_reason: string | null;
   }[];
   created: number;
+  // 后端请求时实际回答的提供商，主提供商失败时可能是备用提供商
+  provider_id?: string;
 } 
\ No newline at end of file
@@ -1,3 +1,16 @@
+// 剪贴板内容类型，与 Rust 后端 ContentType 对应
+export type ClipboardContentType = 'text' | 'image' | 'html' | 'rtf' | 'files';
+
+// 图片条目的元数据，原图需通过 get_clipboard_image 获取
+export interface ClipboardImageInfo {
+  hash: string;
+  width: number;
+  height: number;
+  size: number;
+  mime: string;
+  thumbnail: string; // 缩略图 data URL
+}
+
 // 剪贴板条目类型定义
 export interface ClipboardItem {
   id: string;
@@ -10,6 +23,8 @@ export interface ClipboardItem {
   summary?: string;
   index?: number; // 用于显示序号，在前端处理时添加
   aiAnalysisCount?: number; // 用于记录AI分析次数
+  contentType?: ClipboardContentType;
+  image?: ClipboardImageInfo;
 }
 
 // 剪贴板筛选选项
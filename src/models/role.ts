@@ -1,3 +1,13 @@
+/**
+ * 提示词模板中的自定义输入
+ */
+export interface PromptInput {
+  name: string;
+  label?: string;
+  default?: string;
+  required?: boolean;
+}
+
 /**
  * AI角色定义
  */
@@ -5,10 +15,18 @@ export interface Role {
   id: string;
   name: string;
   description: string;
+  // 提示词模板，可以使用 {{clipboard}} 等变量
   system_prompt: string;
   icon: string;
   avatar: string | null;
   is_custom: boolean;
+  inputs?: PromptInput[];
+  // 角色绑定的提供商、模型和参数，未设置时使用全局AI设置
+  provider_id?: string;
+  model?: string;
+  temperature?: number;
+  max_tokens?: number;
+  stream?: boolean;
   created_at: number;
   updated_at: number;
 }
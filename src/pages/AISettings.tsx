@@ -48,13 +48,38 @@ import { useAISettingsStore } from '../store/aiSettingsStore';
 import { DEFAULT_AI_PROVIDERS } from '../constants/aiProviders';
 import { useTheme } from '../context/ThemeContext';
 import { invoke } from '@tauri-apps/api/core';
-import { AIProviderSettings } from '../models/ai';
+import { AIProviderSettings, DiagnosticStep, RetryPolicy } from '../models/ai';
+import { getSecretStoreStatus, recheckSecretStore, unlockSecretStore, SecretStoreStatus, updateContextStrategy, ContextStrategy, updateSharePasswordsInContext, updateFallbackProviders, updateRetryPolicy } from '../api/apiClient';
 
 const { Option } = Select;
 const { TabPane } = Tabs;
 const { Title, Text, Paragraph } = Typography;
 const { Step } = Steps;
 
+// 连接测试结果说明，列出各诊断步骤及耗时
+const STEP_STATUS_TEXT: Record<DiagnosticStep['status'], string> = {
+  passed: '✓',
+  failed: '✗',
+  warning: '!',
+  skipped: '-'
+};
+
+const renderTestDescription = (message?: string, steps?: DiagnosticStep[]) => (
+  <div>
+    <div>{message}</div>
+    {steps && steps.length > 0 && (
+      <ul style={{ margin: '8px 0 0', paddingLeft: 20 }}>
+        {steps.map(step => (
+          <li key={step.name}>
+            {STEP_STATUS_TEXT[step.status]} {step.label}：{step.message}
+            {step.latency_ms !== undefined && ` (${step.latency_ms}ms)`}
+          </li>
+        ))}
+      </ul>
+    )}
+  </div>
+);
+
 // 添加设置流程组件
 const AISetupFlow: React.FC<{
   selectedProviderId: string;
@@ -65,6 +90,7 @@ const AISetupFlow: React.FC<{
   testResult: {
     success?: boolean;
     message?: string;
+    steps?: DiagnosticStep[];
     loading: boolean;
   }
   loadModels: () => Promise<void>;
@@ -274,7 +300,7 @@ const AISetupFlow: React.FC<{
             {testResult.success !== undefined && (
               <Alert
                 message={testResult.success ? "连接成功" : "连接失败"}
-                description={testResult.message}
+                description={renderTestDescription(testResult.message, testResult.steps)}
                 type={testResult.success ? "success" : "error"}
                 showIcon
                 style={{ 
@@ -399,12 +425,48 @@ const AISettings: React.FC = () => {
   const [testResult, setTestResult] = useState<{
     success?: boolean;
     message?: string;
+    steps?: DiagnosticStep[];
     loading: boolean;
   }>({ loading: false });
   const [loadingModels, setLoadingModels] = useState(false);
   const [savingSettings, setSavingSettings] = useState(false);
   const [configVisible, setConfigVisible] = useState(false);
   const [currentConfig, setCurrentConfig] = useState<string>('');
+  const [secretStatus, setSecretStatus] = useState<SecretStoreStatus | null>(null);
+  const [passphrase, setPassphrase] = useState('');
+  
+  // 查询密钥存储状态，系统钥匙串不可用时需要输入口令解锁加密文件
+  useEffect(() => {
+    getSecretStoreStatus()
+      .then(setSecretStatus)
+      .catch(error => console.error('获取密钥存储状态失败:', error));
+  }, []);
+  
+  // 解锁密钥存储
+  const handleUnlockSecrets = async () => {
+    try {
+      setSecretStatus(await unlockSecretStore(passphrase));
+      setPassphrase('');
+      message.success('密钥存储已解锁');
+    } catch (error) {
+      message.error('解锁失败: ' + (error instanceof Error ? error.message : String(error)));
+    }
+  };
+  
+  // 重新检查系统钥匙串，可用时不再需要设置口令
+  const handleRecheckSecrets = async () => {
+    try {
+      const status = await recheckSecretStore();
+      setSecretStatus(status);
+      if (status.backend === 'keyring') {
+        message.success('已改用系统钥匙串保存API密钥');
+      } else {
+        message.warning('系统钥匙串仍不可用');
+      }
+    } catch (error) {
+      message.error('检查系统钥匙串失败: ' + (error instanceof Error ? error.message : String(error)));
+    }
+  };
   
   // 当前选中的提供商ID
   const selectedProviderId = settings.selected_provider_id;
@@ -431,6 +493,50 @@ const AISettings: React.FC = () => {
     updateSettings({ selected_provider_id: value });
   };
   
+  // 处理备用提供商变更，按选择的顺序依次尝试
+  const handleFallbackProvidersChange = async (value: string[]) => {
+    try {
+      await updateFallbackProviders(value);
+      updateSettings({ fallback_provider_ids: value });
+    } catch (error) {
+      message.error(`保存失败: ${error}`);
+    }
+  };
+  
+  // 处理重试次数变更
+  const handleMaxRetriesChange = async (value: number | null) => {
+    const retry: RetryPolicy = {
+      ...(settings.retry || { max_retries: 2, base_delay_ms: 1000, max_delay_ms: 30000 }),
+      max_retries: value ?? 0
+    };
+    try {
+      await updateRetryPolicy(retry);
+      updateSettings({ retry });
+    } catch (error) {
+      message.error(`保存失败: ${error}`);
+    }
+  };
+  
+  // 处理超出上下文长度时的策略变更
+  const handleContextStrategyChange = async (value: ContextStrategy) => {
+    try {
+      await updateContextStrategy(value);
+      updateSettings({ context_strategy: value });
+    } catch (error) {
+      message.error(`保存失败: ${error}`);
+    }
+  };
+  
+  // 处理是否允许在剪贴板问答中发送密码条目
+  const handleSharePasswordsChange = async (checked: boolean) => {
+    try {
+      await updateSharePasswordsInContext(checked);
+      updateSettings({ share_passwords_in_context: checked });
+    } catch (error) {
+      message.error(`保存失败: ${error}`);
+    }
+  };
+  
   // 处理API地址变更
   const handleApiUrlChange = (e: React.ChangeEvent<HTMLInputElement>) => {
     updateProviderSettings(selectedProviderId, { custom_api_base_url: e.target.value });
@@ -485,6 +591,17 @@ const AISettings: React.FC = () => {
     updateProviderSettings(selectedProviderId, { models_list_url: e.target.value });
   };
   
+  // 处理Azure接口版本变更
+  const handleAzureApiVersionChange = (e: React.ChangeEvent<HTMLInputElement>) => {
+    updateProviderSettings(selectedProviderId, {
+      azure: {
+        endpoint: providerSettings.custom_api_base_url || '',
+        deployment: providerSettings.azure?.deployment || '',
+        api_version: e.target.value
+      }
+    });
+  };
+  
   // 加载模型列表
   const handleLoadModels = async () => {
     if (!selectedProvider) return;
@@ -517,6 +634,7 @@ const AISettings: React.FC = () => {
       setTestResult({
         success: result.success,
         message: result.message,
+        steps: result.steps,
         loading: false
       });
       if (result.success) {
@@ -766,6 +884,34 @@ const AISettings: React.FC = () => {
         }
       >
         <Spin spinning={isLoading} tip="加载中...">
+          {secretStatus?.backend === 'encrypted_file' && !secretStatus.unlocked && (
+            <Alert
+              type="warning"
+              showIcon
+              style={{ marginBottom: 16 }}
+              message={secretStatus.initialized ? '请输入口令解锁API密钥' : '请设置口令以加密保存API密钥'}
+              description={
+                <Space>
+                  <Input.Password
+                    prefix={<KeyOutlined />}
+                    value={passphrase}
+                    onChange={e => setPassphrase(e.target.value)}
+                    onPressEnter={handleUnlockSecrets}
+                    placeholder="系统钥匙串不可用，API密钥保存在加密文件中"
+                    style={{ width: 360 }}
+                    className={isDarkMode ? 'dark-input' : ''}
+                  />
+                  <Button type="primary" onClick={handleUnlockSecrets} disabled={!passphrase}>
+                    {secretStatus.initialized ? '解锁' : '设置口令'}
+                  </Button>
+                  {!secretStatus.initialized && (
+                    <Button onClick={handleRecheckSecrets}>重新检查系统钥匙串</Button>
+                  )}
+                </Space>
+              }
+            />
+          )}
+          
           {/* 添加AI服务设置流程 */}
           <AISetupFlow
             selectedProviderId={selectedProviderId}
@@ -857,6 +1003,85 @@ const AISettings: React.FC = () => {
                           />
                         )}
                         
+                        <Form.Item 
+                          label={
+                            <Space>
+                              <span>超出上下文长度时</span>
+                              <Tooltip title="对话或粘贴的内容超出模型上下文长度时，发送前如何调整消息">
+                                <QuestionCircleOutlined style={{ color: isDarkMode ? 'rgba(255, 255, 255, 0.45)' : 'rgba(0, 0, 0, 0.45)' }} />
+                              </Tooltip>
+                            </Space>
+                          }
+                        >
+                          <Select
+                            value={settings.context_strategy || 'drop_oldest'}
+                            onChange={handleContextStrategyChange}
+                            className={isDarkMode ? 'dark-select' : ''}
+                          >
+                            <Option value="drop_oldest">丢弃最早的对话</Option>
+                            <Option value="summarize">总结较早的对话</Option>
+                            <Option value="chunk">截取过长的内容</Option>
+                          </Select>
+                        </Form.Item>
+                        
+                        <Form.Item 
+                          label={
+                            <Space>
+                              <span>剪贴板问答包含密码</span>
+                              <Tooltip title="基于剪贴板回答时，默认不会把归为密码的条目和命中打码、过期规则的条目发送给模型。开启后归为密码的条目也会作为参考内容发送">
+                                <QuestionCircleOutlined style={{ color: isDarkMode ? 'rgba(255, 255, 255, 0.45)' : 'rgba(0, 0, 0, 0.45)' }} />
+                              </Tooltip>
+                            </Space>
+                          }
+                        >
+                          <Switch
+                            checked={settings.share_passwords_in_context ?? false}
+                            onChange={handleSharePasswordsChange}
+                          />
+                        </Form.Item>
+                        
+                        <Form.Item 
+                          label={
+                            <Space>
+                              <span>备用提供商</span>
+                              <Tooltip title="当前提供商请求失败（或流式输出开始前失败）时，按选择的顺序依次改用这些提供商，使用各自设置中的模型">
+                                <QuestionCircleOutlined style={{ color: isDarkMode ? 'rgba(255, 255, 255, 0.45)' : 'rgba(0, 0, 0, 0.45)' }} />
+                              </Tooltip>
+                            </Space>
+                          }
+                        >
+                          <Select
+                            mode="multiple"
+                            value={settings.fallback_provider_ids || []}
+                            onChange={handleFallbackProvidersChange}
+                            placeholder="不使用备用提供商"
+                            className={isDarkMode ? 'dark-select' : ''}
+                          >
+                            {DEFAULT_AI_PROVIDERS.filter(provider => provider.id !== selectedProviderId).map(provider => (
+                              <Option key={provider.id} value={provider.id}>{provider.name}</Option>
+                            ))}
+                          </Select>
+                        </Form.Item>
+                        
+                        <Form.Item 
+                          label={
+                            <Space>
+                              <span>失败重试次数</span>
+                              <Tooltip title="遇到限流（429）或服务暂时不可用（5xx）时按指数退避重试，服务返回 Retry-After 时按其等待">
+                                <QuestionCircleOutlined style={{ color: isDarkMode ? 'rgba(255, 255, 255, 0.45)' : 'rgba(0, 0, 0, 0.45)' }} />
+                              </Tooltip>
+                            </Space>
+                          }
+                        >
+                          <InputNumber
+                            min={0}
+                            max={5}
+                            value={settings.retry?.max_retries ?? 2}
+                            onChange={handleMaxRetriesChange}
+                            className={isDarkMode ? 'dark-input' : ''}
+                          />
+                        </Form.Item>
+                        
                         <Form.Item 
                           label={
                             <Space>
@@ -898,6 +1123,26 @@ const AISettings: React.FC = () => {
                           />
                         </Form.Item>
 
+                        {selectedProviderId === 'azure' && (
+                          <Form.Item 
+                            label={
+                              <Space>
+                                <span>API版本</span>
+                                <Tooltip title="Azure OpenAI 的 api-version 参数，留空时使用 2024-06-01">
+                                  <QuestionCircleOutlined style={{ color: isDarkMode ? 'rgba(255, 255, 255, 0.45)' : 'rgba(0, 0, 0, 0.45)' }} />
+                                </Tooltip>
+                              </Space>
+                            }
+                          >
+                            <Input
+                              value={providerSettings.azure?.api_version}
+                              onChange={handleAzureApiVersionChange}
+                              placeholder="2024-06-01"
+                              className={isDarkMode ? 'dark-input' : ''}
+                            />
+                          </Form.Item>
+                        )}
+
                         {selectedProviderId === 'Copy2AI' && (
                           <Alert
                             message="默认免费AI，无需输入API密钥"
@@ -1000,7 +1245,7 @@ const AISettings: React.FC = () => {
                         {testResult.success !== undefined && (
                           <Alert
                             message={testResult.success ? "连接成功" : "连接失败"}
-                            description={testResult.message}
+                            description={renderTestDescription(testResult.message, testResult.steps)}
                             type={testResult.success ? "success" : "error"}
                             showIcon
                             style={{ 
@@ -16,7 +16,7 @@ import {
   Typography,
   Row,
   Col,
-  Popconfirm
+  Select
 } from 'antd';
 import {
   PlusOutlined,
@@ -28,6 +28,7 @@ import {
 import { useTheme } from '../context/ThemeContext';
 import type { Color } from 'antd/es/color-picker';
 import { invoke } from '@tauri-apps/api/core';
+import { syncCategories } from '../constants/categories';
 
 const { Title, Text } = Typography;
 
@@ -41,20 +42,6 @@ interface CategoryItem {
   createdAt?: number;
 }
 
-// 默认分类
-const DEFAULT_CATEGORIES: CategoryItem[] = [
-  { id: 'category_all', label: '全部', value: 'all', color: '#d9d9d9', isSystem: true },
-  { id: 'category_text', label: '文本', value: 'text', color: '#1890ff', isSystem: true },
-  { id: 'category_code', label: '代码', value: 'code', color: '#52c41a', isSystem: true },
-  { id: 'category_link', label: '链接', value: 'link', color: '#722ed1', isSystem: true },
-  { id: 'category_email', label: '邮箱', value: 'email', color: '#faad14', isSystem: true },
-  { id: 'category_password', label: '密码', value: 'password', color: '#f5222d', isSystem: true },
-  { id: 'category_other', label: '其他', value: 'other', color: '#d9d9d9', isSystem: true },
-  { id: 'category_study', label: '学习', value: 'study', color: '#13c2c2', isSystem: false },
-  { id: 'category_work', label: '工作', value: 'work', color: '#2f54eb', isSystem: false },
-  { id: 'category_life', label: '生活', value: 'life', color: '#eb2f96', isSystem: false }
-];
-
 // 预设颜色选项
 const PRESET_COLORS = [
   '#1677ff', '#f5222d', '#fa541c', '#fa8c16', '#faad14',
@@ -76,6 +63,9 @@ const CategorySettings: React.FC = () => {
   const [selectedColorIndex, setSelectedColorIndex] = useState<number | null>(null);
   const [previewLabel, setPreviewLabel] = useState<string>('新标签');
   const [valueModifiedByUser, setValueModifiedByUser] = useState<boolean>(false);
+  const [deletingCategory, setDeletingCategory] = useState<CategoryItem | null>(null);
+  // 删除分类时条目改归的分类，为空时由后端重新识别分类
+  const [reassignTo, setReassignTo] = useState<string | undefined>(undefined);
 
   // 加载分类数据
   useEffect(() => {
@@ -86,52 +76,17 @@ const CategorySettings: React.FC = () => {
   const fetchCategories = async () => {
     setLoading(true);
     try {
-      // 尝试从Tauri后端获取分类
-      const categories = await invoke<CategoryItem[]>('get_categories');
-      if (categories && categories.length > 0) {
-        setCategories(categories);
-      } else {
-        // 如果后端没有分类数据，尝试从localStorage获取
-        loadCategories();
-      }
+      const categories = await syncCategories();
+      setCategories(categories as CategoryItem[]);
     } catch (error) {
       console.error('从Tauri获取分类失败:', error);
-      // 如果Tauri调用失败，回退到localStorage
-      loadCategories();
+      message.error(`加载分类失败: ${error}`);
     } finally {
       setLoading(false);
     }
   };
 
-  // 从localStorage加载分类
-  const loadCategories = () => {
-    try {
-      const savedCategories = localStorage.getItem('custom_categories');
-      if (savedCategories) {
-        setCategories(JSON.parse(savedCategories));
-      } else {
-        // 首次使用，初始化默认分类
-        setCategories(DEFAULT_CATEGORIES);
-        localStorage.setItem('custom_categories', JSON.stringify(DEFAULT_CATEGORIES));
-      }
-    } catch (error) {
-      console.error('加载分类失败:', error);
-      message.error('加载分类设置失败');
-    }
-  };
-
-  // 保存分类到localStorage
-  const saveCategories = (newCategories: CategoryItem[]) => {
-    try {
-      localStorage.setItem('custom_categories', JSON.stringify(newCategories));
-      message.success('分类设置已保存');
-    } catch (error) {
-      console.error('保存分类失败:', error);
-      message.error('保存分类设置失败');
-    }
-  };
-
-  // 添加或编辑分类
+  // 添加或编辑分类，后端校验失败时保留弹窗并显示原因
   const handleAddOrEditCategory = async (values: any) => {
     try {
       // 使用colorValue替代表单中的color
@@ -141,57 +96,40 @@ const CategorySettings: React.FC = () => {
       };
 
       if (editingCategory) {
-        // 尝试使用Tauri API
-        try {
-          await invoke('update_category', { id: editingCategory.id, ...formData });
-          message.success('分类已更新');
-        } catch (error) {
-          console.warn('Tauri API调用失败，使用本地存储:', error);
-          // 回退到本地存储
-          const newCategories = [...categories];
-          const index = newCategories.findIndex(c => c.id === editingCategory.id);
-          if (index !== -1) {
-            newCategories[index] = {
-              ...newCategories[index],
-              ...formData
-            };
-          }
-          setCategories(newCategories);
-          saveCategories(newCategories);
-        }
+        await invoke('update_category', { id: editingCategory.id, ...formData });
+        message.success('分类已更新');
       } else {
-        // 尝试使用Tauri API
-        try {
-          await invoke('add_category', formData);
-          message.success('分类已添加');
-        } catch (error) {
-          console.warn('Tauri API调用失败，使用本地存储:', error);
-          // 回退到本地存储
-          const newCategories = [...categories];
-          newCategories.push({
-            id: `category_${Date.now()}`,
-            ...formData,
-            isSystem: false,
-            createdAt: Date.now()
-          });
-          setCategories(newCategories);
-          saveCategories(newCategories);
-        }
+        await invoke('add_category', formData);
+        message.success('分类已添加');
       }
       setModalVisible(false);
       form.resetFields();
       fetchCategories();
     } catch (error) {
       console.error('保存分类失败:', error);
-      message.error('保存分类失败，请重试');
+      message.error(`保存分类失败: ${error}`);
     }
   };
 
-  // 删除分类
-  const handleDeleteCategory = (id: string) => {
-    const newCategories = categories.filter(cat => cat.id !== id);
-    setCategories(newCategories);
-    saveCategories(newCategories);
+  // 显示删除确认框
+  const showDeleteModal = (category: CategoryItem) => {
+    setDeletingCategory(category);
+    setReassignTo(undefined);
+  };
+
+  // 删除分类，原分类下的条目改归所选分类，未选择时由后端重新识别分类
+  const handleDeleteCategory = async () => {
+    if (!deletingCategory) return;
+    try {
+      const count = await invoke<number>('delete_category', { id: deletingCategory.id, reassignTo });
+      const action = reassignTo ? '移动到所选分类' : '重新分类';
+      message.success(count > 0 ? `分类已删除，${count} 条内容已${action}` : '分类已删除');
+      setDeletingCategory(null);
+      fetchCategories();
+    } catch (error) {
+      console.error('删除分类失败:', error);
+      message.error(`删除分类失败: ${error}`);
+    }
   };
 
   // 显示添加/编辑模态框
@@ -259,21 +197,12 @@ const CategorySettings: React.FC = () => {
       onOk: async () => {
         setLoading(true);
         try {
-          // 尝试使用Tauri API
-          try {
-            await invoke('reset_categories');
-            message.success('分类已重置为默认设置');
-          } catch (error) {
-            console.warn('Tauri API调用失败，使用本地存储重置:', error);
-            // 回退到本地存储
-            setCategories(DEFAULT_CATEGORIES);
-            localStorage.setItem('custom_categories', JSON.stringify(DEFAULT_CATEGORIES));
-            message.success('分类已重置为默认设置');
-          }
+          await invoke('reset_categories');
+          message.success('分类已重置为默认设置');
           fetchCategories();
         } catch (error) {
           console.error('重置分类失败:', error);
-          message.error('重置分类失败，请重试');
+          message.error(`重置分类失败: ${error}`);
         } finally {
           setLoading(false);
         }
@@ -341,22 +270,15 @@ const CategorySettings: React.FC = () => {
           >
             编辑
           </Button>
-          <Popconfirm
-            title="确定要删除此分类吗？"
-            description={record.isSystem ? "删除系统分类可能会影响系统功能，确定要继续吗？" : "此操作不可撤销"}
-            onConfirm={() => handleDeleteCategory(record.id)}
-            okText="确定"
-            cancelText="取消"
-            icon={<QuestionCircleOutlined style={{ color: 'red' }} />}
+          <Button
+            type="text"
+            danger
+            icon={<DeleteOutlined />}
+            disabled={record.isSystem}
+            onClick={() => showDeleteModal(record)}
           >
-            <Button
-              type="text"
-              danger
-              icon={<DeleteOutlined />}
-            >
-              删除
-            </Button>
-          </Popconfirm>
+            删除
+          </Button>
         </Space>
       ),
     },
@@ -443,6 +365,7 @@ const CategorySettings: React.FC = () => {
             <Input 
               placeholder="例如：work_notes" 
               onChange={handleValueChange}
+              disabled={!!editingCategory?.isSystem}
             />
           </Form.Item>
 
@@ -482,6 +405,33 @@ const CategorySettings: React.FC = () => {
           </Form.Item>
         </Form>
       </Modal>
+
+      <Modal
+        title="确定要删除此分类吗？"
+        open={!!deletingCategory}
+        onCancel={() => setDeletingCategory(null)}
+        onOk={handleDeleteCategory}
+        okText="确定删除"
+        okType="danger"
+        cancelText="取消"
+        destroyOnClose
+      >
+        <Space direction="vertical" style={{ width: '100%' }}>
+          <Text>
+            删除后，「{deletingCategory?.label}」下的内容将移动到以下分类，此操作不可撤销。
+          </Text>
+          <Select
+            style={{ width: '100%' }}
+            allowClear
+            placeholder="重新自动分类"
+            value={reassignTo}
+            onChange={(value?: string) => setReassignTo(value)}
+            options={categories
+              .filter(c => c.value !== 'all' && c.id !== deletingCategory?.id)
+              .map(c => ({ label: c.label, value: c.value }))}
+          />
+        </Space>
+      </Modal>
     </div>
   );
 };
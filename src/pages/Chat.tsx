@@ -1,11 +1,12 @@
 import React, { useState, useEffect, useRef } from 'react';
-import { Input, Button, Avatar, List, Typography, Spin, Divider, Select, Modal, Tooltip, Card, message, Tag, Space, Tabs, Drawer, Dropdown } from 'antd';
+import { Input, Button, Avatar, List, Typography, Spin, Divider, Select, Modal, Tooltip, Card, message, Tag, Space, Tabs, Drawer, Dropdown, Switch } from 'antd';
 import { SendOutlined, UserOutlined, RobotOutlined, SettingOutlined, ClearOutlined, PaperClipOutlined, FileTextOutlined, ExpandOutlined, OrderedListOutlined, StopOutlined, ArrowLeftOutlined, InfoCircleOutlined, HistoryOutlined, DeleteOutlined, EditOutlined, ImportOutlined, ExportOutlined, PlusCircleOutlined, MessageOutlined, FileAddOutlined, PlusOutlined, SwapOutlined } from '@ant-design/icons';
 import { useChatStore } from '../store/chatStore';
 import { useAISettingsStore } from '../store/aiSettingsStore';
 import { invoke } from '@tauri-apps/api/core';
 import { DEFAULT_AI_PROVIDERS } from '../constants/aiProviders';
 import { ClipboardReference, Message } from '../models/chat';
+import { TokenReport } from '../api/apiClient';
 import { useNavigate } from 'react-router-dom';
 import { ClipboardItem } from '../models/clipboard';
 import { useChatUserPrefsStore } from '../store/chatUserPrefsStore';
@@ -77,11 +78,18 @@ const useMediaQuery = (query: string) => {
 const Chat: React.FC = () => {
   const {
     sessions,
+    sessionTotal,
+    sessionsLoaded,
     currentSession,
     isStreaming,
+    loadSessions,
+    loadMoreSessions,
     createSession,
     setCurrentSession,
-    sendMessage
+    sendMessage,
+    clipboardContext,
+    setClipboardContext,
+    estimateDraftTokens
   } = useChatStore();
   
   const [roles, setRoles] = useState<RoleWithDefault[]>([]);
@@ -97,9 +105,32 @@ const Chat: React.FC = () => {
   const [clipboardRef, setClipboardRef] = useState<ClipboardReference | null>(null);
   const [loading, setLoading] = useState<boolean>(false);
   const [newChatTab, setNewChatTab] = useState<string>('role');
+  // 发送前估算的 token 数
+  const [tokenReport, setTokenReport] = useState<TokenReport | null>(null);
   
   const messagesEndRef = useRef<HTMLDivElement>(null);
   
+  // 输入停顿后估算本次请求的 token 数，超出上下文长度时提前提示
+  useEffect(() => {
+    if (!inputValue.trim() && !clipboardRef) {
+      setTokenReport(null);
+      return;
+    }
+    
+    let cancelled = false;
+    const timer = setTimeout(() => {
+      estimateDraftTokens(inputValue, clipboardRef || undefined)
+        .then(report => {
+          if (!cancelled) setTokenReport(report);
+        })
+        .catch(error => console.warn('估算 token 数失败:', error));
+    }, 500);
+    return () => {
+      cancelled = true;
+      clearTimeout(timer);
+    };
+  }, [inputValue, clipboardRef, currentSession?.id, currentSession?.messages.length]);
+  
   const [clipboardItems, setClipboardItems] = useState<ClipboardItem[]>([]);
   const [showClipboardModal, setShowClipboardModal] = useState<boolean>(false);
   const [selectedClipboardItems, setSelectedClipboardItems] = useState<ClipboardItem[]>([]);
@@ -174,11 +205,17 @@ const Chat: React.FC = () => {
     }
   }, [defaultProviderId, setDefaultProvider]);
   
+  // 从后端加载聊天会话
+  useEffect(() => {
+    setHistoryLoading(true);
+    loadSessions().finally(() => setHistoryLoading(false));
+  }, [loadSessions]);
+  
   // 初始化加载时自动创建会话
   useEffect(() => {
     const initializeChat = async () => {
-      // 仅在尚未尝试自动创建且没有当前会话时执行
-      if (!autoCreationAttempted && !currentSession) {
+      // 等待会话加载完成，仅在尚未尝试自动创建且没有当前会话时执行
+      if (sessionsLoaded && !autoCreationAttempted && !currentSession) {
         setAutoCreationAttempted(true); // 标记已尝试自动创建
         setLoading(true);
         try {
@@ -217,7 +254,8 @@ const Chat: React.FC = () => {
               const newSession = createSession(
                 provider,
                 undefined,
-                rolePrompt
+                rolePrompt,
+                roleId
               );
               console.log('会话创建成功:', newSession);
               
@@ -244,7 +282,8 @@ const Chat: React.FC = () => {
                 createSession(
                   firstProvider,
                   undefined,
-                  rolePrompt
+                  rolePrompt,
+                  roleId
                 );
                 // 更新默认提供商
                 setDefaultProvider(firstProvider.id);
@@ -272,7 +311,7 @@ const Chat: React.FC = () => {
     initializeChat();
     
     // 依赖项不包含autoCreationAttempted，确保只在组件首次加载时执行
-  }, [currentSession, defaultRoleId, defaultProviderId, selectedRole, selectedProvider, roles, createSession, setDefaultRole, setDefaultProvider]);
+  }, [sessionsLoaded, currentSession, defaultRoleId, defaultProviderId, selectedRole, selectedProvider, roles, createSession, setDefaultRole, setDefaultProvider]);
   
   // 消息区域滚动到底部
   useEffect(() => {
@@ -568,7 +607,8 @@ const Chat: React.FC = () => {
       createSession(
         provider,
         undefined, // 系统提示词稍后设置
-        rolePrompt // 角色提示词
+        rolePrompt, // 角色提示词
+        selectedRole
       );
       
       // 更新当前状态，确保UI显示正确
@@ -750,11 +790,34 @@ const Chat: React.FC = () => {
             )}
           </div>
           
-          {/* 消息时间 */}
+          {/* 基于剪贴板历史回答时引用的条目 */}
+          {message.role === 'assistant' && message.sources && message.sources.length > 0 && (
+            <div style={{ marginTop: '4px', display: 'flex', flexDirection: 'column', gap: '2px' }}>
+              {message.sources.map(source => (
+                <Text
+                  key={source.item_id}
+                  type="secondary"
+                  ellipsis
+                  style={{ fontSize: isSmallScreen ? '10px' : '12px', maxWidth: '100%', ...themeStyles.secondaryText }}
+                >
+                  [{source.index}] {new Date(source.timestamp).toLocaleString('zh-CN')} · {source.preview}
+                </Text>
+              ))}
+            </div>
+          )}
+          
+          {/* 消息时间和回答的提供商 */}
           <div style={{ marginTop: '4px' }}>
             <Text type="secondary" style={{ fontSize: isSmallScreen ? '10px' : '12px', ...themeStyles.secondaryText }}>
               {new Date(message.timestamp).toLocaleString('zh-CN')}
             </Text>
+            {message.role === 'assistant' && message.providerId && (
+              <Tooltip title="回答的提供商，主提供商请求失败时为备用提供商">
+                <Tag style={{ marginLeft: '8px', fontSize: isSmallScreen ? '10px' : '12px' }}>
+                  {DEFAULT_AI_PROVIDERS.find(p => p.id === message.providerId)?.name || message.providerId}
+                </Tag>
+              </Tooltip>
+            )}
           </div>
         </div>
       </div>
@@ -1003,7 +1066,7 @@ const Chat: React.FC = () => {
       // 更新会话
       useChatStore.getState().updateSession(currentSession.id, {
         aiProvider: provider,
-        ...(rolePrompt && { rolePrompt }) // 仅当获取到提示词时才更新
+        ...(rolePrompt && { rolePrompt, roleId: selectedRole }) // 仅当获取到提示词时才更新
       });
       
       message.success('设置已更新，将在下次发送消息时生效');
@@ -1430,9 +1493,31 @@ const Chat: React.FC = () => {
           marginTop: '6px',
           fontSize: isSmallScreen ? '10px' : '12px'
         }}>
-          <Text type="secondary" style={{ fontSize: isSmallScreen ? '10px' : '12px' }}>
-            按下Enter发送，Shift+Enter换行
-          </Text>
+          <Space size={isSmallScreen ? 8 : 16}>
+            <Text type="secondary" style={{ fontSize: isSmallScreen ? '10px' : '12px' }}>
+              按下Enter发送，Shift+Enter换行
+            </Text>
+            {tokenReport && (
+              <Tooltip title={tokenReport.fits
+                ? `${tokenReport.model} 的上下文长度为 ${tokenReport.context_limit} tokens，为回复保留 ${tokenReport.max_tokens} tokens`
+                : `超出 ${tokenReport.model} 的上下文长度，发送时将${tokenReport.omitted_chunks > 0 ? `省略 ${tokenReport.omitted_chunks} 段内容` : `${tokenReport.strategy === 'summarize' ? '总结' : '省略'}较早的 ${tokenReport.dropped_messages} 条消息`}`}>
+                <Text
+                  type={tokenReport.fits ? 'secondary' : 'warning'}
+                  style={{ fontSize: isSmallScreen ? '10px' : '12px' }}
+                >
+                  约 {tokenReport.prompt_tokens} / {tokenReport.context_limit} tokens
+                </Text>
+              </Tooltip>
+            )}
+            <Tooltip title="先检索剪贴板历史，基于相关记录回答并标注来源">
+              <Space size={4}>
+                <Switch size="small" checked={clipboardContext} onChange={setClipboardContext} />
+                <Text type="secondary" style={{ fontSize: isSmallScreen ? '10px' : '12px' }}>
+                  基于剪贴板回答
+                </Text>
+              </Space>
+            </Tooltip>
+          </Space>
           
           {isStreaming && (
             <Text type="secondary" style={{ fontSize: isSmallScreen ? '10px' : '12px', display: 'flex', alignItems: 'center' }}>
@@ -1771,6 +1856,13 @@ const Chat: React.FC = () => {
         ) : (
           <List
             dataSource={sessions}
+            loadMore={sessions.length < sessionTotal && (
+              <div style={{ textAlign: 'center', padding: '8px 0' }}>
+                <Button type="link" onClick={loadMoreSessions}>
+                  加载更多（{sessions.length}/{sessionTotal}）
+                </Button>
+              </div>
+            )}
             renderItem={session => (
               <List.Item 
                 key={session.id}
@@ -1858,7 +1950,7 @@ const Chat: React.FC = () => {
                       {session.aiProvider.name}
                     </Tag>
                     <Tag style={{ fontSize: '10px' }}>
-                      {session.messages.length} 条消息
+                      {session.messageCount ?? session.messages.length} 条消息
                     </Tag>
                   </Text>
                 </div>
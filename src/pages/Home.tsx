@@ -26,11 +26,13 @@ import {
   Alert,
   InputNumber,
   Checkbox,
-  Select
+  Select,
+  Image
 } from 'antd';
 import type { MenuProps } from 'antd';
 import { 
   CopyOutlined, 
+  FileTextOutlined,
   DeleteOutlined, 
   PushpinOutlined, 
   StarOutlined,
@@ -83,6 +85,7 @@ import { writeText } from '@tauri-apps/plugin-clipboard-manager';
 import CategoryTag from '../components/CategoryTag';
 import MarkdownRenderer from '../components/MarkdownRenderer';
 import TranslationResult from '../components/TranslationResult';
+import { getClipboardImage } from '../api/apiClient';
 
 // 启用weekday和localeData插件
 dayjs.extend(weekday);
@@ -208,7 +211,18 @@ const ClipboardListItem = memo(({
             style={{ cursor: 'pointer' }}
             onClick={() => showItemDetails(item)}
           >
-            {highlightSearchText(item.content, searchText, isDarkMode)}
+            {item.contentType === 'image' && item.image ? (
+              <Space align="center">
+                <img
+                  src={item.image.thumbnail}
+                  alt={item.content}
+                  style={{ maxWidth: 120, maxHeight: 80, borderRadius: 6, objectFit: 'contain', display: 'block' }}
+                />
+                <Text type="secondary">{item.image.width} × {item.image.height}</Text>
+              </Space>
+            ) : (
+              highlightSearchText(item.content, searchText, isDarkMode)
+            )}
           </div>
           
           <div className="clipboard-footer">
@@ -392,6 +406,8 @@ const Home: React.FC = () => {
   
   const [editMode, setEditMode] = useState(false);
   const [editContent, setEditContent] = useState('');
+  // 详情中展示的原图，缩略图不足以看清细节
+  const [previewImage, setPreviewImage] = useState<string | null>(null);
   
   const [showAIAnalysisModal, setShowAIAnalysisModal] = useState(false);
   const [aiAnalysisState, setAIAnalysisState] = useState<AIAnalysisState>({
@@ -597,6 +613,22 @@ const Home: React.FC = () => {
     setEditMode(false);
     setShowDetailsDrawer(true);
   }, []);
+
+  useEffect(() => {
+    setPreviewImage(null);
+    if (!showDetailsDrawer || currentItem?.contentType !== 'image') return;
+    let cancelled = false;
+    getClipboardImage(currentItem.id)
+      .then(url => {
+        if (!cancelled) setPreviewImage(url);
+      })
+      .catch(err => {
+        if (!cancelled) message.error('加载图片失败: ' + err);
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [showDetailsDrawer, currentItem]);
   
   const handleCategoryChange = useCallback((id: string, category: string) => {
     categorizeItem(id, category);
@@ -1308,6 +1340,7 @@ const Home: React.FC = () => {
               ) : (
                 <Button 
                   icon={<EditOutlined />}
+                  disabled={currentItem.contentType === 'image'}
                   onClick={() => {
                     setEditContent(currentItem.content);
                     setEditMode(true);
@@ -1325,6 +1358,17 @@ const Home: React.FC = () => {
                   autoSize={{ minRows: 3, maxRows: 12 }}
                   style={{ width: '100%' }}
                 />
+              ) : currentItem.contentType === 'image' && currentItem.image ? (
+                <Space direction="vertical" style={{ width: '100%' }}>
+                  <Image
+                    src={previewImage ?? currentItem.image.thumbnail}
+                    alt={currentItem.content}
+                    style={{ maxWidth: '100%' }}
+                  />
+                  <Text type="secondary">
+                    {currentItem.image.width} × {currentItem.image.height} · {(currentItem.image.size / 1024).toFixed(1)} KB
+                  </Text>
+                </Space>
               ) : (
                 <Paragraph copyable style={{ margin: 0, whiteSpace: 'pre-wrap' }}>{currentItem.content}</Paragraph>
               )}
@@ -1423,7 +1467,27 @@ const Home: React.FC = () => {
   
   // 修改菜单项
   const getDropdownMenuItems = useCallback((item: ClipboardItem): MenuProps['items'] => {
+    // 带格式的条目（HTML、RTF、文件列表）可以只粘贴其纯文本
+    const hasRichFormat = item.contentType !== undefined
+      && item.contentType !== 'text'
+      && item.contentType !== 'image';
+
     return [
+      ...(hasRichFormat ? [
+        {
+          key: 'copy-plain',
+          label: '以纯文本复制',
+          icon: <FileTextOutlined />,
+          onClick: (e: { domEvent: React.MouseEvent | React.KeyboardEvent }) => {
+            e.domEvent.stopPropagation();
+            copyToClipboard(item.id, true);
+            message.success('已复制纯文本');
+          }
+        },
+        {
+          type: 'divider' as const
+        }
+      ] : []),
       {
         key: 'polish',
         label: '润色',
@@ -12,7 +12,9 @@ import {
   Avatar,
   Tooltip,
   Select,
-  Popconfirm
+  Popconfirm,
+  Switch,
+  InputNumber
 } from 'antd';
 import { 
   PlusOutlined, 
@@ -20,10 +22,21 @@ import {
   DeleteOutlined, 
   UserOutlined,
   ExclamationCircleOutlined,
-  UndoOutlined
+  UndoOutlined,
+  MinusCircleOutlined
 } from '@ant-design/icons';
 import useRoleStore from '../store/roleStore';
-import { Role, ROLE_ICONS } from '../models/role';
+import { Role, PromptInput, ROLE_ICONS } from '../models/role';
+import { validatePromptTemplate } from '../api/apiClient';
+import {
+  DEFAULT_AI_PROVIDERS,
+  TEMPERATURE_MIN,
+  TEMPERATURE_MAX,
+  TEMPERATURE_STEP,
+  MAX_TOKENS_MIN,
+  MAX_TOKENS_MAX,
+  MAX_TOKENS_STEP
+} from '../constants/aiProviders';
 
 const { Title, Text, Paragraph } = Typography;
 const { TextArea } = Input;
@@ -55,7 +68,8 @@ const Roles: React.FC = () => {
       form.setFieldsValue({
         ...role,
         // 后端使用 system_prompt，前端表单使用 systemPrompt
-        systemPrompt: role.system_prompt
+        systemPrompt: role.system_prompt,
+        stream: role.stream === undefined ? undefined : role.stream ? 'on' : 'off'
       });
     } else {
       setCurrentRole(null);
@@ -69,7 +83,17 @@ const Roles: React.FC = () => {
       // 将表单字段名从驼峰转换为下划线
       const formattedValues = {
         ...values,
-        system_prompt: values.systemPrompt
+        system_prompt: values.systemPrompt,
+        inputs: (values.inputs || []) as PromptInput[]
+      };
+      
+      // 未填写的绑定项使用全局AI设置
+      const binding = {
+        provider_id: values.provider_id || undefined,
+        model: values.model?.trim() || undefined,
+        temperature: values.temperature ?? undefined,
+        max_tokens: values.max_tokens ?? undefined,
+        stream: values.stream ? values.stream === 'on' : undefined
       };
       
       let success = false;
@@ -81,6 +105,8 @@ const Roles: React.FC = () => {
           name: formattedValues.name,
           description: formattedValues.description,
           system_prompt: formattedValues.system_prompt,
+          inputs: formattedValues.inputs,
+          ...binding,
           icon: formattedValues.icon || currentRole.icon
         });
       } else {
@@ -89,6 +115,8 @@ const Roles: React.FC = () => {
           name: formattedValues.name,
           description: formattedValues.description,
           system_prompt: formattedValues.system_prompt,
+          inputs: formattedValues.inputs,
+          ...binding,
           icon: formattedValues.icon || '💻',
           avatar: null,
           is_custom: true
@@ -218,13 +246,99 @@ const Roles: React.FC = () => {
           <Form.Item
             name="systemPrompt"
             label="系统提示词"
-            rules={[{ required: true, message: '请输入系统提示词' }]}
+            tooltip="支持模板变量：{{clipboard}}、{{selection_language}}、{{date}}、{{time}}、{{previous_clip}}、{{category}} 和自定义输入；过滤器如 {{clipboard | truncate:500}}、upper、lower、trim、default:&quot;无&quot;；条件 {{#if previous_clip}}...{{else}}...{{/if}}；原样输出 {{ 时写作 {{{{，整段代码示例可放在 {{#raw}}...{{/raw}} 中。模板引用 {{clipboard}} 时，剪贴板内容不再单独发送"
+            dependencies={['inputs']}
+            rules={[
+              { required: true, message: '请输入系统提示词' },
+              {
+                // 模板在后端检查，与保存时的校验一致
+                validator: (_, value) => value
+                  ? validatePromptTemplate(value, form.getFieldValue('inputs') || [])
+                      .catch(error => Promise.reject(new Error(String(error))))
+                  : Promise.resolve()
+              }
+            ]}
           >
             <TextArea 
               placeholder="输入系统提示词，用于指导AI的行为和回复风格" 
               autoSize={{ minRows: 3, maxRows: 6 }}
             />
           </Form.Item>
+          <Form.Item label="自定义输入" tooltip="在提示词中用 {{名称}} 引用，使用角色时填写；未填写时使用默认值">
+            <Form.List name="inputs">
+              {(fields, { add, remove }) => (
+                <>
+                  {fields.map(field => (
+                    <Space key={field.key} align="baseline">
+                      <Form.Item
+                        name={[field.name, 'name']}
+                        rules={[
+                          { required: true, message: '请输入名称' },
+                          { pattern: /^[A-Za-z_][A-Za-z0-9_]*$/, message: '只能包含字母、数字和下划线' }
+                        ]}
+                      >
+                        <Input placeholder="名称，如 tone" style={{ width: 120 }} />
+                      </Form.Item>
+                      <Form.Item name={[field.name, 'label']}>
+                        <Input placeholder="显示名称" style={{ width: 100 }} />
+                      </Form.Item>
+                      <Form.Item name={[field.name, 'default']}>
+                        <Input placeholder="默认值" style={{ width: 100 }} />
+                      </Form.Item>
+                      <Form.Item name={[field.name, 'required']} valuePropName="checked">
+                        <Switch size="small" checkedChildren="必填" unCheckedChildren="选填" />
+                      </Form.Item>
+                      <MinusCircleOutlined onClick={() => remove(field.name)} />
+                    </Space>
+                  ))}
+                  <Button type="dashed" onClick={() => add({ required: false })} icon={<PlusOutlined />}>
+                    添加输入
+                  </Button>
+                </>
+              )}
+            </Form.List>
+          </Form.Item>
+          <Divider orientation="left" plain style={{ margin: '8px 0 16px 0' }}>
+            <Text type="secondary">模型绑定（留空使用全局AI设置）</Text>
+          </Divider>
+          <Space wrap align="start">
+            <Form.Item name="provider_id" label="提供商">
+              <Select placeholder="跟随全局" allowClear style={{ width: 180 }}>
+                {DEFAULT_AI_PROVIDERS.map(provider => (
+                  <Select.Option key={provider.id} value={provider.id}>{provider.name}</Select.Option>
+                ))}
+              </Select>
+            </Form.Item>
+            <Form.Item name="model" label="模型" tooltip="留空时使用所选提供商设置中的模型">
+              <Input placeholder="跟随提供商设置" style={{ width: 200 }} />
+            </Form.Item>
+          </Space>
+          <Space wrap align="start">
+            <Form.Item name="temperature" label="温度">
+              <InputNumber
+                min={TEMPERATURE_MIN}
+                max={TEMPERATURE_MAX}
+                step={TEMPERATURE_STEP}
+                placeholder="跟随全局"
+                style={{ width: 120 }}
+              />
+            </Form.Item>
+            <Form.Item name="max_tokens" label="最大 tokens">
+              <InputNumber
+                min={MAX_TOKENS_MIN}
+                max={MAX_TOKENS_MAX}
+                step={MAX_TOKENS_STEP}
+                placeholder="跟随全局"
+                style={{ width: 120 }}
+              />
+            </Form.Item>
+            <Form.Item name="stream" label="流式输出">
+              <Select placeholder="跟随全局" allowClear style={{ width: 120 }}>
+                <Select.Option value="on">开启</Select.Option>
+                <Select.Option value="off">关闭</Select.Option>
+              </Select>
+            </Form.Item>
+          </Space>
           <Form.Item
             name="icon"
             label="角色图标"
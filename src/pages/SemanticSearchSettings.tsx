@@ -0,0 +1,195 @@
+import React, { useState, useEffect } from 'react';
+import {
+  Typography,
+  Divider,
+  Form,
+  Input,
+  Button,
+  Card,
+  Select,
+  Switch,
+  message,
+  Spin,
+  Alert,
+  Progress,
+} from 'antd';
+import {
+  SaveOutlined,
+  SearchOutlined,
+  LoadingOutlined
+} from '@ant-design/icons';
+import { DEFAULT_AI_PROVIDERS } from '../constants/aiProviders';
+import {
+  EmbeddingSettings,
+  EmbeddingStatus,
+  getEmbeddingSettings,
+  getEmbeddingStatus,
+  updateEmbeddingSettings,
+} from '../api/apiClient';
+
+const { Title, Text } = Typography;
+const { Option } = Select;
+
+// 计算进度的刷新间隔
+const STATUS_REFRESH_INTERVAL = 5000;
+
+const SemanticSearchSettings: React.FC = () => {
+  const [form] = Form.useForm();
+  const [messageApi, contextHolder] = message.useMessage();
+  const [loading, setLoading] = useState(false);
+  const [initialLoading, setInitialLoading] = useState(true);
+  const [status, setStatus] = useState<EmbeddingStatus | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  // 从后端获取语义搜索设置
+  useEffect(() => {
+    const fetchSettings = async () => {
+      try {
+        setInitialLoading(true);
+        const settings = await getEmbeddingSettings();
+        form.setFieldsValue(settings);
+      } catch (error) {
+        console.error('获取语义搜索设置失败:', error);
+        setError(`获取语义搜索设置失败: ${String(error)}`);
+      } finally {
+        setInitialLoading(false);
+      }
+    };
+
+    fetchSettings();
+  }, [form]);
+
+  // 定时刷新向量计算进度
+  useEffect(() => {
+    const refreshStatus = () => {
+      getEmbeddingStatus()
+        .then(setStatus)
+        .catch(error => console.error('获取向量计算进度失败:', error));
+    };
+
+    refreshStatus();
+    const timer = setInterval(refreshStatus, STATUS_REFRESH_INTERVAL);
+    return () => clearInterval(timer);
+  }, []);
+
+  // 保存设置
+  const onFinish = async (values: EmbeddingSettings) => {
+    try {
+      setLoading(true);
+      setError(null);
+
+      await updateEmbeddingSettings({
+        enabled: values.enabled,
+        provider_id: values.provider_id || '',
+        model: (values.model || '').trim(),
+      });
+      setStatus(await getEmbeddingStatus());
+
+      messageApi.success('语义搜索设置已保存');
+    } catch (error) {
+      console.error('保存语义搜索设置失败:', error);
+      setError(`保存语义搜索设置失败: ${String(error)}`);
+      messageApi.error('保存语义搜索设置失败: ' + String(error));
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <Card bordered={false} className="glass-effect" style={{ position: 'relative', zIndex: 1 }}>
+      {contextHolder}
+
+      {initialLoading ? (
+        <div style={{ textAlign: 'center', padding: '20px' }}>
+          <Spin indicator={<LoadingOutlined style={{ fontSize: 24 }} spin />} />
+          <div style={{ marginTop: '8px' }}>加载语义搜索设置中...</div>
+        </div>
+      ) : (
+        <>
+          {error && (
+            <Alert
+              message="错误"
+              description={error}
+              type="error"
+              showIcon
+              style={{ marginBottom: 16 }}
+              closable
+            />
+          )}
+
+          <div style={{ marginBottom: '16px' }}>
+            <Title level={4}>
+              <SearchOutlined /> 语义搜索
+            </Title>
+            <Text type="secondary">
+              使用向量模型理解剪贴板内容，按含义而不是关键字查找历史记录
+            </Text>
+            <Divider style={{ margin: '12px 0' }} />
+          </div>
+
+          <Form
+            form={form}
+            layout="vertical"
+            onFinish={onFinish}
+            initialValues={{ enabled: false, provider_id: '', model: '' }}
+          >
+            <Form.Item
+              name="enabled"
+              label="开启语义搜索"
+              valuePropName="checked"
+              tooltip="开启后将在后台为剪贴板条目计算向量，内容会发送到所选的AI提供商"
+            >
+              <Switch />
+            </Form.Item>
+
+            <Form.Item
+              name="provider_id"
+              label="AI提供商"
+              tooltip="需要支持 /embeddings 接口的OpenAI兼容服务、Azure OpenAI 或本地的 Ollama"
+            >
+              <Select>
+                <Option value="">当前选择的提供商</Option>
+                {DEFAULT_AI_PROVIDERS.filter(provider => provider.id !== 'anthropic').map(provider => (
+                  <Option key={provider.id} value={provider.id}>{provider.name}</Option>
+                ))}
+              </Select>
+            </Form.Item>
+
+            <Form.Item
+              name="model"
+              label="向量模型"
+              tooltip="更换模型后需要重新计算全部条目的向量"
+              rules={[{ required: true, message: '请输入向量模型' }]}
+            >
+              <Input placeholder="例如 text-embedding-3-small 或 nomic-embed-text" />
+            </Form.Item>
+
+            {status && status.enabled && status.total > 0 && (
+              <Form.Item label="计算进度">
+                <Progress
+                  percent={Math.floor((status.embedded / status.total) * 100)}
+                  format={() => `${status.embedded} / ${status.total}`}
+                />
+              </Form.Item>
+            )}
+
+            <Divider style={{ margin: '8px 0 16px 0' }} />
+
+            <Form.Item>
+              <Button
+                type="primary"
+                htmlType="submit"
+                icon={<SaveOutlined />}
+                loading={loading}
+              >
+                保存设置
+              </Button>
+            </Form.Item>
+          </Form>
+        </>
+      )}
+    </Card>
+  );
+};
+
+export default SemanticSearchSettings;
@@ -16,12 +16,14 @@ import {
   Tabs,
   Alert
 } from 'antd';
-import { SaveOutlined, QuestionCircleOutlined, ExclamationCircleOutlined, TranslationOutlined, TagsOutlined, KeyOutlined, WindowsOutlined, SettingOutlined } from '@ant-design/icons';
+import { SaveOutlined, QuestionCircleOutlined, ExclamationCircleOutlined, TranslationOutlined, SearchOutlined, TagsOutlined, KeyOutlined, WindowsOutlined, SettingOutlined, DollarOutlined } from '@ant-design/icons';
 import { useTheme } from '../context/ThemeContext';
 import ShortcutSettings from './ShortcutSettings';
 import WindowSettings from './WindowSettings';
 import CategorySettings from './CategorySettings';
 import TranslationSettings from './TranslationSettings';
+import SemanticSearchSettings from './SemanticSearchSettings';
+import UsageSettings from './UsageSettings';
 import { invoke } from '@tauri-apps/api/core';
 
 const { Title, Text } = Typography;
@@ -412,6 +414,24 @@ const Settings: React.FC = () => {
               </span>
             ),
             children: <TranslationSettings />
+          },
+          {
+            key: '6',
+            label: (
+              <span>
+                <SearchOutlined /> 语义搜索
+              </span>
+            ),
+            children: <SemanticSearchSettings />
+          },
+          {
+            key: '7',
+            label: (
+              <span>
+                <DollarOutlined /> 用量统计
+              </span>
+            ),
+            children: <UsageSettings />
           }
         ]}
       />
@@ -0,0 +1,301 @@
+import React, { useState, useEffect, useCallback } from 'react';
+import {
+  Typography,
+  Divider,
+  Form,
+  Input,
+  InputNumber,
+  Button,
+  Card,
+  Select,
+  Space,
+  Table,
+  Statistic,
+  Progress,
+  message,
+  Spin,
+  Alert,
+} from 'antd';
+import {
+  SaveOutlined,
+  DollarOutlined,
+  LoadingOutlined,
+  PlusOutlined,
+  MinusCircleOutlined
+} from '@ant-design/icons';
+import { DEFAULT_AI_PROVIDERS } from '../constants/aiProviders';
+import {
+  BudgetStatus,
+  UsageSummary,
+  getBudgetStatus,
+  getUsageSettings,
+  getUsageSummary,
+  updateUsageSettings,
+} from '../api/apiClient';
+
+const { Title, Text } = Typography;
+const { Option } = Select;
+
+type UsageGroup = 'day' | 'provider' | 'model' | 'role';
+
+// 表单中的价格表和提供商预算以列表形式编辑
+interface UsageForm {
+  currency: string;
+  monthly_budget: number | null;
+  prices: { model: string; input: number; output: number }[];
+  provider_budgets: { provider_id: string; budget: number }[];
+}
+
+// 本月第一天零点的时间戳
+const monthStart = () => {
+  const now = new Date();
+  return new Date(now.getFullYear(), now.getMonth(), 1).getTime();
+};
+
+const UsageSettings: React.FC = () => {
+  const [form] = Form.useForm<UsageForm>();
+  const [messageApi, contextHolder] = message.useMessage();
+  const [loading, setLoading] = useState(false);
+  const [initialLoading, setInitialLoading] = useState(true);
+  const [error, setError] = useState<string | null>(null);
+  const [budget, setBudget] = useState<BudgetStatus | null>(null);
+  const [groupBy, setGroupBy] = useState<UsageGroup>('day');
+  const [summary, setSummary] = useState<UsageSummary[]>([]);
+
+  // 从后端获取价格表与预算
+  useEffect(() => {
+    const fetchSettings = async () => {
+      try {
+        setInitialLoading(true);
+        const settings = await getUsageSettings();
+        form.setFieldsValue({
+          currency: settings.currency,
+          monthly_budget: settings.monthly_budget,
+          prices: Object.entries(settings.prices)
+            .map(([model, price]) => ({ model, ...price }))
+            .sort((a, b) => a.model.localeCompare(b.model)),
+          provider_budgets: Object.entries(settings.provider_budgets)
+            .map(([provider_id, budget]) => ({ provider_id, budget })),
+        });
+      } catch (error) {
+        console.error('获取用量设置失败:', error);
+        setError(`获取用量设置失败: ${String(error)}`);
+      } finally {
+        setInitialLoading(false);
+      }
+    };
+
+    fetchSettings();
+  }, [form]);
+
+  // 刷新本月费用和汇总
+  const refreshUsage = useCallback(async () => {
+    try {
+      const [status, rows] = await Promise.all([
+        getBudgetStatus(),
+        getUsageSummary(groupBy, monthStart(), Date.now() + 1)
+      ]);
+      setBudget(status);
+      setSummary(rows);
+    } catch (error) {
+      console.error('获取用量失败:', error);
+    }
+  }, [groupBy]);
+
+  useEffect(() => {
+    refreshUsage();
+  }, [refreshUsage]);
+
+  // 保存设置
+  const onFinish = async (values: UsageForm) => {
+    try {
+      setLoading(true);
+      setError(null);
+
+      await updateUsageSettings({
+        currency: (values.currency || '').trim() || 'USD',
+        monthly_budget: values.monthly_budget ?? null,
+        prices: Object.fromEntries((values.prices || [])
+          .filter(price => price.model && price.model.trim())
+          .map(price => [price.model.trim(), { input: price.input || 0, output: price.output || 0 }])),
+        provider_budgets: Object.fromEntries((values.provider_budgets || [])
+          .filter(item => item.provider_id && item.budget != null)
+          .map(item => [item.provider_id, item.budget])),
+      });
+      await refreshUsage();
+
+      messageApi.success('用量设置已保存');
+    } catch (error) {
+      console.error('保存用量设置失败:', error);
+      setError(`保存用量设置失败: ${String(error)}`);
+      messageApi.error('保存用量设置失败: ' + String(error));
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const currency = budget?.currency || '';
+  const columns = [
+    { title: groupBy === 'day' ? '日期' : groupBy === 'role' ? '角色' : groupBy === 'model' ? '模型' : '提供商', dataIndex: 'key', render: (key: string) => key || '-' },
+    { title: '请求数', dataIndex: 'requests' },
+    { title: '失败', dataIndex: 'failed' },
+    { title: '输入 tokens', dataIndex: 'prompt_tokens' },
+    { title: '输出 tokens', dataIndex: 'completion_tokens' },
+    { title: '平均耗时', dataIndex: 'avg_latency_ms', render: (ms: number) => `${Math.round(ms)} ms` },
+    { title: '费用', dataIndex: 'cost', render: (cost: number) => `${cost.toFixed(4)} ${currency}` },
+  ];
+
+  return (
+    <Card bordered={false} className="glass-effect" style={{ position: 'relative', zIndex: 1 }}>
+      {contextHolder}
+
+      {initialLoading ? (
+        <div style={{ textAlign: 'center', padding: '20px' }}>
+          <Spin indicator={<LoadingOutlined style={{ fontSize: 24 }} spin />} />
+          <div style={{ marginTop: '8px' }}>加载用量设置中...</div>
+        </div>
+      ) : (
+        <>
+          {error && (
+            <Alert
+              message="错误"
+              description={error}
+              type="error"
+              showIcon
+              style={{ marginBottom: 16 }}
+              closable
+            />
+          )}
+
+          <div style={{ marginBottom: '16px' }}>
+            <Title level={4}>
+              <DollarOutlined /> 用量与费用
+            </Title>
+            <Text type="secondary">
+              记录每次AI请求的 token 用量和费用，流式请求的 token 数为估算值
+            </Text>
+            <Divider style={{ margin: '12px 0' }} />
+          </div>
+
+          {budget && (
+            <Space direction="vertical" style={{ width: '100%', marginBottom: 16 }}>
+              <Statistic title="本月费用" value={budget.month_cost} precision={4} suffix={budget.currency} />
+              {budget.monthly_budget != null && budget.monthly_budget > 0 && (
+                <Progress
+                  percent={Math.min(100, Math.round((budget.month_cost / budget.monthly_budget) * 100))}
+                  status={budget.month_cost >= budget.monthly_budget ? 'exception' : 'normal'}
+                  format={() => `${budget.month_cost.toFixed(2)} / ${budget.monthly_budget} ${budget.currency}`}
+                />
+              )}
+            </Space>
+          )}
+
+          <Space style={{ marginBottom: 8 }}>
+            <Text>本月用量，按</Text>
+            <Select value={groupBy} onChange={setGroupBy} style={{ width: 120 }}>
+              <Option value="day">日期</Option>
+              <Option value="provider">提供商</Option>
+              <Option value="model">模型</Option>
+              <Option value="role">角色</Option>
+            </Select>
+            <Text>汇总</Text>
+          </Space>
+          <Table
+            size="small"
+            rowKey="key"
+            columns={columns}
+            dataSource={summary}
+            pagination={false}
+            style={{ marginBottom: 16 }}
+          />
+
+          <Form
+            form={form}
+            layout="vertical"
+            onFinish={onFinish}
+          >
+            <Form.Item name="currency" label="货币" tooltip="价格表使用的货币，仅用于显示">
+              <Input style={{ width: 120 }} />
+            </Form.Item>
+
+            <Form.Item
+              name="monthly_budget"
+              label="每月预算"
+              tooltip="本月费用达到预算后，新的AI请求将被拒绝；留空表示不限制"
+            >
+              <InputNumber min={0} step={1} style={{ width: 200 }} placeholder="不限制" />
+            </Form.Item>
+
+            <Form.Item label="提供商预算" tooltip="单个提供商本月费用达到预算后将被跳过，请求改用备用提供商">
+              <Form.List name="provider_budgets">
+                {(fields, { add, remove }) => (
+                  <>
+                    {fields.map(field => (
+                      <Space key={field.key} align="baseline">
+                        <Form.Item name={[field.name, 'provider_id']} rules={[{ required: true, message: '请选择提供商' }]}>
+                          <Select style={{ width: 180 }} placeholder="提供商">
+                            {DEFAULT_AI_PROVIDERS.map(provider => (
+                              <Option key={provider.id} value={provider.id}>{provider.name}</Option>
+                            ))}
+                          </Select>
+                        </Form.Item>
+                        <Form.Item name={[field.name, 'budget']} rules={[{ required: true, message: '请输入预算' }]}>
+                          <InputNumber min={0} placeholder="每月预算" />
+                        </Form.Item>
+                        <MinusCircleOutlined onClick={() => remove(field.name)} />
+                      </Space>
+                    ))}
+                    <Button type="dashed" onClick={() => add()} icon={<PlusOutlined />}>
+                      添加提供商预算
+                    </Button>
+                  </>
+                )}
+              </Form.List>
+            </Form.Item>
+
+            <Form.Item label="模型价格" tooltip="每百万 token 的价格，模型ID按最长前缀匹配；没有价格的模型费用记为 0">
+              <Form.List name="prices">
+                {(fields, { add, remove }) => (
+                  <>
+                    {fields.map(field => (
+                      <Space key={field.key} align="baseline">
+                        <Form.Item name={[field.name, 'model']} rules={[{ required: true, message: '请输入模型' }]}>
+                          <Input style={{ width: 200 }} placeholder="模型ID或前缀" />
+                        </Form.Item>
+                        <Form.Item name={[field.name, 'input']}>
+                          <InputNumber min={0} placeholder="输入价格" />
+                        </Form.Item>
+                        <Form.Item name={[field.name, 'output']}>
+                          <InputNumber min={0} placeholder="输出价格" />
+                        </Form.Item>
+                        <MinusCircleOutlined onClick={() => remove(field.name)} />
+                      </Space>
+                    ))}
+                    <Button type="dashed" onClick={() => add()} icon={<PlusOutlined />}>
+                      添加模型价格
+                    </Button>
+                  </>
+                )}
+              </Form.List>
+            </Form.Item>
+
+            <Divider style={{ margin: '8px 0 16px 0' }} />
+
+            <Form.Item>
+              <Button
+                type="primary"
+                htmlType="submit"
+                icon={<SaveOutlined />}
+                loading={loading}
+              >
+                保存设置
+              </Button>
+            </Form.Item>
+          </Form>
+        </>
+      )}
+    </Card>
+  );
+};
+
+export default UsageSettings;
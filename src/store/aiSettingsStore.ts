@@ -177,9 +177,11 @@ export const useAISettingsStore = create<AISettingsStore>()(
           const providerSettings = state.providers[providerId];
           
           // 获取参数
-          const url = providerSettings.models_list_url || provider.modelsListUrl;
           const apiKey = providerSettings.api_key;
           const apiBaseUrl = providerSettings.custom_api_base_url || provider.apiBaseUrl;
+          // Azure 没有固定的模型列表地址，后端根据资源地址获取部署列表
+          const url = providerSettings.models_list_url || provider.modelsListUrl
+            || (providerId === 'azure' ? apiBaseUrl : undefined);
           
           if (!url) {
             set({ isLoading: false });
@@ -286,6 +288,7 @@ export const useAISettingsStore = create<AISettingsStore>()(
           
           // 调用Tauri后端测试API连接
           const result = await invoke<APITestResult>('test_api_connection', {
+            providerId,
             apiBaseUrl,
             apiKey,
             model
@@ -345,8 +348,8 @@ export const useAISettingsStore = create<AISettingsStore>()(
           
           // 调用后端保存设置
           await invoke('update_provider_setting', { 
-            provider_id: providerId, 
-            settings: provider 
+            providerId, 
+            setting: provider 
           });
           
           console.log('设置保存完成');
@@ -3,33 +3,142 @@ import { v4 as uuidv4 } from 'uuid';
 import { invoke } from '@tauri-apps/api/core';
 import { message } from 'antd';
 import { ChatSession, Message, StreamResponse, ClipboardReference } from '../models/chat';
-import { AIProvider, AIProviderSettings } from '../models/ai';
-import { useAISettingsStore } from './aiSettingsStore';
-import { persist } from 'zustand/middleware';
+import { AIProvider } from '../models/ai';
+import { Role } from '../models/role';
+import {
+  chatCompletion,
+  estimateChatTokens,
+  TokenReport,
+  renderRolePrompt,
+  listChatSessions,
+  getChatSession,
+  createChatSession,
+  updateChatSession,
+  deleteChatSession,
+  clearChatSession,
+  addChatMessage,
+  updateChatMessage,
+  deleteChatMessage,
+  exportChatSession,
+  importChatSession,
+} from '../api/apiClient';
 
 // 在store外部管理处理状态，避免Zustand状态更新问题
 let processingMessageCount = 0;
 let messageSequence = 0;
 
-// 最大会话历史存储数量
-const MAX_SESSIONS_HISTORY = 100;
+// 每次从后端加载的会话数量
+const SESSIONS_PAGE_SIZE = 50;
+
+// 旧版本保存在 localStorage 中的会话，首次加载时迁移到后端
+const LEGACY_STORAGE_KEY = 'copy2ai-chat-store';
+
+// 按顺序写入后端，避免创建会话前先写入消息；界面不等待写入完成
+let saveQueue: Promise<unknown> = Promise.resolve();
+const saveToBackend = (task: () => Promise<unknown>) => {
+  saveQueue = saveQueue.then(task).catch(error => {
+    console.error('保存聊天记录失败:', error);
+  });
+};
+
+// 将 localStorage 中的旧会话导入后端，成功后删除旧数据
+const migrateLegacySessions = async () => {
+  const legacyData = localStorage.getItem(LEGACY_STORAGE_KEY);
+  if (!legacyData) return;
+
+  try {
+    const sessions: ChatSession[] = JSON.parse(legacyData)?.state?.sessions || [];
+    for (const session of sessions) {
+      await importChatSession(JSON.stringify(session));
+    }
+    localStorage.removeItem(LEGACY_STORAGE_KEY);
+    console.log(`已迁移 ${sessions.length} 个本地会话`);
+  } catch (error) {
+    console.error('迁移本地会话失败:', error);
+  }
+};
+
+// 角色绑定了提供商时由后端按角色绑定解析提供商、模型和参数，否则使用会话的提供商
+const resolveProviderId = async (session: ChatSession): Promise<string | undefined> => {
+  const role = session.roleId
+    ? await invoke<Role>('get_role', { id: session.roleId }).catch(() => null)
+    : null;
+  return role?.provider_id ? undefined : session.aiProvider.id;
+};
+
+// 构建发送给后端的消息列表，同时返回最近引用的剪贴板条目
+// 角色提示词是模板，按最近引用的剪贴板内容在后端渲染，渲染失败时使用原提示词
+const buildChatMessages = async (
+  session: ChatSession,
+  history: Message[],
+  onRenderError?: (error: unknown) => void
+) => {
+  const latestRef = [...history].reverse().find(msg => msg.clipboardRef)?.clipboardRef;
+  let rolePrompt = session.rolePrompt;
+  let renderedClipboardId: string | undefined;
+  if (session.roleId) {
+    try {
+      const rendered = await renderRolePrompt(session.roleId, latestRef?.content, latestRef?.id);
+      rolePrompt = rendered.prompt;
+      if (rendered.uses_clipboard) {
+        renderedClipboardId = latestRef?.id;
+      }
+    } catch (error) {
+      onRenderError?.(error);
+    }
+  }
+  
+  const messages = [
+    ...(session.systemPrompt ? [{
+      role: 'system' as const,
+      content: session.systemPrompt
+    }] : []),
+    ...(rolePrompt ? [{
+      role: 'system' as const,
+      content: rolePrompt
+    }] : []),
+    ...history.map(msg => {
+      // 如果消息有剪贴板引用，将引用内容添加到消息中（已渲染进角色提示词的除外）
+      if (msg.clipboardRef && msg.clipboardRef.content && msg.clipboardRef.id !== renderedClipboardId) {
+        return {
+          role: msg.role,
+          content: `${msg.content}\n\n${msg.clipboardRef.content}`
+        };
+      }
+      return {
+        role: msg.role,
+        content: msg.content
+      };
+    })
+  ];
+  
+  return { messages, latestRef };
+};
 
 interface ChatState {
   sessions: ChatSession[];
+  sessionTotal: number;
+  sessionsLoaded: boolean;
   currentSession: ChatSession | null;
   isStreaming: boolean;
   streamAbortController: AbortController | null;
+  // 是否先检索剪贴板历史，基于相关条目回答
+  clipboardContext: boolean;
+  
+  // 从后端加载会话列表
+  loadSessions: () => Promise<void>;
+  loadMoreSessions: () => Promise<void>;
   
   // 会话管理
-  createSession: (aiProvider: AIProvider, systemPrompt?: string, rolePrompt?: string) => ChatSession;
-  setCurrentSession: (sessionId: string) => void;
+  createSession: (aiProvider: AIProvider, systemPrompt?: string, rolePrompt?: string, roleId?: string) => ChatSession;
+  setCurrentSession: (sessionId: string) => Promise<void>;
   getSession: (sessionId: string) => ChatSession | undefined;
   deleteSession: (sessionId: string) => void;
   updateSession: (sessionId: string, updates: Partial<ChatSession>) => void;
   
   // 消息管理
   addMessage: (message: Omit<Message, 'id' | 'timestamp'>, sessionId?: string) => string;
-  updateMessage: (messageId: string, content: string, sessionId?: string) => void;
+  updateMessage: (messageId: string, content: string, sessionId?: string, providerId?: string) => void;
   deleteMessage: (messageId: string, sessionId?: string) => void;
   clearSession: (sessionId: string) => void;
   
@@ -39,586 +148,574 @@ interface ChatState {
   appendStreamContent: (response: StreamResponse) => void;
   
   // AI对话核心功能
+  setClipboardContext: (value: boolean) => void;
   sendMessage: (content: string, clipboardRef?: ClipboardReference) => Promise<void>;
+  // 估算输入框中的内容连同对话历史的 token 数，不发起网络请求
+  estimateDraftTokens: (content: string, clipboardRef?: ClipboardReference) => Promise<TokenReport | null>;
   
   // 导入/导出会话历史
-  exportSessionHistory: (sessionId: string) => Promise<string>;
+  exportSessionHistory: (sessionId: string, format?: 'json' | 'markdown') => Promise<string>;
   importSessionHistory: (jsonData: string) => Promise<void>;
 }
 
-export const useChatStore = create<ChatState>()(
-  persist(
-    (set, get) => ({
-      sessions: [],
-      currentSession: null,
-      isStreaming: false,
-      streamAbortController: null,
+export const useChatStore = create<ChatState>()((set, get) => ({
+  sessions: [],
+  sessionTotal: 0,
+  sessionsLoaded: false,
+  currentSession: null,
+  isStreaming: false,
+  streamAbortController: null,
+  clipboardContext: false,
+  
+  // 加载第一页会话，没有当前会话时打开最近的会话
+  loadSessions: async () => {
+    try {
+      await migrateLegacySessions();
+      await saveQueue;
+      const page = await listChatSessions(0, SESSIONS_PAGE_SIZE);
+      set(state => ({
+        // 保留已加载消息的当前会话
+        sessions: page.sessions.map(session =>
+          session.id === state.currentSession?.id ? state.currentSession : session
+        ),
+        sessionTotal: page.total
+      }));
       
-      // 创建新会话
-      createSession: (aiProvider, systemPrompt, rolePrompt) => {
-        const newSession: ChatSession = {
-          id: uuidv4(),
-          title: `新对话 ${new Date().toLocaleString('zh-CN', { 
-            month: 'numeric', 
-            day: 'numeric',
-            hour: 'numeric', 
-            minute: 'numeric'
-          })}`,
-          messages: [],
-          aiProvider,
-          systemPrompt,
-          rolePrompt,
-          createdAt: Date.now(),
-          updatedAt: Date.now()
+      if (!get().currentSession && page.sessions.length > 0) {
+        await get().setCurrentSession(page.sessions[0].id);
+      }
+    } catch (error) {
+      console.error('加载会话失败:', error);
+      message.error('加载聊天记录失败');
+    } finally {
+      set({ sessionsLoaded: true });
+    }
+  },
+  
+  // 加载下一页会话
+  loadMoreSessions: async () => {
+    const { sessions, sessionTotal } = get();
+    if (sessions.length >= sessionTotal) return;
+    
+    try {
+      const page = await listChatSessions(sessions.length, SESSIONS_PAGE_SIZE);
+      set(state => {
+        const loadedIds = new Set(state.sessions.map(s => s.id));
+        return {
+          sessions: [...state.sessions, ...page.sessions.filter(s => !loadedIds.has(s.id))],
+          sessionTotal: page.total
         };
+      });
+    } catch (error) {
+      console.error('加载会话失败:', error);
+    }
+  },
+  
+  // 创建新会话
+  createSession: (aiProvider, systemPrompt, rolePrompt, roleId) => {
+    const newSession: ChatSession = {
+      id: uuidv4(),
+      title: `新对话 ${new Date().toLocaleString('zh-CN', { 
+        month: 'numeric', 
+        day: 'numeric',
+        hour: 'numeric', 
+        minute: 'numeric'
+      })}`,
+      messages: [],
+      aiProvider,
+      systemPrompt,
+      rolePrompt,
+      roleId,
+      createdAt: Date.now(),
+      updatedAt: Date.now(),
+      messageCount: 0
+    };
+    
+    set(state => ({
+      sessions: [newSession, ...state.sessions],
+      sessionTotal: state.sessionTotal + 1,
+      currentSession: newSession
+    }));
+    saveToBackend(() => createChatSession(newSession));
+    
+    return newSession;
+  },
+  
+  // 设置当前会话，列表中的会话不含消息，需要从后端加载
+  setCurrentSession: async (sessionId) => {
+    const session = get().sessions.find(s => s.id === sessionId);
+    if (session) {
+      set({ currentSession: session });
+    }
+    
+    try {
+      await saveQueue;
+      const fullSession = await getChatSession(sessionId);
+      if (!fullSession) return;
+      
+      set(state => ({
+        sessions: state.sessions.map(s => s.id === sessionId ? fullSession : s),
+        // 加载期间已切换到其他会话时不覆盖
+        currentSession: state.currentSession && state.currentSession.id !== sessionId
+          ? state.currentSession
+          : fullSession
+      }));
+    } catch (error) {
+      console.error('加载会话失败:', error);
+      message.error('加载会话失败');
+    }
+  },
+  
+  // 获取指定会话
+  getSession: (sessionId) => {
+    const { currentSession, sessions } = get();
+    return currentSession?.id === sessionId ? currentSession : sessions.find(s => s.id === sessionId);
+  },
+  
+  // 删除会话
+  deleteSession: (sessionId) => {
+    const wasCurrent = get().currentSession?.id === sessionId;
+    set(state => {
+      const newSessions = state.sessions.filter(s => s.id !== sessionId);
+      const currentSession = wasCurrent
+        ? (newSessions.length > 0 ? newSessions[0] : null)
+        : state.currentSession;
         
-        set(state => {
-          // 按更新时间排序，确保最新的会话在前面
-          const sortedSessions = [...state.sessions, newSession]
-            .sort((a, b) => b.updatedAt - a.updatedAt)
-            // 保留最大数量的会话
-            .slice(0, MAX_SESSIONS_HISTORY);
-            
+      return {
+        sessions: newSessions,
+        sessionTotal: Math.max(0, state.sessionTotal - 1),
+        currentSession
+      };
+    });
+    saveToBackend(() => deleteChatSession(sessionId));
+    
+    const nextSession = get().currentSession;
+    if (wasCurrent && nextSession) {
+      get().setCurrentSession(nextSession.id);
+    }
+  },
+  
+  // 更新会话属性
+  updateSession: (sessionId, updates) => {
+    set(state => {
+      const updatedSessions = state.sessions.map(session => {
+        if (session.id === sessionId) {
           return {
-            sessions: sortedSessions,
-            currentSession: newSession
+            ...session,
+            ...updates,
+            updatedAt: Date.now()
           };
-        });
-        
-        return newSession;
-      },
-      
-      // 设置当前会话
-      setCurrentSession: (sessionId) => {
-        const session = get().sessions.find(s => s.id === sessionId);
-        if (session) {
-          set({ currentSession: session });
         }
-      },
-      
-      // 获取指定会话
-      getSession: (sessionId) => {
-        return get().sessions.find(s => s.id === sessionId);
-      },
+        return session;
+      });
       
-      // 删除会话
-      deleteSession: (sessionId) => {
-        set(state => {
-          const newSessions = state.sessions.filter(s => s.id !== sessionId);
-          const currentSession = state.currentSession?.id === sessionId 
-            ? (newSessions.length > 0 ? newSessions[0] : null)
-            : state.currentSession;
-            
-          return { sessions: newSessions, currentSession };
-        });
-      },
+      // 更新后按更新时间重新排序
+      const sortedSessions = [...updatedSessions]
+        .sort((a, b) => b.updatedAt - a.updatedAt);
       
-      // 更新会话属性
-      updateSession: (sessionId, updates) => {
-        set(state => {
-          const updatedSessions = state.sessions.map(session => {
-            if (session.id === sessionId) {
-              return {
-                ...session,
-                ...updates,
-                updatedAt: Date.now()
-              };
-            }
-            return session;
-          });
-          
-          // 更新后按更新时间重新排序
-          const sortedSessions = [...updatedSessions]
-            .sort((a, b) => b.updatedAt - a.updatedAt);
-          
-          const updatedCurrentSession = state.currentSession?.id === sessionId
-            ? sortedSessions.find(s => s.id === sessionId) || state.currentSession
-            : state.currentSession;
-            
-          return { 
-            sessions: sortedSessions,
-            currentSession: updatedCurrentSession
-          };
-        });
-      },
-      
-      // 添加消息
-      addMessage: (messageData, sessionId) => {
-        const targetSessionId = sessionId || get().currentSession?.id;
-        if (!targetSessionId) {
-          throw new Error('未指定会话ID且没有当前活跃会话');
-        }
-        
-        const messageId = uuidv4();
-        const message: Message = {
-          id: messageId,
-          ...messageData,
-          timestamp: Date.now()
-        };
+      const updatedCurrentSession = state.currentSession?.id === sessionId
+        ? sortedSessions.find(s => s.id === sessionId) || state.currentSession
+        : state.currentSession;
         
-        set(state => {
-          const updatedSessions = state.sessions.map(session => {
-            if (session.id === targetSessionId) {
-              return {
-                ...session,
-                messages: [...session.messages, message],
-                updatedAt: Date.now()
-              };
-            }
-            return session;
-          });
-          
-          // 更新后按更新时间重新排序
-          const sortedSessions = [...updatedSessions]
-            .sort((a, b) => b.updatedAt - a.updatedAt);
-          
-          const updatedCurrentSession = state.currentSession?.id === targetSessionId
-            ? sortedSessions.find(s => s.id === targetSessionId) || state.currentSession
-            : state.currentSession;
-            
-          return { 
-            sessions: sortedSessions,
-            currentSession: updatedCurrentSession
+      return { 
+        sessions: sortedSessions,
+        currentSession: updatedCurrentSession
+      };
+    });
+    
+    const { title, aiProvider, systemPrompt, rolePrompt, roleId } = updates;
+    saveToBackend(() => updateChatSession(sessionId, { title, aiProvider, systemPrompt, rolePrompt, roleId }));
+  },
+  
+  // 添加消息
+  addMessage: (messageData, sessionId) => {
+    const targetSessionId = sessionId || get().currentSession?.id;
+    if (!targetSessionId) {
+      throw new Error('未指定会话ID且没有当前活跃会话');
+    }
+    
+    const messageId = uuidv4();
+    const message: Message = {
+      id: messageId,
+      ...messageData,
+      timestamp: Date.now()
+    };
+    
+    set(state => {
+      const updatedSessions = state.sessions.map(session => {
+        if (session.id === targetSessionId) {
+          return {
+            ...session,
+            messages: [...session.messages, message],
+            messageCount: (session.messageCount ?? session.messages.length) + 1,
+            clipboardItemId: session.clipboardItemId ?? message.clipboardRef?.id,
+            updatedAt: Date.now()
           };
-        });
-        
-        return messageId;
-      },
+        }
+        return session;
+      });
+      
+      // 更新后按更新时间重新排序
+      const sortedSessions = [...updatedSessions]
+        .sort((a, b) => b.updatedAt - a.updatedAt);
       
-      // 更新消息
-      updateMessage: (messageId, content, sessionId) => {
-        const targetSessionId = sessionId || get().currentSession?.id;
-        if (!targetSessionId) return;
+      const updatedCurrentSession = state.currentSession?.id === targetSessionId
+        ? sortedSessions.find(s => s.id === targetSessionId) || state.currentSession
+        : state.currentSession;
         
-        set(state => {
-          const updatedSessions = state.sessions.map(session => {
-            if (session.id === targetSessionId) {
-              const updatedMessages = session.messages.map(msg => {
-                if (msg.id === messageId) {
-                  return { ...msg, content };
-                }
-                return msg;
-              });
-              
-              return {
-                ...session,
-                messages: updatedMessages,
-                updatedAt: Date.now()
-              };
+      return { 
+        sessions: sortedSessions,
+        currentSession: updatedCurrentSession
+      };
+    });
+    saveToBackend(() => addChatMessage(targetSessionId, message));
+    
+    return messageId;
+  },
+  
+  // 更新消息
+  updateMessage: (messageId, content, sessionId, providerId) => {
+    const targetSessionId = sessionId || get().currentSession?.id;
+    if (!targetSessionId) return;
+    
+    set(state => {
+      const updatedSessions = state.sessions.map(session => {
+        if (session.id === targetSessionId) {
+          const updatedMessages = session.messages.map(msg => {
+            if (msg.id === messageId) {
+              return { ...msg, content, ...(providerId && { providerId }) };
             }
-            return session;
+            return msg;
           });
           
-          const updatedCurrentSession = state.currentSession?.id === targetSessionId
-            ? updatedSessions.find(s => s.id === targetSessionId) || state.currentSession
-            : state.currentSession;
-            
-          return { 
-            sessions: updatedSessions,
-            currentSession: updatedCurrentSession
+          return {
+            ...session,
+            messages: updatedMessages,
+            updatedAt: Date.now()
           };
-        });
-      },
+        }
+        return session;
+      });
       
-      // 删除消息
-      deleteMessage: (messageId, sessionId) => {
-        const targetSessionId = sessionId || get().currentSession?.id;
-        if (!targetSessionId) return;
+      const updatedCurrentSession = state.currentSession?.id === targetSessionId
+        ? updatedSessions.find(s => s.id === targetSessionId) || state.currentSession
+        : state.currentSession;
         
-        set(state => {
-          const updatedSessions = state.sessions.map(session => {
-            if (session.id === targetSessionId) {
-              return {
-                ...session,
-                messages: session.messages.filter(msg => msg.id !== messageId),
-                updatedAt: Date.now()
-              };
-            }
-            return session;
-          });
-          
-          const updatedCurrentSession = state.currentSession?.id === targetSessionId
-            ? updatedSessions.find(s => s.id === targetSessionId) || state.currentSession
-            : state.currentSession;
-            
-          return { 
-            sessions: updatedSessions,
-            currentSession: updatedCurrentSession
+      return { 
+        sessions: updatedSessions,
+        currentSession: updatedCurrentSession
+      };
+    });
+    saveToBackend(() => updateChatMessage(targetSessionId, messageId, content, providerId));
+  },
+  
+  // 删除消息
+  deleteMessage: (messageId, sessionId) => {
+    const targetSessionId = sessionId || get().currentSession?.id;
+    if (!targetSessionId) return;
+    
+    set(state => {
+      const updatedSessions = state.sessions.map(session => {
+        if (session.id === targetSessionId) {
+          const messages = session.messages.filter(msg => msg.id !== messageId);
+          return {
+            ...session,
+            messages,
+            messageCount: messages.length,
+            updatedAt: Date.now()
           };
-        });
-      },
+        }
+        return session;
+      });
       
-      // 清空会话消息
-      clearSession: (sessionId) => {
-        set(state => {
-          const updatedSessions = state.sessions.map(session => {
-            if (session.id === sessionId) {
-              return {
-                ...session,
-                messages: [],
-                updatedAt: Date.now()
-              };
-            }
-            return session;
-          });
-          
-          const updatedCurrentSession = state.currentSession?.id === sessionId
-            ? updatedSessions.find(s => s.id === sessionId) || state.currentSession
-            : state.currentSession;
-            
-          return { 
-            sessions: updatedSessions,
-            currentSession: updatedCurrentSession
+      const updatedCurrentSession = state.currentSession?.id === targetSessionId
+        ? updatedSessions.find(s => s.id === targetSessionId) || state.currentSession
+        : state.currentSession;
+        
+      return { 
+        sessions: updatedSessions,
+        currentSession: updatedCurrentSession
+      };
+    });
+    saveToBackend(() => deleteChatMessage(targetSessionId, messageId));
+  },
+  
+  // 清空会话消息
+  clearSession: (sessionId) => {
+    set(state => {
+      const updatedSessions = state.sessions.map(session => {
+        if (session.id === sessionId) {
+          return {
+            ...session,
+            messages: [],
+            messageCount: 0,
+            updatedAt: Date.now()
           };
-        });
-      },
-      
-      // 开始流式响应
-      startStreaming: () => {
-        const controller = new AbortController();
-        set({ isStreaming: true, streamAbortController: controller });
-        return controller;
-      },
-      
-      // 停止流式响应
-      stopStreaming: () => {
-        const { streamAbortController } = get();
-        if (streamAbortController) {
-          streamAbortController.abort();
         }
-        set({ isStreaming: false, streamAbortController: null });
-      },
+        return session;
+      });
       
-      // 追加流式内容
-      appendStreamContent: (response) => {
-        const { currentSession } = get();
-        if (!currentSession) return;
-        
-        const lastMessage = currentSession.messages[currentSession.messages.length - 1];
-        if (!lastMessage || lastMessage.role !== 'assistant') return;
+      const updatedCurrentSession = state.currentSession?.id === sessionId
+        ? updatedSessions.find(s => s.id === sessionId) || state.currentSession
+        : state.currentSession;
         
-        const delta = response.choices[0]?.delta.content || '';
+      return { 
+        sessions: updatedSessions,
+        currentSession: updatedCurrentSession
+      };
+    });
+    saveToBackend(() => clearChatSession(sessionId));
+  },
+  
+  // 开始流式响应
+  startStreaming: () => {
+    const controller = new AbortController();
+    set({ isStreaming: true, streamAbortController: controller });
+    return controller;
+  },
+  
+  // 停止流式响应，chatCompletion 收到中止信号后取消后端的请求
+  stopStreaming: () => {
+    const { streamAbortController } = get();
+    if (streamAbortController) {
+      streamAbortController.abort();
+    }
+    set({ isStreaming: false, streamAbortController: null });
+  },
+  
+  // 追加流式内容，只更新内存，结束后由 sendMessage 保存
+  appendStreamContent: (response) => {
+    const { currentSession } = get();
+    if (!currentSession) return;
+    
+    const lastMessage = currentSession.messages[currentSession.messages.length - 1];
+    if (!lastMessage || lastMessage.role !== 'assistant') return;
+    
+    const delta = response.choices[0]?.delta.content || '';
 
-        set(state => {
-          const updatedSessions = state.sessions.map(session => {
-            if (session.id === currentSession.id) {
-              const updatedMessages = session.messages.map((msg, index) => {
-                if (index === session.messages.length - 1) {
-                  return { ...msg, content: msg.content + delta };
-                }
-                return msg;
-              });
-              
-              return {
-                ...session,
-                messages: updatedMessages
-              };
+    set(state => {
+      const updatedSessions = state.sessions.map(session => {
+        if (session.id === currentSession.id) {
+          const updatedMessages = session.messages.map((msg, index) => {
+            if (index === session.messages.length - 1) {
+              return { ...msg, content: msg.content + delta };
             }
-            return session;
+            return msg;
           });
           
-          const updatedCurrentSession = updatedSessions.find(s => s.id === currentSession.id) 
-            || state.currentSession;
-            
-          return { 
-            sessions: updatedSessions,
-            currentSession: updatedCurrentSession
+          return {
+            ...session,
+            messages: updatedMessages
           };
-        });
-      },
-      
-      // 发送消息并获取AI响应
-      sendMessage: async (content, clipboardRef) => {
-        // 如果有消息正在处理中，则加入等待队列
-        if (processingMessageCount > 0) {
-          message.info('有消息正在处理中，请稍等...');
-          
-          // 随机延迟500-1500ms后重试，避免不必要的并发冲突
-          const delay = 500 + Math.floor(Math.random() * 1000);
-          await new Promise(resolve => setTimeout(resolve, delay));
-          
-          // 延迟后再次检查，如果还在处理中，则排队等待
-          if (processingMessageCount > 0) {
-            const currentSequence = ++messageSequence;
-            console.log(`消息 #${currentSequence} 排队等待中...`);
-            
-            // 等待直到没有消息在处理
-            while (processingMessageCount > 0) {
-              await new Promise(resolve => setTimeout(resolve, 100));
-            }
-            
-            console.log(`消息 #${currentSequence} 开始处理`);
-          }
         }
+        return session;
+      });
+      
+      const updatedCurrentSession = updatedSessions.find(s => s.id === currentSession.id) 
+        || state.currentSession;
         
-        // 标记开始处理新消息
-        processingMessageCount++;
-        const mySequence = ++messageSequence;
-        console.log(`开始处理消息 #${mySequence}`);
-        
-        const { currentSession, addMessage, startStreaming, stopStreaming, appendStreamContent, updateMessage } = get();
-        
-        if (!currentSession) {
-          processingMessageCount--; // 减少计数
-          message.error('请先创建或选择一个对话');
-          return;
-        }
+      return { 
+        sessions: updatedSessions,
+        currentSession: updatedCurrentSession
+      };
+    });
+  },
+  
+  // 设置是否基于剪贴板历史回答
+  setClipboardContext: (value) => {
+    set({ clipboardContext: value });
+  },
+  
+  // 发送消息并获取AI响应
+  sendMessage: async (content, clipboardRef) => {
+    // 如果有消息正在处理中，则加入等待队列
+    if (processingMessageCount > 0) {
+      message.info('有消息正在处理中，请稍等...');
+      
+      // 随机延迟500-1500ms后重试，避免不必要的并发冲突
+      const delay = 500 + Math.floor(Math.random() * 1000);
+      await new Promise(resolve => setTimeout(resolve, delay));
+      
+      // 延迟后再次检查，如果还在处理中，则排队等待
+      if (processingMessageCount > 0) {
+        const currentSequence = ++messageSequence;
+        console.log(`消息 #${currentSequence} 排队等待中...`);
         
-        // 强制重置流式状态，确保清洁开始
-        if (get().isStreaming) {
-          console.log(`消息 #${mySequence} 检测到流式状态未清除，强制重置`);
-          stopStreaming();
-          // 给状态更新一些时间
-          await new Promise(resolve => setTimeout(resolve, 50));
+        // 等待直到没有消息在处理
+        while (processingMessageCount > 0) {
+          await new Promise(resolve => setTimeout(resolve, 100));
         }
         
-        // 重要：创建一个当前会话消息的完整副本，避免依赖zustand状态更新
-        const currentMessages = [...currentSession.messages];
-        
-        // 创建用户消息对象
-        const userMessage = {
-          id: uuidv4(),
-          role: 'user' as const,
-          content,
-          clipboardRef,
-          timestamp: Date.now()
-        };
-        
-        // 添加用户消息到界面
-        addMessage({
-          role: 'user',
-          content,
-          clipboardRef
-        });
-        
-        // 将新消息直接添加到我们的本地副本中
-        currentMessages.push(userMessage);
-        
-        // 创建助手消息占位
-        const assistantMessageId = addMessage({
-          role: 'assistant',
-          content: ''
-        });
-        
-        // 记录助手消息对象，但不添加到消息历史用于API请求
-        const assistantMessage = {
-          id: assistantMessageId,
-          role: 'assistant' as const,
-          content: '',
-          timestamp: Date.now()
-        };
-        
-        try {
-          // 获取AI设置
-          const aiSettings = useAISettingsStore.getState();
-          const providerId = currentSession.aiProvider.id;
-          const providerSettings = aiSettings.settings.providers[providerId];
-          
-          if (!providerSettings) {
-            throw new Error(`未找到提供商设置: ${providerId}`);
-          }
-          
-          // 构建消息历史 - 使用我们的本地副本
-          const messages = [
-            ...(currentSession.systemPrompt ? [{
-              role: 'system' as const,
-              content: currentSession.systemPrompt
-            }] : []),
-            ...(currentSession.rolePrompt ? [{
-              role: 'system' as const,
-              content: currentSession.rolePrompt
-            }] : []),
-            // 使用本地副本，不依赖zustand状态
-            ...currentMessages.map(msg => {
-              // 如果消息有剪贴板引用，将引用内容添加到消息中
-              if (msg.clipboardRef && msg.clipboardRef.content) {
-                return {
-                  role: msg.role,
-                  content: `${msg.content}\n\n${msg.clipboardRef.content}`
-                };
-              }
-              return {
-                role: msg.role,
-                content: msg.content
-              };
-            })
-          ];
-          
+        console.log(`消息 #${currentSequence} 开始处理`);
+      }
+    }
     
-          // 是否使用流式响应
-          if (providerSettings.use_stream) {
-            const controller = startStreaming();
-
-            try {
-              const response = await fetch(`${currentSession.aiProvider.apiBaseUrl}/chat/completions`, {
-                method: 'POST',
-                headers: {
-                  'Content-Type': 'application/json',
-                  'Authorization': `Bearer ${providerSettings.api_key || ''}`
-                },
-                body: JSON.stringify({
-                  model: providerSettings.selected_model,
-                  messages,
-                  temperature: providerSettings.temperature,
-                  max_tokens: providerSettings.max_tokens,
-                  stream: true
-                }),
-                signal: controller.signal
-              });
-              
-              if (!response.ok) {
-                const errorData = await response.json().catch(() => ({ error: { message: response.statusText } }));
-                throw new Error(`API错误: ${errorData.error?.message || response.statusText}`);
-              }
-              
-              const reader = response.body?.getReader();
-              if (!reader) throw new Error('无法创建流式读取器');
-              
-              const decoder = new TextDecoder();
-              let buffer = '';
-              
-              // 记录完整响应内容，用于调试
-              let fullResponseContent = '';
-              
-              while (true) {
-                const { done, value } = await reader.read();
-                if (done) break;
-                
-                buffer += decoder.decode(value, { stream: true });
-                const lines = buffer.split('\n');
-                buffer = lines.pop() || '';
-                
-                for (const line of lines) {
-                  const trimmedLine = line.trim();
-                  if (!trimmedLine || trimmedLine === 'data: [DONE]') continue;
-                  
-                  try {
-                    const data = JSON.parse(trimmedLine.replace(/^data: /, ''));
-                    const content = data.choices[0]?.delta.content || '';
-                    
-                    // 收集完整响应并检查思考标签
-                    fullResponseContent += content;
-                    
-                    appendStreamContent(data);
-                  } catch (e) {
-                    console.error('解析流响应失败:', e, trimmedLine);
-                  }
-                }
-              }
-              
-              // 响应完成后，检查完整内容
-              console.log(`消息 #${mySequence} 流式请求完成`);
-              
-            } catch (error: any) {
-              if (error.name !== 'AbortError') {
-                throw error;
-              }
-            } finally {
-              stopStreaming();
-              // 确保状态完全更新
-              await new Promise(resolve => setTimeout(resolve, 50));
-            }
-          } else {
-            console.log(`消息 #${mySequence} 开始非流式请求`);
-            try {
-              const response = await fetch(`${currentSession.aiProvider.apiBaseUrl}/chat/completions`, {
-                method: 'POST',
-                headers: {
-                  'Content-Type': 'application/json',
-                  'Authorization': `Bearer ${providerSettings.api_key || ''}`
-                },
-                body: JSON.stringify({
-                  model: providerSettings.selected_model,
-                  messages,
-                  temperature: providerSettings.temperature,
-                  max_tokens: providerSettings.max_tokens,
-                  stream: false
-                })
-              });
-              
-              if (!response.ok) {
-                const errorData = await response.json().catch(() => ({ error: { message: response.statusText } }));
-                throw new Error(`API错误: ${errorData.error?.message || response.statusText}`);
-              }
-              
-              const data = await response.json();
-              const responseContent = data.choices[0]?.message?.content || '';
-              
-              updateMessage(assistantMessageId, responseContent);
-              console.log(`消息 #${mySequence} 非流式请求完成`);
-            } catch (error: any) {
-              throw error;
-            }
-          }
-        } catch (error: any) {
-          console.error(`消息 #${mySequence} 处理失败:`, error);
-          message.error(`获取AI响应失败: ${error.message}`);
-          updateMessage(assistantMessageId, `错误: ${error.message}`);
-        } finally {
-          // 不管成功还是失败，最后都要减少消息处理计数
-          processingMessageCount--;
-          console.log(`消息 #${mySequence} 处理完成，当前处理中消息数: ${processingMessageCount}`);
-        }
-      },
+    // 标记开始处理新消息
+    processingMessageCount++;
+    const mySequence = ++messageSequence;
+    console.log(`开始处理消息 #${mySequence}`);
+    
+    const { currentSession, addMessage, startStreaming, stopStreaming, appendStreamContent, updateMessage } = get();
+    
+    if (!currentSession) {
+      processingMessageCount--; // 减少计数
+      message.error('请先创建或选择一个对话');
+      return;
+    }
+    
+    // 强制重置流式状态，确保清洁开始
+    if (get().isStreaming) {
+      console.log(`消息 #${mySequence} 检测到流式状态未清除，强制重置`);
+      stopStreaming();
+      // 给状态更新一些时间
+      await new Promise(resolve => setTimeout(resolve, 50));
+    }
+    
+    // 重要：创建一个当前会话消息的完整副本，避免依赖zustand状态更新
+    const currentMessages = [...currentSession.messages];
+    
+    // 创建用户消息对象
+    const userMessage = {
+      id: uuidv4(),
+      role: 'user' as const,
+      content,
+      clipboardRef,
+      timestamp: Date.now()
+    };
+    
+    // 添加用户消息到界面
+    addMessage({
+      role: 'user',
+      content,
+      clipboardRef
+    });
+    
+    // 将新消息直接添加到我们的本地副本中
+    currentMessages.push(userMessage);
+    
+    // 创建助手消息占位
+    const assistantMessageId = addMessage({
+      role: 'assistant',
+      content: ''
+    });
+    
+    // 记录助手消息对象，但不添加到消息历史用于API请求
+    const assistantMessage = {
+      id: assistantMessageId,
+      role: 'assistant' as const,
+      content: '',
+      timestamp: Date.now()
+    };
+    
+    try {
+      const providerId = await resolveProviderId(currentSession);
+      
+      // 构建消息历史 - 使用我们的本地副本，不依赖zustand状态
+      const { messages, latestRef } = await buildChatMessages(currentSession, currentMessages, error => {
+        console.warn('渲染角色提示词失败，使用原提示词:', error);
+        message.warning(`角色提示词渲染失败，已按原文发送：${error}`);
+      });
       
-      // 导出会话历史为JSON
-      exportSessionHistory: async (sessionId) => {
-        const session = get().sessions.find(s => s.id === sessionId);
-        if (!session) {
-          throw new Error('未找到指定会话');
+      // 超出模型上下文长度时后端会按设置的策略调整，这里只提示用户
+      try {
+        const report = await estimateChatTokens(providerId, undefined, messages, undefined, currentSession.roleId);
+        if (!report.fits) {
+          message.info(report.omitted_chunks > 0
+            ? `内容超出模型上下文长度，将省略 ${report.omitted_chunks} 段`
+            : `对话超出模型上下文长度，将${report.strategy === 'summarize' ? '总结' : '省略'}较早的 ${report.dropped_messages} 条消息`);
         }
-        
-        return JSON.stringify(session);
-      },
+      } catch (error) {
+        console.warn('估算 token 数失败:', error);
+      }
       
-      // 导入会话历史
-      importSessionHistory: async (jsonData) => {
-        try {
-          const session = JSON.parse(jsonData) as ChatSession;
-          
-          // 验证必要的字段
-          if (!session.id || !session.title || !Array.isArray(session.messages) || !session.aiProvider) {
-            throw new Error('会话数据无效');
+      // 是否流式由角色绑定或提供商设置决定，非流式请求停止后仍会显示返回的结果
+      const controller = startStreaming();
+      try {
+        console.log(`消息 #${mySequence} 开始请求`);
+        const result = await chatCompletion({
+          providerId,
+          messages,
+          clipboardContext: get().clipboardContext,
+          usageSource: {
+            role_id: currentSession.roleId,
+            item_id: latestRef?.id
           }
-          
-          // 检查是否已存在相同ID的会话
-          const existingSession = get().sessions.find(s => s.id === session.id);
-          if (existingSession) {
-            // 如果已存在，使用新ID
-            session.id = uuidv4();
-          }
-          
-          // 设置时间戳
-          session.createdAt = session.createdAt || Date.now();
-          session.updatedAt = Date.now();
-          
-          set(state => {
-            // 添加会话并排序
-            const sortedSessions = [...state.sessions, session]
-              .sort((a, b) => b.updatedAt - a.updatedAt)
-              .slice(0, MAX_SESSIONS_HISTORY);
-              
-            return { 
-              sessions: sortedSessions,
-              currentSession: session 
-            };
+        }, appendStreamContent, controller.signal);
+        
+        // 流式内容只在内存中追加，结束（或停止）后保存完整回复和实际回答的提供商
+        updateMessage(assistantMessageId, result.content, currentSession.id, result.providerId);
+        
+        // 引用的剪贴板条目显示在回答下方
+        if (result.references.length > 0) {
+          const withSources = (session: ChatSession): ChatSession => ({
+            ...session,
+            messages: session.messages.map(msg =>
+              msg.id === assistantMessageId ? { ...msg, sources: result.references } : msg
+            )
           });
-          
-          message.success('会话导入成功');
-        } catch (error) {
-          console.error('导入会话失败:', error);
-          throw new Error(`导入会话失败: ${error instanceof Error ? error.message : String(error)}`);
+          set(state => ({
+            sessions: state.sessions.map(session => session.id === currentSession.id ? withSources(session) : session),
+            currentSession: state.currentSession?.id === currentSession.id ? withSources(state.currentSession) : state.currentSession
+          }));
         }
-      },
-    }),
-    {
-      name: 'copy2ai-chat-store',  // 存储的键名
-      // 可选地配置持久化哪些字段
-      partialize: (state) => ({
-        sessions: state.sessions,
-        currentSession: state.currentSession,
-        // 不存储流式状态和控制器
-      }),
+        console.log(`消息 #${mySequence} 请求完成`);
+      } finally {
+        stopStreaming();
+        // 确保状态完全更新
+        await new Promise(resolve => setTimeout(resolve, 50));
+      }
+    } catch (error: any) {
+      console.error(`消息 #${mySequence} 处理失败:`, error);
+      message.error(`获取AI响应失败: ${error.message}`);
+      updateMessage(assistantMessageId, `错误: ${error.message}`);
+    } finally {
+      // 不管成功还是失败，最后都要减少消息处理计数
+      processingMessageCount--;
+      console.log(`消息 #${mySequence} 处理完成，当前处理中消息数: ${processingMessageCount}`);
+    }
+  },
+  
+  // 估算输入框中的内容连同对话历史的 token 数
+  estimateDraftTokens: async (content, clipboardRef) => {
+    const { currentSession } = get();
+    if (!currentSession) return null;
+    
+    const draft: Message = { id: 'draft', role: 'user', content, clipboardRef, timestamp: Date.now() };
+    const providerId = await resolveProviderId(currentSession);
+    const { messages } = await buildChatMessages(currentSession, [...currentSession.messages, draft]);
+    return estimateChatTokens(providerId, undefined, messages, undefined, currentSession.roleId);
+  },
+  
+  // 导出会话历史，JSON 可以再导入，Markdown 便于阅读
+  exportSessionHistory: async (sessionId, format = 'json') => {
+    await saveQueue;
+    return exportChatSession(sessionId, format);
+  },
+  
+  // 导入会话历史
+  importSessionHistory: async (jsonData) => {
+    try {
+      const session = JSON.parse(jsonData) as ChatSession;
+      
+      // 验证必要的字段
+      if (!session.id || !session.title || !Array.isArray(session.messages) || !session.aiProvider) {
+        throw new Error('会话数据无效');
+      }
+      
+      // 已存在相同ID的会话时后端会使用新ID
+      await saveQueue;
+      const imported = await importChatSession(jsonData);
+      
+      set(state => ({
+        sessions: [imported, ...state.sessions],
+        sessionTotal: state.sessionTotal + 1,
+        currentSession: imported
+      }));
+      
+      message.success('会话导入成功');
+    } catch (error) {
+      console.error('导入会话失败:', error);
+      throw new Error(`导入会话失败: ${error instanceof Error ? error.message : String(error)}`);
     }
-  )
-);
\ No newline at end of file
+  },
+}));
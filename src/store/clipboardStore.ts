@@ -3,8 +3,31 @@ import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 import { v4 as uuidv4 } from 'uuid';
 import { ClipboardItem, ClipboardFilter, ClipboardOperationResult, ClearOption } from '../models/clipboard';
-import { detectCategory } from '../constants/categories';
 import { message } from 'antd';
+import { syncCategories } from '../constants/categories';
+import { searchClipboard } from '../api/apiClient';
+
+// 全文搜索返回的最大条目数
+const SEARCH_LIMIT = 500;
+
+// 排序并添加序号，rankedIds 为搜索结果时按相关度排序，否则固定 > 收藏 > 时间倒序
+const sortAndNumber = (items: ClipboardItem[], rankedIds?: string[]): ClipboardItem[] => {
+  const rank = rankedIds ? new Map(rankedIds.map((id, index) => [id, index])) : null;
+  const sorted = [...items].sort((a, b) => {
+    if (rank) return (rank.get(a.id) ?? 0) - (rank.get(b.id) ?? 0);
+    if (a.pinned && !b.pinned) return -1;
+    if (!a.pinned && b.pinned) return 1;
+    if (a.favorite && !b.favorite) return -1;
+    if (!a.favorite && b.favorite) return 1;
+    return b.timestamp - a.timestamp; // 时间戳降序排序，确保新的内容在前
+  });
+  
+  // 添加序号信息
+  return sorted.map((item, index) => ({
+    ...item,
+    index: index + 1 // 从1开始的序号
+  }));
+};
 
 // 剪贴板状态接口
 interface ClipboardState {
@@ -36,7 +59,7 @@ interface ClipboardState {
   favoriteItem: (id: string) => Promise<void>;
   pinItem: (id: string) => Promise<void>;
   editItem: (id: string, newContent: string) => Promise<void>;
-  copyToClipboard: (id: string) => Promise<void>;
+  copyToClipboard: (id: string, plainText?: boolean) => Promise<void>;
   translateItem: (id: string) => Promise<void>;
   summarizeItem: (id: string) => Promise<void>;
   categorizeItem: (id: string, category: string) => Promise<void>;
@@ -76,6 +99,8 @@ export const useClipboardStore = create<ClipboardState>((set, get) => ({
     set({ loading: true });
     
     try {
+      // 分类由后端统一保存，先同步到本地缓存
+      await syncCategories().catch(error => console.error('同步分类失败:', error));
       await get().fetchItems();
       
       // 确保事件监听器已设置
@@ -172,57 +197,62 @@ export const useClipboardStore = create<ClipboardState>((set, get) => ({
       return;
     }
     
-    // 使用 requestAnimationFrame 优化性能
-    requestAnimationFrame(() => {
-      let filtered: ClipboardItem[];
+    // 其余筛选条件在本地应用
+    const applyLocalFilters = (source: ClipboardItem[]): ClipboardItem[] => {
+      let filtered = source;
       
-      // 优先使用分类缓存来提高筛选速度
-      if (selectedCategory && !showFavoritesOnly && !showPinnedOnly && !searchText) {
-        filtered = [...(categoryCache[selectedCategory] || [])];
-      } else {
-        // 复制数组以避免修改原始数据
-        filtered = [...items];
-        
-        // 使用更高效的筛选方式
-        if (searchText) {
-          const lowerSearchText = searchText.toLowerCase();
-          filtered = filtered.filter(item => 
-            item.content.toLowerCase().includes(lowerSearchText)
-          );
-        }
-        
-        // 应用收藏筛选
-        if (showFavoritesOnly) {
-          filtered = filtered.filter(item => item.favorite);
-        }
-        
-        // 应用固定项筛选
-        if (showPinnedOnly) {
-          filtered = filtered.filter(item => item.pinned);
-        }
-        
-        // 应用分类筛选
-        if (selectedCategory) {
-          filtered = filtered.filter(item => item.category === selectedCategory);
-        }
+      // 应用收藏筛选
+      if (showFavoritesOnly) {
+        filtered = filtered.filter(item => item.favorite);
       }
       
-      // 首先按照重要性排序（固定 > 收藏），然后按照时间戳逆序排序（新的在前）
-      filtered.sort((a, b) => {
-        if (a.pinned && !b.pinned) return -1;
-        if (!a.pinned && b.pinned) return 1;
-        if (a.favorite && !b.favorite) return -1;
-        if (!a.favorite && b.favorite) return 1;
-        return b.timestamp - a.timestamp; // 时间戳降序排序，确保新的内容在前
-      });
+      // 应用固定项筛选
+      if (showPinnedOnly) {
+        filtered = filtered.filter(item => item.pinned);
+      }
       
-      // 添加序号信息
-      filtered = filtered.map((item, index) => ({
-        ...item,
-        index: index + 1 // 从1开始的序号
-      }));
+      // 应用分类筛选
+      if (selectedCategory) {
+        filtered = filtered.filter(item => item.category === selectedCategory);
+      }
+      return filtered;
+    };
+    
+    // 有搜索词时使用后端全文索引，结果按相关度排序
+    if (searchText) {
+      searchClipboard(searchText, SEARCH_LIMIT)
+        .then(hits => hits.map(hit => hit.item.id))
+        .catch(error => {
+          console.error('全文搜索失败，改用本地匹配:', error);
+          return null;
+        })
+        .then(rankedIds => {
+          // 搜索期间输入已变化，丢弃过期的结果
+          if (get().searchText !== searchText) return;
+          
+          const currentItems = get().items;
+          let matched: ClipboardItem[];
+          if (rankedIds) {
+            const idSet = new Set(rankedIds);
+            matched = currentItems.filter(item => idSet.has(item.id));
+          } else {
+            const lowerSearchText = searchText.toLowerCase();
+            matched = currentItems.filter(item => item.content.toLowerCase().includes(lowerSearchText));
+          }
+          set({ filteredItems: sortAndNumber(applyLocalFilters(matched), rankedIds ?? undefined) });
+        });
+      return;
+    }
+    
+    // 使用 requestAnimationFrame 优化性能
+    requestAnimationFrame(() => {
+      // 优先使用分类缓存来提高筛选速度
+      const filtered = selectedCategory && !showFavoritesOnly && !showPinnedOnly
+        ? categoryCache[selectedCategory] || []
+        : applyLocalFilters(items);
       
-      set({ filteredItems: filtered });
+      // 首先按照重要性排序（固定 > 收藏），然后按照时间戳逆序排序（新的在前）
+      set({ filteredItems: sortAndNumber(filtered) });
     });
   },
   
@@ -235,14 +265,17 @@ export const useClipboardStore = create<ClipboardState>((set, get) => ({
         content,
         timestamp: Date.now(),
         favorite: false,
-        pinned: false,
-        category: detectCategory(content)
+        pinned: false
       };
       
-      // 调用Rust后端保存剪贴板条目
+      // 调用Rust后端保存剪贴板条目，分类由后端识别
       const result = await invoke<ClipboardOperationResult>('add_clipboard_item', { item: newItem });
       
       if (result.success) {
+        if (result.data) {
+          Object.assign(newItem, result.data);
+        }
+        
         // 更新本地数据和分类缓存
         set(state => {
           // 更新项目数组
@@ -414,7 +447,7 @@ export const useClipboardStore = create<ClipboardState>((set, get) => ({
   },
   
   // 复制到剪贴板
-  copyToClipboard: async (id: string) => {
+  copyToClipboard: async (id: string, plainText = false) => {
     try {
       const { items } = get();
       const item = items.find(item => item.id === id);
@@ -425,7 +458,7 @@ export const useClipboardStore = create<ClipboardState>((set, get) => ({
       }
       
       // 调用Rust后端将内容复制到系统剪贴板
-      const result = await invoke<ClipboardOperationResult>('copy_to_clipboard', { content: item.content });
+      const result = await invoke<ClipboardOperationResult>('copy_to_clipboard', { content: item.content, id: item.id, plainText });
       
       if (!result.success) {
         set({ error: result.message || '复制到剪贴板失败' });
@@ -817,6 +850,12 @@ export const useClipboardStore = create<ClipboardState>((set, get) => ({
         await get().fetchItems();
       });
       
+      // 分类在任一窗口中修改后，重新同步分类并刷新条目（重命名或删除分类会修改条目的分类）
+      await listen('categories-changed', async () => {
+        await syncCategories().catch(error => console.error('同步分类失败:', error));
+        await get().fetchItems();
+      });
+      
       console.log('已成功设置剪贴板变化事件监听');
       set({ isEventListenerSet: true });
     } catch (error) {
@@ -52,15 +52,22 @@ const useRoleStore = create<RoleState>((set, get) => ({
   addRole: async (role) => {
     set({ loading: true });
     try {
-      // 直接传递各个字段，而不是整个对象
-      // 注意：进行字段名映射，从前端命名规则转换为后端命名规则
-      const result = await invoke<RoleOperationResult>('add_role', { 
-        name: role.name,
-        description: role.description,
-        systemPrompt: role.system_prompt,  // 前端: system_prompt -> 后端: systemPrompt
-        icon: role.icon || '💻',
-        isCustom: role.is_custom,          // 前端: is_custom -> 后端: isCustom
-        avatar: role.avatar || null
+      // ID和时间戳由后端生成，绑定的提供商、模型和参数与角色字段平铺在一起
+      const result = await invoke<RoleOperationResult>('add_role', {
+        role: {
+          name: role.name,
+          description: role.description,
+          system_prompt: role.system_prompt,
+          icon: role.icon || '💻',
+          is_custom: role.is_custom,
+          avatar: role.avatar || null,
+          inputs: role.inputs || [],
+          provider_id: role.provider_id,
+          model: role.model,
+          temperature: role.temperature,
+          max_tokens: role.max_tokens,
+          stream: role.stream
+        }
       });
       
       if (result.success) {
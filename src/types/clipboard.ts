@@ -13,6 +13,19 @@ export interface ClipboardItem {
   isPinned?: boolean;
   isFavorite?: boolean;
   contentType?: string;
+  image?: {
+    hash: string;
+    width: number;
+    height: number;
+    size: number;
+    mime: string;
+    thumbnail: string;
+  };
+  formats?: {
+    html?: string;
+    rtf?: string;
+    files?: string[];
+  };
   metadata?: {
     [key: string]: any;
   };